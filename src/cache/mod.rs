@@ -1,11 +1,196 @@
 //! Request cache (TBD) and chat session persistence.
 
-use std::{fs, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    ops::Range,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use anyhow::Result;
-// serde traits not needed directly here; use serde_json helpers
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
-use crate::{config::Config, llm::ChatMessage};
+use crate::{
+    config::Config,
+    llm::{ChatMessage, ChatOptions, Role},
+};
+
+/// Disk usage summary of a cache directory.
+#[derive(Debug, Clone, Default)]
+pub struct CacheStats {
+    pub count: usize,
+    pub total_bytes: u64,
+    pub oldest: Option<SystemTime>,
+    pub newest: Option<SystemTime>,
+}
+
+impl CacheStats {
+    fn collect(dir: &Path, include: impl Fn(&str) -> bool) -> Self {
+        let mut stats = CacheStats::default();
+        let Ok(read_dir) = fs::read_dir(dir) else {
+            return stats;
+        };
+        for entry in read_dir.filter_map(|e| e.ok()) {
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            if !meta.is_file() || !include(&entry.file_name().to_string_lossy()) {
+                continue;
+            }
+            stats.count += 1;
+            stats.total_bytes += meta.len();
+            if let Ok(modified) = meta.modified() {
+                if stats.oldest.is_none_or(|t| modified < t) {
+                    stats.oldest = Some(modified);
+                }
+                if stats.newest.is_none_or(|t| modified > t) {
+                    stats.newest = Some(modified);
+                }
+            }
+        }
+        stats
+    }
+}
+
+impl std::fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "  entries: {}", self.count)?;
+        writeln!(f, "  size:    {}", format_bytes(self.total_bytes))?;
+        writeln!(f, "  oldest:  {}", format_age(self.oldest))?;
+        write!(f, "  newest:  {}", format_age(self.newest))
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Format a Unix timestamp as `YYYY-MM-DD HH:MM` (UTC).
+pub fn format_timestamp(secs: i64) -> String {
+    let days = secs.div_euclid(86400);
+    let rem = secs.rem_euclid(86400);
+    // Civil-from-days (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60
+    )
+}
+
+fn format_age(t: Option<SystemTime>) -> String {
+    let Some(t) = t else {
+        return "-".to_string();
+    };
+    let secs = SystemTime::now()
+        .duration_since(t)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    if secs < 60 {
+        format!("{}s ago", secs)
+    } else if secs < 3600 {
+        format!("{}m ago", secs / 60)
+    } else if secs < 86400 {
+        format!("{}h ago", secs / 3600)
+    } else {
+        format!("{}d ago", secs / 86400)
+    }
+}
+
+const SESSION_FORMAT_VERSION: u8 = 1;
+
+/// On-disk chat session: the messages plus metadata shown by `--list-chats --long`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionFile {
+    pub version: u8,
+    pub model: String,
+    /// Unix timestamps (seconds).
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub messages: Vec<ChatMessage>,
+}
+
+/// Sessions written before metadata existed are a bare message array.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredSession {
+    Wrapped(SessionFile),
+    Legacy(Vec<ChatMessage>),
+}
+
+/// One row of `--list-chats --long`, read from a session file's metadata.
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub path: PathBuf,
+    /// `None` when the file couldn't be read as a session.
+    pub message_count: Option<usize>,
+    /// Model of the last write; `None` for legacy sessions and unreadable files.
+    pub last_model: Option<String>,
+    /// Unix timestamp (seconds) of the last write.
+    pub updated_at: Option<i64>,
+}
+
+/// A message line matched by `--search-chat`.
+#[derive(Debug, Clone)]
+pub struct ChatMatch {
+    pub chat_id: String,
+    pub role: Role,
+    pub line: String,
+    /// Byte range of the match within `line`.
+    pub range: Range<usize>,
+}
+
+impl ChatMatch {
+    /// Split the line into (before, match, after), keeping at most `context` chars on each side.
+    pub fn snippet(&self, context: usize) -> (String, &str, String) {
+        let before = &self.line[..self.range.start];
+        let after = &self.line[self.range.end..];
+        let skip = before.chars().count().saturating_sub(context);
+        let mut head: String = before.chars().skip(skip).collect();
+        if skip > 0 {
+            head.insert_str(0, "...");
+        }
+        let mut tail: String = after.chars().take(context).collect();
+        if after.chars().count() > context {
+            tail.push_str("...");
+        }
+        (head, &self.line[self.range.clone()], tail)
+    }
+}
+
+pub(crate) fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
 
 #[derive(Debug, Clone)]
 pub struct ChatSession {
@@ -40,16 +225,42 @@ impl ChatSession {
     }
 
     pub fn read(&self, chat_id: &str) -> Result<Vec<ChatMessage>> {
+        Ok(self
+            .read_session(chat_id)?
+            .map(|s| s.messages)
+            .unwrap_or_default())
+    }
+
+    /// Read a session with its metadata. Legacy files get an empty model and the
+    /// file's modification time as both timestamps.
+    pub fn read_session(&self, chat_id: &str) -> Result<Option<SessionFile>> {
         let p = self.file_path(chat_id);
         if !p.exists() {
-            return Ok(Vec::new());
+            return Ok(None);
         }
-        let text = fs::read_to_string(p)?;
-        let msgs: Vec<ChatMessage> = serde_json::from_str(&text)?;
-        Ok(msgs)
+        let text = fs::read_to_string(&p)?;
+        let session = match serde_json::from_str(&text)? {
+            StoredSession::Wrapped(s) => s,
+            StoredSession::Legacy(messages) => {
+                let modified = fs::metadata(&p)
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs() as i64)
+                    .unwrap_or(0);
+                SessionFile {
+                    version: 0,
+                    model: String::new(),
+                    created_at: modified,
+                    updated_at: modified,
+                    messages,
+                }
+            }
+        };
+        Ok(Some(session))
     }
 
-    pub fn write(&self, chat_id: &str, mut messages: Vec<ChatMessage>) -> Result<()> {
+    pub fn write(&self, chat_id: &str, model: &str, mut messages: Vec<ChatMessage>) -> Result<()> {
         // Retain the first message (system role), truncate the rest to at most `length`.
         if messages.len() > 1 {
             let keep = self.length;
@@ -58,28 +269,129 @@ impl ChatSession {
             let start = if over > 0 { 1 + over as usize } else { 1 };
             let mut truncated = Vec::with_capacity(1 + keep);
             truncated.push(messages.remove(0));
-            let slice = messages.into_iter().skip(start - 1).collect::<Vec<_>>();
+            // A tool result whose assistant call was cut off would be rejected by the provider
+            let slice = messages
+                .into_iter()
+                .skip(start - 1)
+                .skip_while(|m| m.role == Role::Tool)
+                .collect::<Vec<_>>();
             truncated.extend(slice);
             messages = truncated;
         }
 
-        let p = self.file_path(chat_id);
-        fs::write(p, serde_json::to_string(&messages)?)?;
+        let now = unix_now();
+        let created_at = self
+            .read_session(chat_id)
+            .ok()
+            .flatten()
+            .map_or(now, |s| s.created_at);
+        let session = SessionFile {
+            version: SESSION_FORMAT_VERSION,
+            model: model.to_string(),
+            created_at,
+            updated_at: now,
+            messages,
+        };
+        fs::write(self.file_path(chat_id), serde_json::to_string(&session)?)?;
         Ok(())
     }
 
+    /// Session files, most recently modified first.
     pub fn list(&self) -> Vec<PathBuf> {
         if let Ok(read_dir) = fs::read_dir(&self.storage_path) {
             let mut files: Vec<PathBuf> =
                 read_dir.filter_map(|e| e.ok().map(|e| e.path())).collect();
-            files.sort_by_key(|p| fs::metadata(p).and_then(|m| m.modified()).ok());
+            files.sort_by_key(|p| {
+                std::cmp::Reverse(fs::metadata(p).and_then(|m| m.modified()).ok())
+            });
             files
         } else {
             Vec::new()
         }
     }
+
+    /// Sessions with their metadata, in [`list`](Self::list) order.
+    pub fn list_with_metadata(&self) -> Vec<SessionInfo> {
+        self.list()
+            .into_iter()
+            .map(|path| {
+                let session = path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .and_then(|id| self.read_session(id).ok().flatten());
+                match session {
+                    Some(s) => SessionInfo {
+                        path,
+                        message_count: Some(s.messages.len()),
+                        last_model: Some(s.model).filter(|m| !m.is_empty()),
+                        updated_at: Some(s.updated_at),
+                    },
+                    None => SessionInfo {
+                        path,
+                        message_count: None,
+                        last_model: None,
+                        updated_at: None,
+                    },
+                }
+            })
+            .collect()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats::collect(&self.storage_path, |_| true)
+    }
+
+    /// Find message lines matching `pattern`, newest chat first, stopping after `max_results`.
+    pub fn search(&self, pattern: &Regex, max_results: Option<usize>) -> Vec<ChatMatch> {
+        let limit = max_results.unwrap_or(usize::MAX);
+        let mut matches = Vec::new();
+        for path in self.list() {
+            let Some(chat_id) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            // Unreadable or foreign files in the chat directory are skipped
+            let Ok(messages) = self.read(chat_id) else {
+                continue;
+            };
+            for m in messages {
+                let text = m.content.extract_text();
+                for line in text.lines() {
+                    if let Some(found) = pattern.find(line) {
+                        matches.push(ChatMatch {
+                            chat_id: chat_id.to_string(),
+                            role: m.role.clone(),
+                            line: line.to_string(),
+                            range: found.range(),
+                        });
+                        if matches.len() >= limit {
+                            return matches;
+                        }
+                    }
+                }
+            }
+        }
+        matches
+    }
+}
+
+/// Mixed into every request key; bump it when the key derivation changes so old
+/// entries become misses (and age out through pruning) instead of being reused.
+const CACHE_VERSION: u32 = 4;
+const INDEX_FILE: &str = "cache_index.json";
+const INDEX_LOCK_FILE: &str = "cache_index.lock";
+/// A lock older than this is assumed to belong to a crashed process.
+const STALE_LOCK_AFTER: Duration = Duration::from_secs(10);
+
+/// Per-entry bookkeeping for LRU eviction, persisted in `cache_index.json`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct IndexEntry {
+    /// Milliseconds since the Unix epoch of the last `get`/`set`.
+    last_used: u64,
+    size_bytes: u64,
 }
 
+type CacheIndex = HashMap<String, IndexEntry>;
+
 #[derive(Debug, Clone)]
 pub struct RequestCache {
     length: usize,
@@ -100,49 +412,416 @@ impl RequestCache {
         }
     }
 
+    /// Key for sending `messages` to `base_url` with the sampling settings in `opts`.
     pub fn key_for(
         &self,
         base_url: &str,
-        model: &str,
-        temperature: f32,
-        top_p: f32,
+        opts: &ChatOptions,
         messages: &Vec<ChatMessage>,
     ) -> String {
         let payload = serde_json::json!({
+            "cache_version": CACHE_VERSION,
             "base_url": base_url,
-            "model": model,
-            "temperature": temperature,
-            "top_p": top_p,
+            "model": opts.model,
+            "temperature": opts.temperature,
+            "top_p": opts.top_p,
+            "stop": opts.stop_sequences,
+            "presence_penalty": opts.presence_penalty,
+            "frequency_penalty": opts.frequency_penalty,
             "messages": messages,
         });
         let data = serde_json::to_vec(&payload).unwrap_or_default();
-        let digest = md5::compute(data);
-        format!("{:x}", digest)
+        let digest = Sha256::digest(data);
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
     }
 
     pub fn get(&self, key: &str) -> Option<String> {
         let p = self.cache_path.join(key);
-        fs::read_to_string(p).ok()
+        let value = fs::read_to_string(p).ok()?;
+        // Recording the hit is best-effort; a busy index must not turn a hit into a miss.
+        let _ = self.with_index(|index| {
+            index.insert(
+                key.to_string(),
+                IndexEntry {
+                    last_used: now_millis(),
+                    size_bytes: value.len() as u64,
+                },
+            );
+            Ok(())
+        });
+        Some(value)
     }
 
     pub fn set(&self, key: &str, value: &str) -> Result<()> {
         let p = self.cache_path.join(key);
         fs::write(p, value)?;
-        self.prune()?;
-        Ok(())
+        self.with_index(|index| {
+            index.insert(
+                key.to_string(),
+                IndexEntry {
+                    last_used: now_millis(),
+                    size_bytes: value.len() as u64,
+                },
+            );
+            self.prune(index);
+            Ok(())
+        })
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats::collect(&self.cache_path, is_cache_key)
     }
 
-    fn prune(&self) -> Result<()> {
-        let mut entries: Vec<_> = fs::read_dir(&self.cache_path)?
-            .filter_map(|e| e.ok())
+    /// Evict least-recently-used entries until at most `length` remain.
+    fn prune(&self, index: &mut CacheIndex) {
+        // Reconcile with the directory: forget vanished files and adopt entries written
+        // before the index existed, using their mtime as the last use.
+        index.retain(|key, _| self.cache_path.join(key).is_file());
+        if let Ok(rd) = fs::read_dir(&self.cache_path) {
+            for entry in rd.filter_map(|e| e.ok()) {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if !is_cache_key(&name) || index.contains_key(&name) {
+                    continue;
+                }
+                let Ok(meta) = entry.metadata() else {
+                    continue;
+                };
+                if !meta.is_file() {
+                    continue;
+                }
+                let last_used = meta
+                    .modified()
+                    .ok()
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map(|d| d.as_millis() as u64)
+                    .unwrap_or(0);
+                index.insert(
+                    name,
+                    IndexEntry {
+                        last_used,
+                        size_bytes: meta.len(),
+                    },
+                );
+            }
+        }
+
+        if index.len() <= self.length {
+            return;
+        }
+        let mut by_age: Vec<(String, u64)> = index
+            .iter()
+            .map(|(k, e)| (k.clone(), e.last_used))
             .collect();
-        entries.sort_by_key(|e| e.metadata().and_then(|m| m.modified()).ok());
-        if entries.len() > self.length {
-            let to_delete = entries.len() - self.length;
-            for i in 0..to_delete {
-                let _ = fs::remove_file(entries[i].path());
+        by_age.sort_by_key(|(_, last_used)| *last_used);
+        let to_delete = index.len() - self.length;
+        for (key, _) in by_age.into_iter().take(to_delete) {
+            let _ = fs::remove_file(self.cache_path.join(&key));
+            index.remove(&key);
+        }
+    }
+
+    /// Run `f` against the on-disk index while holding the index lock, then persist it.
+    fn with_index<T>(&self, f: impl FnOnce(&mut CacheIndex) -> Result<T>) -> Result<T> {
+        let _lock = IndexLock::acquire(&self.cache_path.join(INDEX_LOCK_FILE))?;
+        let index_path = self.cache_path.join(INDEX_FILE);
+        let mut index: CacheIndex = fs::read_to_string(&index_path)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        let out = f(&mut index)?;
+        // Write-then-rename so readers never observe a half-written index.
+        let tmp = self.cache_path.join(format!("{}.tmp", INDEX_FILE));
+        fs::write(&tmp, serde_json::to_vec(&index)?)?;
+        fs::rename(&tmp, &index_path)?;
+        Ok(out)
+    }
+}
+
+/// Exclusive lock on the cache index, held for as long as the value lives.
+struct IndexLock {
+    path: PathBuf,
+}
+
+impl IndexLock {
+    fn acquire(path: &Path) -> Result<Self> {
+        for _ in 0..100 {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+            {
+                Ok(_) => {
+                    return Ok(Self {
+                        path: path.to_path_buf(),
+                    })
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let stale = fs::metadata(path)
+                        .and_then(|m| m.modified())
+                        .ok()
+                        .and_then(|t| t.elapsed().ok())
+                        .is_some_and(|age| age > STALE_LOCK_AFTER);
+                    if stale {
+                        let _ = fs::remove_file(path);
+                        continue;
+                    }
+                    thread::sleep(Duration::from_millis(20));
+                }
+                Err(e) => return Err(e.into()),
             }
         }
-        Ok(())
+        Err(anyhow!(
+            "timed out waiting for cache lock {}",
+            path.display()
+        ))
+    }
+}
+
+impl Drop for IndexLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Cache entries are named by their hex digest; anything else in the directory is not ours.
+fn is_cache_key(name: &str) -> bool {
+    name.len() >= 32 && name.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_counts_files_and_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), "hello").unwrap();
+        fs::write(dir.path().join("b"), "world!").unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+
+        let stats = CacheStats::collect(dir.path(), |_| true);
+        assert_eq!(stats.count, 2);
+        assert_eq!(stats.total_bytes, 11);
+        assert!(stats.oldest.is_some() && stats.newest.is_some());
+        assert!(stats.oldest <= stats.newest);
+    }
+
+    #[test]
+    fn stats_on_missing_dir_is_empty() {
+        let stats = CacheStats::collect(Path::new("/nonexistent/sgpt_rs/cache"), |_| true);
+        assert_eq!(stats.count, 0);
+        assert_eq!(stats.total_bytes, 0);
+        assert!(stats.oldest.is_none());
+    }
+
+    #[test]
+    fn format_bytes_uses_binary_units() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(2048), "2.0 KiB");
+        assert_eq!(format_bytes(5 * 1024 * 1024), "5.0 MiB");
+    }
+
+    fn key(n: u8) -> String {
+        format!("{:064x}", n)
+    }
+
+    #[test]
+    fn request_keys_are_sha256_hex() {
+        let cache = RequestCache {
+            length: 2,
+            cache_path: PathBuf::from("/nonexistent"),
+        };
+        let messages = vec![ChatMessage::new(Role::User, "hi")];
+        let mut opts = ChatOptions {
+            model: "gpt-4o".into(),
+            temperature: 0.0,
+            top_p: 1.0,
+            tools: None,
+            parallel_tool_calls: false,
+            tool_choice: None,
+            max_tokens: None,
+            stop_sequences: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            resume_on_error: false,
+        };
+        let base = "https://api.openai.com";
+        let a = cache.key_for(base, &opts, &messages);
+        assert_eq!(a.len(), 64);
+        assert!(is_cache_key(&a));
+        assert_eq!(a, cache.key_for(base, &opts, &messages));
+
+        opts.stop_sequences = Some(vec!["```".into()]);
+        let stopped = cache.key_for(base, &opts, &messages);
+        assert_ne!(a, stopped);
+
+        opts.presence_penalty = Some(0.5);
+        let penalized = cache.key_for(base, &opts, &messages);
+        assert_ne!(stopped, penalized);
+        opts.presence_penalty = None;
+        opts.frequency_penalty = Some(0.5);
+        assert_ne!(stopped, cache.key_for(base, &opts, &messages));
+
+        opts.model = "gpt-4o-mini".into();
+        assert_ne!(penalized, cache.key_for(base, &opts, &messages));
+    }
+
+    #[test]
+    fn prune_evicts_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = RequestCache {
+            length: 2,
+            cache_path: dir.path().to_path_buf(),
+        };
+        cache.set(&key(1), "one").unwrap();
+        thread::sleep(Duration::from_millis(5));
+        cache.set(&key(2), "two").unwrap();
+        thread::sleep(Duration::from_millis(5));
+        // Touch the oldest entry so the second one becomes least recently used
+        assert_eq!(cache.get(&key(1)).as_deref(), Some("one"));
+        thread::sleep(Duration::from_millis(5));
+        cache.set(&key(3), "three").unwrap();
+
+        assert!(cache.get(&key(1)).is_some());
+        assert!(cache.get(&key(2)).is_none());
+        assert!(cache.get(&key(3)).is_some());
+        assert!(dir.path().join(INDEX_FILE).exists());
+        assert!(!dir.path().join(INDEX_LOCK_FILE).exists());
+        assert_eq!(cache.stats().count, 2);
+    }
+
+    #[test]
+    fn stale_lock_is_reclaimed() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock_path = dir.path().join(INDEX_LOCK_FILE);
+        fs::write(&lock_path, "").unwrap();
+        let old = SystemTime::now() - Duration::from_secs(60);
+        fs::File::options()
+            .write(true)
+            .open(&lock_path)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+
+        let lock = IndexLock::acquire(&lock_path).unwrap();
+        drop(lock);
+        assert!(!lock_path.exists());
+    }
+
+    #[test]
+    fn session_reads_legacy_array_and_writes_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let session = ChatSession {
+            length: 100,
+            storage_path: dir.path().to_path_buf(),
+        };
+        let legacy = vec![
+            ChatMessage::new(Role::System, "sys"),
+            ChatMessage::new(Role::User, "hi"),
+        ];
+        fs::write(
+            dir.path().join("old"),
+            serde_json::to_string(&legacy).unwrap(),
+        )
+        .unwrap();
+
+        let read = session.read_session("old").unwrap().unwrap();
+        assert_eq!(read.version, 0);
+        assert_eq!(read.messages.len(), 2);
+
+        session.write("old", "gpt-4o", read.messages).unwrap();
+        let wrapped = session.read_session("old").unwrap().unwrap();
+        assert_eq!(wrapped.version, SESSION_FORMAT_VERSION);
+        assert_eq!(wrapped.model, "gpt-4o");
+        assert_eq!(wrapped.created_at, read.created_at);
+        assert!(wrapped.updated_at >= wrapped.created_at);
+        assert_eq!(session.read("old").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn format_timestamp_is_utc_date_time() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00");
+        assert_eq!(format_timestamp(951_827_696), "2000-02-29 12:34");
+    }
+
+    #[test]
+    fn search_finds_matching_lines_across_chats() {
+        let dir = tempfile::tempdir().unwrap();
+        let session = ChatSession {
+            length: 100,
+            storage_path: dir.path().to_path_buf(),
+        };
+        session
+            .write(
+                "rust",
+                "m",
+                vec![
+                    ChatMessage::new(Role::System, "sys"),
+                    ChatMessage::new(Role::User, "intro\nwhy does the Borrow checker complain?"),
+                ],
+            )
+            .unwrap();
+        session
+            .write(
+                "other",
+                "m",
+                vec![ChatMessage::new(Role::Assistant, "nothing here")],
+            )
+            .unwrap();
+
+        let pattern = Regex::new(&format!("(?i){}", regex::escape("borrow CHECKER"))).unwrap();
+        let found = session.search(&pattern, None);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].chat_id, "rust");
+        assert_eq!(found[0].role, Role::User);
+        assert_eq!(&found[0].line[found[0].range.clone()], "Borrow checker");
+
+        let (before, hit, after) = found[0].snippet(4);
+        assert_eq!(
+            (before.as_str(), hit, after.as_str()),
+            ("...the ", "Borrow checker", " com...")
+        );
+
+        let any = Regex::new("e").unwrap();
+        assert_eq!(session.search(&any, Some(2)).len(), 2);
+    }
+
+    #[test]
+    fn list_puts_recent_sessions_first_with_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let session = ChatSession {
+            length: 100,
+            storage_path: dir.path().to_path_buf(),
+        };
+        let messages = vec![
+            ChatMessage::new(Role::System, "sys"),
+            ChatMessage::new(Role::User, "hi"),
+        ];
+        session.write("older", "gpt-4o", messages.clone()).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        fs::write(dir.path().join("broken"), "not json").unwrap();
+        thread::sleep(Duration::from_millis(20));
+        session.write("newer", "deepseek-chat", messages).unwrap();
+
+        let names: Vec<String> = session
+            .list()
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["newer", "broken", "older"]);
+
+        let infos = session.list_with_metadata();
+        assert_eq!(infos[0].last_model.as_deref(), Some("deepseek-chat"));
+        assert_eq!(infos[0].message_count, Some(2));
+        assert!(infos[0].updated_at.is_some());
+        assert!(infos[1].message_count.is_none() && infos[1].last_model.is_none());
+        assert_eq!(infos[2].last_model.as_deref(), Some("gpt-4o"));
     }
 }
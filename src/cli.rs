@@ -18,22 +18,38 @@ pub struct Cli {
     #[arg(long)]
     pub model: Option<String>,
 
-    /// Randomness of generated output.
-    #[arg(long, default_value_t = 0.0, value_parser = clap::value_parser!(f32))]
-    pub temperature: f32,
+    /// Randomness of generated output [default: mode config, then DEFAULT_TEMPERATURE].
+    #[arg(long, value_parser = clap::value_parser!(f32))]
+    pub temperature: Option<f32>,
 
-    /// Limits highest probable tokens (words).
-    #[arg(long = "top-p", default_value_t = 1.0, value_parser = clap::value_parser!(f32))]
-    pub top_p: f32,
+    /// Limits highest probable tokens (words) [default: DEFAULT_TOP_P].
+    #[arg(long = "top-p", value_parser = clap::value_parser!(f32))]
+    pub top_p: Option<f32>,
+
+    /// Penalize tokens that already appeared, nudging towards new topics (-2.0 to 2.0) [default: PRESENCE_PENALTY].
+    #[arg(long = "presence-penalty", value_name = "N", allow_negative_numbers = true,
+          value_parser = clap::value_parser!(f32))]
+    pub presence_penalty: Option<f32>,
+
+    /// Penalize tokens by how often they appeared, reducing repetition (-2.0 to 2.0) [default: FREQUENCY_PENALTY].
+    #[arg(long = "frequency-penalty", value_name = "N", allow_negative_numbers = true,
+          value_parser = clap::value_parser!(f32))]
+    pub frequency_penalty: Option<f32>,
 
     /// Maximum tokens for the response (model-dependent upper bound).
     #[arg(long = "max-tokens", visible_alias = "max_tokens", value_parser = clap::value_parser!(u32))]
     pub max_tokens: Option<u32>,
 
+    /// Stop generating when the model outputs SEQ (repeatable), e.g. --stop 'if __name__'.
+    #[arg(long, value_name = "SEQ", action = clap::ArgAction::Append,
+          conflicts_with_all = ["repl", "search", "enhanced_search"])]
+    pub stop: Vec<String>,
+
     /// Prettify Markdown output (buffer then render at end).
     ///
     /// Note: default/--chat/--repl all use SSE streaming under the hood.
-    /// - With `--md` (or `PRETTIFY_MARKDOWN=true`, default), output is buffered and printed as Markdown after completion.
+    /// - With `--md` (or `PRETTIFY_MARKDOWN=true`, default), Markdown is rendered progressively: finished blocks are
+    ///   printed as they close and the unfinished tail is redrawn in place (rendered at the end when piped).
     /// - Use `--no-md` (or set `PRETTIFY_MARKDOWN=false`) for realtime streaming to the terminal.
     #[arg(long)]
     pub md: bool,
@@ -55,7 +71,7 @@ pub struct Cli {
     #[arg(long = "r")]
     pub r: bool,
 
-    /// Override target shell for command generation (auto|powershell|cmd|bash|zsh|fish|sh).
+    /// Override target shell for command generation (auto|pwsh|powershell|cmd|bash|zsh|fish|xonsh|nu|sh).
     #[arg(long = "target-shell")]
     pub target_shell: Option<String>,
 
@@ -69,14 +85,52 @@ pub struct Cli {
     #[arg(long = "no-interaction")]
     pub no_interaction: bool,
 
+    /// Allow --no-interaction to auto-execute commands flagged as dangerous.
+    #[arg(long = "i-know-what-im-doing")]
+    pub i_know_what_im_doing: bool,
+
+    /// Capture output when a shell command runs; on failure offer [F]ix to ask the
+    /// model for a corrected command.
+    #[arg(long = "fix-loop")]
+    pub fix_loop: bool,
+
+    /// Copy the generated shell command to the clipboard (instead of executing it
+    /// in non-interactive mode).
+    #[arg(long)]
+    pub copy: bool,
+
     /// Describe a shell command.
     #[arg(short = 'd', long = "describe-shell")]
     pub describe_shell: bool,
 
+    /// Describe the last command sgpt executed (from --shell or the TUI).
+    #[arg(long, conflicts_with_all = ["shell", "code", "search", "enhanced_search"])]
+    pub explain_last: bool,
+
     /// Generate only code.
     #[arg(short = 'c', long = "code")]
     pub code: bool,
 
+    /// With --code, stream the model output as-is instead of stripping Markdown fences.
+    #[arg(long, requires = "code")]
+    pub raw: bool,
+
+    /// With --code, save the code to PATH instead of printing it.
+    #[arg(long = "output-file", value_name = "PATH", requires = "code")]
+    pub output_file: Option<String>,
+
+    /// With --output-file, replace an existing file.
+    #[arg(long, requires = "output_file")]
+    pub force: bool,
+
+    /// With --code, the language to generate (also picks the --output-file extension).
+    #[arg(long, value_name = "LANG", requires = "code")]
+    pub lang: Option<String>,
+
+    /// With --code, ask for a unified diff against FILE and apply it after confirmation.
+    #[arg(long, value_name = "FILE", requires = "code", conflicts_with_all = ["raw", "output_file"])]
+    pub edit: Option<String>,
+
     /// Use Tavily to search the web for the prompt.
     #[arg(long = "search")]
     pub search: bool,
@@ -85,8 +139,28 @@ pub struct Cli {
     #[arg(short = 'e', long = "enhanced-search")]
     pub enhanced_search: bool,
 
-    /// Process document files (.md, .txt) and use their content as context.
-    /// Can be used multiple times: --doc file1.md --doc file2.txt
+    /// Use the clipboard text as the prompt, or prepend it to PROMPT/stdin
+    /// (falls back to the SGPT_CLIPBOARD env var on headless systems).
+    #[arg(long)]
+    pub paste: bool,
+
+    /// With --enhanced-search, how many search queries to plan (1-8); overrides ENHANCED_SEARCH_QUERIES.
+    #[arg(long = "num-queries", visible_alias = "queries", value_name = "N", requires = "enhanced_search",
+          value_parser = clap::value_parser!(u8).range(1..=8))]
+    pub num_queries: Option<u8>,
+
+    /// With --enhanced-search, up to N extra rounds that search for what the draft answer is
+    /// still missing (0-5); overrides ENHANCED_SEARCH_ITERATIONS.
+    #[arg(long, value_name = "N", requires = "enhanced_search",
+          value_parser = clap::value_parser!(u8).range(0..=5))]
+    pub iterations: Option<u8>,
+
+    /// With --enhanced-search, also write the full report (queries, sources, answer) to FILE as Markdown.
+    #[arg(long, value_name = "FILE", requires = "enhanced_search")]
+    pub report: Option<String>,
+
+    /// Process document files (text, PDF, EPUB, CSV, HTML) or web page URLs and use their content as context.
+    /// Can be used multiple times: --doc file1.md --doc file2.txt; globs like "src/**/*.rs" are expanded
     #[arg(long = "doc", action = clap::ArgAction::Append)]
     pub doc: Vec<String>,
 
@@ -96,14 +170,72 @@ pub struct Cli {
     #[arg(long = "image", action = clap::ArgAction::Append)]
     pub image: Vec<String>,
 
+    /// Resolution the model sees --image files at: `low` costs fewer tokens, `high` enables
+    /// full-detail analysis, `auto` lets the provider choose.
+    #[arg(long = "image-detail", value_name = "LEVEL", default_value = "auto",
+          value_parser = ["low", "high", "auto"])]
+    pub image_detail: String,
+
     /// Enable function calls (disabled by default).
     #[arg(long)]
     pub functions: bool,
 
+    /// Wrap Markdown output at N columns; overrides MARKDOWN_WIDTH (default: terminal width).
+    #[arg(long, value_name = "N")]
+    pub width: Option<usize>,
+
+    /// Reload tool files from the functions directory when they change.
+    #[arg(long = "watch-functions")]
+    pub watch_functions: bool,
+
+    /// With --functions, print the tool call the model requests instead of executing it.
+    #[arg(long = "tool-dry-run")]
+    pub tool_dry_run: bool,
+
+    /// Color for plain-text output (e.g. green, bright_cyan, none); overrides DEFAULT_COLOR.
+    #[arg(long, value_name = "NAME")]
+    pub color: Option<String>,
+
+    /// Disable colored output (same as setting the NO_COLOR env var).
+    #[arg(long = "no-color", conflicts_with = "color")]
+    pub no_color: bool,
+
+    /// Print time-to-first-token, total time and throughput after each response.
+    #[arg(long)]
+    pub measure: bool,
+
+    /// Show rolling averages of recorded --measure samples per model.
+    #[arg(long = "measure-stats")]
+    pub measure_stats: bool,
+
+    /// Print the resolved system prompt to stderr before sending the request.
+    #[arg(long = "print-system-role")]
+    pub print_system_role: bool,
+
+    /// Print the request body (JSON) instead of sending it; combine with --print-system-role.
+    #[arg(long = "dry-run", conflicts_with_all = ["repl", "search", "enhanced_search"])]
+    pub dry_run: bool,
+
+    /// Don't send a system message; role prompts that shape the output go in as a user message.
+    #[arg(long = "no-system-role")]
+    pub no_system_role: bool,
+
+    /// Only expose the named tool to the model (repeatable); implies --functions.
+    #[arg(long = "tool", visible_alias = "function", value_name = "NAME", action = clap::ArgAction::Append)]
+    pub tool: Vec<String>,
+
+    /// Hide the named tool from the model (repeatable).
+    #[arg(long = "exclude-tool", value_name = "NAME", action = clap::ArgAction::Append)]
+    pub exclude_tool: Vec<String>,
+
     /// Open $EDITOR to provide a prompt.
     #[arg(long)]
     pub editor: bool,
 
+    /// Ignore cached web search responses for this run (they are still refreshed).
+    #[arg(long)]
+    pub fresh: bool,
+
     /// Cache completion results.
     #[arg(long)]
     pub cache: bool,
@@ -127,6 +259,40 @@ pub struct Cli {
     #[arg(short = 'l', long = "list-chats", visible_alias = "lc")]
     pub list_chats: bool,
 
+    /// With --list-chats, show model, last update and message count per chat.
+    #[arg(long, requires = "list_chats")]
+    pub long: bool,
+
+    /// Search all chat sessions for QUERY (case-insensitive substring).
+    #[arg(long = "search-chat", value_name = "QUERY")]
+    pub search_chat: Option<String>,
+
+    /// Treat the --search-chat query as a regular expression.
+    #[arg(long, requires = "search_chat")]
+    pub regex: bool,
+
+    /// Stop --search-chat after N matching lines.
+    #[arg(long = "max-results", value_name = "N", requires = "search_chat")]
+    pub max_results: Option<usize>,
+
+    /// Print the last N logged shell commands (default 20; requires SHELL_HISTORY=true).
+    #[arg(
+        long = "shell-history",
+        value_name = "N",
+        num_args = 0..=1,
+        default_missing_value = "20"
+    )]
+    pub shell_history: Option<usize>,
+
+    /// Output format for --shell-history (text, json: one JSON object per line) or
+    /// --search (text, json: array of {title,url,snippet,score}, md: bulleted links).
+    #[arg(long, value_name = "FORMAT", value_parser = ["text", "json", "md"])]
+    pub format: Option<String>,
+
+    /// Show request cache and chat cache disk usage.
+    #[arg(long = "cache-info")]
+    pub cache_info: bool,
+
     /// System role for GPT model.
     #[arg(long)]
     pub role: Option<String>,
@@ -135,6 +301,22 @@ pub struct Cli {
     #[arg(long = "create-role")]
     pub create_role: Option<String>,
 
+    /// Create a role from the text in FILE (no terminal needed).
+    #[arg(long = "create-role-from-file", num_args = 2, value_names = ["NAME", "FILE"])]
+    pub create_role_from_file: Option<Vec<String>>,
+
+    /// Write all roles to FILE as a JSON array.
+    #[arg(long = "export-roles", value_name = "FILE")]
+    pub export_roles: Option<String>,
+
+    /// Import roles from a JSON array written by --export-roles.
+    #[arg(long = "import-roles", value_name = "FILE")]
+    pub import_roles: Option<String>,
+
+    /// With --import-roles, replace roles that already exist.
+    #[arg(long, requires = "import_roles")]
+    pub overwrite: bool,
+
     /// Show role.
     #[arg(long = "show-role")]
     pub show_role: Option<String>,
@@ -147,6 +329,10 @@ pub struct Cli {
     #[arg(long = "install-integration", hide = true)]
     pub install_integration: bool,
 
+    /// Download a tool definition (JSON) from an https URL into the functions directory.
+    #[arg(long = "install-function", value_name = "URL")]
+    pub install_function: Option<String>,
+
     /// Install default functions (hidden).
     #[arg(long = "install-functions", hide = true)]
     pub install_functions: bool,
@@ -7,6 +7,8 @@ use std::{
 
 use directories::BaseDirs;
 
+mod netrc;
+
 #[derive(Debug, Clone)]
 pub struct Config {
     inner: HashMap<String, String>,
@@ -53,7 +55,16 @@ impl Config {
         if let Ok(v) = env::var(key) {
             return Some(v);
         }
-        self.inner.get(key).cloned()
+        let value = self.inner.get(key).cloned();
+        if key == "OPENAI_API_KEY" && !matches!(value.as_deref(), Some(v) if !v.is_empty()) {
+            // Last resort: a `machine` entry in ~/.netrc for the endpoint the key is sent to
+            let endpoint = self
+                .get("AZURE_OPENAI_ENDPOINT")
+                .filter(|e| !e.trim().is_empty())
+                .or_else(|| self.get("API_BASE_URL"));
+            return netrc::api_key(endpoint.as_deref()).or(value);
+        }
+        value
     }
 
     pub fn get_bool(&self, key: &str) -> bool {
@@ -62,6 +73,10 @@ impl Config {
             .unwrap_or(false)
     }
 
+    pub fn get_f32(&self, key: &str) -> Option<f32> {
+        self.get(key).and_then(|v| v.trim().parse::<f32>().ok())
+    }
+
     #[allow(dead_code)]
     pub fn get_usize(&self, key: &str) -> Option<usize> {
         self.get(key).and_then(|v| v.parse::<usize>().ok())
@@ -94,23 +109,71 @@ fn is_config_key(k: &str) -> bool {
     const KEYS: &[&str] = &[
         "OPENAI_API_KEY",
         "API_BASE_URL",
+        "AZURE_OPENAI_ENDPOINT",
+        "AZURE_API_VERSION",
         "CHAT_CACHE_PATH",
         "CACHE_PATH",
         "CHAT_CACHE_LENGTH",
+        "MAX_DOC_CHARS",
+        "DOC_CSV_SAMPLE_ROWS",
+        "DOC_URL_CACHE_TTL",
+        "DOC_CODE_EXTENSIONS",
+        "DOC_MAX_FILE_BYTES",
+        "MAX_INPUT_TOKENS",
+        "DOC_TRUNCATE_STRATEGY",
+        "PDF_OCR",
+        "PDF_EXTERNAL_TOOL",
+        "PDFTOTEXT_PATH",
+        "MAX_CONTEXT_TOKENS",
+        "ENHANCED_SEARCH_QUERIES",
+        "ENHANCED_SEARCH_RESULTS_PER_QUERY",
+        "ENHANCED_SEARCH_CONCURRENCY",
+        "ENHANCED_SEARCH_RERANK",
+        "ENHANCED_SEARCH_ITERATIONS",
+        "ENHANCED_SEARCH_MAX_SEARCHES",
+        "TAVILY_INCLUDE_ANSWER",
+        "SEARCH_PROVIDER",
+        "SEARCH_CACHE_TTL",
+        "SEARCH_FRESH",
         "CACHE_LENGTH",
         "REQUEST_TIMEOUT",
         "DEFAULT_MODEL",
+        "DEFAULT_TEMPERATURE",
+        "DEFAULT_TOP_P",
+        "PRESENCE_PENALTY",
+        "FREQUENCY_PENALTY",
+        "SHELL_TEMPERATURE",
+        "CODE_TEMPERATURE",
         "DEFAULT_COLOR",
         "ROLE_STORAGE_PATH",
         "DEFAULT_EXECUTE_SHELL_CMD",
         "DISABLE_STREAMING",
+        "STREAM_RESUME_ON_ERROR",
         "CODE_THEME",
         "OPENAI_FUNCTIONS_PATH",
         "OPENAI_USE_FUNCTIONS",
+        "REPL_FUNCTIONS",
+        "WATCH_FUNCTIONS",
+        "TOOL_DRY_RUN",
         "SHOW_FUNCTIONS_OUTPUT",
+        "FUNCTIONS_ALLOWLIST",
+        "FUNCTIONS_DENYLIST",
+        "FUNCTIONS_MAX_OUTPUT",
+        "MCP_CONFIG_PATH",
+        "DANGEROUS_PATTERNS",
+        "SHELL_HISTORY",
+        "SHELL_TTY_PROMPT",
+        "TUI_EXEC_TIMEOUT",
+        "SHELL_HISTORY_PATH",
         "PRETTIFY_MARKDOWN",
+        "MARKDOWN_WIDTH",
+        "NO_SYSTEM_ROLE",
+        "MEASURE_LATENCY",
+        "SHOW_TOKEN_USAGE",
         "USE_LITELLM",
         "SHELL_INTERACTION",
+        "SHELL_FIX_LOOP",
+        "SHELL_FIX_MAX_ATTEMPTS",
         "OS_NAME",
         "SHELL_NAME",
     ];
@@ -151,28 +214,81 @@ fn default_map() -> HashMap<String, String> {
         "OPENAI_FUNCTIONS_PATH".into(),
         sgpt_dir.join("functions").to_string_lossy().into_owned(),
     );
+    m.insert(
+        "MCP_CONFIG_PATH".into(),
+        sgpt_dir.join("mcp.json").to_string_lossy().into_owned(),
+    );
+    m.insert(
+        "SHELL_HISTORY_PATH".into(),
+        sgpt_dir
+            .join("shell_history.jsonl")
+            .to_string_lossy()
+            .into_owned(),
+    );
+    m.insert(
+        "DANGEROUS_PATTERNS".into(),
+        sgpt_dir
+            .join("dangerous_patterns")
+            .to_string_lossy()
+            .into_owned(),
+    );
 
     // Numbers
     m.insert("CHAT_CACHE_LENGTH".into(), "100".into());
+    m.insert("MAX_DOC_CHARS".into(), "0".into());
+    m.insert("DOC_CSV_SAMPLE_ROWS".into(), "10".into());
+    m.insert("DOC_URL_CACHE_TTL".into(), "3600".into());
+    m.insert("SEARCH_CACHE_TTL".into(), "3600".into());
+    m.insert("DOC_MAX_FILE_BYTES".into(), "1048576".into());
+    m.insert("MAX_INPUT_TOKENS".into(), "0".into());
+    m.insert("DOC_TRUNCATE_STRATEGY".into(), "head+tail".into());
+    m.insert("MAX_CONTEXT_TOKENS".into(), "0".into());
+    m.insert("ENHANCED_SEARCH_QUERIES".into(), "3".into());
+    m.insert("ENHANCED_SEARCH_RESULTS_PER_QUERY".into(), "5".into());
+    m.insert("ENHANCED_SEARCH_CONCURRENCY".into(), "10".into());
+    m.insert("ENHANCED_SEARCH_ITERATIONS".into(), "0".into());
+    m.insert("ENHANCED_SEARCH_MAX_SEARCHES".into(), "20".into());
+    m.insert("ENHANCED_SEARCH_RERANK".into(), "false".into());
+    m.insert("TAVILY_INCLUDE_ANSWER".into(), "true".into());
     m.insert("CACHE_LENGTH".into(), "100".into());
     m.insert("REQUEST_TIMEOUT".into(), "60".into());
 
     // Strings
     m.insert("DEFAULT_MODEL".into(), "gpt-4o".into());
+    m.insert("DEFAULT_TEMPERATURE".into(), "0.0".into());
+    m.insert("DEFAULT_TOP_P".into(), "1.0".into());
     m.insert("DEFAULT_COLOR".into(), "magenta".into());
     m.insert("CODE_THEME".into(), "dracula".into());
     m.insert("API_BASE_URL".into(), "default".into());
     m.insert("OS_NAME".into(), "auto".into());
     m.insert("SHELL_NAME".into(), "auto".into());
+    m.insert("PDF_EXTERNAL_TOOL".into(), "auto".into());
+    m.insert("SEARCH_PROVIDER".into(), "tavily".into());
 
     // Bools as strings
     m.insert("DEFAULT_EXECUTE_SHELL_CMD".into(), "false".into());
     m.insert("DISABLE_STREAMING".into(), "false".into());
+    m.insert("PDF_OCR".into(), "false".into());
     m.insert("OPENAI_USE_FUNCTIONS".into(), "false".into());
+    m.insert("REPL_FUNCTIONS".into(), "true".into());
+    m.insert("WATCH_FUNCTIONS".into(), "false".into());
+    m.insert("TOOL_DRY_RUN".into(), "false".into());
+    m.insert("SEARCH_FRESH".into(), "false".into());
     m.insert("SHOW_FUNCTIONS_OUTPUT".into(), "false".into());
+    m.insert("FUNCTIONS_MAX_OUTPUT".into(), "65536".into());
     m.insert("PRETTIFY_MARKDOWN".into(), "true".into());
+    m.insert("MARKDOWN_WIDTH".into(), "auto".into());
+    m.insert("STREAM_RESUME_ON_ERROR".into(), "false".into());
+    m.insert("NO_SYSTEM_ROLE".into(), "false".into());
+    m.insert("MEASURE_LATENCY".into(), "false".into());
+    m.insert("SHOW_TOKEN_USAGE".into(), "false".into());
     m.insert("USE_LITELLM".into(), "false".into());
     m.insert("SHELL_INTERACTION".into(), "true".into());
+    m.insert("SHELL_FIX_LOOP".into(), "false".into());
+    m.insert("SHELL_HISTORY".into(), "false".into());
+    m.insert("SHELL_TTY_PROMPT".into(), "true".into());
+    m.insert("TUI_EXEC_TIMEOUT".into(), "300".into());
+    m.insert("SHELL_FIX_MAX_ATTEMPTS".into(), "3".into());
 
     m
 }
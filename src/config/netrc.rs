@@ -0,0 +1,154 @@
+//! Minimal `~/.netrc` reader used as a fallback source for `OPENAI_API_KEY`.
+
+use std::{env, fs, path::PathBuf};
+
+use directories::BaseDirs;
+
+/// Host used when `API_BASE_URL` is `default`.
+const OPENAI_HOST: &str = "api.openai.com";
+
+/// Password of the `machine` entry for the host of `base_url` (`api.openai.com` when it is
+/// unset or `default`). The file is `$NETRC` if set, otherwise `~/.netrc`.
+pub fn api_key(base_url: Option<&str>) -> Option<String> {
+    let text = fs::read_to_string(netrc_path()?).ok()?;
+    password_for(&text, &hosts(base_url))
+}
+
+/// Hosts to look up, most specific first.
+///
+/// The `api.openai.com` entry is only used for OpenAI itself: a custom endpoint must not
+/// be sent the OpenAI key just because it has no entry of its own.
+fn hosts(base_url: Option<&str>) -> Vec<String> {
+    let Some(base_url) = base_url
+        .map(str::trim)
+        .filter(|b| !b.is_empty() && *b != "default")
+    else {
+        return vec![OPENAI_HOST.to_string()];
+    };
+    let mut hosts: Vec<String> = host_of(base_url).into_iter().collect();
+    if hosts
+        .iter()
+        .any(|h| h != OPENAI_HOST && h.ends_with(".openai.com"))
+    {
+        hosts.push(OPENAI_HOST.to_string());
+    }
+    hosts
+}
+
+fn netrc_path() -> Option<PathBuf> {
+    if let Some(p) = env::var_os("NETRC") {
+        return Some(PathBuf::from(p));
+    }
+    BaseDirs::new().map(|b| b.home_dir().join(".netrc"))
+}
+
+fn host_of(base_url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(base_url.trim()).ok()?;
+    url.host_str().map(str::to_lowercase)
+}
+
+/// Password of the first host in `hosts` that has a `machine` entry with one.
+///
+/// `default` entries are ignored (they'd hand the key to any host), and `macdef`
+/// bodies are skipped up to the next blank line.
+fn password_for(text: &str, hosts: &[String]) -> Option<String> {
+    let mut in_macdef = false;
+    let mut tokens = Vec::new();
+    for line in text.lines() {
+        if in_macdef {
+            in_macdef = !line.trim().is_empty();
+            continue;
+        }
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        while let Some(word) = words.next() {
+            if word == "macdef" {
+                // Macro name, then the body on the following lines
+                words.next();
+                in_macdef = true;
+                break;
+            }
+            tokens.push(word);
+        }
+    }
+
+    // (machine, password) pairs; `None` machine means a `default` entry
+    let mut entries: Vec<(Option<&str>, &str)> = Vec::new();
+    let mut machine = None;
+    let mut iter = tokens.into_iter();
+    while let Some(token) = iter.next() {
+        match token {
+            "machine" => machine = iter.next(),
+            "default" => machine = None,
+            "password" => {
+                if let Some(value) = iter.next() {
+                    entries.push((machine, value));
+                }
+            }
+            "login" | "account" => {
+                iter.next();
+            }
+            _ => {}
+        }
+    }
+
+    hosts.iter().find_map(|host| {
+        entries.iter().find_map(|(m, password)| {
+            m.filter(|m| m.eq_ignore_ascii_case(host))
+                .map(|_| password.to_string())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_password_for_openai_or_base_url_host() {
+        let netrc = "\
+# credentials
+machine github.com login me password ghp_x
+macdef init
+machine api.openai.com password sk-wrong
+
+machine API.OPENAI.COM login me password sk-openai
+machine api.deepseek.com
+  login me
+  password sk-deep
+default login anon password secret
+";
+        assert_eq!(
+            password_for(netrc, &hosts(Some("https://api.deepseek.com/v1"))).as_deref(),
+            Some("sk-deep")
+        );
+        assert_eq!(
+            password_for(netrc, &hosts(Some("default"))).as_deref(),
+            Some("sk-openai")
+        );
+        assert_eq!(
+            password_for(
+                "machine example.com password p\ndefault password d",
+                &hosts(None)
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn openai_entry_is_not_used_for_other_endpoints() {
+        let netrc = "machine api.openai.com password sk-openai\n";
+        for base in [
+            "https://proxy.example.com/v1",
+            "https://my-resource.openai.azure.com",
+        ] {
+            assert_eq!(password_for(netrc, &hosts(Some(base))), None, "{}", base);
+        }
+        assert_eq!(
+            password_for(netrc, &hosts(Some("https://us.api.openai.com/v1"))).as_deref(),
+            Some("sk-openai")
+        );
+    }
+}
@@ -10,6 +10,8 @@ pub struct ExecutionResult {
     pub output: String,
     pub errors: Vec<String>,
     pub variables: HashMap<String, String>,
+    /// Exit status reported by the interpreter (0 on success), if any
+    pub exit_code: Option<i32>,
     #[expect(dead_code)]
     pub plots: Vec<String>,
 }
@@ -11,6 +11,7 @@ pub async fn execute_ndjson(_code: &str) -> Result<ExecutionResult> {
         output: String::new(),
         errors: vec![],
         variables: Default::default(),
+        exit_code: Some(0),
         plots: vec![],
     })
 }
@@ -0,0 +1,148 @@
+//! Brave Search backend (web search API).
+
+use anyhow::{anyhow, bail, Result};
+use futures::future::BoxFuture;
+use reqwest::{Client, StatusCode};
+use serde_json::Value;
+
+use super::{str_field, SearchItem, SearchParams, SearchProvider};
+use crate::config::Config;
+
+const DEFAULT_BRAVE_BASE: &str = "https://api.search.brave.com";
+/// Largest `count` the web search endpoint accepts.
+const MAX_COUNT: usize = 20;
+
+pub struct BraveClient {
+    client: Client,
+    base: String,
+    api_key: String,
+}
+
+impl BraveClient {
+    pub fn from_config(cfg: &Config) -> Result<Self> {
+        let api_key = cfg
+            .get("BRAVE_API_KEY")
+            .filter(|s| !s.trim().is_empty())
+            .ok_or_else(|| {
+                anyhow!("Missing BRAVE_API_KEY. Set it in env or ~/.config/sgpt_rs/.sgptrc")
+            })?;
+        let base = cfg
+            .get("BRAVE_API_BASE")
+            .unwrap_or_else(|| DEFAULT_BRAVE_BASE.to_string());
+        Ok(Self {
+            client: super::http_client(cfg)?,
+            base,
+            api_key,
+        })
+    }
+}
+
+impl SearchProvider for BraveClient {
+    fn search<'a>(
+        &'a self,
+        query: &'a str,
+        params: &'a SearchParams,
+    ) -> BoxFuture<'a, Result<Vec<SearchItem>>> {
+        Box::pin(async move {
+            let url = format!("{}/res/v1/web/search", self.base.trim_end_matches('/'));
+            let resp = super::send_with_retry(|| {
+                let request = self
+                    .client
+                    .get(&url)
+                    .header("Accept", "application/json")
+                    .header("X-Subscription-Token", &self.api_key)
+                    .query(&[("q", query)]);
+                match params.max_results {
+                    Some(n) => request.query(&[("count", n.clamp(1, MAX_COUNT))]),
+                    None => request,
+                }
+            })
+            .await?;
+            match resp.status() {
+                StatusCode::OK => Ok(parse_response(&resp.json::<Value>().await?)),
+                status => {
+                    let text = resp.text().await.unwrap_or_default();
+                    bail!("Brave search failed: {} - {}", status, text)
+                }
+            }
+        })
+    }
+
+    fn settings(&self) -> serde_json::Value {
+        serde_json::json!({ "base": self.base })
+    }
+}
+
+fn parse_response(value: &Value) -> Vec<SearchItem> {
+    value
+        .pointer("/web/results")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .map(|item| SearchItem {
+            title: strip_tags(&str_field(item, "title")),
+            url: str_field(item, "url"),
+            snippet: strip_tags(&str_field(item, "description")),
+            score: None,
+        })
+        .collect()
+}
+
+/// Brave highlights query terms with `<strong>`; drop the markup.
+fn strip_tags(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    #[tokio::test]
+    async fn web_results_are_fetched_with_the_subscription_token() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/res/v1/web/search")
+                    .header("x-subscription-token", "brave-key")
+                    .query_param("q", "tokio")
+                    .query_param("count", "20");
+                then.status(200).json_body(serde_json::json!({
+                    "web": {"results": [
+                        {"title": "<strong>Tokio</strong>", "url": "https://tokio.rs", "description": "An async <strong>runtime</strong>."}
+                    ]}
+                }));
+            })
+            .await;
+        let client = BraveClient {
+            client: Client::new(),
+            base: server.base_url(),
+            api_key: "brave-key".into(),
+        };
+        let params = SearchParams {
+            max_results: Some(50),
+        };
+        let items = client.search("tokio", &params).await.unwrap();
+        mock.assert_async().await;
+        assert_eq!(
+            items,
+            [SearchItem {
+                title: "Tokio".into(),
+                url: "https://tokio.rs".into(),
+                snippet: "An async runtime.".into(),
+                score: None,
+            }]
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! On-disk cache of search responses under `CACHE_PATH/search/`.
+//!
+//! Entries are keyed by provider (with its endpoint and options), query and parameters
+//! and reused for `SEARCH_CACHE_TTL` seconds, so iterating on the same `--enhanced-search`
+//! doesn't pay for every query again.
+//! `--fresh` (`SEARCH_FRESH`) skips the lookup but still stores the new response.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{SearchItem, SearchParams, SearchProvider};
+use crate::config::Config;
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    answer: Option<String>,
+    items: Vec<SearchItem>,
+}
+
+pub struct CachedProvider {
+    inner: Arc<dyn SearchProvider>,
+    /// Provider name and its [`SearchProvider::settings`], part of every key.
+    provider: String,
+    settings: serde_json::Value,
+    dir: PathBuf,
+    ttl: Duration,
+    /// Don't read entries; responses are still written.
+    fresh: bool,
+}
+
+impl CachedProvider {
+    /// Wrap `inner`, or return it unchanged when `SEARCH_CACHE_TTL` is 0.
+    pub fn wrap(
+        cfg: &Config,
+        provider: &str,
+        inner: Arc<dyn SearchProvider>,
+    ) -> Arc<dyn SearchProvider> {
+        let ttl = Duration::from_secs(cfg.get_usize("SEARCH_CACHE_TTL").unwrap_or(3600) as u64);
+        if ttl.is_zero() {
+            return inner;
+        }
+        Arc::new(Self {
+            settings: inner.settings(),
+            inner,
+            provider: provider.to_string(),
+            dir: cfg.cache_path().join("search"),
+            ttl,
+            fresh: cfg.get_bool("SEARCH_FRESH"),
+        })
+    }
+
+    fn path_for(&self, method: &str, query: &str, params: &SearchParams) -> PathBuf {
+        let key = serde_json::json!({
+            "provider": self.provider,
+            "settings": self.settings,
+            "method": method,
+            "query": query,
+            "max_results": params.max_results,
+        });
+        let digest = Sha256::digest(key.to_string().as_bytes());
+        self.dir.join(format!("{:x}.json", digest))
+    }
+
+    fn load(&self, path: &Path) -> Option<Entry> {
+        if self.fresh {
+            return None;
+        }
+        let age = fs::metadata(path).ok()?.modified().ok()?.elapsed().ok()?;
+        if age >= self.ttl {
+            return None;
+        }
+        serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+    }
+
+    fn store(&self, path: &Path, entry: &Entry) {
+        // Caching is best-effort; a read-only cache dir must not fail the search
+        let _ = fs::create_dir_all(&self.dir);
+        if let Ok(json) = serde_json::to_string(entry) {
+            let _ = fs::write(path, json);
+        }
+    }
+}
+
+impl SearchProvider for CachedProvider {
+    fn search<'a>(
+        &'a self,
+        query: &'a str,
+        params: &'a SearchParams,
+    ) -> BoxFuture<'a, Result<Vec<SearchItem>>> {
+        Box::pin(async move {
+            let path = self.path_for("search", query, params);
+            if let Some(entry) = self.load(&path) {
+                return Ok(entry.items);
+            }
+            let items = self.inner.search(query, params).await?;
+            let entry = Entry {
+                answer: None,
+                items,
+            };
+            self.store(&path, &entry);
+            Ok(entry.items)
+        })
+    }
+
+    fn search_with_answer<'a>(
+        &'a self,
+        query: &'a str,
+        params: &'a SearchParams,
+    ) -> BoxFuture<'a, Result<(Option<String>, Vec<SearchItem>)>> {
+        Box::pin(async move {
+            let path = self.path_for("search_with_answer", query, params);
+            if let Some(entry) = self.load(&path) {
+                return Ok((entry.answer, entry.items));
+            }
+            let (answer, items) = self.inner.search_with_answer(query, params).await?;
+            let entry = Entry { answer, items };
+            self.store(&path, &entry);
+            Ok((entry.answer, entry.items))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts calls and answers with the query as the only result.
+    #[derive(Default)]
+    struct Counting(AtomicUsize);
+
+    impl SearchProvider for Counting {
+        fn search<'a>(
+            &'a self,
+            query: &'a str,
+            _params: &'a SearchParams,
+        ) -> BoxFuture<'a, Result<Vec<SearchItem>>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                Ok(vec![SearchItem {
+                    title: query.to_string(),
+                    url: "https://example.com".into(),
+                    snippet: String::new(),
+                    score: None,
+                }])
+            })
+        }
+    }
+
+    fn cached(dir: &Path, inner: Arc<Counting>, fresh: bool) -> CachedProvider {
+        CachedProvider {
+            inner,
+            provider: "test".into(),
+            settings: serde_json::Value::Null,
+            dir: dir.to_path_buf(),
+            ttl: Duration::from_secs(3600),
+            fresh,
+        }
+    }
+
+    #[tokio::test]
+    async fn responses_are_reused_per_query_and_params() {
+        let dir = tempfile::tempdir().unwrap();
+        let inner = Arc::new(Counting::default());
+        let provider = cached(dir.path(), inner.clone(), false);
+        let params = SearchParams {
+            max_results: Some(5),
+        };
+
+        let first = provider.search("rust", &params).await.unwrap();
+        assert_eq!(provider.search("rust", &params).await.unwrap(), first);
+        assert_eq!(inner.0.load(Ordering::SeqCst), 1);
+
+        provider.search("tokio", &params).await.unwrap();
+        provider
+            .search("rust", &SearchParams::default())
+            .await
+            .unwrap();
+        assert_eq!(inner.0.load(Ordering::SeqCst), 3);
+
+        // --fresh goes to the provider again
+        cached(dir.path(), inner.clone(), true)
+            .search("rust", &params)
+            .await
+            .unwrap();
+        assert_eq!(inner.0.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn provider_settings_are_part_of_the_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let params = SearchParams::default();
+        let mut provider = cached(dir.path(), Arc::default(), false);
+        provider.settings = serde_json::json!({ "base": "https://searx.one" });
+        let one = provider.path_for("search", "rust", &params);
+        provider.settings = serde_json::json!({ "base": "https://searx.two" });
+        assert_ne!(provider.path_for("search", "rust", &params), one);
+    }
+}
@@ -0,0 +1,237 @@
+//! Keyless DuckDuckGo backend scraping the HTML-only results page.
+//!
+//! Used when no search API key is configured. The page is not an API: results are
+//! limited, requests are spaced out politely, and a layout change is reported as an
+//! error instead of silently returning nothing.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use futures::future::BoxFuture;
+use reqwest::{Client, StatusCode, Url};
+use scraper::{ElementRef, Html, Selector};
+use tokio::sync::Mutex;
+
+use super::{SearchItem, SearchParams, SearchProvider};
+use crate::config::Config;
+
+const DEFAULT_DDG_BASE: &str = "https://html.duckduckgo.com";
+/// Minimum spacing between requests from this process.
+const MIN_INTERVAL: Duration = Duration::from_millis(1500);
+/// The HTML endpoint rejects requests without a browser-like user agent.
+const USER_AGENT: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0";
+
+pub struct DuckDuckGoClient {
+    client: Client,
+    base: String,
+    /// When the last request was sent; concurrent searches queue on it.
+    last_request: Mutex<Option<Instant>>,
+}
+
+impl DuckDuckGoClient {
+    pub fn from_config(cfg: &Config) -> Result<Self> {
+        Ok(Self {
+            client: super::http_client(cfg)?,
+            base: DEFAULT_DDG_BASE.to_string(),
+            last_request: Mutex::new(None),
+        })
+    }
+
+    async fn fetch(&self, query: &str) -> Result<String> {
+        {
+            let mut last = self.last_request.lock().await;
+            if let Some(at) = *last {
+                let wait = MIN_INTERVAL.saturating_sub(at.elapsed());
+                if !wait.is_zero() {
+                    tokio::time::sleep(wait).await;
+                }
+            }
+            *last = Some(Instant::now());
+        }
+        let url = format!("{}/html/", self.base.trim_end_matches('/'));
+        let resp = super::send_with_retry(|| {
+            self.client
+                .get(&url)
+                .header("User-Agent", USER_AGENT)
+                .query(&[("q", query)])
+        })
+        .await?;
+        match resp.status() {
+            StatusCode::OK => Ok(resp.text().await?),
+            // Served instead of results when DuckDuckGo suspects automated traffic
+            StatusCode::ACCEPTED | StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS => {
+                bail!(
+                    "DuckDuckGo rate-limited the search ({}); wait a bit or configure a search API key",
+                    resp.status()
+                )
+            }
+            status => bail!("DuckDuckGo search failed: {}", status),
+        }
+    }
+}
+
+impl SearchProvider for DuckDuckGoClient {
+    fn search<'a>(
+        &'a self,
+        query: &'a str,
+        params: &'a SearchParams,
+    ) -> BoxFuture<'a, Result<Vec<SearchItem>>> {
+        Box::pin(async move {
+            let html = self.fetch(query).await?;
+            let mut items = parse_results(&html)?;
+            if let Some(n) = params.max_results {
+                items.truncate(n);
+            }
+            Ok(items)
+        })
+    }
+
+    fn settings(&self) -> serde_json::Value {
+        serde_json::json!({ "base": self.base })
+    }
+}
+
+/// Organic results of a results page. A page with neither results nor the "no results"
+/// notice means the layout changed (or a challenge page was served), which is an error.
+fn parse_results(html: &str) -> Result<Vec<SearchItem>> {
+    let doc = Html::parse_document(html);
+    let result = Selector::parse("div.result").expect("valid selector");
+    let link = Selector::parse("a.result__a").expect("valid selector");
+    let snippet = Selector::parse(".result__snippet").expect("valid selector");
+    let no_results = Selector::parse(".no-results").expect("valid selector");
+
+    let mut items = Vec::new();
+    for node in doc.select(&result) {
+        let classes = node.value().attr("class").unwrap_or("");
+        if classes.split_whitespace().any(|c| c == "result--ad") {
+            continue;
+        }
+        let Some(anchor) = node.select(&link).next() else {
+            continue;
+        };
+        let Some(url) = anchor.value().attr("href").and_then(unwrap_redirect) else {
+            continue;
+        };
+        items.push(SearchItem {
+            title: element_text(anchor),
+            url,
+            snippet: node
+                .select(&snippet)
+                .next()
+                .map(element_text)
+                .unwrap_or_default(),
+            score: None,
+        });
+    }
+
+    if items.is_empty() && doc.select(&no_results).next().is_none() {
+        bail!("Could not find results on the DuckDuckGo page; its layout may have changed");
+    }
+    Ok(items)
+}
+
+/// Target of a result link. DuckDuckGo wraps them as `//duckduckgo.com/l/?uddg=<url>&rut=...`;
+/// plain absolute links are returned as-is and anything else is dropped.
+fn unwrap_redirect(href: &str) -> Option<String> {
+    let absolute = if href.starts_with("//") {
+        format!("https:{}", href)
+    } else {
+        href.to_string()
+    };
+    let url = Url::parse(&absolute).ok()?;
+    if url.path() == "/l/" {
+        return url
+            .query_pairs()
+            .find(|(k, _)| k == "uddg")
+            .map(|(_, v)| v.into_owned());
+    }
+    matches!(url.scheme(), "http" | "https").then(|| url.to_string())
+}
+
+/// Text of an element with whitespace runs collapsed.
+fn element_text(el: ElementRef) -> String {
+    el.text()
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<html><body><div id="links">
+      <div class="result results_links result--ad">
+        <a class="result__a" href="https://duckduckgo.com/y.js?ad_provider=x">Sponsored</a>
+      </div>
+      <div class="result results_links results_links_deep web-result">
+        <h2 class="result__title">
+          <a rel="nofollow" class="result__a" href="//duckduckgo.com/l/?uddg=https%3A%2F%2Fwww.rust-lang.org%2Flearn%3Fa%3D1%26b%3D2&amp;rut=abc">Learn <b>Rust</b></a>
+        </h2>
+        <a class="result__snippet" href="//duckduckgo.com/l/?uddg=x">The <b>Rust</b> book
+          and more.</a>
+      </div>
+      <div class="result results_links web-result">
+        <a class="result__a" href="https://doc.rust-lang.org/std/">std - Rust</a>
+      </div>
+    </div></body></html>"#;
+
+    #[test]
+    fn results_are_parsed_without_ads_and_with_unwrapped_urls() {
+        let items = parse_results(PAGE).unwrap();
+        assert_eq!(
+            items,
+            [
+                SearchItem {
+                    title: "Learn Rust".into(),
+                    url: "https://www.rust-lang.org/learn?a=1&b=2".into(),
+                    snippet: "The Rust book and more.".into(),
+                    score: None,
+                },
+                SearchItem {
+                    title: "std - Rust".into(),
+                    url: "https://doc.rust-lang.org/std/".into(),
+                    snippet: String::new(),
+                    score: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_and_unrecognised_pages_are_told_apart() {
+        let none = r#"<html><body><div class="no-results">No results.</div></body></html>"#;
+        assert!(parse_results(none).unwrap().is_empty());
+        let err = parse_results("<html><body><form id='challenge'></form></body></html>")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("layout may have changed"), "{}", err);
+        assert_eq!(unwrap_redirect("/settings"), None);
+    }
+
+    #[tokio::test]
+    async fn requests_are_spaced_out() {
+        use httpmock::prelude::*;
+
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/html/").query_param("q", "rust");
+                then.status(200).body(PAGE);
+            })
+            .await;
+        let client = DuckDuckGoClient {
+            client: Client::new(),
+            base: server.base_url(),
+            last_request: Mutex::new(None),
+        };
+        let params = SearchParams {
+            max_results: Some(1),
+        };
+        let started = Instant::now();
+        assert_eq!(client.search("rust", &params).await.unwrap().len(), 1);
+        client.search("rust", &params).await.unwrap();
+        assert!(started.elapsed() >= MIN_INTERVAL);
+        mock.assert_hits_async(2).await;
+    }
+}
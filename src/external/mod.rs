@@ -1 +1,315 @@
+//! Web search backends used by `--search` and `--enhanced-search`.
+//!
+//! `SEARCH_PROVIDER` picks the backend (`tavily`, `searxng`, `brave` or `duckduckgo`);
+//! callers only see the `SearchProvider` trait, so switching needs no flag changes.
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use reqwest::{Client, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+pub mod brave;
+pub mod cache;
+pub mod duckduckgo;
+pub mod searxng;
 pub mod tavily;
+
+/// One search hit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchItem {
+    pub title: String,
+    pub url: String,
+    pub snippet: String,
+    /// Relevance score reported by the backend (Tavily, SearxNG), if any.
+    #[serde(default)]
+    pub score: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchParams {
+    /// Results wanted at most; the backend's own default otherwise.
+    pub max_results: Option<usize>,
+}
+
+pub trait SearchProvider: Send + Sync {
+    fn search<'a>(
+        &'a self,
+        query: &'a str,
+        params: &'a SearchParams,
+    ) -> BoxFuture<'a, Result<Vec<SearchItem>>>;
+
+    /// Search plus a direct answer to the query, for backends that provide one.
+    fn search_with_answer<'a>(
+        &'a self,
+        query: &'a str,
+        params: &'a SearchParams,
+    ) -> BoxFuture<'a, Result<(Option<String>, Vec<SearchItem>)>> {
+        Box::pin(async move { Ok((None, self.search(query, params).await?)) })
+    }
+
+    /// Settings that change this backend's responses (endpoint, options), so the response
+    /// cache doesn't hand results from one configuration to another.
+    fn settings(&self) -> serde_json::Value {
+        serde_json::Value::Null
+    }
+}
+
+/// The backend named by `SEARCH_PROVIDER`, configured from its own keys and wrapped in
+/// the response cache.
+///
+/// With the default `tavily` but no `TVLY_API_KEY`, the keyless DuckDuckGo backend is
+/// used instead (with a note on stderr).
+pub fn provider_from_config(cfg: &Config) -> Result<Arc<dyn SearchProvider>> {
+    let name = cfg
+        .get("SEARCH_PROVIDER")
+        .unwrap_or_else(|| "tavily".into());
+    let (name, provider): (&str, Arc<dyn SearchProvider>) = match name
+        .trim()
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "searxng" => (
+            "searxng",
+            Arc::new(searxng::SearxngClient::from_config(cfg)?),
+        ),
+        "brave" => ("brave", Arc::new(brave::BraveClient::from_config(cfg)?)),
+        "duckduckgo" => (
+            "duckduckgo",
+            Arc::new(duckduckgo::DuckDuckGoClient::from_config(cfg)?),
+        ),
+        "tavily" => tavily_or_fallback(cfg)?,
+        other => {
+            eprintln!(
+                    "Warning: unknown SEARCH_PROVIDER '{}' (expected tavily, searxng, brave or duckduckgo); using tavily",
+                    other
+                );
+            tavily_or_fallback(cfg)?
+        }
+    };
+    Ok(cache::CachedProvider::wrap(cfg, name, provider))
+}
+
+fn tavily_or_fallback(cfg: &Config) -> Result<(&'static str, Arc<dyn SearchProvider>)> {
+    if cfg
+        .get("TVLY_API_KEY")
+        .is_some_and(|key| !key.trim().is_empty())
+    {
+        return Ok(("tavily", Arc::new(tavily::TavilyClient::from_config(cfg)?)));
+    }
+    eprintln!(
+        "Note: no TVLY_API_KEY configured; searching DuckDuckGo without a key (results may be limited)"
+    );
+    Ok((
+        "duckduckgo",
+        Arc::new(duckduckgo::DuckDuckGoClient::from_config(cfg)?),
+    ))
+}
+
+/// HTTP client honoring `REQUEST_TIMEOUT` (default 60s).
+pub(crate) fn http_client(cfg: &Config) -> Result<Client> {
+    let timeout_secs = cfg
+        .get("REQUEST_TIMEOUT")
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(60);
+    Ok(Client::builder()
+        .timeout(std::time::Duration::from_secs(timeout_secs))
+        .build()?)
+}
+
+/// Attempts per search request, including the first.
+const MAX_ATTEMPTS: u32 = 3;
+/// Longest wait honored from a `Retry-After` header.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(10);
+
+/// Send the request built by `build`, retrying with backoff (0.5s, then 1s) on 429, 5xx,
+/// timeouts and connection errors. The last response is returned as-is so callers can
+/// report its status.
+async fn send_with_retry(build: impl Fn() -> RequestBuilder) -> Result<Response> {
+    let mut attempt = 1;
+    loop {
+        let retry_after = match build().send().await {
+            Ok(resp) => {
+                let status = resp.status();
+                let retryable =
+                    status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+                if !retryable || attempt >= MAX_ATTEMPTS {
+                    return Ok(resp);
+                }
+                resp.headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.trim().parse::<u64>().ok())
+                    .map(|secs| Duration::from_secs(secs).min(MAX_RETRY_AFTER))
+            }
+            Err(e) if (e.is_timeout() || e.is_connect()) && attempt < MAX_ATTEMPTS => None,
+            Err(e) => return Err(e.into()),
+        };
+        let backoff = Duration::from_millis(500 * 2u64.pow(attempt - 1));
+        tokio::time::sleep(retry_after.unwrap_or(backoff)).await;
+        attempt += 1;
+    }
+}
+
+/// String field of a JSON result object, empty when missing.
+fn str_field(item: &serde_json::Value, key: &str) -> String {
+    item.get(key)
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .to_string()
+}
+
+/// Output format of `--search` (`--format`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchFormat {
+    /// Numbered list: title, URL, snippet.
+    Text,
+    /// JSON array of `{title, url, snippet, score}`; the direct answer is left out.
+    Json,
+    /// Bulleted list of links, ready to paste into notes.
+    Markdown,
+}
+
+impl SearchFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(Self::Text),
+            "json" => Some(Self::Json),
+            "md" | "markdown" => Some(Self::Markdown),
+            _ => None,
+        }
+    }
+}
+
+/// Format search results: the direct answer (if any, not in JSON), then each result with
+/// title, URL and snippet, in that order. Used by `--search` and by `--enhanced-search`
+/// to report what each query found.
+pub fn format_search_results(
+    answer: Option<&str>,
+    items: &[SearchItem],
+    format: SearchFormat,
+) -> String {
+    if format == SearchFormat::Json {
+        let array: Vec<_> = items
+            .iter()
+            .map(|item| {
+                serde_json::json!({
+                    "title": item.title,
+                    "url": item.url,
+                    "snippet": item.snippet,
+                    "score": item.score,
+                })
+            })
+            .collect();
+        return serde_json::to_string_pretty(&array).unwrap_or_default() + "\n";
+    }
+
+    let mut out = String::new();
+    if let Some(answer) = answer.map(str::trim).filter(|a| !a.is_empty()) {
+        if format == SearchFormat::Markdown {
+            out.push_str(&format!("**Answer:** {}\n\n", answer));
+        } else {
+            out.push_str(&format!("Answer: {}\n\n", answer));
+        }
+    }
+    if items.is_empty() {
+        out.push_str("No results.\n");
+        return out;
+    }
+    for (i, item) in items.iter().enumerate() {
+        let (title, url, snippet) = (&item.title, &item.url, item.snippet.trim());
+        if format == SearchFormat::Markdown {
+            let label = if title.is_empty() { url } else { title };
+            out.push_str(&format!("- [{}]({})", label.replace(['[', ']'], ""), url));
+            if !snippet.is_empty() {
+                out.push_str(&format!(" — {}", snippet));
+            }
+            out.push('\n');
+        } else {
+            out.push_str(&format!("{}. {}\n{}\n{}\n\n", i + 1, title, url, snippet));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_results_format_as_text_json_or_markdown() {
+        let items = [
+            SearchItem {
+                title: "Lionel Messi".into(),
+                url: "https://en.wikipedia.org/wiki/Lionel_Messi".into(),
+                snippet: "Argentine player.".into(),
+                score: Some(0.9),
+            },
+            SearchItem {
+                title: String::new(),
+                url: "https://example.com".into(),
+                snippet: String::new(),
+                score: None,
+            },
+        ];
+        let answer = Some(" Messi is a footballer. ");
+        assert_eq!(
+            format_search_results(answer, &items[..1], SearchFormat::Text),
+            "Answer: Messi is a footballer.\n\n1. Lionel Messi\nhttps://en.wikipedia.org/wiki/Lionel_Messi\nArgentine player.\n\n"
+        );
+        assert_eq!(
+            format_search_results(answer, &items, SearchFormat::Markdown),
+            "**Answer:** Messi is a footballer.\n\n- [Lionel Messi](https://en.wikipedia.org/wiki/Lionel_Messi) — Argentine player.\n- [https://example.com](https://example.com)\n"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&format_search_results(answer, &items, SearchFormat::Json))
+                .unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {"title": "Lionel Messi", "url": "https://en.wikipedia.org/wiki/Lionel_Messi", "snippet": "Argentine player.", "score": 0.9},
+                {"title": "", "url": "https://example.com", "snippet": "", "score": null}
+            ])
+        );
+        assert_eq!(
+            format_search_results(None, &[], SearchFormat::Markdown),
+            "No results.\n"
+        );
+        assert_eq!(format_search_results(None, &[], SearchFormat::Json), "[]\n");
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried_a_bounded_number_of_times() {
+        use httpmock::prelude::*;
+
+        let server = MockServer::start_async().await;
+        let failing = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/flaky");
+                then.status(502);
+            })
+            .await;
+        let missing = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/missing");
+                then.status(404);
+            })
+            .await;
+        let client = Client::new();
+
+        let resp = send_with_retry(|| client.get(server.url("/flaky")))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 502);
+        failing.assert_hits_async(MAX_ATTEMPTS as usize).await;
+
+        let resp = send_with_retry(|| client.get(server.url("/missing")))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 404);
+        missing.assert_hits_async(1).await;
+    }
+}
@@ -0,0 +1,126 @@
+//! SearxNG backend using an instance's JSON API (`format=json` must be enabled in its
+//! `settings.yml`).
+
+use anyhow::{anyhow, bail, Result};
+use futures::future::BoxFuture;
+use reqwest::{Client, StatusCode};
+use serde_json::Value;
+
+use super::{str_field, SearchItem, SearchParams, SearchProvider};
+use crate::config::Config;
+
+pub struct SearxngClient {
+    client: Client,
+    base: String,
+}
+
+impl SearxngClient {
+    pub fn from_config(cfg: &Config) -> Result<Self> {
+        let base = cfg
+            .get("SEARXNG_BASE_URL")
+            .filter(|s| !s.trim().is_empty())
+            .ok_or_else(|| {
+                anyhow!("Missing SEARXNG_BASE_URL (e.g. https://searx.example.com) for SEARCH_PROVIDER=searxng")
+            })?;
+        Ok(Self {
+            client: super::http_client(cfg)?,
+            base,
+        })
+    }
+}
+
+impl SearchProvider for SearxngClient {
+    fn search<'a>(
+        &'a self,
+        query: &'a str,
+        params: &'a SearchParams,
+    ) -> BoxFuture<'a, Result<Vec<SearchItem>>> {
+        Box::pin(async move {
+            let url = format!("{}/search", self.base.trim().trim_end_matches('/'));
+            let resp = super::send_with_retry(|| {
+                self.client
+                    .get(&url)
+                    .query(&[("q", query), ("format", "json")])
+            })
+            .await?;
+            let value = match resp.status() {
+                StatusCode::OK => resp.json::<Value>().await?,
+                StatusCode::FORBIDDEN => {
+                    bail!("SearxNG refused the JSON API (403); enable `json` under search.formats in settings.yml")
+                }
+                status => {
+                    let text = resp.text().await.unwrap_or_default();
+                    bail!("SearxNG search failed: {} - {}", status, text)
+                }
+            };
+            let mut items = parse_response(&value);
+            // SearxNG has no result-count parameter
+            if let Some(n) = params.max_results {
+                items.truncate(n);
+            }
+            Ok(items)
+        })
+    }
+
+    fn settings(&self) -> serde_json::Value {
+        serde_json::json!({ "base": self.base })
+    }
+}
+
+fn parse_response(value: &Value) -> Vec<SearchItem> {
+    value
+        .get("results")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .map(|item| SearchItem {
+            title: str_field(item, "title"),
+            url: str_field(item, "url"),
+            snippet: str_field(item, "content"),
+            score: item.get("score").and_then(|v| v.as_f64()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    #[tokio::test]
+    async fn json_results_are_fetched_and_truncated() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/search")
+                    .query_param("q", "rust async")
+                    .query_param("format", "json");
+                then.status(200).json_body(serde_json::json!({
+                    "results": [
+                        {"title": "Async book", "url": "https://rust-lang.github.io/async-book/", "content": "Asynchronous programming in Rust.", "score": 2.5},
+                        {"title": "Tokio", "url": "https://tokio.rs", "content": "Runtime."}
+                    ]
+                }));
+            })
+            .await;
+        let client = SearxngClient {
+            client: Client::new(),
+            base: format!("{}/", server.base_url()),
+        };
+        let params = SearchParams {
+            max_results: Some(1),
+        };
+        let items = client.search("rust async", &params).await.unwrap();
+        mock.assert_async().await;
+        assert_eq!(
+            items,
+            [SearchItem {
+                title: "Async book".into(),
+                url: "https://rust-lang.github.io/async-book/".into(),
+                snippet: "Asynchronous programming in Rust.".into(),
+                score: Some(2.5),
+            }]
+        );
+    }
+}
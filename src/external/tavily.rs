@@ -1,7 +1,9 @@
 use anyhow::{bail, Result};
+use futures::future::BoxFuture;
 use reqwest::{Client, StatusCode};
 use serde_json::Value;
 
+use super::{str_field, SearchItem, SearchParams, SearchProvider};
 use crate::config::Config;
 
 const DEFAULT_TAVILY_BASE: &str = "https://api.tavily.com";
@@ -10,6 +12,8 @@ pub struct TavilyClient {
     client: Client,
     base: String,
     api_key: String,
+    /// Ask Tavily for a direct answer alongside the results (`TAVILY_INCLUDE_ANSWER`).
+    include_answer: bool,
 }
 
 impl TavilyClient {
@@ -26,32 +30,31 @@ impl TavilyClient {
             .get("TAVILY_API_BASE")
             .unwrap_or_else(|| DEFAULT_TAVILY_BASE.to_string());
 
-        // Honor REQUEST_TIMEOUT if present; default 60s
-        let timeout_secs = cfg
-            .get("REQUEST_TIMEOUT")
-            .and_then(|v| v.parse::<u64>().ok())
-            .unwrap_or(60);
-
-        let client = Client::builder()
-            .timeout(std::time::Duration::from_secs(timeout_secs))
-            .build()?;
-
         Ok(Self {
-            client,
+            client: super::http_client(cfg)?,
             base,
             api_key,
+            include_answer: cfg.get_bool("TAVILY_INCLUDE_ANSWER"),
         })
     }
 
-    pub async fn search(&self, query: &str) -> Result<Value> {
+    /// Raw Tavily response, asking for at most `max_results` results (its own default otherwise).
+    async fn search_raw(&self, query: &str, max_results: Option<usize>) -> Result<Value> {
         let url = format!("{}/search", self.base.trim_end_matches('/'));
-        let resp = self
-            .client
-            .post(&url)
-            .bearer_auth(&self.api_key)
-            .json(&serde_json::json!({ "query": query }))
-            .send()
-            .await?;
+        let mut body = serde_json::json!({
+            "query": query,
+            "include_answer": self.include_answer,
+        });
+        if let Some(n) = max_results {
+            body["max_results"] = n.into();
+        }
+        let resp = super::send_with_retry(|| {
+            self.client
+                .post(&url)
+                .bearer_auth(&self.api_key)
+                .json(&body)
+        })
+        .await?;
 
         match resp.status() {
             StatusCode::OK => Ok(resp.json::<Value>().await?),
@@ -63,9 +66,73 @@ impl TavilyClient {
     }
 }
 
-// Convenience helper when you don't want to manage a client explicitly.
-#[allow(dead_code)]
-pub async fn search_with_config(cfg: &Config, query: &str) -> Result<Value> {
-    let client = TavilyClient::from_config(cfg)?;
-    client.search(query).await
+impl SearchProvider for TavilyClient {
+    fn search<'a>(
+        &'a self,
+        query: &'a str,
+        params: &'a SearchParams,
+    ) -> BoxFuture<'a, Result<Vec<SearchItem>>> {
+        Box::pin(async move { Ok(self.search_with_answer(query, params).await?.1) })
+    }
+
+    fn search_with_answer<'a>(
+        &'a self,
+        query: &'a str,
+        params: &'a SearchParams,
+    ) -> BoxFuture<'a, Result<(Option<String>, Vec<SearchItem>)>> {
+        Box::pin(async move {
+            let value = self.search_raw(query, params.max_results).await?;
+            parse_response(&value)
+        })
+    }
+
+    fn settings(&self) -> serde_json::Value {
+        serde_json::json!({ "base": self.base, "include_answer": self.include_answer })
+    }
+}
+
+/// Answer and results of a Tavily response; one without a `results` array is an error.
+fn parse_response(value: &Value) -> Result<(Option<String>, Vec<SearchItem>)> {
+    let Some(results) = value.get("results").and_then(|v| v.as_array()) else {
+        bail!("Unexpected Tavily response: {}", value);
+    };
+    let answer = value
+        .get("answer")
+        .and_then(|v| v.as_str())
+        .map(str::to_string);
+    let items = results
+        .iter()
+        .map(|item| SearchItem {
+            title: str_field(item, "title"),
+            url: str_field(item, "url"),
+            snippet: item
+                .get("snippet")
+                .or_else(|| item.get("content"))
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string(),
+            score: item.get("score").and_then(|v| v.as_f64()),
+        })
+        .collect();
+    Ok((answer, items))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn response_is_parsed_into_answer_and_items() {
+        let value = serde_json::json!({
+            "answer": "Messi is a footballer.",
+            "results": [
+                {"title": "Lionel Messi", "url": "https://en.wikipedia.org/wiki/Lionel_Messi", "content": "Argentine player."}
+            ]
+        });
+        let (answer, items) = parse_response(&value).unwrap();
+        assert_eq!(answer.as_deref(), Some("Messi is a footballer."));
+        assert_eq!(items[0].snippet, "Argentine player.");
+        assert_eq!(items[0].score, None);
+        assert!(parse_response(&serde_json::json!({"error": "quota"})).is_err());
+    }
 }
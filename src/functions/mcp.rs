@@ -0,0 +1,324 @@
+//! Minimal MCP (Model Context Protocol) client over stdio.
+//!
+//! Servers are listed in `mcp.json` using the common `mcpServers` layout:
+//!
+//! ```json
+//! { "mcpServers": { "files": { "command": "mcp-files", "args": ["--root", "."], "env": {} } } }
+//! ```
+//!
+//! Each server is spawned once per registry, initialized, and asked for its tools. Messages are
+//! newline-delimited JSON-RPC 2.0.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    process::Stdio,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::{Child, ChildStdin, ChildStdout, Command},
+    sync::Mutex,
+    time::timeout,
+};
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerSpec {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Timeout for a single `tools/call`, in seconds.
+    #[serde(default)]
+    pub timeout_sec: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct McpConfig {
+    #[serde(default, rename = "mcpServers")]
+    servers: HashMap<String, ServerSpec>,
+}
+
+/// Read server definitions; a missing file means no servers.
+pub fn load_config(path: &Path) -> Result<Vec<(String, ServerSpec)>> {
+    let Ok(text) = fs::read_to_string(path) else {
+        return Ok(Vec::new());
+    };
+    let cfg: McpConfig = serde_json::from_str(&text)
+        .with_context(|| format!("parsing MCP config: {}", path.display()))?;
+    let mut servers: Vec<_> = cfg.servers.into_iter().collect();
+    servers.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(servers)
+}
+
+/// A tool advertised by a server via `tools/list`.
+#[derive(Debug, Clone)]
+pub struct McpTool {
+    pub name: String,
+    pub description: Option<String>,
+    pub input_schema: Value,
+}
+
+#[derive(Debug)]
+struct Io {
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+#[derive(Debug)]
+pub struct McpServer {
+    pub name: String,
+    call_timeout: Duration,
+    io: Mutex<Io>,
+    next_id: AtomicU64,
+    // Held so the server is killed when the registry goes away
+    _child: Child,
+}
+
+impl McpServer {
+    /// Spawn the server, run the initialize handshake and list its tools.
+    pub async fn connect(name: &str, spec: &ServerSpec) -> Result<(Self, Vec<McpTool>)> {
+        let mut child = Command::new(&spec.command)
+            .args(&spec.args)
+            .envs(&spec.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("failed to start {}", spec.command))?;
+        let io = Io {
+            stdin: child.stdin.take().context("server stdin unavailable")?,
+            stdout: BufReader::new(child.stdout.take().context("server stdout unavailable")?),
+        };
+        let server = Self {
+            name: name.to_string(),
+            call_timeout: Duration::from_secs(spec.timeout_sec.unwrap_or(60)),
+            io: Mutex::new(io),
+            next_id: AtomicU64::new(1),
+            _child: child,
+        };
+
+        let init = json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": { "name": "sgpt", "version": env!("CARGO_PKG_VERSION") },
+        });
+        server
+            .request("initialize", init, HANDSHAKE_TIMEOUT)
+            .await?;
+        server.notify("notifications/initialized").await?;
+        let tools = server.list_tools().await?;
+        Ok((server, tools))
+    }
+
+    async fn list_tools(&self) -> Result<Vec<McpTool>> {
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(c) => json!({ "cursor": c }),
+                None => json!({}),
+            };
+            let result = self
+                .request("tools/list", params, HANDSHAKE_TIMEOUT)
+                .await?;
+            for t in result
+                .get("tools")
+                .and_then(|v| v.as_array())
+                .into_iter()
+                .flatten()
+            {
+                let Some(name) = t.get("name").and_then(|v| v.as_str()) else {
+                    continue;
+                };
+                tools.push(McpTool {
+                    name: name.to_string(),
+                    description: t
+                        .get("description")
+                        .and_then(|v| v.as_str())
+                        .map(str::to_string),
+                    input_schema: t
+                        .get("inputSchema")
+                        .cloned()
+                        .unwrap_or_else(|| json!({"type": "object"})),
+                });
+            }
+            cursor = result
+                .get("nextCursor")
+                .and_then(|v| v.as_str())
+                .map(str::to_string);
+            if cursor.is_none() {
+                return Ok(tools);
+            }
+        }
+    }
+
+    /// Invoke `tools/call` and flatten the returned content into text.
+    pub async fn call_tool(&self, tool: &str, arguments: &Value) -> Result<String> {
+        let params = json!({ "name": tool, "arguments": arguments });
+        let result = self
+            .request("tools/call", params, self.call_timeout)
+            .await?;
+        let mut parts: Vec<String> = Vec::new();
+        for item in result
+            .get("content")
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+        {
+            match item.get("type").and_then(|v| v.as_str()) {
+                Some("text") => parts.push(
+                    item.get("text")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or_default()
+                        .to_string(),
+                ),
+                Some(other) => parts.push(format!("[{} content omitted]", other)),
+                None => parts.push(item.to_string()),
+            }
+        }
+        let text = parts.join("\n");
+        if result.get("isError").and_then(|v| v.as_bool()) == Some(true) {
+            Ok(format!("Error: {}", text))
+        } else {
+            Ok(text)
+        }
+    }
+
+    async fn request(&self, method: &str, params: Value, limit: Duration) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let msg = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        let mut io = self.io.lock().await;
+        write_message(&mut io.stdin, &msg).await?;
+
+        let response = timeout(limit, async {
+            let mut line = String::new();
+            loop {
+                line.clear();
+                if io.stdout.read_line(&mut line).await? == 0 {
+                    bail!("server closed the connection");
+                }
+                let Ok(value) = serde_json::from_str::<Value>(line.trim()) else {
+                    // Stray log output on stdout; not a protocol message
+                    continue;
+                };
+                // Skip notifications and server-initiated requests
+                if value.get("id").and_then(|v| v.as_u64()) == Some(id)
+                    && value.get("method").is_none()
+                {
+                    return Ok(value);
+                }
+            }
+        })
+        .await
+        .map_err(|_| anyhow!("MCP server '{}' timed out on {}", self.name, method))??;
+
+        if let Some(err) = response.get("error") {
+            let message = err
+                .get("message")
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown error");
+            bail!("MCP server '{}' {} failed: {}", self.name, method, message);
+        }
+        Ok(response.get("result").cloned().unwrap_or(Value::Null))
+    }
+
+    async fn notify(&self, method: &str) -> Result<()> {
+        let msg = json!({ "jsonrpc": "2.0", "method": method });
+        let mut io = self.io.lock().await;
+        write_message(&mut io.stdin, &msg).await
+    }
+}
+
+async fn write_message(stdin: &mut ChildStdin, msg: &Value) -> Result<()> {
+    let mut line = serde_json::to_string(msg)?;
+    line.push('\n');
+    stdin.write_all(line.as_bytes()).await?;
+    stdin.flush().await?;
+    Ok(())
+}
+
+/// Name under which a server's tool is exposed to the model, e.g. `files__read_file`.
+///
+/// OpenAI-style function names only allow `[A-Za-z0-9_-]`.
+pub fn exposed_name(server: &str, tool: &str) -> String {
+    format!("{}__{}", server, tool)
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    /// A scripted server that answers the handshake, one listing and one call, in order.
+    fn scripted_server() -> ServerSpec {
+        let script = r#"
+read -r _init
+echo 'not json: starting up'
+echo '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2024-11-05","capabilities":{"tools":{}},"serverInfo":{"name":"echo","version":"1"}}}'
+read -r _initialized
+read -r _list
+echo '{"jsonrpc":"2.0","method":"notifications/message","params":{}}'
+echo '{"jsonrpc":"2.0","id":2,"result":{"tools":[{"name":"echo","description":"Echo text","inputSchema":{"type":"object","properties":{"text":{"type":"string"}},"required":["text"]}}]}}'
+read -r _call
+echo '{"jsonrpc":"2.0","id":3,"result":{"content":[{"type":"text","text":"hello"}],"isError":false}}'
+"#;
+        ServerSpec {
+            command: "/bin/sh".into(),
+            args: vec!["-c".into(), script.into()],
+            env: HashMap::new(),
+            timeout_sec: Some(5),
+        }
+    }
+
+    #[tokio::test]
+    async fn handshake_lists_and_calls_tools() {
+        let (server, tools) = McpServer::connect("demo", &scripted_server())
+            .await
+            .unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, "echo");
+        assert_eq!(tools[0].input_schema["required"], json!(["text"]));
+
+        let out = server
+            .call_tool("echo", &json!({"text": "hello"}))
+            .await
+            .unwrap();
+        assert_eq!(out, "hello");
+    }
+
+    #[tokio::test]
+    async fn failing_server_reports_error() {
+        let spec = ServerSpec {
+            command: "/nonexistent/mcp-server".into(),
+            args: Vec::new(),
+            env: HashMap::new(),
+            timeout_sec: None,
+        };
+        assert!(McpServer::connect("broken", &spec).await.is_err());
+    }
+
+    #[test]
+    fn exposed_names_are_sanitized() {
+        assert_eq!(exposed_name("git hub", "list.repos"), "git_hub__list_repos");
+    }
+}
@@ -1,16 +1,31 @@
 //! Native JSON tools registry and executor.
 
-use std::{collections::HashMap, fs, path::PathBuf, time::Duration};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::{anyhow, Context, Result};
+use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
-use tokio::{io::AsyncWriteExt, process::Command, time::timeout};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    process::{Child, Command},
+    time::timeout,
+};
 
 use crate::{
     config::Config,
     llm::{FunctionSchema, ToolSchema},
 };
 
+pub mod mcp;
+pub mod schema;
+pub mod watch;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExecSpec {
     pub program: String,
@@ -20,6 +35,22 @@ pub struct ExecSpec {
     pub stdin: bool,
     #[serde(default)]
     pub timeout_sec: Option<u64>,
+    /// Working directory for the spawned program.
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Environment of the spawned program. When this or `env_inherit` is non-empty the
+    /// parent environment is cleared first, so the tool only sees what is listed.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Names of parent environment variables passed through to a hermetic tool (e.g. `PATH`).
+    #[serde(default)]
+    pub env_inherit: Vec<String>,
+    /// Shell-quote substituted values so they survive being spliced into a shell command line.
+    #[serde(default)]
+    pub quote_args: bool,
+    /// Cap on stdout and stderr each; defaults to `FUNCTIONS_MAX_OUTPUT`.
+    #[serde(default)]
+    pub max_output_bytes: Option<usize>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,38 +60,201 @@ pub struct ToolDef {
     pub description: Option<String>,
     #[serde(default)]
     pub parameters: serde_json::Value,
-    pub exec: ExecSpec,
+    #[serde(flatten)]
+    pub kind: ToolKind,
+}
+
+/// How a tool is carried out: an `exec` block spawns a program, an `http` block calls an endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolKind {
+    Exec(ExecSpec),
+    Http(HttpSpec),
+    /// Discovered from an MCP server at load time; never read from a tool file.
+    #[serde(skip)]
+    Mcp {
+        server: usize,
+        tool: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpSpec {
+    #[serde(default = "default_http_method")]
+    pub method: String,
+    /// URL with `{{param}}` placeholders; substituted values are percent-encoded.
+    pub url_template: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Request body. A JSON object/array is sent as JSON with `{{param}}` strings replaced by
+    /// the argument value (keeping its type); a plain string is substituted as text.
+    #[serde(default)]
+    pub body_template: Option<serde_json::Value>,
+    #[serde(default)]
+    pub timeout_sec: Option<u64>,
+    /// Cap on the response body; defaults to `FUNCTIONS_MAX_OUTPUT`.
+    #[serde(default)]
+    pub max_output_bytes: Option<usize>,
+}
+
+/// Parse every `*.json` tool definition in `dir`; a missing directory has no tools.
+fn load_tool_files(dir: &Path) -> Result<HashMap<String, ToolDef>> {
+    let mut map = HashMap::new();
+    if let Ok(rd) = fs::read_dir(dir) {
+        for e in rd.filter_map(|e| e.ok()) {
+            let p = e.path();
+            if p.extension().and_then(|s| s.to_str()) != Some("json") {
+                continue;
+            }
+            let text = fs::read_to_string(&p)
+                .with_context(|| format!("reading tool file: {}", p.display()))?;
+            let def: ToolDef = serde_json::from_str(&text)
+                .with_context(|| format!("parsing tool file: {}", p.display()))?;
+            schema::validate_json_schema(&def.parameters).with_context(|| {
+                format!("invalid parameters schema in tool file: {}", p.display())
+            })?;
+            map.insert(def.name.clone(), def);
+        }
+    }
+    Ok(map)
+}
+
+fn default_http_method() -> String {
+    "GET".into()
 }
 
 #[derive(Debug, Clone)]
 pub struct Registry {
     tools: HashMap<String, ToolDef>,
+    /// When set, only these tools are exposed (`FUNCTIONS_ALLOWLIST` / `--tool`).
+    allow: Option<HashSet<String>>,
+    /// Tools never exposed (`FUNCTIONS_DENYLIST` / `--exclude-tool`).
+    deny: HashSet<String>,
+    /// Default output cap for tools that don't set `max_output_bytes`.
+    max_output: usize,
+    http: reqwest::Client,
+    mcp: Vec<Arc<mcp::McpServer>>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self {
+            tools: HashMap::new(),
+            allow: None,
+            deny: HashSet::new(),
+            max_output: DEFAULT_MAX_OUTPUT,
+            http: reqwest::Client::new(),
+            mcp: Vec::new(),
+        }
+    }
 }
 
 impl Registry {
-    pub fn load(cfg: &Config) -> Result<Self> {
-        let mut map = HashMap::new();
+    pub async fn load(cfg: &Config) -> Result<Self> {
         let dir = cfg.functions_path();
         let _ = fs::create_dir_all(&dir);
-        if let Ok(rd) = fs::read_dir(&dir) {
-            for e in rd.filter_map(|e| e.ok()) {
-                let p = e.path();
-                if p.extension().and_then(|s| s.to_str()) != Some("json") {
-                    continue;
+        let mut map = load_tool_files(&dir)?;
+
+        // MCP servers are optional extras: one that fails to start must not take the
+        // file-based tools down with it.
+        let mut servers = Vec::new();
+        let mcp_path = PathBuf::from(cfg.get("MCP_CONFIG_PATH").unwrap_or_default());
+        let server_specs = mcp::load_config(&mcp_path).unwrap_or_else(|e| {
+            eprintln!("Warning: ignoring MCP config: {:#}", e);
+            Vec::new()
+        });
+        for (server_name, spec) in server_specs {
+            match mcp::McpServer::connect(&server_name, &spec).await {
+                Ok((server, tools)) => {
+                    let idx = servers.len();
+                    servers.push(Arc::new(server));
+                    for t in tools {
+                        let name = mcp::exposed_name(&server_name, &t.name);
+                        let def = ToolDef {
+                            name: name.clone(),
+                            description: t.description,
+                            parameters: t.input_schema,
+                            kind: ToolKind::Mcp {
+                                server: idx,
+                                tool: t.name,
+                            },
+                        };
+                        map.insert(name, def);
+                    }
                 }
-                let text = fs::read_to_string(&p)
-                    .with_context(|| format!("reading tool file: {}", p.display()))?;
-                let def: ToolDef = serde_json::from_str(&text)
-                    .with_context(|| format!("parsing tool file: {}", p.display()))?;
-                map.insert(def.name.clone(), def);
+                Err(e) => eprintln!("Warning: skipping MCP server '{}': {:#}", server_name, e),
             }
         }
-        Ok(Self { tools: map })
+        let allow = cfg.get("FUNCTIONS_ALLOWLIST").map(|v| parse_name_list(&v));
+        let deny = cfg
+            .get("FUNCTIONS_DENYLIST")
+            .map(|v| parse_name_list(&v))
+            .unwrap_or_default();
+        let max_output = cfg
+            .get("FUNCTIONS_MAX_OUTPUT")
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_MAX_OUTPUT);
+        let registry = Self {
+            tools: map,
+            allow,
+            deny,
+            max_output,
+            http: reqwest::Client::new(),
+            mcp: servers,
+        };
+        registry.warn_unknown_names();
+        Ok(registry)
+    }
+
+    /// Re-read the tool files (`--watch-functions`), keeping MCP tools as they are.
+    /// On error the previous tools stay in place.
+    pub fn reload_files(&mut self, dir: &Path) -> Result<()> {
+        let files = load_tool_files(dir)?;
+        self.tools
+            .retain(|_, def| matches!(def.kind, ToolKind::Mcp { .. }));
+        for (name, def) in files {
+            // MCP tools win on a name clash, as they do at load time
+            self.tools.entry(name).or_insert(def);
+        }
+        Ok(())
+    }
+
+    /// Whether `name` may be offered to the model and executed.
+    fn is_enabled(&self, name: &str) -> bool {
+        !self.deny.contains(name) && self.allow.as_ref().is_none_or(|a| a.contains(name))
+    }
+
+    fn warn_unknown_names(&self) {
+        let mut unknown: Vec<&str> = self
+            .allow
+            .iter()
+            .flatten()
+            .chain(self.deny.iter())
+            .map(String::as_str)
+            .filter(|n| !self.tools.contains_key(*n))
+            .collect();
+        if unknown.is_empty() {
+            return;
+        }
+        unknown.sort_unstable();
+        unknown.dedup();
+        let mut available: Vec<&str> = self.tools.keys().map(String::as_str).collect();
+        available.sort_unstable();
+        eprintln!(
+            "Warning: unknown tool(s): {}. Available tools: {}",
+            unknown.join(", "),
+            if available.is_empty() {
+                "(none)".to_string()
+            } else {
+                available.join(", ")
+            }
+        );
     }
 
     pub fn schemas(&self) -> Vec<ToolSchema> {
         self.tools
             .values()
+            .filter(|t| self.is_enabled(&t.name))
             .map(|t| ToolSchema {
                 r#type: "function".into(),
                 function: FunctionSchema {
@@ -76,18 +270,65 @@ impl Registry {
         let tool = self
             .tools
             .get(name)
+            .filter(|_| self.is_enabled(name))
             .ok_or_else(|| anyhow!("tool not found: {}", name))?;
         let args_val: serde_json::Value = serde_json::from_str(args_json)
             .with_context(|| format!("invalid tool args json: {}", args_json))?;
 
+        // Reject arguments that don't match the declared schema before spawning anything,
+        // and hand the violations back so the model can correct itself.
+        let violations = schema::validate(&args_val, &tool.parameters);
+        if !violations.is_empty() {
+            let report = serde_json::json!({
+                "error": "invalid_arguments",
+                "tool": name,
+                "details": violations,
+                "hint": "Call the tool again with arguments matching its parameters schema.",
+            });
+            return Ok(report.to_string());
+        }
+
+        match &tool.kind {
+            ToolKind::Exec(spec) => self.run_exec(name, spec, &args_val).await,
+            ToolKind::Http(spec) => self.run_http(name, spec, &args_val).await,
+            ToolKind::Mcp { server, tool } => {
+                let text = self.mcp[*server].call_tool(tool, &args_val).await?;
+                let mut out = CappedOutput::new(self.max_output);
+                out.push(text.as_bytes());
+                Ok(out.finish())
+            }
+        }
+    }
+
+    async fn run_exec(
+        &self,
+        name: &str,
+        spec: &ExecSpec,
+        args_val: &serde_json::Value,
+    ) -> Result<String> {
         let mut args: Vec<String> = Vec::new();
-        for t in &tool.exec.args_template {
-            args.push(apply_template(t, &args_val));
+        for t in &spec.args_template {
+            args.push(apply_template(t, args_val, spec.quote_args));
         }
 
-        let mut cmd = Command::new(&tool.exec.program);
+        let mut cmd = Command::new(&spec.program);
         cmd.args(&args);
-        if tool.exec.stdin {
+        // Own process group so a timeout can take down everything the tool spawned
+        #[cfg(unix)]
+        cmd.process_group(0);
+        if let Some(dir) = &spec.cwd {
+            cmd.current_dir(dir);
+        }
+        if !spec.env.is_empty() || !spec.env_inherit.is_empty() {
+            cmd.env_clear();
+            for key in &spec.env_inherit {
+                if let Some(val) = std::env::var_os(key) {
+                    cmd.env(key, val);
+                }
+            }
+            cmd.envs(&spec.env);
+        }
+        if spec.stdin {
             cmd.stdin(std::process::Stdio::piped());
         }
         cmd.stdout(std::process::Stdio::piped());
@@ -96,51 +337,289 @@ impl Registry {
         let mut child = cmd.spawn().with_context(|| {
             format!(
                 "failed to spawn tool {} with program {}",
-                name, tool.exec.program
+                name, spec.program
             )
         })?;
 
-        if tool.exec.stdin {
+        if spec.stdin {
             if let Some(mut stdin) = child.stdin.take() {
-                let payload = serde_json::to_string(&args_val)?;
+                let payload = serde_json::to_string(args_val)?;
                 stdin.write_all(payload.as_bytes()).await.ok();
             }
         }
 
-        let timeout_dur = Duration::from_secs(tool.exec.timeout_sec.unwrap_or(60));
-        let out = timeout(timeout_dur, child.wait_with_output())
-            .await
-            .map_err(|_| anyhow!("tool execution timeout: {}", name))??;
+        // Kept for the kill: `child.id()` is gone once the tool process has been reaped
+        let pid = child.id();
+        let cap = spec.max_output_bytes.unwrap_or(self.max_output);
+        let stdout = tokio::spawn(read_capped(child.stdout.take(), cap));
+        let stderr = tokio::spawn(read_capped(child.stderr.take(), cap));
+
+        // The pipes count towards the deadline: a process the tool left running in the
+        // background keeps them open after the tool itself has exited
+        let (mut stdout, mut stderr) = (stdout, stderr);
+        let run = async {
+            let status = child.wait().await?;
+            let stdout = (&mut stdout).await??;
+            let stderr = (&mut stderr).await??;
+            anyhow::Ok((status, stdout, stderr))
+        };
+        let timeout_dur = Duration::from_secs(spec.timeout_sec.unwrap_or(60));
+        let finished = timeout(timeout_dur, run).await;
+        let (status, stdout, stderr) = match finished {
+            Ok(done) => done?,
+            Err(_) => {
+                kill_tree(&mut child, pid).await;
+                stdout.abort();
+                stderr.abort();
+                return Err(anyhow!("tool execution timeout: {}", name));
+            }
+        };
 
-        let code = out.status.code().unwrap_or(-1);
+        let code = status.code().unwrap_or(-1);
         let mut body = String::new();
-        if !out.stdout.is_empty() {
-            body.push_str(&String::from_utf8_lossy(&out.stdout));
+        if !stdout.is_empty() {
+            body.push_str(&stdout);
         }
-        if !out.stderr.is_empty() {
+        if !stderr.is_empty() {
             if !body.is_empty() {
                 body.push_str("\n");
             }
-            body.push_str(&String::from_utf8_lossy(&out.stderr));
+            body.push_str(&stderr);
         }
         Ok(format!("Exit code: {}\n{}", code, body))
     }
-}
 
-fn apply_template(t: &str, args: &serde_json::Value) -> String {
-    let mut s = t.to_string();
-    if let Some(obj) = args.as_object() {
-        for (k, v) in obj {
-            let needle = format!("{{{{{}}}}}", k);
-            let repl = if v.is_string() {
-                v.as_str().unwrap().to_string()
+    async fn run_http(
+        &self,
+        name: &str,
+        spec: &HttpSpec,
+        args_val: &serde_json::Value,
+    ) -> Result<String> {
+        let url = render_template(&spec.url_template, args_val, percent_encode);
+        let method = reqwest::Method::from_bytes(spec.method.to_ascii_uppercase().as_bytes())
+            .with_context(|| format!("invalid HTTP method for tool {}: {}", name, spec.method))?;
+        let mut req = self
+            .http
+            .request(method, &url)
+            .timeout(Duration::from_secs(spec.timeout_sec.unwrap_or(60)));
+        for (k, v) in &spec.headers {
+            req = req.header(k, apply_template(v, args_val, false));
+        }
+        match &spec.body_template {
+            None => {}
+            Some(serde_json::Value::String(t)) => {
+                req = req.body(apply_template(t, args_val, false));
+            }
+            Some(t) => req = req.json(&fill_json_template(t, args_val)),
+        }
+
+        let resp = req.send().await.map_err(|e| {
+            if e.is_timeout() {
+                anyhow!("tool execution timeout: {}", name)
             } else {
-                v.to_string()
-            };
-            s = s.replace(&needle, &repl);
+                anyhow!("tool {} request to {} failed: {}", name, url, e)
+            }
+        })?;
+        let status = resp.status();
+        let mut out = CappedOutput::new(spec.max_output_bytes.unwrap_or(self.max_output));
+        let mut body = resp.bytes_stream();
+        while let Some(chunk) = body.next().await {
+            out.push(&chunk?);
+        }
+        Ok(format!(
+            "HTTP status: {}\n{}",
+            status.as_u16(),
+            out.finish()
+        ))
+    }
+}
+
+const DEFAULT_MAX_OUTPUT: usize = 64 * 1024;
+
+/// Keeps the first and last `cap / 2` bytes of a stream and counts the rest, so a tool that
+/// dumps megabytes of logs costs a bounded amount of memory and context.
+struct CappedOutput {
+    cap: usize,
+    head: Vec<u8>,
+    tail: VecDeque<u8>,
+    total: usize,
+}
+
+impl CappedOutput {
+    fn new(cap: usize) -> Self {
+        Self {
+            cap,
+            head: Vec::new(),
+            tail: VecDeque::new(),
+            total: 0,
+        }
+    }
+
+    fn push(&mut self, mut chunk: &[u8]) {
+        self.total += chunk.len();
+        let head_room = (self.cap / 2).saturating_sub(self.head.len());
+        let n = head_room.min(chunk.len());
+        self.head.extend_from_slice(&chunk[..n]);
+        chunk = &chunk[n..];
+
+        let tail_cap = self.cap - self.cap / 2;
+        if chunk.len() >= tail_cap {
+            self.tail.clear();
+            self.tail.extend(&chunk[chunk.len() - tail_cap..]);
+        } else {
+            self.tail.extend(chunk);
+            let excess = self.tail.len().saturating_sub(tail_cap);
+            self.tail.drain(..excess);
+        }
+    }
+
+    fn finish(self) -> String {
+        let kept = self.head.len() + self.tail.len();
+        let tail: Vec<u8> = self.tail.into_iter().collect();
+        if kept == self.total {
+            let mut all = self.head;
+            all.extend_from_slice(&tail);
+            return String::from_utf8_lossy(&all).into_owned();
+        }
+        format!(
+            "{}\n[... {} bytes elided; original output was {} bytes ...]\n{}",
+            String::from_utf8_lossy(&self.head),
+            self.total - kept,
+            self.total,
+            String::from_utf8_lossy(&tail)
+        )
+    }
+}
+
+async fn read_capped<R: AsyncRead + Unpin>(pipe: Option<R>, cap: usize) -> Result<String> {
+    let mut out = CappedOutput::new(cap);
+    if let Some(mut pipe) = pipe {
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = pipe.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            out.push(&buf[..n]);
         }
     }
-    s
+    Ok(out.finish())
+}
+
+/// Kill a timed-out tool together with any processes it started.
+async fn kill_tree(child: &mut Child, pid: Option<u32>) {
+    #[cfg(unix)]
+    if let Some(pid) = pid {
+        // SAFETY: plain syscall; the child leads its own process group (see `process_group(0)`).
+        unsafe {
+            libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+    #[cfg(windows)]
+    if let Some(pid) = pid {
+        let _ = Command::new("taskkill")
+            .args(["/T", "/F", "/PID", &pid.to_string()])
+            .output()
+            .await;
+    }
+    let _ = child.kill().await;
+}
+
+fn parse_name_list(value: &str) -> HashSet<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn apply_template(t: &str, args: &serde_json::Value, quote: bool) -> String {
+    if quote {
+        render_template(t, args, shell_quote)
+    } else {
+        render_template(t, args, str::to_string)
+    }
+}
+
+/// Replace each `{{key}}` with the argument value passed through `escape`.
+///
+/// The template is scanned once, so placeholders inside substituted values are left alone;
+/// unknown keys are kept verbatim.
+fn render_template(t: &str, args: &serde_json::Value, escape: impl Fn(&str) -> String) -> String {
+    let mut out = String::with_capacity(t.len());
+    let mut rest = t;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        match args.get(&after[..end]) {
+            Some(serde_json::Value::String(v)) => out.push_str(&escape(v)),
+            Some(v) => out.push_str(&escape(&v.to_string())),
+            None => out.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Substitute placeholders inside a JSON body template. A string that is exactly `{{key}}`
+/// becomes the raw argument value so numbers, booleans and objects keep their type.
+fn fill_json_template(t: &serde_json::Value, args: &serde_json::Value) -> serde_json::Value {
+    use serde_json::Value;
+    match t {
+        Value::String(s) => {
+            let whole = s
+                .strip_prefix("{{")
+                .and_then(|rest| rest.strip_suffix("}}"))
+                .and_then(|key| args.get(key));
+            match whole {
+                Some(v) => v.clone(),
+                None => Value::String(apply_template(s, args, false)),
+            }
+        }
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| fill_json_template(item, args))
+                .collect(),
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), fill_json_template(v, args)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Percent-encode everything outside the RFC 3986 unreserved set.
+fn percent_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// Quote a value as a single shell word.
+///
+/// POSIX shells and PowerShell both treat single-quoted strings literally; they differ only
+/// in how an embedded single quote is escaped.
+fn shell_quote(value: &str) -> String {
+    if cfg!(windows) {
+        format!("'{}'", value.replace('\'', "''"))
+    } else {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
 }
 
 pub fn install_default_functions(cfg: &Config) -> Result<PathBuf> {
@@ -169,3 +648,419 @@ pub fn install_default_functions(cfg: &Config) -> Result<PathBuf> {
     fs::write(&path, serde_json::to_string_pretty(&spec)?)?;
     Ok(path)
 }
+
+/// Download a tool definition from an `https` `url` into `dir` (the functions directory)
+/// as `<name>.json`.
+///
+/// The definition must parse as a [`ToolDef`] with a valid parameters schema. When the
+/// target file exists, `confirm_overwrite` decides; `Ok(None)` means it declined.
+pub async fn install_from_url(
+    dir: &Path,
+    url: &str,
+    confirm_overwrite: impl FnOnce(&Path) -> bool,
+) -> Result<Option<(ToolDef, PathBuf)>> {
+    let parsed = reqwest::Url::parse(url).with_context(|| format!("invalid URL: {}", url))?;
+    // A tool definition decides what gets executed, so it must not be swappable in transit
+    if parsed.scheme() != "https" {
+        return Err(anyhow!(
+            "unsupported URL scheme: {} (tool definitions are only fetched over https)",
+            parsed.scheme()
+        ));
+    }
+    // Certificates are verified against the bundled roots (rustls)
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?;
+    let text = client
+        .get(parsed)
+        .send()
+        .await
+        .with_context(|| format!("downloading {}", url))?
+        .error_for_status()?
+        .text()
+        .await?;
+    save_definition(dir, url, &text, confirm_overwrite)
+}
+
+/// Validate the downloaded definition `text` from `source` and write it into `dir`.
+fn save_definition(
+    dir: &Path,
+    source: &str,
+    text: &str,
+    confirm_overwrite: impl FnOnce(&Path) -> bool,
+) -> Result<Option<(ToolDef, PathBuf)>> {
+    let value: serde_json::Value = serde_json::from_str(text)
+        .with_context(|| format!("{} is not a JSON tool definition", source))?;
+    let def: ToolDef = serde_json::from_value(value.clone())
+        .with_context(|| format!("parsing tool definition from {}", source))?;
+    schema::validate_json_schema(&def.parameters)
+        .with_context(|| format!("invalid parameters schema in {}", source))?;
+    // The name becomes a file name, so keep it to what providers accept anyway
+    let valid_name = !def.name.is_empty()
+        && def.name.len() <= 64
+        && def
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid_name {
+        return Err(anyhow!(
+            "invalid tool name {:?} (use letters, digits, '_' or '-')",
+            def.name
+        ));
+    }
+
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.json", def.name));
+    if path.exists() && !confirm_overwrite(&path) {
+        return Ok(None);
+    }
+    fs::write(&path, serde_json::to_string_pretty(&value)?)?;
+    Ok(Some((def, path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry_with(def: serde_json::Value) -> Registry {
+        let def: ToolDef = serde_json::from_value(def).unwrap();
+        let mut tools = HashMap::new();
+        tools.insert(def.name.clone(), def);
+        Registry {
+            tools,
+            ..Registry::default()
+        }
+    }
+
+    #[test]
+    fn reload_replaces_file_tools_and_keeps_mcp() {
+        let dir = tempfile::tempdir().unwrap();
+        let tool =
+            |name: &str| serde_json::json!({"name": name, "exec": {"program": "true"}}).to_string();
+        fs::write(dir.path().join("old.json"), tool("old")).unwrap();
+        let mut registry = Registry {
+            tools: load_tool_files(dir.path()).unwrap(),
+            ..Registry::default()
+        };
+        registry.tools.insert(
+            "srv__t".into(),
+            ToolDef {
+                name: "srv__t".into(),
+                description: None,
+                parameters: serde_json::Value::Null,
+                kind: ToolKind::Mcp {
+                    server: 0,
+                    tool: "t".into(),
+                },
+            },
+        );
+
+        fs::remove_file(dir.path().join("old.json")).unwrap();
+        fs::write(dir.path().join("new.json"), tool("new")).unwrap();
+        registry.reload_files(dir.path()).unwrap();
+        let mut names: Vec<&str> = registry.tools.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        assert_eq!(names, ["new", "srv__t"]);
+
+        // A half-written file keeps the previous tools
+        fs::write(dir.path().join("broken.json"), "{").unwrap();
+        assert!(registry.reload_files(dir.path()).is_err());
+        assert!(registry.tools.contains_key("new"));
+
+        // So does a tool whose parameters aren't a usable schema, and the file is named
+        fs::remove_file(dir.path().join("broken.json")).unwrap();
+        let bad = serde_json::json!({
+            "name": "bad",
+            "parameters": {"type": "object", "properties": {"x": {}}},
+            "exec": {"program": "true"}
+        });
+        fs::write(dir.path().join("bad.json"), bad.to_string()).unwrap();
+        let err = registry.reload_files(dir.path()).unwrap_err();
+        assert!(format!("{:#}", err).contains("bad.json"), "{:#}", err);
+        assert!(registry.tools.contains_key("new"));
+    }
+
+    #[test]
+    fn downloaded_definitions_are_validated_and_overwrites_confirmed() {
+        let tool = serde_json::json!({
+            "name": "weather",
+            "description": "Current weather",
+            "parameters": {"type": "object", "properties": {"city": {"type": "string"}}},
+            "exec": {"program": "curl", "args_template": ["wttr.in/{{city}}"]}
+        })
+        .to_string();
+        let source = "https://example.com/weather.json";
+
+        let dir = tempfile::tempdir().unwrap();
+        let (def, path) = save_definition(dir.path(), source, &tool, |_| {
+            panic!("nothing to overwrite yet")
+        })
+        .unwrap()
+        .unwrap();
+        assert_eq!(def.description.as_deref(), Some("Current weather"));
+        assert_eq!(path, dir.path().join("weather.json"));
+        assert!(load_tool_files(dir.path()).unwrap().contains_key("weather"));
+
+        let declined = save_definition(dir.path(), source, &tool, |_| false).unwrap();
+        assert!(declined.is_none());
+
+        let untyped = serde_json::json!({
+            "name": "untyped",
+            "parameters": {"type": "object", "properties": {"city": {}}},
+            "exec": {"program": "true"}
+        });
+        assert!(save_definition(dir.path(), source, &untyped.to_string(), |_| true).is_err());
+        let escape = serde_json::json!({"name": "../evil", "exec": {"program": "true"}});
+        assert!(save_definition(dir.path(), source, &escape.to_string(), |_| true).is_err());
+    }
+
+    #[tokio::test]
+    async fn install_from_url_requires_https() {
+        let dir = tempfile::tempdir().unwrap();
+        for url in ["http://example.com/weather.json", "file:///etc/passwd"] {
+            let err = install_from_url(dir.path(), url, |_| true)
+                .await
+                .unwrap_err();
+            assert!(
+                err.to_string().contains("unsupported URL scheme"),
+                "{}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn template_quoting_wraps_values() {
+        let args = serde_json::json!({"msg": "it's here", "n": 3});
+        assert_eq!(
+            apply_template("echo {{msg}} {{n}}", &args, false),
+            "echo it's here 3"
+        );
+        #[cfg(not(windows))]
+        assert_eq!(
+            apply_template("echo {{msg}} {{n}}", &args, true),
+            r"echo 'it'\''s here' '3'"
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn substituted_values_are_not_expanded_again() {
+        // `a` smuggles a placeholder for `b`; a second pass would splice `b` in unquoted
+        let args = serde_json::json!({"a": "{{b}}", "b": "'; rm -rf ~; '"});
+        assert_eq!(
+            apply_template("echo {{a}} {{missing}} {{", &args, true),
+            "echo '{{b}}' {{missing}} {{"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn quoted_args_survive_shell_with_embedded_quotes() {
+        let registry = registry_with(serde_json::json!({
+            "name": "say",
+            "parameters": {
+                "type": "object",
+                "properties": { "msg": {"type": "string"} },
+                "required": ["msg"]
+            },
+            "exec": {
+                "program": "/bin/sh",
+                "args_template": ["-c", "printf '%s' {{msg}}"],
+                "quote_args": true
+            }
+        }));
+        let msg = r#"it's a "quoted" $HOME; echo injected"#;
+        let args = serde_json::json!({ "msg": msg }).to_string();
+        let out = registry.execute("say", &args).await.unwrap();
+        assert_eq!(out, format!("Exit code: 0\n{}", msg));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn cwd_and_env_are_applied() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = registry_with(serde_json::json!({
+            "name": "where",
+            "exec": {
+                "program": "/bin/sh",
+                "args_template": ["-c", "pwd; printf '%s' \"$SGPT_TOOL_TEST\""],
+                "cwd": dir.path(),
+                "env": { "SGPT_TOOL_TEST": "from-env" }
+            }
+        }));
+        let out = registry.execute("where", "{}").await.unwrap();
+        let expected_dir = dir.path().canonicalize().unwrap();
+        assert!(out.contains(&expected_dir.display().to_string()), "{}", out);
+        assert!(out.ends_with("from-env"), "{}", out);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn env_map_makes_the_tool_hermetic() {
+        std::env::set_var("SGPT_TOOL_LEAK", "leaked");
+        std::env::set_var("SGPT_TOOL_PASS", "passed");
+        let registry = registry_with(serde_json::json!({
+            "name": "env",
+            "exec": {
+                "program": "/bin/sh",
+                "args_template": ["-c", "printf '%s|%s|%s' \"$SGPT_TOOL_LEAK\" \"$SGPT_TOOL_PASS\" \"$SGPT_TOOL_SET\""],
+                "env": { "SGPT_TOOL_SET": "set" },
+                "env_inherit": ["SGPT_TOOL_PASS", "SGPT_TOOL_MISSING"]
+            }
+        }));
+        let out = registry.execute("env", "{}").await.unwrap();
+        assert!(out.ends_with("|passed|set"), "{}", out);
+    }
+
+    #[tokio::test]
+    async fn invalid_arguments_are_reported_without_spawning() {
+        let registry = registry_with(serde_json::json!({
+            "name": "run",
+            "parameters": {
+                "type": "object",
+                "properties": { "cmd": {"type": "string"} },
+                "required": ["cmd"]
+            },
+            "exec": { "program": "/nonexistent/sgpt-tool", "args_template": ["{{cmd}}"] }
+        }));
+
+        // A spawn attempt would fail with an error; validation must short-circuit first.
+        let out = registry
+            .execute("run", r#"{"command": "ls"}"#)
+            .await
+            .unwrap();
+        let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(report["error"], "invalid_arguments");
+        assert_eq!(report["details"][0], "$: missing required property 'cmd'");
+
+        let out = registry.execute("run", r#"{"cmd": 1}"#).await.unwrap();
+        assert!(out.contains("expected string, got integer"));
+    }
+
+    #[tokio::test]
+    async fn filtered_tools_are_hidden_and_not_executed() {
+        let mut registry = registry_with(serde_json::json!({
+            "name": "noop",
+            "parameters": {},
+            "exec": { "program": "true" }
+        }));
+        registry.allow = Some(parse_name_list("other, "));
+        assert!(registry.schemas().is_empty());
+        assert!(registry.execute("noop", "{}").await.is_err());
+
+        registry.allow = None;
+        registry.deny = parse_name_list("noop");
+        assert!(registry.schemas().is_empty());
+        assert!(registry.execute("noop", "{}").await.is_err());
+
+        registry.deny.clear();
+        assert_eq!(registry.schemas().len(), 1);
+    }
+
+    #[test]
+    fn capped_output_keeps_head_and_tail() {
+        let mut out = CappedOutput::new(8);
+        out.push(b"abc");
+        out.push(b"defghij");
+        out.push(b"klmnopqrstuvwxyz");
+        assert_eq!(
+            out.finish(),
+            "abcd\n[... 18 bytes elided; original output was 26 bytes ...]\nwxyz"
+        );
+
+        let mut small = CappedOutput::new(8);
+        small.push(b"abcdefgh");
+        assert_eq!(small.finish(), "abcdefgh");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn timeout_kills_process_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("survived");
+        let registry = registry_with(serde_json::json!({
+            "name": "hang",
+            "parameters": {},
+            "exec": {
+                "program": "/bin/sh",
+                "args_template": ["-c", format!("(sleep 2; touch {}) & wait", marker.display())],
+                "timeout_sec": 1
+            }
+        }));
+        let err = registry.execute("hang", "{}").await.unwrap_err();
+        assert!(err.to_string().contains("tool execution timeout"));
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(!marker.exists(), "background child outlived the timeout");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn timeout_covers_pipes_held_by_background_processes() {
+        let registry = registry_with(serde_json::json!({
+            "name": "detach",
+            "parameters": {},
+            "exec": {
+                "program": "/bin/sh",
+                "args_template": ["-c", "sleep 30 &"],
+                "timeout_sec": 1
+            }
+        }));
+        let started = std::time::Instant::now();
+        let err = registry.execute("detach", "{}").await.unwrap_err();
+        assert!(err.to_string().contains("tool execution timeout"));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn json_body_template_keeps_value_types() {
+        let args = serde_json::json!({"text": "hi \"there\"", "n": 3});
+        let body = fill_json_template(
+            &serde_json::json!({"msg": "{{text}}", "count": "{{n}}", "label": "n={{n}}"}),
+            &args,
+        );
+        assert_eq!(
+            body,
+            serde_json::json!({"msg": "hi \"there\"", "count": 3, "label": "n=3"})
+        );
+        assert_eq!(percent_encode("a b&c/é"), "a%20b%26c%2F%C3%A9");
+    }
+
+    #[tokio::test]
+    async fn http_tool_calls_endpoint_with_substituted_params() {
+        let server = httpmock::MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(httpmock::Method::POST)
+                    .path("/hooks/ops")
+                    .header("x-token", "secret")
+                    .json_body(serde_json::json!({"text": "deploy done", "urgent": true}));
+                then.status(201).body("x".repeat(100));
+            })
+            .await;
+        let mut registry = registry_with(serde_json::json!({
+            "name": "notify",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "channel": {"type": "string"},
+                    "text": {"type": "string"},
+                    "urgent": {"type": "boolean"}
+                }
+            },
+            "http": {
+                "method": "post",
+                "url_template": format!("{}/hooks/{{{{channel}}}}", server.base_url()),
+                "headers": {"x-token": "secret"},
+                "body_template": {"text": "{{text}}", "urgent": "{{urgent}}"}
+            }
+        }));
+        registry.max_output = 20;
+
+        let args = serde_json::json!({"channel": "ops", "text": "deploy done", "urgent": true});
+        let out = registry.execute("notify", &args.to_string()).await.unwrap();
+        mock.assert_async().await;
+        assert!(out.starts_with("HTTP status: 201\n"), "{}", out);
+        assert!(out.contains("original output was 100 bytes"), "{}", out);
+    }
+}
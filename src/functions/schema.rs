@@ -0,0 +1,234 @@
+//! Minimal JSON Schema validation for tool arguments.
+//!
+//! Covers the subset of JSON Schema that tool definitions use in practice:
+//! `type`, `properties`, `required`, `additionalProperties`, `items` and `enum`.
+//! Unknown keywords are ignored.
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+
+/// Validate `value` against `schema`, returning one message per violation.
+/// An empty vector means the value is valid.
+pub fn validate(value: &Value, schema: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    validate_at(value, schema, "$", &mut errors);
+    errors
+}
+
+/// Check that a tool's `parameters` is a usable schema: `"type": "object"` with a
+/// `properties` object whose entries each declare a `type`. A missing schema (`null`)
+/// is allowed for tools without arguments.
+pub fn validate_json_schema(schema: &Value) -> Result<()> {
+    if schema.is_null() {
+        return Ok(());
+    }
+    let Some(obj) = schema.as_object() else {
+        bail!(
+            "parameters must be a JSON object, got {}",
+            type_name(schema)
+        );
+    };
+    match obj.get("type") {
+        Some(Value::String(t)) if t == "object" => {}
+        Some(other) => bail!("parameters.type must be \"object\", got {}", other),
+        None => bail!("parameters is missing \"type\": \"object\""),
+    }
+    let Some(props) = obj.get("properties") else {
+        bail!("parameters is missing a \"properties\" object");
+    };
+    let Some(props) = props.as_object() else {
+        bail!(
+            "parameters.properties must be an object, got {}",
+            type_name(props)
+        );
+    };
+    for (name, prop) in props {
+        if prop.get("type").is_none() {
+            bail!("parameters.properties.{} has no \"type\"", name);
+        }
+    }
+    Ok(())
+}
+
+fn validate_at(value: &Value, schema: &Value, path: &str, errors: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        // `true`/`{}`/null schemas accept anything
+        return;
+    };
+
+    if let Some(ty) = schema.get("type") {
+        let allowed: Vec<&str> = match ty {
+            Value::String(s) => vec![s.as_str()],
+            Value::Array(arr) => arr.iter().filter_map(|v| v.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|t| matches_type(value, t)) {
+            errors.push(format!(
+                "{}: expected {}, got {}",
+                path,
+                allowed.join(" or "),
+                type_name(value)
+            ));
+            // Nested checks are meaningless once the type is wrong
+            return;
+        }
+    }
+
+    if let Some(options) = schema.get("enum").and_then(|v| v.as_array()) {
+        if !options.contains(value) {
+            let listed: Vec<String> = options.iter().map(|v| v.to_string()).collect();
+            errors.push(format!(
+                "{}: value {} is not one of [{}]",
+                path,
+                value,
+                listed.join(", ")
+            ));
+        }
+    }
+
+    if let Some(obj) = value.as_object() {
+        let props = schema.get("properties").and_then(|v| v.as_object());
+
+        if let Some(required) = schema.get("required").and_then(|v| v.as_array()) {
+            for name in required.iter().filter_map(|v| v.as_str()) {
+                if !obj.contains_key(name) {
+                    errors.push(format!("{}: missing required property '{}'", path, name));
+                }
+            }
+        }
+
+        for (key, val) in obj {
+            let child_path = format!("{}.{}", path, key);
+            if let Some(prop_schema) = props.and_then(|p| p.get(key)) {
+                validate_at(val, prop_schema, &child_path, errors);
+                continue;
+            }
+            match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => {
+                    errors.push(format!("{}: unexpected property '{}'", path, key));
+                }
+                Some(extra @ Value::Object(_)) => validate_at(val, extra, &child_path, errors),
+                _ => {}
+            }
+        }
+    }
+
+    if let (Some(items), Some(arr)) = (schema.get("items"), value.as_array()) {
+        for (i, item) in arr.iter().enumerate() {
+            validate_at(item, items, &format!("{}[{}]", path, i), errors);
+        }
+    }
+}
+
+fn matches_type(value: &Value, ty: &str) -> bool {
+    match ty {
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "null" => value.is_null(),
+        // Unknown type names are not ours to reject
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn cmd_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "cmd": {"type": "string"},
+                "timeout": {"type": "integer"},
+                "mode": {"type": "string", "enum": ["fast", "safe"]}
+            },
+            "required": ["cmd"]
+        })
+    }
+
+    #[test]
+    fn valid_arguments_pass() {
+        let errors = validate(&json!({"cmd": "ls", "timeout": 5}), &cmd_schema());
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    #[test]
+    fn missing_required_field_is_reported() {
+        let errors = validate(&json!({"timeout": 5}), &cmd_schema());
+        assert_eq!(errors, vec!["$: missing required property 'cmd'"]);
+    }
+
+    #[test]
+    fn wrong_type_is_reported() {
+        let errors = validate(&json!({"cmd": 42, "timeout": "soon"}), &cmd_schema());
+        assert_eq!(errors.len(), 2);
+        assert!(errors.contains(&"$.cmd: expected string, got integer".to_string()));
+        assert!(errors.contains(&"$.timeout: expected integer, got string".to_string()));
+    }
+
+    #[test]
+    fn extra_fields_allowed_unless_forbidden() {
+        let args = json!({"cmd": "ls", "verbose": true});
+        assert!(validate(&args, &cmd_schema()).is_empty());
+
+        let mut strict = cmd_schema();
+        strict["additionalProperties"] = json!(false);
+        assert_eq!(
+            validate(&args, &strict),
+            vec!["$: unexpected property 'verbose'"]
+        );
+    }
+
+    #[test]
+    fn enum_and_non_object_arguments() {
+        let errors = validate(&json!({"cmd": "ls", "mode": "yolo"}), &cmd_schema());
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("$.mode: value \"yolo\" is not one of"));
+
+        let errors = validate(&json!(["ls"]), &cmd_schema());
+        assert_eq!(errors, vec!["$: expected object, got array"]);
+    }
+
+    #[test]
+    fn tool_parameter_schemas_are_checked() {
+        assert!(validate_json_schema(&cmd_schema()).is_ok());
+        assert!(validate_json_schema(&Value::Null).is_ok());
+
+        let err = |s: Value| validate_json_schema(&s).unwrap_err().to_string();
+        assert_eq!(
+            err(json!({"type": "string"})),
+            "parameters.type must be \"object\", got \"string\""
+        );
+        assert_eq!(
+            err(json!({"type": "object"})),
+            "parameters is missing a \"properties\" object"
+        );
+        assert_eq!(
+            err(json!({"type": "object", "properties": {"cmd": {"description": "x"}}})),
+            "parameters.properties.cmd has no \"type\""
+        );
+    }
+
+    #[test]
+    fn empty_schema_accepts_anything() {
+        assert!(validate(&json!({"anything": 1}), &Value::Null).is_empty());
+        assert!(validate(&json!("x"), &json!({})).is_empty());
+    }
+}
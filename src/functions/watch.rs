@@ -0,0 +1,31 @@
+//! `--watch-functions`: notice edits to tool files while a session is running.
+
+use anyhow::{Context, Result};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::config::Config;
+
+/// Watch `OPENAI_FUNCTIONS_PATH` and call `on_change` whenever a `.json` tool file is
+/// created, modified or removed. Watching stops when the returned watcher is dropped.
+pub fn watch_functions(
+    cfg: &Config,
+    on_change: impl Fn() + Send + 'static,
+) -> Result<RecommendedWatcher> {
+    let dir = cfg.functions_path();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+        let Ok(ev) = res else {
+            return;
+        };
+        let touches_tool_file = ev
+            .paths
+            .iter()
+            .any(|p| p.extension().is_some_and(|e| e == "json"));
+        if touches_tool_file && !matches!(ev.kind, EventKind::Access(_)) {
+            on_change();
+        }
+    })?;
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("watching {}", dir.display()))?;
+    Ok(watcher)
+}
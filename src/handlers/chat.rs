@@ -8,29 +8,39 @@ use crate::config::Config;
 use crate::functions::Registry;
 use crate::llm::{ChatMessage, ChatOptions, LlmClient, Role, StreamEvent};
 use crate::llm::{FunctionCall, ToolCall, ToolSchema};
-use crate::printer::MarkdownPrinter;
-use crate::role::{resolve_role_text, DefaultRole};
+use crate::printer::{MarkdownStream, Spinner, TextPrinter};
+use crate::role::{resolve_role_text, role_messages, DefaultRole};
+use crate::utils::measure::Measure;
 
 pub async fn run(
+    client: &LlmClient,
     chat_id: &str,
     prompt: &str,
     model: &str,
     temperature: f32,
     top_p: f32,
     max_tokens: Option<u32>,
+    stop_sequences: Option<Vec<String>>,
     caching: bool,
     markdown: bool,
     allow_functions: bool,
+    default_role: DefaultRole,
     role_name: Option<&str>,
     image_parts: Option<Vec<crate::llm::ContentPart>>,
 ) -> Result<()> {
     let cfg = Config::load();
-    let client = LlmClient::from_config(&cfg)?;
     let session = ChatSession::from_config(&cfg);
     let base_url = cfg.get("API_BASE_URL").unwrap_or_else(|| "default".into());
     let req_cache = RequestCache::from_config(&cfg);
-    let registry = Registry::load(&cfg)?;
-    let system_text = resolve_role_text(&cfg, role_name, DefaultRole::Default);
+    let printer = TextPrinter::from_config(&cfg);
+    // Only spin up tools (including MCP servers) when they can actually be called
+    let mut registry = if allow_functions {
+        Registry::load(&cfg).await?
+    } else {
+        Registry::default()
+    };
+    // --chat --shell/--code/--describe-shell keep their behaviour across the session
+    let system_text = resolve_role_text(&cfg, role_name, default_role);
 
     // temp chat id shouldn't persist
     if chat_id == "temp" {
@@ -41,7 +51,11 @@ pub async fn run(
     let mut messages = if session.exists(chat_id) {
         session.read(chat_id)?
     } else {
-        vec![ChatMessage::new(Role::System, system_text)]
+        role_messages(
+            &cfg,
+            system_text,
+            role_name.is_some() || default_role != DefaultRole::Default,
+        )
     };
     if !prompt.is_empty() {
         // Create user message with optional images
@@ -62,6 +76,10 @@ pub async fn run(
         parallel_tool_calls: false,
         tool_choice: None,
         max_tokens,
+        stop_sequences,
+        presence_penalty: cfg.get_f32("PRESENCE_PENALTY"),
+        frequency_penalty: cfg.get_f32("FREQUENCY_PENALTY"),
+        resume_on_error: cfg.get_bool("STREAM_RESUME_ON_ERROR"),
     };
     if allow_functions {
         let schemas: Vec<ToolSchema> = registry.schemas();
@@ -73,33 +91,49 @@ pub async fn run(
 
     // Cache check
     if caching {
-        let key = req_cache.key_for(&base_url, model, temperature, top_p, &messages);
+        let key = req_cache.key_for(&base_url, &opts, &messages);
         if let Some(text) = req_cache.get(&key) {
-            print!("{}\n", text);
+            printer.println(&text);
             if chat_id != "temp" && !text.is_empty() {
                 let mut msgs_to_persist = messages.clone();
                 msgs_to_persist.push(ChatMessage::new(Role::Assistant, text));
-                session.write(chat_id, msgs_to_persist)?;
+                session.write(chat_id, model, msgs_to_persist)?;
             }
             return Ok(());
         }
     }
 
     let mut stream = client.chat_stream(messages.clone(), opts.clone());
+    let mut md_stream = markdown.then(|| MarkdownStream::from_config(&cfg));
+    let mut spinner = Spinner::start("Waiting for response...");
+    let mut measure = Measure::start(&cfg, model);
     let mut assistant_text = String::new();
     let mut saw_tool_calls = false;
+    let mut tool_id: Option<String> = None;
     let mut tool_name: Option<String> = None;
     let mut tool_args = String::new();
     while let Some(ev) = stream.next().await {
-        match ev? {
+        spinner.stop();
+        let ev = ev?;
+        measure.observe(&ev);
+        match ev {
             StreamEvent::Content(t) => {
                 assistant_text.push_str(&t);
-                if !markdown {
-                    print!("{}", t);
+                if let Some(md) = md_stream.as_mut() {
+                    md.push(&t);
+                } else {
+                    printer.print(&t);
                 }
             }
-            StreamEvent::ToolCallDelta { name, arguments } => {
+            StreamEvent::ToolCallDelta {
+                id,
+                name,
+                arguments,
+            } => {
                 saw_tool_calls = true;
+                if let Some(i) = id {
+                    tool_id = Some(i);
+                }
                 if let Some(n) = name {
                     tool_name = Some(n);
                 }
@@ -110,6 +144,8 @@ pub async fn run(
             StreamEvent::ToolCallsFinish => {
                 saw_tool_calls = true;
             }
+            // Kept by `measure` for SHOW_TOKEN_USAGE / --measure
+            StreamEvent::UsageReport { .. } => {}
             StreamEvent::Done => {
                 if !markdown {
                     println!();
@@ -118,23 +154,35 @@ pub async fn run(
             }
         }
     }
-    if markdown && !assistant_text.is_empty() {
-        MarkdownPrinter::default().print(&assistant_text);
+    if let Some(md) = md_stream {
+        md.finish();
     }
+    measure.report();
 
-    // Persist chat if not temp
-    if chat_id != "temp" {
+    // Persist chat if not temp; a tool round is persisted as a whole below
+    if chat_id != "temp" && !(saw_tool_calls && tool_name.is_some()) {
         if !assistant_text.is_empty() {
             messages.push(ChatMessage::new(Role::Assistant, assistant_text.clone()));
-            session.write(chat_id, messages.clone())?;
+            session.write(chat_id, model, messages.clone())?;
         }
     }
     // Tool call execution and second pass
     if saw_tool_calls {
         if let Some(name) = tool_name.clone() {
-            let mut assistant_msg = ChatMessage::new(Role::Assistant, String::new());
+            // --tool-dry-run: show the call the model asked for and stop there
+            if cfg.get_bool("TOOL_DRY_RUN") {
+                println!("[Would call: {}({})]", name, tool_args);
+                return Ok(());
+            }
+            // Any text streamed before the call belongs to the same assistant turn
+            let mut assistant_msg = ChatMessage::new(Role::Assistant, assistant_text.clone());
+            // Providers reject tool results that don't reference their call, so make sure
+            // there is an id even when the backend didn't stream one.
+            let call_id = tool_id
+                .clone()
+                .unwrap_or_else(|| format!("call_{}", messages.len()));
             assistant_msg.tool_calls = Some(vec![ToolCall {
-                id: None,
+                id: Some(call_id.clone()),
                 r#type: "function".into(),
                 function: FunctionCall {
                     name: name.clone(),
@@ -146,18 +194,43 @@ pub async fn run(
                 .execute(&name, &tool_args)
                 .await
                 .unwrap_or_else(|e| format!("tool error: {}", e));
+            if cfg.get_bool("SHOW_FUNCTIONS_OUTPUT") {
+                eprintln!("[Tool: {}] {}", name, result);
+            }
             let mut tool_msg = ChatMessage::new(Role::Tool, result);
             tool_msg.name = Some(name);
+            tool_msg.tool_call_id = Some(call_id);
             messages.push(tool_msg);
+            // Persist the tool round before the follow-up request so a failed second stream
+            // still leaves a history the provider accepts on the next turn.
+            if chat_id != "temp" {
+                session.write(chat_id, model, messages.clone())?;
+            }
+            // --watch-functions: pick up tool file edits before the follow-up request
+            if cfg.get_bool("WATCH_FUNCTIONS") {
+                if let Err(e) = registry.reload_files(&cfg.functions_path()) {
+                    eprintln!("Warning: tool reload failed: {:#}", e);
+                }
+                opts.tools = Some(registry.schemas()).filter(|s| !s.is_empty());
+                opts.tool_choice = opts.tools.as_ref().map(|_| "auto".into());
+            }
             assistant_text.clear();
             tool_args.clear();
             let mut stream2 = client.chat_stream(messages.clone(), opts.clone());
+            let mut md_stream = markdown.then(|| MarkdownStream::from_config(&cfg));
+            let mut spinner = Spinner::start("Waiting for response...");
+            let mut measure = Measure::start(&cfg, model);
             while let Some(ev) = stream2.next().await {
-                match ev? {
+                spinner.stop();
+                let ev = ev?;
+                measure.observe(&ev);
+                match ev {
                     StreamEvent::Content(t) => {
                         assistant_text.push_str(&t);
-                        if !markdown {
-                            print!("{}", t);
+                        if let Some(md) = md_stream.as_mut() {
+                            md.push(&t);
+                        } else {
+                            printer.print(&t);
                         }
                     }
                     StreamEvent::Done => {
@@ -169,18 +242,19 @@ pub async fn run(
                     _ => {}
                 }
             }
-            if markdown && !assistant_text.is_empty() {
-                MarkdownPrinter::default().print(&assistant_text);
+            if let Some(md) = md_stream {
+                md.finish();
             }
+            measure.report();
             if chat_id != "temp" && !assistant_text.is_empty() {
                 messages.push(ChatMessage::new(Role::Assistant, assistant_text.clone()));
-                session.write(chat_id, messages.clone())?;
+                session.write(chat_id, model, messages.clone())?;
             }
         }
     }
     // Write request cache last
     if caching && !assistant_text.is_empty() && !saw_tool_calls {
-        let key = req_cache.key_for(&base_url, model, temperature, top_p, &messages);
+        let key = req_cache.key_for(&base_url, &opts, &messages);
         let _ = req_cache.set(&key, &assistant_text);
     }
     Ok(())
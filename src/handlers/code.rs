@@ -1,36 +1,55 @@
 //! Code-only handler: streams code output without explanations.
 
+use std::path::Path;
+
 use anyhow::Result;
 use futures_util::StreamExt;
 
 use crate::{
     config::Config,
     llm::{ChatMessage, ChatOptions, LlmClient, Role, StreamEvent},
-    role::{default_role_text, DefaultRole},
+    printer::{Spinner, TextPrinter},
+    role::{default_role_text, role_messages, DefaultRole},
+    utils::{
+        code::{sanitize_generated_code, write_code_file},
+        measure::Measure,
+    },
 };
 
 pub async fn run(
+    client: &LlmClient,
     prompt: &str,
     model: &str,
     temperature: f32,
     top_p: f32,
     max_tokens: Option<u32>,
+    stop_sequences: Option<Vec<String>>,
+    raw: bool,
+    output_file: Option<&str>,
+    force: bool,
+    lang: Option<&str>,
     image_parts: Option<Vec<crate::llm::ContentPart>>,
 ) -> Result<()> {
     let cfg = Config::load();
-    let client = LlmClient::from_config(&cfg)?;
+    let printer = TextPrinter::from_config(&cfg);
     let role_text = default_role_text(&cfg, DefaultRole::Code);
 
+    let prompt = match lang {
+        Some(lang) => format!("{}\nWrite the code in {}.", prompt, lang),
+        None => prompt.to_string(),
+    };
+
     // Create user message with optional images
     let user_message = match image_parts {
         Some(mut parts) => {
-            parts.insert(0, crate::llm::ContentPart::text(prompt.to_string()));
+            parts.insert(0, crate::llm::ContentPart::text(prompt));
             ChatMessage::multimodal(Role::User, parts)
         }
-        None => ChatMessage::new(Role::User, prompt.to_string()),
+        None => ChatMessage::new(Role::User, prompt),
     };
 
-    let messages = vec![ChatMessage::new(Role::System, role_text), user_message];
+    let mut messages = role_messages(&cfg, role_text, true);
+    messages.push(user_message);
     let opts = ChatOptions {
         model: model.to_string(),
         temperature,
@@ -39,17 +58,46 @@ pub async fn run(
         parallel_tool_calls: false,
         tool_choice: None,
         max_tokens,
+        stop_sequences,
+        presence_penalty: cfg.get_f32("PRESENCE_PENALTY"),
+        frequency_penalty: cfg.get_f32("FREQUENCY_PENALTY"),
+        resume_on_error: cfg.get_bool("STREAM_RESUME_ON_ERROR"),
     };
 
     let mut stream = client.chat_stream(messages, opts);
+    let mut spinner = Spinner::start("Waiting for response...");
+    let mut measure = Measure::start(&cfg, model);
+    // Fences can only be stripped once the whole answer is known, so buffer unless --raw.
+    // A file target is always buffered; only its path is printed.
+    let stream_raw = raw && output_file.is_none();
+    let mut code = String::new();
     while let Some(ev) = stream.next().await {
-        match ev? {
-            StreamEvent::Content(t) => print!("{}", t),
-            StreamEvent::Done => {
-                println!();
+        let ev = ev?;
+        measure.observe(&ev);
+        match ev {
+            StreamEvent::Content(t) if stream_raw => {
+                spinner.stop();
+                printer.print(&t);
             }
+            StreamEvent::Content(t) => code.push_str(&t),
+            StreamEvent::Done => break,
             _ => {}
         }
     }
+    spinner.stop();
+    if let Some(path) = output_file {
+        let code = if raw {
+            code
+        } else {
+            sanitize_generated_code(&code)
+        };
+        let (path, bytes) = write_code_file(Path::new(path), &code, lang, force)?;
+        println!("Wrote {} bytes to {}", bytes, path.display());
+    } else if raw {
+        println!();
+    } else {
+        printer.println(&sanitize_generated_code(&code));
+    }
+    measure.report();
     Ok(())
 }
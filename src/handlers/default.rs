@@ -8,15 +8,18 @@ use crate::config::Config;
 use crate::functions::Registry;
 use crate::llm::{ChatMessage, ChatOptions, LlmClient, Role, StreamEvent};
 use crate::llm::{FunctionCall, ToolCall, ToolSchema};
-use crate::printer::MarkdownPrinter;
-use crate::role::{resolve_role_text, DefaultRole};
+use crate::printer::{MarkdownStream, Spinner, TextPrinter};
+use crate::role::{resolve_role_text, role_messages, DefaultRole};
+use crate::utils::measure::Measure;
 
 pub async fn run(
+    client: &LlmClient,
     prompt: &str,
     model: &str,
     temperature: f32,
     top_p: f32,
     max_tokens: Option<u32>,
+    stop_sequences: Option<Vec<String>>,
     caching: bool,
     markdown: bool,
     allow_functions: bool,
@@ -24,10 +27,15 @@ pub async fn run(
     image_parts: Option<Vec<crate::llm::ContentPart>>,
 ) -> Result<()> {
     let cfg = Config::load();
-    let client = LlmClient::from_config(&cfg)?;
     let base_url = cfg.get("API_BASE_URL").unwrap_or_else(|| "default".into());
     let req_cache = RequestCache::from_config(&cfg);
-    let registry = Registry::load(&cfg)?;
+    let printer = TextPrinter::from_config(&cfg);
+    // Only spin up tools (including MCP servers) when they can actually be called
+    let mut registry = if allow_functions {
+        Registry::load(&cfg).await?
+    } else {
+        Registry::default()
+    };
     let system_text = resolve_role_text(&cfg, role_name, DefaultRole::Default);
 
     // Create user message with optional images
@@ -39,7 +47,9 @@ pub async fn run(
         None => ChatMessage::new(Role::User, prompt.to_string()),
     };
 
-    let mut messages = vec![ChatMessage::new(Role::System, system_text), user_message];
+    // An explicit --role is behaviour the user asked for, so keep it even without a system role
+    let mut messages = role_messages(&cfg, system_text, role_name.is_some());
+    messages.push(user_message);
     let mut opts = ChatOptions {
         model: model.to_string(),
         temperature,
@@ -48,6 +58,10 @@ pub async fn run(
         parallel_tool_calls: false,
         tool_choice: None,
         max_tokens,
+        stop_sequences,
+        presence_penalty: cfg.get_f32("PRESENCE_PENALTY"),
+        frequency_penalty: cfg.get_f32("FREQUENCY_PENALTY"),
+        resume_on_error: cfg.get_bool("STREAM_RESUME_ON_ERROR"),
     };
     if allow_functions {
         let schemas: Vec<ToolSchema> = registry.schemas();
@@ -59,28 +73,44 @@ pub async fn run(
 
     // Cache check
     if caching {
-        let key = req_cache.key_for(&base_url, model, temperature, top_p, &messages);
+        let key = req_cache.key_for(&base_url, &opts, &messages);
         if let Some(text) = req_cache.get(&key) {
-            print!("{}\n", text);
+            printer.println(&text);
             return Ok(());
         }
     }
 
     let mut stream = client.chat_stream(messages.clone(), opts.clone());
+    let mut md_stream = markdown.then(|| MarkdownStream::from_config(&cfg));
+    let mut spinner = Spinner::start("Waiting for response...");
+    let mut measure = Measure::start(&cfg, model);
     let mut assistant_text = String::new();
     let mut saw_tool_calls = false;
+    let mut tool_id: Option<String> = None;
     let mut tool_name: Option<String> = None;
     let mut tool_args = String::new();
     while let Some(ev) = stream.next().await {
-        match ev? {
+        spinner.stop();
+        let ev = ev?;
+        measure.observe(&ev);
+        match ev {
             StreamEvent::Content(t) => {
                 assistant_text.push_str(&t);
-                if !markdown {
-                    print!("{}", t);
+                if let Some(md) = md_stream.as_mut() {
+                    md.push(&t);
+                } else {
+                    printer.print(&t);
                 }
             }
-            StreamEvent::ToolCallDelta { name, arguments } => {
+            StreamEvent::ToolCallDelta {
+                id,
+                name,
+                arguments,
+            } => {
                 saw_tool_calls = true;
+                if let Some(i) = id {
+                    tool_id = Some(i);
+                }
                 if let Some(n) = name {
                     tool_name = Some(n);
                 }
@@ -91,6 +121,8 @@ pub async fn run(
             StreamEvent::ToolCallsFinish => {
                 saw_tool_calls = true;
             }
+            // Kept by `measure` for SHOW_TOKEN_USAGE / --measure
+            StreamEvent::UsageReport { .. } => {}
             StreamEvent::Done => {
                 if !markdown {
                     println!();
@@ -100,17 +132,29 @@ pub async fn run(
         }
     }
 
-    if markdown && !assistant_text.is_empty() {
-        MarkdownPrinter::default().print(&assistant_text);
+    if let Some(md) = md_stream {
+        md.finish();
     }
 
+    measure.report();
+
     // If tool call happened, execute once and continue the conversation
     if saw_tool_calls {
         if let Some(name) = tool_name.clone() {
+            // --tool-dry-run: show the call the model asked for and stop there
+            if cfg.get_bool("TOOL_DRY_RUN") {
+                println!("[Would call: {}({})]", name, tool_args);
+                return Ok(());
+            }
             // append assistant tool_calls message
             let mut assistant_msg = ChatMessage::new(Role::Assistant, String::new());
+            // Providers reject tool results that don't reference their call, so make sure
+            // there is an id even when the backend didn't stream one.
+            let call_id = tool_id
+                .clone()
+                .unwrap_or_else(|| format!("call_{}", messages.len()));
             assistant_msg.tool_calls = Some(vec![ToolCall {
-                id: None,
+                id: Some(call_id.clone()),
                 r#type: "function".into(),
                 function: FunctionCall {
                     name: name.clone(),
@@ -123,19 +167,39 @@ pub async fn run(
                 .execute(&name, &tool_args)
                 .await
                 .unwrap_or_else(|e| format!("tool error: {}", e));
+            if cfg.get_bool("SHOW_FUNCTIONS_OUTPUT") {
+                eprintln!("[Tool: {}] {}", name, result);
+            }
             let mut tool_msg = ChatMessage::new(Role::Tool, result);
             tool_msg.name = Some(name);
+            tool_msg.tool_call_id = Some(call_id);
             messages.push(tool_msg);
+            // --watch-functions: pick up tool file edits before the follow-up request
+            if cfg.get_bool("WATCH_FUNCTIONS") {
+                if let Err(e) = registry.reload_files(&cfg.functions_path()) {
+                    eprintln!("Warning: tool reload failed: {:#}", e);
+                }
+                opts.tools = Some(registry.schemas()).filter(|s| !s.is_empty());
+                opts.tool_choice = opts.tools.as_ref().map(|_| "auto".into());
+            }
             // second call without caching
             assistant_text.clear();
             tool_args.clear();
             let mut stream2 = client.chat_stream(messages.clone(), opts.clone());
+            let mut md_stream = markdown.then(|| MarkdownStream::from_config(&cfg));
+            let mut spinner = Spinner::start("Waiting for response...");
+            let mut measure = Measure::start(&cfg, model);
             while let Some(ev) = stream2.next().await {
-                match ev? {
+                spinner.stop();
+                let ev = ev?;
+                measure.observe(&ev);
+                match ev {
                     StreamEvent::Content(t) => {
                         assistant_text.push_str(&t);
-                        if !markdown {
-                            print!("{}", t);
+                        if let Some(md) = md_stream.as_mut() {
+                            md.push(&t);
+                        } else {
+                            printer.print(&t);
                         }
                     }
                     StreamEvent::Done => {
@@ -147,14 +211,15 @@ pub async fn run(
                     _ => {}
                 }
             }
-            if markdown && !assistant_text.is_empty() {
-                MarkdownPrinter::default().print(&assistant_text);
+            if let Some(md) = md_stream {
+                md.finish();
             }
+            measure.report();
         }
     }
 
     if caching && !assistant_text.is_empty() && !saw_tool_calls {
-        let key = req_cache.key_for(&base_url, model, temperature, top_p, &messages);
+        let key = req_cache.key_for(&base_url, &opts, &messages);
         let _ = req_cache.set(&key, &assistant_text);
     }
     Ok(())
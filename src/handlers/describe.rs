@@ -1,26 +1,29 @@
 //! Describe-shell handler: streams brief description of a shell command.
 
-use crate::printer::MarkdownPrinter;
+use crate::printer::{MarkdownPrinter, Spinner, TextPrinter};
 use anyhow::Result;
 use futures_util::StreamExt;
 
 use crate::{
     config::Config,
     llm::{ChatMessage, ChatOptions, LlmClient, Role, StreamEvent},
-    role::{default_role_text, DefaultRole},
+    role::{default_role_text, role_messages, DefaultRole},
+    utils::measure::Measure,
 };
 
 pub async fn run(
+    client: &LlmClient,
     prompt: &str,
     model: &str,
     temperature: f32,
     top_p: f32,
     markdown: bool,
     max_tokens: Option<u32>,
+    stop_sequences: Option<Vec<String>>,
     image_parts: Option<Vec<crate::llm::ContentPart>>,
 ) -> Result<()> {
     let cfg = Config::load();
-    let client = LlmClient::from_config(&cfg)?;
+    let printer = TextPrinter::from_config(&cfg);
     let role_text = default_role_text(&cfg, DefaultRole::DescribeShell);
 
     // Create user message with optional images
@@ -32,7 +35,8 @@ pub async fn run(
         None => ChatMessage::new(Role::User, prompt.to_string()),
     };
 
-    let messages = vec![ChatMessage::new(Role::System, role_text), user_message];
+    let mut messages = role_messages(&cfg, role_text, true);
+    messages.push(user_message);
     let opts = ChatOptions {
         model: model.to_string(),
         temperature,
@@ -41,15 +45,24 @@ pub async fn run(
         parallel_tool_calls: false,
         tool_choice: None,
         max_tokens,
+        stop_sequences,
+        presence_penalty: cfg.get_f32("PRESENCE_PENALTY"),
+        frequency_penalty: cfg.get_f32("FREQUENCY_PENALTY"),
+        resume_on_error: cfg.get_bool("STREAM_RESUME_ON_ERROR"),
     };
 
     let mut stream = client.chat_stream(messages, opts);
+    let mut spinner = Spinner::start("Waiting for response...");
+    let mut measure = Measure::start(&cfg, model);
     let mut text = String::new();
     while let Some(ev) = stream.next().await {
-        match ev? {
+        spinner.stop();
+        let ev = ev?;
+        measure.observe(&ev);
+        match ev {
             StreamEvent::Content(t) => {
                 if !markdown {
-                    print!("{}", t)
+                    printer.print(&t)
                 } else {
                     text.push_str(&t);
                 }
@@ -63,7 +76,8 @@ pub async fn run(
         }
     }
     if markdown && !text.is_empty() {
-        MarkdownPrinter::default().print(&text);
+        MarkdownPrinter::from_config(&cfg).print(&text);
     }
+    measure.report();
     Ok(())
 }
@@ -0,0 +1,130 @@
+//! Edit handler (`--code --edit FILE`): asks for a unified diff, applies it after confirmation.
+
+use std::{
+    fs,
+    io::{self, BufRead},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context, Result};
+use futures_util::StreamExt;
+use owo_colors::OwoColorize;
+
+use crate::{
+    config::Config,
+    llm::{ChatMessage, ChatOptions, LlmClient, Role, StreamEvent},
+    printer::{color_enabled, Spinner},
+    role::{default_role_text, role_messages, DefaultRole},
+    utils::{code::sanitize_generated_code, measure::Measure, patch::apply_unified_diff, tty},
+};
+
+pub async fn run(
+    client: &LlmClient,
+    file: &str,
+    prompt: &str,
+    model: &str,
+    temperature: f32,
+    top_p: f32,
+    max_tokens: Option<u32>,
+    stop_sequences: Option<Vec<String>>,
+) -> Result<()> {
+    let cfg = Config::load();
+    let path = Path::new(file);
+    let original =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let role_text = default_role_text(&cfg, DefaultRole::EditDiff);
+
+    let request = format!(
+        "File: {}\n\n{}\n\nChange request: {}",
+        file, original, prompt
+    );
+    let mut messages = role_messages(&cfg, role_text, true);
+    messages.push(ChatMessage::new(Role::User, request));
+    let opts = ChatOptions {
+        model: model.to_string(),
+        temperature,
+        top_p,
+        tools: None,
+        parallel_tool_calls: false,
+        tool_choice: None,
+        max_tokens,
+        stop_sequences,
+        presence_penalty: cfg.get_f32("PRESENCE_PENALTY"),
+        frequency_penalty: cfg.get_f32("FREQUENCY_PENALTY"),
+        resume_on_error: cfg.get_bool("STREAM_RESUME_ON_ERROR"),
+    };
+
+    let mut stream = client.chat_stream(messages, opts);
+    let mut spinner = Spinner::start("Waiting for diff...");
+    let mut measure = Measure::start(&cfg, model);
+    let mut answer = String::new();
+    while let Some(ev) = stream.next().await {
+        let ev = ev?;
+        measure.observe(&ev);
+        match ev {
+            StreamEvent::Content(t) => answer.push_str(&t),
+            StreamEvent::Done => break,
+            _ => {}
+        }
+    }
+    spinner.stop();
+    measure.report();
+    let diff = sanitize_generated_code(&answer);
+
+    let patched = match apply_unified_diff(&original, &diff) {
+        Ok(patched) => patched,
+        Err(e) => {
+            // Leave the file alone; the diff may still be useful with `patch` or by hand
+            println!("{}", diff);
+            return Err(anyhow!(
+                "{}: the diff does not apply cleanly to {} (printed above, file left unchanged)",
+                e,
+                path.display()
+            ));
+        }
+    };
+    if patched == original {
+        println!("No changes to {}.", path.display());
+        return Ok(());
+    }
+
+    print_colored_diff(&diff);
+    eprint!("Apply changes to {}? [y/N]: ", path.display());
+    let mut answer = String::new();
+    let confirmed = tty::prompt_reader(&cfg)
+        .map(|mut input| input.read_line(&mut answer).is_ok())
+        .unwrap_or(false)
+        && matches!(answer.trim(), "y" | "Y" | "yes");
+    if !confirmed {
+        println!("Not applied.");
+        return Ok(());
+    }
+
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    fs::copy(path, &backup).with_context(|| format!("failed to back up {}", path.display()))?;
+    fs::write(path, patched).with_context(|| format!("failed to write {}", path.display()))?;
+    println!(
+        "Patched {} (backup: {})",
+        path.display(),
+        Path::new(&backup).display()
+    );
+    Ok(())
+}
+
+fn print_colored_diff(diff: &str) {
+    let color = color_enabled(&io::stdout());
+    for line in diff.lines() {
+        if !color || line.starts_with("+++") || line.starts_with("---") {
+            println!("{}", line);
+        } else if line.starts_with('+') {
+            println!("{}", line.green());
+        } else if line.starts_with('-') {
+            println!("{}", line.red());
+        } else if line.starts_with("@@") {
+            println!("{}", line.cyan());
+        } else {
+            println!("{}", line);
+        }
+    }
+}
@@ -1,17 +1,23 @@
-use anyhow::{bail, Result};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use tokio::{sync::Semaphore, task::JoinSet};
 
 use crate::{
+    cache::ChatSession,
     config::Config,
-    external::tavily::TavilyClient,
+    external::{self, SearchItem, SearchParams, SearchProvider},
     llm::{ChatMessage, ChatOptions, LlmClient, Role, StreamEvent},
     printer::MarkdownPrinter,
+    role::role_messages,
 };
 
 #[derive(Debug, Serialize, Deserialize)]
 struct SearchQuery {
     query: String,
+    #[serde(default)]
     purpose: String,
 }
 
@@ -20,64 +26,208 @@ struct SearchPlan {
     queries: Vec<SearchQuery>,
 }
 
+/// The model's verdict on a draft answer in an iterative (`--iterations`) run.
+#[derive(Debug, Deserialize)]
+struct GapCheck {
+    #[serde(default)]
+    sufficient: bool,
+    #[serde(default)]
+    missing: String,
+    #[serde(default)]
+    queries: Vec<SearchQuery>,
+}
+
 #[derive(Debug)]
 struct SearchResult {
     query: String,
     results: Vec<SearchItem>,
 }
 
-#[derive(Debug)]
-struct SearchItem {
-    title: String,
-    url: String,
-    snippet: String,
+/// The synthesis request (question plus numbered search results) and the model's answer.
+struct Synthesis {
+    prompt: String,
+    answer: String,
 }
 
+/// System message for a chat session continued from an enhanced search.
+const FOLLOW_UP_SYSTEM_PROMPT: &str = "You are a helpful assistant continuing a conversation that began with a web search. The search results are embedded above as a numbered list of sources, followed by the answer built from them. Answer follow-up questions from those sources, citing them as [n]; if they don't cover the question, say so instead of guessing.";
+
+/// Bounds for `ENHANCED_SEARCH_QUERIES` and the number of queries accepted from the model.
+const MIN_QUERIES: usize = 1;
+const MAX_QUERIES: usize = 8;
+/// Upper bound for `ENHANCED_SEARCH_ITERATIONS`.
+const MAX_ITERATIONS: usize = 5;
+
 pub struct EnhancedSearchHandler {
     llm_client: LlmClient,
-    tavily_client: TavilyClient,
+    /// Backend chosen by `SEARCH_PROVIDER`, shared with the concurrent search tasks.
+    search_provider: Arc<dyn SearchProvider>,
     markdown_enabled: bool,
+    md_printer: MarkdownPrinter,
+    /// Queries to ask the planner for (`ENHANCED_SEARCH_QUERIES`).
+    num_queries: usize,
+    /// Results fetched per query (`ENHANCED_SEARCH_RESULTS_PER_QUERY`).
+    results_per_query: usize,
+    /// Searches allowed in flight at once (`ENHANCED_SEARCH_CONCURRENCY`).
+    search_concurrency: usize,
+    /// Re-rank snippets with an extra LLM call before answering (`ENHANCED_SEARCH_RERANK`).
+    rerank: bool,
+    /// Follow-up search rounds after the first draft (`ENHANCED_SEARCH_ITERATIONS`).
+    iterations: usize,
+    /// Cap on provider calls across all rounds (`ENHANCED_SEARCH_MAX_SEARCHES`).
+    max_searches: usize,
+    /// Decides how the prompts' role text is sent (`NO_SYSTEM_ROLE`).
+    config: Config,
 }
 
 impl EnhancedSearchHandler {
-    pub fn new(config: &Config, md_enabled: bool) -> Result<Self> {
-        let llm_client = LlmClient::from_config(config)?;
-        let tavily_client = TavilyClient::from_config(config)?;
+    pub fn new(client: &LlmClient, config: &Config, md_enabled: bool) -> Result<Self> {
+        let search_provider = external::provider_from_config(config)?;
+
+        let num_queries = config
+            .get_usize("ENHANCED_SEARCH_QUERIES")
+            .unwrap_or(3)
+            .clamp(MIN_QUERIES, MAX_QUERIES);
+        let results_per_query = config
+            .get_usize("ENHANCED_SEARCH_RESULTS_PER_QUERY")
+            .unwrap_or(5)
+            .max(1);
+        let search_concurrency = config
+            .get_usize("ENHANCED_SEARCH_CONCURRENCY")
+            .unwrap_or(MAX_QUERIES)
+            .max(1);
+        let iterations = config
+            .get_usize("ENHANCED_SEARCH_ITERATIONS")
+            .unwrap_or(0)
+            .min(MAX_ITERATIONS);
+        let max_searches = config
+            .get_usize("ENHANCED_SEARCH_MAX_SEARCHES")
+            .unwrap_or(20)
+            .max(1);
 
         Ok(Self {
-            llm_client,
-            tavily_client,
+            llm_client: client.clone(),
+            search_provider,
             markdown_enabled: md_enabled,
+            md_printer: MarkdownPrinter::from_config(config),
+            num_queries,
+            results_per_query,
+            search_concurrency,
+            rerank: config.get_bool("ENHANCED_SEARCH_RERANK"),
+            iterations,
+            max_searches,
+            config: config.clone(),
         })
     }
 
     pub async fn run(
+        client: &LlmClient,
         query: &str,
         model: &str,
         temperature: Option<f32>,
         top_p: Option<f32>,
         config: &Config,
         md_enabled: bool,
+        report: Option<&str>,
+        chat_id: Option<&str>,
     ) -> Result<()> {
-        let mut handler = Self::new(config, md_enabled)?;
+        let mut handler = Self::new(client, config, md_enabled)?;
 
         println!("🔍 Step 1: Analyzing intent and building search queries...");
-        let search_plan = handler
+        let mut queries = handler
             .analyze_intent_and_build_queries(query, model, temperature, top_p)
-            .await?;
+            .await?
+            .queries;
+        queries.truncate(handler.max_searches);
 
-        println!("📊 Generated {} search queries:", search_plan.queries.len());
-        for (i, sq) in search_plan.queries.iter().enumerate() {
+        println!("📊 Generated {} search queries:", queries.len());
+        for (i, sq) in queries.iter().enumerate() {
             println!("  {}. {} ({})", i + 1, sq.query, sq.purpose);
         }
 
         println!("\n🔎 Step 2: Executing multi-dimensional search...");
-        let search_results = handler.execute_multi_search(&search_plan.queries).await?;
+        let mut search_results = handler.execute_multi_search(&queries).await?;
+
+        // Deep research: draft an answer, ask what is missing and search again
+        for round in 1..=handler.iterations {
+            let budget = handler.max_searches.saturating_sub(queries.len());
+            if budget == 0 {
+                println!(
+                    "\n💰 Search budget reached ({} searches, ENHANCED_SEARCH_MAX_SEARCHES); stopping",
+                    handler.max_searches
+                );
+                break;
+            }
+            println!(
+                "\n🧩 Round {}/{}: drafting an answer and checking what is still missing...",
+                round, handler.iterations
+            );
+            let draft = handler
+                .generate_final_answer(query, &search_results, model, temperature, top_p, false)
+                .await?;
+            let limit = budget.min(handler.num_queries);
+            let Some(gaps) = handler
+                .find_missing_information(query, &draft.answer, &queries, limit, model, top_p)
+                .await?
+            else {
+                println!("  ✅ The results cover the question; no more searching needed");
+                break;
+            };
+            if !gaps.missing.trim().is_empty() {
+                println!("  Missing: {}", gaps.missing.trim());
+            }
+            for (i, sq) in gaps.queries.iter().enumerate() {
+                println!("  {}. {} ({})", queries.len() + i + 1, sq.query, sq.purpose);
+            }
+            search_results.extend(handler.execute_multi_search(&gaps.queries).await?);
+            queries.extend(gaps.queries);
+            println!(
+                "  📚 {} unique source(s) from {} search(es) so far",
+                number_sources(&search_results).len(),
+                queries.len()
+            );
+        }
+
+        if handler.rerank {
+            println!("⚖️  Re-ranking results by relevance...");
+            search_results = handler
+                .rerank_results(query, search_results, model, top_p)
+                .await;
+        }
 
         println!("📝 Step 3: Analyzing results and generating comprehensive answer...\n");
-        handler
-            .generate_final_answer(query, &search_results, model, temperature, top_p)
+        let synthesis = handler
+            .generate_final_answer(query, &search_results, model, temperature, top_p, true)
             .await?;
+        let sources = number_sources(&search_results);
+        let answer = format!(
+            "{}{}",
+            synthesis.answer.trim_end(),
+            format_sources(&sources, &synthesis.answer, true)
+        );
+
+        if let Some(path) = report {
+            let text = format_report(query, &queries, &search_results, &answer);
+            std::fs::write(path, text)
+                .map_err(|e| anyhow!("failed to write report {}: {}", path, e))?;
+            println!("\n📄 Report saved to {}", path);
+        }
+
+        if let Some(chat_id) = chat_id {
+            let session = ChatSession::from_config(config);
+            let mut messages = if session.exists(chat_id) {
+                session.read(chat_id)?
+            } else {
+                role_messages(config, FOLLOW_UP_SYSTEM_PROMPT.to_string(), true)
+            };
+            messages.push(ChatMessage::new(Role::User, synthesis.prompt));
+            messages.push(ChatMessage::new(Role::Assistant, answer));
+            session.write(chat_id, model, messages)?;
+            println!(
+                "\n💬 Saved to chat '{}'; ask follow-ups with: sgpt --chat {} \"...\"",
+                chat_id, chat_id
+            );
+        }
 
         Ok(())
     }
@@ -89,36 +239,45 @@ impl EnhancedSearchHandler {
         temperature: Option<f32>,
         top_p: Option<f32>,
     ) -> Result<SearchPlan> {
-        let system_prompt = r#"You are a search query planning expert. Your task is to analyze the user's question and create 3 different search queries that will help gather comprehensive information to answer their question.
+        let n = self.num_queries;
+        let example = (1..=n)
+            .map(|i| {
+                format!(
+                    "    {{\"query\": \"search term {}\", \"purpose\": \"aspect this query covers\"}}",
+                    i
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",\n");
+        let system_prompt = format!(
+            r#"You are a search query planning expert. Your task is to analyze the user's question and create {n} different search queries that will help gather comprehensive information to answer their question.
+Each query will return up to {results} web results.
 
 For each search query, provide:
 1. The actual search query string
 2. A brief purpose explaining what aspect this query covers
 
 Return your response as JSON in this exact format:
-{
+{{
   "queries": [
-    {"query": "search term 1", "purpose": "covers main topic"},
-    {"query": "search term 2", "purpose": "covers related aspect"},
-    {"query": "search term 3", "purpose": "covers context/background"}
+{example}
   ]
-}
+}}
 
 Guidelines:
 - Make queries specific and focused
 - Cover different angles: main topic, related concepts, recent developments
 - Use keywords that are likely to find relevant results
-- Keep queries concise but informative"#;
+- Keep queries concise but informative"#,
+            results = self.results_per_query,
+        );
 
         let user_message = format!(
-            "Please analyze this question and create 3 search queries: {}",
-            user_query
+            "Please analyze this question and create {} search queries: {}",
+            n, user_query
         );
 
-        let messages = vec![
-            ChatMessage::new(Role::System, system_prompt.to_string()),
-            ChatMessage::new(Role::User, user_message),
-        ];
+        let mut messages = self.prompt_messages(system_prompt, user_message);
 
         let opts = ChatOptions {
             model: model.to_string(),
@@ -128,8 +287,102 @@ Guidelines:
             parallel_tool_calls: false,
             tool_choice: None,
             max_tokens: Some(1024), // Set to 1024 tokens for search query generation
+            stop_sequences: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            resume_on_error: false,
         };
 
+        let response = self.complete(messages.clone(), opts.clone()).await?;
+        let error = match parse_search_plan(&response) {
+            Ok(plan) => return Ok(plan),
+            Err(e) => e,
+        };
+
+        // One corrective round before giving up on the plan
+        messages.push(ChatMessage::new(Role::Assistant, response));
+        messages.push(ChatMessage::new(
+            Role::User,
+            format!(
+                "Your reply could not be used ({}). Reply with only the JSON object in the requested format, without code fences or any other text.",
+                error
+            ),
+        ));
+        let retried = self.complete(messages, opts).await?;
+        match parse_search_plan(&retried) {
+            Ok(plan) => Ok(plan),
+            Err(e) => {
+                println!(
+                    "  ⚠️  Could not parse the search plan ({}); searching for the question itself",
+                    e
+                );
+                Ok(SearchPlan {
+                    queries: vec![SearchQuery {
+                        query: user_query.to_string(),
+                        purpose: "original question".to_string(),
+                    }],
+                })
+            }
+        }
+    }
+
+    /// Ask whether `draft` still leaves parts of `user_query` unanswered. Returns up to `limit`
+    /// new queries for what is missing, or `None` once the model reports the results suffice
+    /// (an unparseable verdict also ends the research rather than guessing at queries).
+    async fn find_missing_information(
+        &self,
+        user_query: &str,
+        draft: &str,
+        asked: &[SearchQuery],
+        limit: usize,
+        model: &str,
+        top_p: Option<f32>,
+    ) -> Result<Option<GapCheck>> {
+        let system_prompt = format!(
+            r#"You review a draft answer to a research question and decide whether more web searches are needed.
+Reply with only this JSON object:
+{{"sufficient": true or false, "missing": "what information is still missing", "queries": [{{"query": "search term", "purpose": "gap it fills"}}]}}
+
+- Propose at most {limit} new queries that target the missing information and differ from the queries already run
+- If the draft already answers the question well, set "sufficient" to true and leave "queries" empty"#
+        );
+        let already = asked
+            .iter()
+            .map(|q| format!("- {}", q.query))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let user_message = format!(
+            "Question: {}\n\nQueries already run:\n{}\n\nDraft answer:\n{}",
+            user_query, already, draft
+        );
+        let opts = ChatOptions {
+            model: model.to_string(),
+            temperature: 0.0,
+            top_p: top_p.unwrap_or(1.0),
+            tools: None,
+            parallel_tool_calls: false,
+            tool_choice: None,
+            max_tokens: Some(1024),
+            stop_sequences: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            resume_on_error: false,
+        };
+        let messages = self.prompt_messages(system_prompt, user_message);
+        let response = self.complete(messages, opts).await?;
+        Ok(parse_gap_check(&response, asked, limit))
+    }
+
+    /// The role prompt followed by the user message; with `NO_SYSTEM_ROLE` both are user
+    /// messages.
+    fn prompt_messages(&self, system_prompt: String, user_message: String) -> Vec<ChatMessage> {
+        let mut messages = role_messages(&self.config, system_prompt, true);
+        messages.push(ChatMessage::new(Role::User, user_message));
+        messages
+    }
+
+    /// Full text of a non-interactive completion.
+    async fn complete(&self, messages: Vec<ChatMessage>, opts: ChatOptions) -> Result<String> {
         let mut stream = self.llm_client.chat_stream(messages, opts);
         let mut response = String::new();
         while let Some(ev) = futures_util::StreamExt::next(&mut stream).await {
@@ -139,80 +392,127 @@ Guidelines:
                 _ => {}
             }
         }
-
-        // Parse the JSON response
-        let search_plan: SearchPlan = serde_json::from_str(&response.trim())
-            .map_err(|e| anyhow::anyhow!("Failed to parse search plan JSON: {}", e))?;
-
-        if search_plan.queries.len() != 3 {
-            bail!(
-                "Expected exactly 3 search queries, got {}",
-                search_plan.queries.len()
-            );
-        }
-
-        Ok(search_plan)
+        Ok(response)
     }
 
     async fn execute_multi_search(&self, queries: &[SearchQuery]) -> Result<Vec<SearchResult>> {
-        let mut results = Vec::new();
-
-        for query in queries {
+        let permits = Arc::new(Semaphore::new(self.search_concurrency));
+        let mut tasks = JoinSet::new();
+        for (i, query) in queries.iter().enumerate() {
             println!("  Searching: {}", query.query);
-            match self.tavily_client.search(&query.query).await {
-                Ok(value) => {
-                    let search_items = self.parse_tavily_results(&value);
-                    results.push(SearchResult {
-                        query: query.query.clone(),
-                        results: search_items,
-                    });
+            let provider = Arc::clone(&self.search_provider);
+            let permits = Arc::clone(&permits);
+            let text = query.query.clone();
+            let params = SearchParams {
+                max_results: Some(self.results_per_query),
+            };
+            tasks.spawn(async move {
+                let _permit = permits.acquire_owned().await;
+                (i, provider.search(&text, &params).await)
+            });
+        }
+
+        // Tasks finish in any order; slot them back by query index
+        let mut slots: Vec<Option<SearchResult>> = queries.iter().map(|_| None).collect();
+        while let Some(joined) = tasks.join_next().await {
+            let (i, outcome) = joined?;
+            let query = &queries[i].query;
+            let results = match outcome {
+                Ok(mut items) => {
+                    items.truncate(self.results_per_query);
+                    println!("  ✓ {} result(s) for '{}'", items.len(), query);
+                    items
                 }
                 Err(e) => {
-                    println!("  ⚠️  Search failed for '{}': {}", query.query, e);
-                    results.push(SearchResult {
-                        query: query.query.clone(),
-                        results: Vec::new(),
-                    });
+                    println!("  ⚠️  Search failed for '{}': {}", query, e);
+                    Vec::new()
                 }
-            }
+            };
+            slots[i] = Some(SearchResult {
+                query: query.clone(),
+                results,
+            });
         }
 
+        let results: Vec<SearchResult> = slots.into_iter().flatten().collect();
+        for result in &results {
+            println!("  Results for '{}':", result.query);
+            let list = external::format_search_results(
+                None,
+                &result.results,
+                external::SearchFormat::Markdown,
+            );
+            for line in list.lines() {
+                println!("    {}", line);
+            }
+        }
         Ok(results)
     }
 
-    fn parse_tavily_results(&self, value: &Value) -> Vec<SearchItem> {
-        let mut items = Vec::new();
-
-        if let Some(results) = value.get("results").and_then(|v| v.as_array()) {
-            for item in results {
-                let title = item
-                    .get("title")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
-                let url = item
-                    .get("url")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
-                let snippet = item
-                    .get("snippet")
-                    .or_else(|| item.get("content"))
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
-
-                items.push(SearchItem {
-                    title,
-                    url,
-                    snippet,
-                });
-            }
+    /// Ask the model to order every snippet by relevance to `user_query` and merge them into
+    /// a single list, most relevant first. Falls back to the original order if the call
+    /// fails or the answer can't be parsed, since ranking is only an optimisation.
+    async fn rerank_results(
+        &self,
+        user_query: &str,
+        search_results: Vec<SearchResult>,
+        model: &str,
+        top_p: Option<f32>,
+    ) -> Vec<SearchResult> {
+        let items: Vec<SearchItem> = search_results
+            .iter()
+            .flat_map(|r| r.results.iter().cloned())
+            .collect();
+        if items.len() < 2 {
+            return search_results;
         }
 
-        items
+        let mut snippets = String::new();
+        for (i, item) in items.iter().enumerate() {
+            snippets.push_str(&format!("[{}] {}\n{}\n\n", i + 1, item.title, item.snippet));
+        }
+        let system_prompt = "You rank web search snippets. Rate the relevance of each snippet to the query on a scale of 1-10. Output a JSON array of indices in descending order of relevance, e.g. [3, 1, 2]. Output only the JSON array.";
+        let user_message = format!("Query: {}\n\nSnippets:\n\n{}", user_query, snippets);
+        let messages = self.prompt_messages(system_prompt.to_string(), user_message);
+        let opts = ChatOptions {
+            model: model.to_string(),
+            temperature: 0.0,
+            top_p: top_p.unwrap_or(1.0),
+            tools: None,
+            parallel_tool_calls: false,
+            tool_choice: None,
+            max_tokens: Some(512),
+            stop_sequences: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            resume_on_error: false,
+        };
+
+        let response = match self.complete(messages, opts).await {
+            Ok(response) => response,
+            Err(e) => {
+                println!("  ⚠️  Re-ranking failed, keeping search order: {}", e);
+                return search_results;
+            }
+        };
+        let Some(ranking) = parse_ranking(&response) else {
+            println!("  ⚠️  Could not parse the ranking, keeping search order");
+            return search_results;
+        };
+
+        let mut slots: Vec<Option<SearchItem>> = items.into_iter().map(Some).collect();
+        let ranked = rank_order(slots.len(), &ranking)
+            .into_iter()
+            .filter_map(|i| slots[i].take())
+            .collect();
+        vec![SearchResult {
+            query: "all queries, most relevant first".to_string(),
+            results: ranked,
+        }]
     }
 
+    /// Synthesize an answer from the results. With `show` the answer is streamed and
+    /// followed by its Sources list; otherwise it is only returned (a draft for the next round).
     async fn generate_final_answer(
         &mut self,
         user_query: &str,
@@ -220,7 +520,8 @@ Guidelines:
         model: &str,
         temperature: Option<f32>,
         top_p: Option<f32>,
-    ) -> Result<()> {
+        show: bool,
+    ) -> Result<Synthesis> {
         let system_prompt = r#"You are a helpful assistant that provides comprehensive answers based on web search results. 
 
 Your task:
@@ -228,23 +529,26 @@ Your task:
 2. Synthesize information from multiple sources
 3. Provide a well-structured, informative answer to the user's question
 4. Include relevant details and context
-5. Cite sources when appropriate using the format [Source: URL]
+5. Cite sources by their number in square brackets, e.g. [1] or [2][3], right after the statement they support
 
 Guidelines:
 - Be accurate and factual
+- Never write bare URLs in the answer; the source list is added for you
 - Organize information clearly
 - Highlight key points
 - Provide context and background when helpful
 - If information is conflicting, mention different perspectives"#;
 
-        // Format search results for the prompt
+        // Format search results for the prompt, numbering each source once across all queries
+        let sources = number_sources(search_results);
         let mut context = String::new();
         context.push_str("Search Results:\n\n");
 
         for (i, result) in search_results.iter().enumerate() {
             context.push_str(&format!("Query {}: {}\n", i + 1, result.query));
-            for (j, item) in result.results.iter().enumerate() {
-                context.push_str(&format!("{}. {}\n", j + 1, item.title));
+            for item in &result.results {
+                let n = sources.iter().position(|s| s.url == item.url).unwrap_or(0) + 1;
+                context.push_str(&format!("[{}] {}\n", n, item.title));
                 context.push_str(&format!("   URL: {}\n", item.url));
                 context.push_str(&format!("   Content: {}\n", item.snippet));
                 context.push_str("\n");
@@ -257,10 +561,7 @@ Guidelines:
             user_query, context
         );
 
-        let messages = vec![
-            ChatMessage::new(Role::System, system_prompt.to_string()),
-            ChatMessage::new(Role::User, user_message),
-        ];
+        let messages = self.prompt_messages(system_prompt.to_string(), user_message.clone());
 
         let opts = ChatOptions {
             model: model.to_string(),
@@ -270,6 +571,10 @@ Guidelines:
             parallel_tool_calls: false,
             tool_choice: None,
             max_tokens: Some(4096), // Much larger for comprehensive final answer
+            stop_sequences: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            resume_on_error: false,
         };
 
         let mut stream = self.llm_client.chat_stream(messages, opts);
@@ -279,7 +584,7 @@ Guidelines:
             match chunk {
                 Ok(StreamEvent::Content(content)) => {
                     assistant_text.push_str(&content);
-                    if !self.markdown_enabled {
+                    if show && !self.markdown_enabled {
                         print!("{}", content);
                     }
                 }
@@ -292,11 +597,358 @@ Guidelines:
             }
         }
 
-        if self.markdown_enabled && !assistant_text.is_empty() {
-            MarkdownPrinter::default().print(&assistant_text);
-        } else if !self.markdown_enabled {
-            println!(); // Add final newline for non-markdown
+        if show {
+            let sources_section = format_sources(&sources, &assistant_text, self.markdown_enabled);
+            if self.markdown_enabled && !assistant_text.is_empty() {
+                self.md_printer
+                    .print(&format!("{}{}", assistant_text, sources_section));
+            } else if !self.markdown_enabled {
+                println!(); // Add final newline for non-markdown
+                print!("{}", sources_section);
+            }
         }
-        Ok(())
+        Ok(Synthesis {
+            prompt: user_message,
+            answer: assistant_text,
+        })
+    }
+}
+
+/// Verdict from a gap-check reply: `None` when the model reports the results suffice, proposes
+/// nothing new or the reply can't be parsed. Blank and already-run queries are dropped and at
+/// most `limit` are kept.
+fn parse_gap_check(response: &str, asked: &[SearchQuery], limit: usize) -> Option<GapCheck> {
+    let mut check: GapCheck = serde_json::from_str(first_json_object(response)?).ok()?;
+    let mut seen: Vec<String> = asked
+        .iter()
+        .map(|q| q.query.trim().to_lowercase())
+        .collect();
+    check.queries.retain(|q| {
+        let key = q.query.trim().to_lowercase();
+        if key.is_empty() || seen.contains(&key) {
+            return false;
+        }
+        seen.push(key);
+        true
+    });
+    check.queries.truncate(limit);
+    if check.sufficient || check.queries.is_empty() {
+        None
+    } else {
+        Some(check)
+    }
+}
+
+/// Search plan from a model reply that may wrap the JSON in code fences or prose. Blank
+/// queries are dropped and at most `MAX_QUERIES` are kept; a plan with none is an error.
+fn parse_search_plan(response: &str) -> Result<SearchPlan> {
+    let json = first_json_object(response).ok_or_else(|| anyhow!("no JSON object found"))?;
+    let mut plan: SearchPlan =
+        serde_json::from_str(json).map_err(|e| anyhow!("invalid search plan JSON: {}", e))?;
+    plan.queries.retain(|q| !q.query.trim().is_empty());
+    if plan.queries.len() < MIN_QUERIES {
+        bail!("the plan has no search queries");
+    }
+    plan.queries.truncate(MAX_QUERIES);
+    Ok(plan)
+}
+
+/// The first balanced `{...}` block in `text`, ignoring braces inside JSON strings.
+fn first_json_object(text: &str) -> Option<&str> {
+    let start = text.find('{')?;
+    let (mut depth, mut in_string, mut escaped) = (0usize, false, false);
+    for (i, c) in text[start..].char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&text[start..=start + i]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Pull the JSON array of 1-based snippet numbers out of the model's answer.
+fn parse_ranking(response: &str) -> Option<Vec<usize>> {
+    let start = response.find('[')?;
+    let end = response.rfind(']')?;
+    serde_json::from_str(response.get(start..=end)?).ok()
+}
+
+/// Unique sources across all queries in order of first appearance; source `n` is `[n]`
+/// in the synthesis prompt and the answer.
+fn number_sources(search_results: &[SearchResult]) -> Vec<&SearchItem> {
+    let mut sources: Vec<&SearchItem> = Vec::new();
+    for item in search_results.iter().flat_map(|r| &r.results) {
+        if !sources.iter().any(|s| s.url == item.url) {
+            sources.push(item);
+        }
+    }
+    sources
+}
+
+/// Source numbers the answer cites as `[n]` or `[n, m]`, ignoring numbers with no source.
+fn cited_numbers(answer: &str, count: usize) -> Vec<bool> {
+    let pattern = Regex::new(r"\[(\d+(?:\s*,\s*\d+)*)\]").expect("citation pattern");
+    let mut cited = vec![false; count];
+    for caps in pattern.captures_iter(answer) {
+        for n in caps[1]
+            .split(',')
+            .filter_map(|n| n.trim().parse::<usize>().ok())
+        {
+            if let Some(slot) = n.checked_sub(1).and_then(|i| cited.get_mut(i)) {
+                *slot = true;
+            }
+        }
+    }
+    cited
+}
+
+/// "Sources" section mapping citation numbers to titles and URLs, followed by the sources
+/// the answer never cited. Empty when there were no results.
+fn format_sources(sources: &[&SearchItem], answer: &str, markdown: bool) -> String {
+    if sources.is_empty() {
+        return String::new();
+    }
+    let cited = cited_numbers(answer, sources.len());
+    let line = |n: usize, item: &SearchItem| {
+        let title = item.title.trim();
+        match (markdown, title.is_empty()) {
+            (true, true) => format!("- **[{}]** `{}`\n", n, item.url),
+            (true, false) => format!("- **[{}]** {} — `{}`\n", n, title, item.url),
+            (false, true) => format!("[{}] {}\n", n, item.url),
+            (false, false) => format!("[{}] {} - {}\n", n, title, item.url),
+        }
+    };
+    let heading = |text: &str| {
+        if markdown {
+            format!("\n\n## {}\n\n", text)
+        } else {
+            format!("\n{}:\n", text)
+        }
+    };
+
+    let mut out = String::new();
+    let (referenced, unreferenced): (Vec<_>, Vec<_>) =
+        sources.iter().enumerate().partition(|(i, _)| cited[*i]);
+    if !referenced.is_empty() {
+        out.push_str(&heading("Sources"));
+        for (i, item) in &referenced {
+            out.push_str(&line(i + 1, item));
+        }
+    }
+    if !unreferenced.is_empty() {
+        out.push_str(&heading(if referenced.is_empty() {
+            "Sources"
+        } else {
+            "Other sources consulted"
+        }));
+        for (i, item) in &unreferenced {
+            out.push_str(&line(i + 1, item));
+        }
+    }
+    out
+}
+
+/// Markdown report for `--report`: the planned queries, the sources each one returned and
+/// the answer (which already ends with its Sources list).
+fn format_report(
+    query: &str,
+    queries: &[SearchQuery],
+    search_results: &[SearchResult],
+    answer: &str,
+) -> String {
+    let sources = number_sources(search_results);
+    let mut out = format!("# {}\n\n## Search queries\n\n", query.trim());
+    for (i, sq) in queries.iter().enumerate() {
+        if sq.purpose.trim().is_empty() {
+            out.push_str(&format!("{}. {}\n", i + 1, sq.query));
+        } else {
+            out.push_str(&format!("{}. {} ({})\n", i + 1, sq.query, sq.purpose));
+        }
+    }
+    out.push_str("\n## Results\n");
+    for result in search_results {
+        out.push_str(&format!("\n### {}\n\n", result.query));
+        if result.results.is_empty() {
+            out.push_str("_No results._\n");
+        }
+        for item in &result.results {
+            let n = sources.iter().position(|s| s.url == item.url).unwrap_or(0) + 1;
+            out.push_str(&format!(
+                "- **[{}]** {} — <{}>\n",
+                n,
+                item.title.trim(),
+                item.url
+            ));
+            let snippet = item
+                .snippet
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            if !snippet.is_empty() {
+                out.push_str(&format!("  > {}\n", snippet));
+            }
+        }
+    }
+    out.push_str(&format!("\n## Answer\n\n{}\n", answer.trim_end()));
+    out
+}
+
+/// Turn a 1-based ranking into 0-based indices over `len` items. Out-of-range and repeated
+/// entries are dropped, and anything the model left out keeps its place at the end.
+fn rank_order(len: usize, ranking: &[usize]) -> Vec<usize> {
+    let mut seen = vec![false; len];
+    let mut order = Vec::with_capacity(len);
+    for &n in ranking {
+        if let Some(i) = n.checked_sub(1).filter(|&i| i < len && !seen[i]) {
+            seen[i] = true;
+            order.push(i);
+        }
+    }
+    order.extend((0..len).filter(|&i| !seen[i]));
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_plan_is_found_in_fenced_or_chatty_replies() {
+        let reply = "Sure! Here is the plan:\n```json\n{\"queries\": [{\"query\": \"rust {async}\", \"purpose\": \"a \\\"quoted\\\" }\"}, {\"query\": \"tokio\"}, {\"query\": \" \"}]}\n```\nLet me know {if} you need more.";
+        let plan = parse_search_plan(reply).unwrap();
+        let queries: Vec<&str> = plan.queries.iter().map(|q| q.query.as_str()).collect();
+        assert_eq!(queries, ["rust {async}", "tokio"]);
+        assert_eq!(plan.queries[1].purpose, "");
+
+        let many = serde_json::json!({
+            "queries": (0..12).map(|i| serde_json::json!({"query": format!("q{}", i)})).collect::<Vec<_>>()
+        });
+        assert_eq!(
+            parse_search_plan(&many.to_string()).unwrap().queries.len(),
+            MAX_QUERIES
+        );
+        assert!(parse_search_plan("{\"queries\": []}").is_err());
+        assert!(parse_search_plan("I can't help with that.").is_err());
+        assert!(parse_search_plan("{\"queries\": [").is_err());
+    }
+
+    fn item(title: &str, url: &str) -> SearchItem {
+        SearchItem {
+            title: title.to_string(),
+            url: url.to_string(),
+            snippet: String::new(),
+            score: None,
+        }
+    }
+
+    #[test]
+    fn sources_are_numbered_once_and_split_by_citation() {
+        let results = vec![
+            SearchResult {
+                query: "a".into(),
+                results: vec![item("One", "https://one"), item("Two", "https://two")],
+            },
+            SearchResult {
+                query: "b".into(),
+                results: vec![item("Two again", "https://two"), item("", "https://three")],
+            },
+        ];
+        let sources = number_sources(&results);
+        assert_eq!(sources.len(), 3);
+        assert_eq!(sources[1].title, "Two");
+
+        let answer = "Rust is fast [2]. It is safe [2, 3][9].";
+        assert_eq!(cited_numbers(answer, 3), [false, true, true]);
+        assert_eq!(
+            format_sources(&sources, answer, false),
+            "\nSources:\n[2] Two - https://two\n[3] https://three\n\
+             \nOther sources consulted:\n[1] One - https://one\n"
+        );
+        assert!(format_sources(&sources, "no citations", true)
+            .starts_with("\n\n## Sources\n\n- **[1]** One — `https://one`\n"));
+        assert_eq!(format_sources(&[], answer, true), "");
+    }
+
+    #[test]
+    fn report_lists_queries_sources_and_answer() {
+        let queries = vec![SearchQuery {
+            query: "rust speed".into(),
+            purpose: "benchmarks".into(),
+        }];
+        let mut hit = item("Fast", "https://fast");
+        hit.snippet = "Rust is\n  fast.".into();
+        let results = vec![
+            SearchResult {
+                query: "rust speed".into(),
+                results: vec![hit],
+            },
+            SearchResult {
+                query: "rust safety".into(),
+                results: vec![],
+            },
+        ];
+        let report = format_report("Is Rust fast?", &queries, &results, "Yes [1].\n");
+        assert_eq!(
+            report,
+            "# Is Rust fast?\n\n## Search queries\n\n1. rust speed (benchmarks)\n\n## Results\n\
+             \n### rust speed\n\n- **[1]** Fast — <https://fast>\n  > Rust is fast.\n\
+             \n### rust safety\n\n_No results._\n\n## Answer\n\nYes [1].\n"
+        );
+    }
+
+    #[test]
+    fn gap_check_drops_repeated_queries_and_stops_when_sufficient() {
+        let asked = vec![SearchQuery {
+            query: "Rust speed".into(),
+            purpose: String::new(),
+        }];
+        let reply = "```json\n{\"sufficient\": false, \"missing\": \"memory use\", \"queries\": [{\"query\": \"rust speed \"}, {\"query\": \"rust memory\"}, {\"query\": \"Rust memory\"}, {\"query\": \"rust binary size\"}]}\n```";
+        let check = parse_gap_check(reply, &asked, 1).unwrap();
+        assert_eq!(check.missing, "memory use");
+        let queries: Vec<&str> = check.queries.iter().map(|q| q.query.as_str()).collect();
+        assert_eq!(queries, ["rust memory"]);
+
+        assert!(parse_gap_check(
+            "{\"sufficient\": true, \"queries\": [{\"query\": \"x\"}]}",
+            &asked,
+            3
+        )
+        .is_none());
+        assert!(parse_gap_check(
+            "{\"sufficient\": false, \"queries\": [{\"query\": \"rust speed\"}]}",
+            &asked,
+            3
+        )
+        .is_none());
+        assert!(parse_gap_check("not sure", &asked, 3).is_none());
+    }
+
+    #[test]
+    fn ranking_is_parsed_from_noisy_answers() {
+        assert_eq!(parse_ranking("[3, 1, 2]"), Some(vec![3, 1, 2]));
+        assert_eq!(parse_ranking("```json\n[2,1]\n```"), Some(vec![2, 1]));
+        assert_eq!(parse_ranking("no idea"), None);
+    }
+
+    #[test]
+    fn rank_order_ignores_bad_indices_and_keeps_missing_items() {
+        assert_eq!(rank_order(4, &[3, 1, 3, 0, 9]), vec![2, 0, 1, 3]);
+        assert_eq!(rank_order(2, &[]), vec![0, 1]);
     }
 }
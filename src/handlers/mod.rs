@@ -4,6 +4,7 @@ pub mod chat;
 pub mod code;
 pub mod default;
 pub mod describe;
+pub mod edit;
 pub mod enhanced_search;
 pub mod repl;
 pub mod shell;
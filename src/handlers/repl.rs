@@ -3,11 +3,13 @@
 use anyhow::Result;
 use std::io;
 
+use crate::llm::LlmClient;
 use crate::process::InterpreterType;
 use crate::tui::run_tui_repl;
 
 /// Run REPL mode with TUI interface
 pub async fn run(
+    client: &LlmClient,
     chat_id: &str,
     init_prompt: Option<&str>,
     model: &str,
@@ -19,6 +21,7 @@ pub async fn run(
     allow_interaction: bool,
     role_name: Option<&str>,
     interpreter: Option<InterpreterType>,
+    allow_functions: bool,
 ) -> Result<()> {
     // Check if TUI mode is available
     if !io::IsTerminal::is_terminal(&io::stdout()) {
@@ -32,6 +35,7 @@ pub async fn run(
     }
 
     run_tui_repl(
+        client,
         chat_id,
         init_prompt,
         model,
@@ -43,6 +47,7 @@ pub async fn run(
         allow_interaction,
         role_name,
         interpreter,
+        allow_functions,
     )
     .await
 }
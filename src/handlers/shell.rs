@@ -1,41 +1,67 @@
 //! Shell command handler with interactive flow.
 
-use std::io::{self, Write};
+use std::io::{self, BufRead, Write};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use futures_util::StreamExt;
+use owo_colors::OwoColorize;
 
 use crate::{
     config::Config,
     llm::{ChatMessage, ChatOptions, LlmClient, Role, StreamEvent},
-    role::{resolve_role_text, DefaultRole},
-    utils::run_command,
+    printer::{color_enabled, Spinner, TextPrinter},
+    role::{resolve_role_text, role_messages, DefaultRole},
+    utils::{
+        clipboard,
+        command::{run_command_captured, CommandOutput},
+        danger::DangerChecker,
+        run_command,
+        shell_history::{HistoryAction, ShellHistory},
+        tty,
+    },
 };
 
+/// Only the tail of a failed command's output is sent back; errors are usually at the end.
+const FIX_OUTPUT_TAIL_CHARS: usize = 4000;
+
 /// Generate shell command for a prompt and optionally interact/execute.
 pub async fn run(
+    client: &LlmClient,
     prompt: &str,
     model: &str,
     temperature: f32,
     top_p: f32,
     max_tokens: Option<u32>,
+    stop_sequences: Option<Vec<String>>,
     no_interaction: bool,
     auto_execute: bool,
+    copy: bool,
+    allow_dangerous: bool,
     image_parts: Option<Vec<crate::llm::ContentPart>>,
 ) -> Result<()> {
     let cfg = Config::load();
-    let client = LlmClient::from_config(&cfg)?;
-    let role_text = resolve_role_text(&cfg, None, DefaultRole::Shell);
+    let printer = TextPrinter::from_config(&cfg);
+    let role_msgs = role_messages(
+        &cfg,
+        resolve_role_text(&cfg, None, DefaultRole::Shell),
+        true,
+    );
     let default_exec = cfg.get_bool("DEFAULT_EXECUTE_SHELL_CMD");
+    let fix_loop = cfg.get_bool("SHELL_FIX_LOOP");
+    let max_fixes = cfg.get_usize("SHELL_FIX_MAX_ATTEMPTS").unwrap_or(3);
+    let danger = DangerChecker::from_config(&cfg);
+    let history = ShellHistory::from_config(&cfg);
 
     // Helper to ask LLM for a command based on a user prompt
     async fn gen_cmd(
         client: &LlmClient,
-        role_text: &str,
+        cfg: &Config,
+        role_msgs: &[ChatMessage],
         model: &str,
         temperature: f32,
         top_p: f32,
         max_tokens: Option<u32>,
+        stop_sequences: Option<Vec<String>>,
         user_prompt: String,
         image_parts: Option<Vec<crate::llm::ContentPart>>,
     ) -> Result<String> {
@@ -48,10 +74,8 @@ pub async fn run(
             None => ChatMessage::new(Role::User, user_prompt),
         };
 
-        let messages = vec![
-            ChatMessage::new(Role::System, role_text.to_string()),
-            user_message,
-        ];
+        let mut messages = role_msgs.to_vec();
+        messages.push(user_message);
         let opts = ChatOptions {
             model: model.to_string(),
             temperature,
@@ -60,10 +84,16 @@ pub async fn run(
             parallel_tool_calls: false,
             tool_choice: None,
             max_tokens,
+            stop_sequences,
+            presence_penalty: cfg.get_f32("PRESENCE_PENALTY"),
+            frequency_penalty: cfg.get_f32("FREQUENCY_PENALTY"),
+            resume_on_error: false,
         };
         let mut stream = client.chat_stream(messages, opts);
+        let mut spinner = Spinner::start("Waiting for response...");
         let mut cmd = String::new();
         while let Some(ev) = stream.next().await {
+            spinner.stop();
             if let StreamEvent::Content(t) = ev? {
                 cmd.push_str(&t);
             }
@@ -72,35 +102,72 @@ pub async fn run(
     }
 
     let mut cmd = gen_cmd(
-        &client,
-        &role_text,
+        client,
+        &cfg,
+        &role_msgs,
         model,
         temperature,
         top_p,
         max_tokens,
+        stop_sequences.clone(),
         prompt.to_string(),
         image_parts.clone(),
     )
     .await?;
-    println!("{}", cmd);
-    if no_interaction {
-        if auto_execute {
-            run_command(&cmd);
+    printer.println(&cmd);
+    // Held until the handler returns, so a copied command outlives the process
+    let mut clipboard = clipboard::Clipboard::for_cli();
+    // Piped stdin (e.g. the shell integration) still gets the menu via the controlling terminal
+    let input = if no_interaction {
+        None
+    } else {
+        tty::prompt_reader(&cfg)
+    };
+    let Some(mut input) = input else {
+        // Copying means the user wants to edit the command first, so don't run it
+        if copy {
+            copy_command(&mut clipboard, &cmd);
+            history.record(prompt, &cmd, HistoryAction::Copied, None);
+        } else if auto_execute {
+            if let Some(rule) = danger.check(&cmd) {
+                warn_dangerous(&rule.name);
+                if !allow_dangerous {
+                    history.record(prompt, &cmd, HistoryAction::Refused, None);
+                    bail!("refusing to auto-execute a dangerous command (pass --i-know-what-im-doing to override)");
+                }
+            }
+            let code = run_command(&cmd);
+            history.record(prompt, &cmd, HistoryAction::Executed, code);
+        } else {
+            history.record(prompt, &cmd, HistoryAction::NotExecuted, None);
         }
         return Ok(());
+    };
+    if copy {
+        copy_command(&mut clipboard, &cmd);
     }
 
     // Interactive loop until execute or abort
+    let mut failed: Option<CommandOutput> = None;
+    let mut fixes = 0usize;
     loop {
+        let can_fix = failed.is_some() && fixes < max_fixes;
+        let fix_entry = if can_fix { "[F]ix, " } else { "" };
         let prompt_str = if default_exec {
-            "[E]xecute, [M]odify, [D]escribe, [A]bort (Enter=Execute): "
+            format!(
+                "[E]xecute, [M]odify, [D]escribe, [C]opy, {}[A]bort (Enter=Execute): ",
+                fix_entry
+            )
         } else {
-            "[E]xecute, [M]odify, [D]escribe, [A]bort: "
+            format!(
+                "[E]xecute, [M]odify, [D]escribe, [C]opy, {}[A]bort: ",
+                fix_entry
+            )
         };
         print!("{}", prompt_str);
         io::stdout().flush().ok();
         let mut choice = String::new();
-        io::stdin().read_line(&mut choice)?;
+        input.read_line(&mut choice)?;
         let c = choice.trim().to_lowercase();
         let c = if c.is_empty() && default_exec {
             "e".to_string()
@@ -108,36 +175,105 @@ pub async fn run(
             c
         };
 
+        if matches!(c.as_str(), "e" | "y") {
+            if let Some(rule) = danger.check(&cmd) {
+                warn_dangerous(&rule.name);
+                print!("Type 'yes' to run it anyway: ");
+                io::stdout().flush().ok();
+                let mut answer = String::new();
+                input.read_line(&mut answer)?;
+                if answer.trim() != "yes" {
+                    continue;
+                }
+            }
+        }
+
         match c.as_str() {
+            "e" | "y" if fix_loop => {
+                let output = run_command_captured(&cmd);
+                history.record(prompt, &cmd, HistoryAction::Executed, output.code);
+                if output.success() {
+                    break;
+                }
+                match output.code {
+                    Some(code) => eprintln!("Command failed with exit code {}.", code),
+                    None => eprintln!("Command failed."),
+                }
+                if fixes >= max_fixes {
+                    eprintln!("Fix attempt limit ({}) reached.", max_fixes);
+                }
+                failed = Some(output);
+            }
             "e" | "y" => {
-                run_command(&cmd);
+                let code = run_command(&cmd);
+                history.record(prompt, &cmd, HistoryAction::Executed, code);
                 break;
             }
+            "f" if can_fix => {
+                let Some(output) = failed.take() else {
+                    continue;
+                };
+                fixes += 1;
+                cmd = gen_cmd(
+                    client,
+                    &cfg,
+                    &role_msgs,
+                    model,
+                    temperature,
+                    top_p,
+                    max_tokens,
+                    stop_sequences.clone(),
+                    fix_prompt(prompt, &cmd, &output),
+                    image_parts.clone(),
+                )
+                .await?;
+                // Shown for confirmation only; it runs when the user picks [E]xecute
+                printer.println(&cmd);
+            }
             "d" => {
-                super::describe::run(&cmd, model, temperature, top_p, false, max_tokens, None)
-                    .await?;
+                super::describe::run(
+                    client,
+                    &cmd,
+                    model,
+                    temperature,
+                    top_p,
+                    false,
+                    max_tokens,
+                    None,
+                    None,
+                )
+                .await?;
                 // After describe, show prompt again
             }
+            "c" => {
+                copy_command(&mut clipboard, &cmd);
+                history.record(prompt, &cmd, HistoryAction::Copied, None);
+            }
             "m" => {
                 print!("Modify with instructions: ");
                 io::stdout().flush().ok();
                 let mut add = String::new();
-                io::stdin().read_line(&mut add)?;
+                input.read_line(&mut add)?;
                 let refine = format!("{}\n\n{}", prompt, add.trim());
+                history.record(prompt, &cmd, HistoryAction::Modified, None);
+                failed = None;
                 cmd = gen_cmd(
-                    &client,
-                    &role_text,
+                    client,
+                    &cfg,
+                    &role_msgs,
                     model,
                     temperature,
                     top_p,
                     max_tokens,
+                    stop_sequences.clone(),
                     refine,
                     image_parts.clone(),
                 )
                 .await?;
-                println!("{}", cmd);
+                printer.println(&cmd);
             }
             _ => {
+                history.record(prompt, &cmd, HistoryAction::Aborted, None);
                 break;
             } // Abort on anything else
         }
@@ -145,3 +281,76 @@ pub async fn run(
 
     Ok(())
 }
+
+fn warn_dangerous(rule: &str) {
+    let msg = format!("Warning: this command matches the dangerous rule: {}", rule);
+    if color_enabled(&io::stderr()) {
+        eprintln!("{}", msg.red().bold());
+    } else {
+        eprintln!("{}", msg);
+    }
+}
+
+/// Copy a command to the clipboard, reporting failures without aborting.
+fn copy_command(clipboard: &mut clipboard::Clipboard, cmd: &str) {
+    match clipboard.copy(cmd) {
+        Ok(clipboard::CopyTarget::System) => println!("Copied to clipboard."),
+        Ok(clipboard::CopyTarget::Terminal) => {
+            println!("Copied to clipboard via terminal (OSC 52).")
+        }
+        Err(e) => eprintln!("Copy failed: {}", e),
+    }
+}
+
+/// Ask for a corrected command, including the failed command's exit code and output.
+fn fix_prompt(request: &str, cmd: &str, output: &CommandOutput) -> String {
+    let code = output
+        .code
+        .map_or_else(|| "unknown".to_string(), |c| c.to_string());
+    format!(
+        "{}\n\nThe command `{}` failed with exit code {} and this output.\n\
+         stdout:\n{}\n\nstderr:\n{}\n\nProvide a corrected command.",
+        request,
+        cmd,
+        code,
+        tail(&output.stdout, FIX_OUTPUT_TAIL_CHARS),
+        tail(&output.stderr, FIX_OUTPUT_TAIL_CHARS)
+    )
+}
+
+fn tail(text: &str, max_chars: usize) -> &str {
+    let count = text.chars().count();
+    if count <= max_chars {
+        return text.trim_end();
+    }
+    let start = text
+        .char_indices()
+        .nth(count - max_chars)
+        .map_or(0, |(i, _)| i);
+    text[start..].trim_end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fix_prompt_includes_failure_details() {
+        let output = CommandOutput {
+            code: Some(2),
+            stdout: String::new(),
+            stderr: "ls: cannot access 'nope'\n".into(),
+        };
+        let p = fix_prompt("list nope", "ls nope", &output);
+        assert!(p.starts_with("list nope\n\n"));
+        assert!(p.contains("`ls nope` failed with exit code 2"));
+        assert!(p.contains("stderr:\nls: cannot access 'nope'\n"));
+    }
+
+    #[test]
+    fn tail_keeps_the_end() {
+        assert_eq!(tail("abcdef", 3), "def");
+        assert_eq!(tail("日本語テキスト", 2), "スト");
+        assert_eq!(tail("short\n", 10), "short");
+    }
+}
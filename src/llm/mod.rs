@@ -1,6 +1,13 @@
 //! Reqwest-based LLM client implementing OpenAI-compatible Chat Completions streaming and Responses API.
 
-use std::{pin::Pin, time::Duration};
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use anyhow::{Context, Result};
 use async_stream::try_stream;
@@ -23,6 +30,18 @@ pub enum Role {
     Developer, // New role for Responses API
 }
 
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+            Role::Developer => "developer",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum MessageContent {
@@ -54,6 +73,8 @@ pub struct ChatMessage {
     pub name: Option<String>, // for tool messages if needed
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_calls: Option<Vec<ToolCall>>, // for assistant with tool_calls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>, // for tool results, matches ToolCall::id
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -74,6 +95,7 @@ pub struct ToolSchema {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolCall {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<String>,
     #[serde(rename = "type")]
     pub r#type: String, // "function"
@@ -95,6 +117,14 @@ pub struct ChatOptions {
     pub parallel_tool_calls: bool,
     pub tool_choice: Option<String>, // e.g., "auto"
     pub max_tokens: Option<u32>,
+    /// Strings that end generation when the model produces one of them (sent as `stop`).
+    pub stop_sequences: Option<Vec<String>>,
+    /// -2.0..=2.0; positive values push the model towards new topics.
+    pub presence_penalty: Option<f32>,
+    /// -2.0..=2.0; positive values discourage repeating the same lines.
+    pub frequency_penalty: Option<f32>,
+    /// Re-send the request and continue the reply if the connection drops mid-stream.
+    pub resume_on_error: bool,
 }
 
 // New structures for Responses API (feature-gated)
@@ -297,6 +327,7 @@ impl ChatMessage {
             content: MessageContent::text(content),
             name: None,
             tool_calls: None,
+            tool_call_id: None,
         }
     }
 
@@ -307,6 +338,7 @@ impl ChatMessage {
             content: MessageContent::multimodal(parts),
             name: None,
             tool_calls: None,
+            tool_call_id: None,
         }
     }
 
@@ -337,7 +369,7 @@ impl ChatMessage {
 }
 
 /// Simple base64 encoding function
-fn base64_encode(data: &[u8]) -> String {
+pub(crate) fn base64_encode(data: &[u8]) -> String {
     const CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
     let mut result = String::new();
 
@@ -366,13 +398,46 @@ fn base64_encode(data: &[u8]) -> String {
     result
 }
 
+/// Clones share the underlying `reqwest::Client` and its connection pool, so build one per
+/// process and pass it around rather than calling `from_config` per request.
 #[derive(Debug, Clone)]
 pub struct LlmClient {
     http: reqwest::Client,
     base_url: String,
     api_key: Option<String>,
+    /// Set for Azure OpenAI (`AZURE_OPENAI_ENDPOINT`): the model names the deployment in the
+    /// path, this is sent as `api-version` and the key goes in an `api-key` header.
+    azure_api_version: Option<String>,
+    /// Ask for a final usage chunk (`stream_options.include_usage`), for `SHOW_TOKEN_USAGE`
+    /// and `--measure`. Off by default since some compatible backends reject the field.
+    include_usage: bool,
+    /// `DISABLE_STREAMING`: send plain requests for backends without SSE support.
+    disable_streaming: bool,
+    /// `NO_SYSTEM_ROLE`: instructions the client adds itself (the resume hint) go in as a
+    /// user message.
+    no_system_role: bool,
+    /// `--print-system-role`: show the system prompt of the next request on stderr.
+    /// Shared between clones, so it is printed once per run.
+    print_system_role: Arc<AtomicBool>,
+    /// `--dry-run`: print each request body and fail with [`DryRun`] instead of sending it.
+    dry_run: bool,
+}
+
+/// Returned instead of a reply under `--dry-run`, after the request body was printed.
+#[derive(Debug)]
+pub struct DryRun;
+
+impl std::fmt::Display for DryRun {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("dry run: request not sent")
+    }
 }
 
+impl std::error::Error for DryRun {}
+
+/// `api-version` used with `AZURE_OPENAI_ENDPOINT` when `AZURE_API_VERSION` is unset.
+const DEFAULT_AZURE_API_VERSION: &str = "2024-02-01";
+
 #[cfg(feature = "responses-api")]
 #[expect(dead_code)]
 impl ResponseOptions {
@@ -479,14 +544,114 @@ impl LlmClient {
         let http = reqwest::Client::builder()
             .timeout(Duration::from_secs(timeout))
             .build()?;
+        let include_usage = cfg.get_bool("SHOW_TOKEN_USAGE") || cfg.get_bool("MEASURE_LATENCY");
+        let disable_streaming = cfg.get_bool("DISABLE_STREAMING");
+        let no_system_role = cfg.get_bool("NO_SYSTEM_ROLE");
+
+        // Azure OpenAI replaces API_BASE_URL: https://<resource>.openai.azure.com/openai/deployments/...
+        if let Some(endpoint) = cfg
+            .get("AZURE_OPENAI_ENDPOINT")
+            .filter(|e| !e.trim().is_empty())
+        {
+            let endpoint = endpoint.trim().trim_end_matches('/');
+            let endpoint = endpoint.strip_suffix("/openai").unwrap_or(endpoint);
+            let api_version = cfg
+                .get("AZURE_API_VERSION")
+                .filter(|v| !v.trim().is_empty())
+                .unwrap_or_else(|| DEFAULT_AZURE_API_VERSION.to_string());
+            return Ok(Self {
+                http,
+                base_url: format!("{}/openai/deployments", endpoint),
+                api_key,
+                azure_api_version: Some(api_version.trim().to_string()),
+                include_usage,
+                disable_streaming,
+                no_system_role,
+                print_system_role: Arc::default(),
+                dry_run: false,
+            });
+        }
 
         Ok(Self {
             http,
             base_url,
             api_key,
+            azure_api_version: None,
+            include_usage,
+            disable_streaming,
+            no_system_role,
+            print_system_role: Arc::default(),
+            dry_run: false,
         })
     }
 
+    /// Print the system prompt of the next request to stderr (`--print-system-role`).
+    pub fn with_print_system_role(self, enabled: bool) -> Self {
+        self.print_system_role.store(enabled, Ordering::SeqCst);
+        self
+    }
+
+    /// Print request bodies instead of sending them (`--dry-run`).
+    pub fn with_dry_run(mut self, enabled: bool) -> Self {
+        self.dry_run = enabled;
+        self
+    }
+
+    /// `--print-system-role`: print the system prompt in `messages`, the first time only.
+    pub fn show_system_role(&self, messages: &[ChatMessage]) {
+        if self.print_system_role.swap(false, Ordering::SeqCst) {
+            eprintln!("{}", system_role_banner(messages));
+        }
+    }
+
+    /// Body of a `/chat/completions` request, streamed or not. Under `--dry-run` the body
+    /// is printed and [`DryRun`] returned instead.
+    fn request_body(
+        &self,
+        messages: &[ChatMessage],
+        opts: &ChatOptions,
+        stream: bool,
+    ) -> Result<serde_json::Value> {
+        self.show_system_role(messages);
+        let mut body = chat_body(messages, opts)?;
+        if !stream {
+            if let Some(fields) = body.as_object_mut() {
+                fields.remove("stream");
+            }
+        } else if self.include_usage {
+            body["stream_options"] = serde_json::json!({ "include_usage": true });
+        }
+        if self.dry_run {
+            println!("{}", serde_json::to_string_pretty(&body)?);
+            return Err(DryRun.into());
+        }
+        Ok(body)
+    }
+
+    /// URL of the API `path` (e.g. `chat/completions`) for `model`.
+    fn endpoint(&self, model: &str, path: &str) -> String {
+        let base = self.base_url.trim_end_matches('/');
+        match &self.azure_api_version {
+            Some(version) => format!("{}/{}/{}?api-version={}", base, model, path, version),
+            None => format!("{}/{}", base, path),
+        }
+    }
+
+    /// JSON content type plus the key as `Authorization: Bearer` (or `api-key` on Azure).
+    fn request_headers(&self) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        if let Some(key) = &self.api_key {
+            if self.azure_api_version.is_some() {
+                headers.insert("api-key", HeaderValue::from_str(key)?);
+            } else {
+                let hv = HeaderValue::from_str(&format!("Bearer {}", key))?;
+                headers.insert(AUTHORIZATION, hv);
+            }
+        }
+        Ok(headers)
+    }
+
     /// Create a response using the Responses API (non-streaming)
     #[cfg(feature = "responses-api")]
     pub async fn create_response(
@@ -499,14 +664,8 @@ impl LlmClient {
             return Ok(self.fake_response(input, opts));
         }
 
-        let url = format!("{}/responses", self.base_url.trim_end_matches('/'));
-
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-        if let Some(key) = &self.api_key {
-            let hv = HeaderValue::from_str(&format!("Bearer {}", key))?;
-            headers.insert(AUTHORIZATION, hv);
-        }
+        let url = self.endpoint(&opts.model, "responses");
+        let headers = self.request_headers()?;
 
         let mut body = serde_json::json!({
             "model": opts.model
@@ -652,6 +811,48 @@ impl LlmClient {
         }
     }
 
+    /// Error for a failed request, with the provider's payload and actionable hints
+    /// (e.g. tools 422) for easier debugging.
+    fn status_error(status: reqwest::StatusCode, text: &str) -> anyhow::Error {
+        let mut msg = String::new();
+        let snippet: String = text.chars().take(800).collect();
+        msg.push_str(&snippet);
+
+        let code = status.as_u16();
+        let lower = text.to_lowercase();
+        let mut hints: Vec<&str> = Vec::new();
+        if code == 401 {
+            hints.push("Set OPENAI_API_KEY or export it in your shell");
+        }
+        if code == 422 || code == 400 {
+            if lower.contains("tool_choice")
+                || lower.contains("parallel_tool_calls")
+                || lower.contains("\"tools\"")
+                || lower.contains("function_call")
+                || lower.contains("tool calls")
+            {
+                hints.push("Your backend may not support OpenAI tools; retry without --functions or set OPENAI_USE_FUNCTIONS=false");
+            }
+            if lower.contains("model")
+                && (lower.contains("not found")
+                    || lower.contains("unknown")
+                    || lower.contains("invalid"))
+            {
+                hints.push("Check model name via --model or set DEFAULT_MODEL appropriately for your provider");
+            }
+        }
+        if lower.contains("rate limit") || lower.contains("quota") {
+            hints.push("You may be rate limited; retry later or reduce concurrency");
+        }
+
+        if !hints.is_empty() {
+            msg.push_str("\nHint: ");
+            msg.push_str(&hints.join("; "));
+        }
+
+        Self::enhance_multimodal_error(anyhow::anyhow!("LLM error: {} {}", status, msg))
+    }
+
     /// Check if an error indicates multimodal/vision API incompatibility and enhance error message
     fn enhance_multimodal_error(error: anyhow::Error) -> anyhow::Error {
         let error_str = error.to_string().to_lowercase();
@@ -682,42 +883,143 @@ impl LlmClient {
         messages: Vec<ChatMessage>,
         opts: ChatOptions,
     ) -> Pin<Box<dyn Stream<Item = Result<StreamEvent>> + Send>> {
-        // Check for fake mode
+        // Check for fake mode; the flags that inspect the request still apply
         if opts.model.to_lowercase() == "fake" {
+            if let Err(e) = self.request_body(&messages, &opts, true) {
+                return Box::pin(futures_util::stream::iter([Err(e)]));
+            }
             return Box::pin(self.fake_stream(messages, opts));
         }
+        // Callers still get a stream: the whole reply arrives as one chunk
+        if self.disable_streaming {
+            let client = self.clone();
+            return Box::pin(try_stream! {
+                let (text, usage) = client.completion(messages, opts).await?;
+                if !text.is_empty() {
+                    yield StreamEvent::Content(text);
+                }
+                if let Some((prompt_tokens, completion_tokens)) = usage {
+                    yield StreamEvent::UsageReport { prompt_tokens, completion_tokens };
+                }
+                yield StreamEvent::Done;
+            });
+        }
+        if !opts.resume_on_error {
+            return Box::pin(self.request_stream(messages, opts));
+        }
 
-        let http = self.http.clone();
-        let base_url = self.base_url.clone();
-        let api_key = self.api_key.clone();
-
+        let client = self.clone();
         Box::pin(try_stream! {
-            let url = format!("{}/chat/completions", base_url.trim_end_matches('/'));
-
-            let mut headers = HeaderMap::new();
-            headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-            headers.insert(reqwest::header::ACCEPT, HeaderValue::from_static("text/event-stream"));
-            if let Some(key) = api_key.clone() {
-                let hv = HeaderValue::from_str(&format!("Bearer {}", key))?;
-                headers.insert(AUTHORIZATION, hv);
-            }
+            use futures_util::StreamExt as _;
 
-            let mut body = serde_json::json!({
-                "model": opts.model,
-                "temperature": opts.temperature,
-                "top_p": opts.top_p,
-                "messages": messages,
-                "stream": true,
-                "max_tokens": opts.max_tokens.unwrap_or(512)
-            });
+            // Content already handed to the caller, across all attempts
+            let mut emitted = String::new();
+            let mut attempt: u64 = 0;
+            loop {
+                let mut attempt_messages = messages.clone();
+                if !emitted.is_empty() {
+                    let role = if client.no_system_role { Role::User } else { Role::System };
+                    attempt_messages.push(ChatMessage::new(
+                        role,
+                        format!(
+                            "continue_from: your previous reply was cut off by a network error after the text below. \
+                             Continue exactly where it stops without repeating any of it.\n\n{}",
+                            emitted
+                        ),
+                    ));
+                }
+                let mut filter = ResumeFilter::new(emitted.clone());
+                let mut inner = Box::pin(client.request_stream(attempt_messages, opts.clone()));
+                let mut saw_tool_call = false;
+                let mut failure = None;
+                while let Some(ev) = inner.next().await {
+                    match ev {
+                        Ok(StreamEvent::Content(t)) => {
+                            if let Some(fresh) = filter.feed(&t) {
+                                emitted.push_str(&fresh);
+                                yield StreamEvent::Content(fresh);
+                            }
+                        }
+                        Ok(StreamEvent::Done) => {
+                            yield StreamEvent::Done;
+                            return;
+                        }
+                        Ok(ev @ StreamEvent::UsageReport { .. }) => yield ev,
+                        Ok(ev) => {
+                            saw_tool_call = true;
+                            yield ev;
+                        }
+                        Err(e) => {
+                            failure = Some(e);
+                            break;
+                        }
+                    }
+                }
 
-            if let Some(tools) = &opts.tools {
-                body["tools"] = serde_json::to_value(tools)?;
-                body["parallel_tool_calls"] = serde_json::json!(opts.parallel_tool_calls);
-                if let Some(choice) = &opts.tool_choice {
-                    body["tool_choice"] = serde_json::json!(choice);
+                let Some(e) = failure else { return; };
+                attempt += 1;
+                // Tool call deltas can't be stitched together, and HTTP errors won't go away on retry
+                let is_transport = e.chain().any(|c| c.downcast_ref::<reqwest::Error>().is_some());
+                if saw_tool_call || !is_transport || attempt > MAX_STREAM_RESUMES {
+                    Err(e)?;
                 }
+                tokio::time::sleep(Duration::from_millis(500 * attempt)).await;
             }
+        })
+    }
+
+    /// One plain (non-SSE) request against `/chat/completions`, returning the reply text.
+    #[expect(dead_code)]
+    pub async fn chat_non_streaming(
+        &self,
+        messages: Vec<ChatMessage>,
+        opts: ChatOptions,
+    ) -> Result<String> {
+        Ok(self.completion(messages, opts).await?.0)
+    }
+
+    /// [`Self::chat_non_streaming`] plus the `(prompt, completion)` token usage when the
+    /// server reports it.
+    async fn completion(
+        &self,
+        messages: Vec<ChatMessage>,
+        opts: ChatOptions,
+    ) -> Result<(String, Option<(u32, u32)>)> {
+        let url = self.endpoint(&opts.model, "chat/completions");
+        let body = self.request_body(&messages, &opts, false)?;
+
+        let resp = self
+            .http
+            .post(url)
+            .headers(self.request_headers()?)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| Self::enhance_multimodal_error(anyhow::Error::from(e)))
+            .context("failed to send chat request")?;
+        let status = resp.status();
+        let text = resp.text().await.context("failed to read chat response")?;
+        if !status.is_success() {
+            return Err(Self::status_error(status, &text));
+        }
+        completion_text(&text)
+    }
+
+    /// Single streaming request against `/chat/completions`.
+    fn request_stream(
+        &self,
+        messages: Vec<ChatMessage>,
+        opts: ChatOptions,
+    ) -> impl Stream<Item = Result<StreamEvent>> + Send {
+        let http = self.http.clone();
+        let url = self.endpoint(&opts.model, "chat/completions");
+        let headers = self.request_headers();
+        let body = self.request_body(&messages, &opts, true);
+
+        try_stream! {
+            let mut headers = headers?;
+            headers.insert(reqwest::header::ACCEPT, HeaderValue::from_static("text/event-stream"));
+            let body = body?;
 
             let resp = http
                 .post(url)
@@ -734,38 +1036,13 @@ impl LlmClient {
             if !status.is_success() {
                 // Include provider error payload + actionable hints (e.g., tools 422) for easier debugging
                 let text = resp_opt.take().unwrap().text().await.unwrap_or_default();
-                let mut msg = String::new();
-                let snippet: String = text.chars().take(800).collect();
-                msg.push_str(&snippet);
-
-                let code = status.as_u16();
-                let lower = text.to_lowercase();
-                let mut hints: Vec<&str> = Vec::new();
-                if code == 401 {
-                    hints.push("Set OPENAI_API_KEY or export it in your shell");
-                }
-                if code == 422 || code == 400 {
-                    if lower.contains("tool_choice") || lower.contains("parallel_tool_calls") || lower.contains("\"tools\"") || lower.contains("function_call") || lower.contains("tool calls") {
-                        hints.push("Your backend may not support OpenAI tools; retry without --functions or set OPENAI_USE_FUNCTIONS=false");
-                    }
-                    if lower.contains("model") && (lower.contains("not found") || lower.contains("unknown") || lower.contains("invalid")) {
-                        hints.push("Check model name via --model or set DEFAULT_MODEL appropriately for your provider");
-                    }
-                }
-                if lower.contains("rate limit") || lower.contains("quota") {
-                    hints.push("You may be rate limited; retry later or reduce concurrency");
-                }
-
-                if !hints.is_empty() {
-                    msg.push_str("\nHint: ");
-                    msg.push_str(&hints.join("; "));
-                }
-
-                let llm_error = anyhow::anyhow!("LLM error: {} {}", status, msg);
-                Err(Self::enhance_multimodal_error(llm_error))?;
+                Err(Self::status_error(status, &text))?;
             }
 
             let mut buf = String::new();
+            let mut sse = SseDecoder::default();
+            // Providers may repeat running totals; only the last report is passed on, right before Done
+            let mut usage = None;
             let mut stream = resp_opt.take().unwrap().bytes_stream();
             use futures_util::StreamExt as _;
 
@@ -774,43 +1051,38 @@ impl LlmClient {
                 buf.push_str(&String::from_utf8_lossy(&bytes));
                 // process lines
                 while let Some(pos) = buf.find('\n') {
-                    let mut line = buf[..pos].to_string();
-                    buf = buf[pos+1..].to_string();
-                    line = line.trim().to_string();
-                    if line.is_empty() || line.starts_with(":") { continue; }
-                    if let Some(payload) = line.strip_prefix("data:") {
-                        let payload = payload.trim();
-                        if payload == "[DONE]" { yield StreamEvent::Done; return; }
-                        match serde_json::from_str::<Chunk>(payload) {
-                            Ok(chunk) => {
-                                for choice in chunk.choices.into_iter() {
-                                    if let Some(delta) = choice.delta {
-                                        if let Some(content) = delta.content {
-                                            if !content.is_empty() {
-                                                yield StreamEvent::Content(content);
-                                            }
-                                        }
-                                        if let Some(tcalls) = delta.tool_calls {
-                                            for t in tcalls.into_iter() {
-                                                let name = t.function.as_ref().and_then(|f| f.name.clone());
-                                                let args = t.function.as_ref().and_then(|f| f.arguments.clone());
-                                                yield StreamEvent::ToolCallDelta { name, arguments: args };
-                                            }
-                                        }
-                                    }
-                                    if let Some(fr) = choice.finish_reason {
-                                        if fr == "tool_calls" { yield StreamEvent::ToolCallsFinish; }
-                                    }
-                                }
-                            }
-                            Err(_e) => {
-                                // ignore malformed lines
+                    let line: String = buf.drain(..=pos).collect();
+                    if let Some(payload) = sse.push_line(&line) {
+                        if payload == "[DONE]" {
+                            if let Some(report) = usage.take() { yield report; }
+                            yield StreamEvent::Done;
+                            return;
+                        }
+                        for event in chunk_events(&payload) {
+                            match event {
+                                StreamEvent::UsageReport { .. } => usage = Some(event),
+                                event => yield event,
                             }
                         }
                     }
                 }
             }
-        })
+            // A body that ends without a final newline or blank line still holds one event
+            if let Some(payload) = sse.push_line(&buf).or_else(|| sse.push_line("")) {
+                if payload != "[DONE]" {
+                    for event in chunk_events(&payload) {
+                        match event {
+                            StreamEvent::UsageReport { .. } => usage = Some(event),
+                            event => yield event,
+                        }
+                    }
+                }
+                if let Some(report) = usage.take() { yield report; }
+                if payload == "[DONE]" { yield StreamEvent::Done; }
+            } else if let Some(report) = usage.take() {
+                yield report;
+            }
+        }
     }
 
     /// Create a fake stream that outputs the request content instead of calling the API
@@ -849,6 +1121,12 @@ impl LlmClient {
                 tokio::time::sleep(std::time::Duration::from_millis(20)).await;
             }
 
+            // Rough counts (~4 characters per token) so usage display can be tried offline
+            let prompt_chars: usize = messages.iter().map(|m| m.content.extract_text().len()).sum();
+            yield StreamEvent::UsageReport {
+                prompt_tokens: (prompt_chars / 4) as u32,
+                completion_tokens: (response.len() / 4) as u32,
+            };
             yield StreamEvent::Done;
         }
     }
@@ -923,17 +1201,230 @@ fn generate_fake_chat_response(user_input: &str) -> String {
     }
 }
 
+/// `choices[0].message.content` and `(prompt, completion)` token usage of a non-streaming
+/// chat completion.
+fn completion_text(body: &str) -> Result<(String, Option<(u32, u32)>)> {
+    let completion: Completion =
+        serde_json::from_str(body).context("unexpected chat completion response")?;
+    let usage = completion
+        .usage
+        .map(|u| (u.prompt_tokens, u.completion_tokens));
+    let message = completion
+        .choices
+        .into_iter()
+        .next()
+        .map(|c| c.message)
+        .ok_or_else(|| anyhow::anyhow!("chat completion response has no choices"))?;
+    let content = message.content.unwrap_or_default();
+    if content.is_empty() && !message.tool_calls.is_empty() {
+        anyhow::bail!(
+            "the model requested a tool call, which needs streaming; unset DISABLE_STREAMING or run without --functions"
+        );
+    }
+    Ok((content, usage))
+}
+
+/// `--print-system-role` output for the system message in `messages`, if any.
+fn system_role_banner(messages: &[ChatMessage]) -> String {
+    let text = messages
+        .iter()
+        .find(|m| m.role == Role::System)
+        .map(|m| m.content.extract_text());
+    format!(
+        "--- system role ---\n{}\n-------------------",
+        text.as_deref().unwrap_or("(none)")
+    )
+}
+
+/// JSON body of a streaming `/chat/completions` request.
+fn chat_body(messages: &[ChatMessage], opts: &ChatOptions) -> Result<serde_json::Value> {
+    let mut body = serde_json::json!({
+        "model": opts.model,
+        "temperature": opts.temperature,
+        "top_p": opts.top_p,
+        "messages": messages,
+        "stream": true,
+        "max_tokens": opts.max_tokens.unwrap_or(512)
+    });
+
+    if let Some(stop) = opts.stop_sequences.as_ref().filter(|s| !s.is_empty()) {
+        body["stop"] = serde_json::json!(stop);
+    }
+    if let Some(penalty) = opts.presence_penalty {
+        body["presence_penalty"] = serde_json::json!(penalty);
+    }
+    if let Some(penalty) = opts.frequency_penalty {
+        body["frequency_penalty"] = serde_json::json!(penalty);
+    }
+
+    if let Some(tools) = &opts.tools {
+        body["tools"] = serde_json::to_value(tools)?;
+        body["parallel_tool_calls"] = serde_json::json!(opts.parallel_tool_calls);
+        if let Some(choice) = &opts.tool_choice {
+            body["tool_choice"] = serde_json::json!(choice);
+        }
+    }
+    Ok(body)
+}
+
+const MAX_STREAM_RESUMES: u64 = 3;
+
+/// Drops the part of a resumed reply that repeats what was already shown.
+///
+/// Models asked to continue either pick up where they stopped or start over; in the latter
+/// case the new stream is held back while it still matches the old text.
+struct ResumeFilter {
+    already: String,
+    pending: String,
+    passing: bool,
+}
+
+impl ResumeFilter {
+    fn new(already: String) -> Self {
+        let passing = already.is_empty();
+        Self {
+            already,
+            pending: String::new(),
+            passing,
+        }
+    }
+
+    fn feed(&mut self, chunk: &str) -> Option<String> {
+        if self.passing {
+            return Some(chunk.to_string());
+        }
+        self.pending.push_str(chunk);
+        if self.already.starts_with(&self.pending) {
+            self.passing = self.pending.len() == self.already.len();
+            return None;
+        }
+        self.passing = true;
+        let pending = std::mem::take(&mut self.pending);
+        // Whatever the restart shares with the old text was shown already, including when it
+        // rewords the rest; nothing shared means the model continued rather than restarting
+        let shared = pending
+            .char_indices()
+            .zip(self.already.chars())
+            .find(|((_, a), b)| a != b)
+            .map(|((i, _), _)| i)
+            .unwrap_or_else(|| pending.len().min(self.already.len()));
+        let fresh = &pending[shared..];
+        (!fresh.is_empty()).then(|| fresh.to_string())
+    }
+}
+
 #[derive(Debug)]
 pub enum StreamEvent {
     Content(String),
     ToolCallDelta {
+        id: Option<String>,
         name: Option<String>,
         arguments: Option<String>,
     },
     ToolCallsFinish,
+    /// Token counts from the provider's final chunk; sent just before `Done` when available.
+    UsageReport {
+        prompt_tokens: u32,
+        completion_tokens: u32,
+    },
     Done,
 }
 
+/// Line-by-line decoder for `text/event-stream` bodies that tolerates provider quirks:
+/// `data:` with or without a space, `event:`/`id:`/`retry:` lines and comments (skipped),
+/// and `data` split over several lines. A payload is released at the blank line ending
+/// its event, or as soon as it is complete JSON for streams that omit the blank lines.
+#[derive(Default)]
+struct SseDecoder {
+    data: String,
+}
+
+impl SseDecoder {
+    /// Feed one line (its newline may be included); returns a payload once one is complete.
+    fn push_line(&mut self, line: &str) -> Option<String> {
+        let line = line.trim();
+        if line.is_empty() {
+            return self.take();
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        if field.trim() != "data" {
+            return None;
+        }
+        if !self.data.is_empty() {
+            self.data.push('\n');
+        }
+        self.data.push_str(value.trim());
+        let pending = self.data.trim();
+        if pending == "[DONE]" || serde_json::from_str::<serde::de::IgnoredAny>(pending).is_ok() {
+            return self.take();
+        }
+        None
+    }
+
+    fn take(&mut self) -> Option<String> {
+        let data = std::mem::take(&mut self.data);
+        let data = data.trim();
+        (!data.is_empty()).then(|| data.to_string())
+    }
+}
+
+/// Events carried by one chat completion chunk; malformed payloads yield nothing.
+fn chunk_events(payload: &str) -> Vec<StreamEvent> {
+    let Ok(chunk) = serde_json::from_str::<Chunk>(payload) else {
+        return Vec::new();
+    };
+    let mut events = Vec::new();
+    for choice in chunk.choices {
+        if let Some(delta) = choice.delta {
+            if let Some(content) = delta.content.filter(|c| !c.is_empty()) {
+                events.push(StreamEvent::Content(content));
+            }
+            for t in delta.tool_calls.unwrap_or_default() {
+                let name = t.function.as_ref().and_then(|f| f.name.clone());
+                let arguments = t.function.as_ref().and_then(|f| f.arguments.clone());
+                events.push(StreamEvent::ToolCallDelta {
+                    id: t.id,
+                    name,
+                    arguments,
+                });
+            }
+        }
+        if choice.finish_reason.as_deref() == Some("tool_calls") {
+            events.push(StreamEvent::ToolCallsFinish);
+        }
+    }
+    if let Some(usage) = chunk.usage {
+        events.push(StreamEvent::UsageReport {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+        });
+    }
+    events
+}
+
+// Non-streaming completion (`DISABLE_STREAMING`)
+#[derive(Debug, Deserialize)]
+struct Completion {
+    #[serde(default)]
+    choices: Vec<CompletionChoice>,
+    usage: Option<ChunkUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionChoice {
+    message: CompletionMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionMessage {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<serde_json::Value>,
+}
+
 // Minimal chunk structures for OpenAI-like streaming
 #[derive(Debug, Deserialize)]
 struct Chunk {
@@ -941,7 +1432,18 @@ struct Chunk {
     id: Option<String>,
     #[allow(dead_code)]
     model: Option<String>,
+    // The usage chunk may have an empty or missing `choices`
+    #[serde(default)]
     choices: Vec<Choice>,
+    usage: Option<ChunkUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChunkUsage {
+    #[serde(default)]
+    prompt_tokens: u32,
+    #[serde(default)]
+    completion_tokens: u32,
 }
 
 #[derive(Debug, Deserialize)]
@@ -960,6 +1462,7 @@ struct Delta {
 
 #[derive(Debug, Deserialize)]
 struct ToolCallDeltaPart {
+    id: Option<String>,
     function: Option<FunctionDeltaPart>,
 }
 
@@ -968,3 +1471,250 @@ struct FunctionDeltaPart {
     name: Option<String>,
     arguments: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(base_url: &str, azure_api_version: Option<&str>) -> LlmClient {
+        LlmClient {
+            http: reqwest::Client::new(),
+            base_url: base_url.to_string(),
+            api_key: Some("secret".into()),
+            azure_api_version: azure_api_version.map(str::to_string),
+            include_usage: false,
+            disable_streaming: false,
+            no_system_role: false,
+            print_system_role: Arc::default(),
+            dry_run: false,
+        }
+    }
+
+    #[test]
+    fn dry_run_stops_before_sending_and_system_role_prints_once() {
+        let opts = ChatOptions {
+            model: "gpt-4o".into(),
+            temperature: 0.0,
+            top_p: 1.0,
+            tools: None,
+            parallel_tool_calls: false,
+            tool_choice: None,
+            max_tokens: None,
+            stop_sequences: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            resume_on_error: false,
+        };
+        let messages = [
+            ChatMessage::new(Role::System, "Be brief."),
+            ChatMessage::new(Role::User, "hi"),
+        ];
+        assert_eq!(
+            system_role_banner(&messages),
+            "--- system role ---\nBe brief.\n-------------------"
+        );
+        assert!(system_role_banner(&messages[1..]).contains("(none)"));
+
+        let plain = client("http://127.0.0.1:9/v1", None).with_print_system_role(true);
+        let body = plain.request_body(&messages, &opts, false).unwrap();
+        assert!(body.get("stream").is_none());
+        assert!(!plain.print_system_role.load(Ordering::SeqCst));
+
+        let dry = client("http://127.0.0.1:9/v1", None).with_dry_run(true);
+        let err = dry.request_body(&messages, &opts, true).unwrap_err();
+        assert!(err.downcast_ref::<DryRun>().is_some());
+    }
+
+    #[test]
+    fn azure_endpoint_puts_the_deployment_in_the_path_and_key_in_api_key() {
+        let azure = client(
+            "https://res.openai.azure.com/openai/deployments",
+            Some("2024-02-01"),
+        );
+        assert_eq!(
+            azure.endpoint("gpt-4o", "chat/completions"),
+            "https://res.openai.azure.com/openai/deployments/gpt-4o/chat/completions?api-version=2024-02-01"
+        );
+        let headers = azure.request_headers().unwrap();
+        assert_eq!(headers["api-key"], "secret");
+        assert!(!headers.contains_key(AUTHORIZATION));
+
+        let openai = client("https://api.openai.com/v1/", None);
+        assert_eq!(
+            openai.endpoint("gpt-4o", "chat/completions"),
+            "https://api.openai.com/v1/chat/completions"
+        );
+        let headers = openai.request_headers().unwrap();
+        assert_eq!(headers[AUTHORIZATION], "Bearer secret");
+        assert!(!headers.contains_key("api-key"));
+    }
+
+    fn decode(body: &str) -> Vec<String> {
+        let mut sse = SseDecoder::default();
+        let mut out: Vec<String> = body.lines().filter_map(|l| sse.push_line(l)).collect();
+        out.extend(sse.push_line(""));
+        out
+    }
+
+    #[test]
+    fn chat_body_sends_stop_only_when_given() {
+        let mut opts = ChatOptions {
+            model: "gpt-4o".into(),
+            temperature: 0.0,
+            top_p: 1.0,
+            tools: None,
+            parallel_tool_calls: false,
+            tool_choice: None,
+            max_tokens: None,
+            stop_sequences: Some(Vec::new()),
+            presence_penalty: None,
+            frequency_penalty: None,
+            resume_on_error: false,
+        };
+        let messages = [ChatMessage::new(Role::User, "hi")];
+        assert!(chat_body(&messages, &opts).unwrap().get("stop").is_none());
+
+        opts.stop_sequences = Some(vec!["if __name__".into(), "```".into()]);
+        let body = chat_body(&messages, &opts).unwrap();
+        assert_eq!(body["stop"], serde_json::json!(["if __name__", "```"]));
+    }
+
+    #[test]
+    fn chat_body_sends_penalties_only_when_set() {
+        let mut opts = ChatOptions {
+            model: "gpt-4o".into(),
+            temperature: 0.0,
+            top_p: 1.0,
+            tools: None,
+            parallel_tool_calls: false,
+            tool_choice: None,
+            max_tokens: None,
+            stop_sequences: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            resume_on_error: false,
+        };
+        let messages = [ChatMessage::new(Role::User, "hi")];
+        let body = chat_body(&messages, &opts).unwrap();
+        assert!(body.get("presence_penalty").is_none());
+        assert!(body.get("frequency_penalty").is_none());
+
+        opts.presence_penalty = Some(0.5);
+        opts.frequency_penalty = Some(-1.0);
+        let body = chat_body(&messages, &opts).unwrap();
+        assert_eq!(body["presence_penalty"], serde_json::json!(0.5));
+        assert_eq!(body["frequency_penalty"], serde_json::json!(-1.0));
+    }
+
+    #[test]
+    fn chunk_events_reports_usage_from_a_choiceless_chunk() {
+        let payload = r#"{"choices":[],"usage":{"prompt_tokens":12,"completion_tokens":34,"total_tokens":46}}"#;
+        assert!(matches!(
+            chunk_events(payload).as_slice(),
+            [StreamEvent::UsageReport {
+                prompt_tokens: 12,
+                completion_tokens: 34
+            }]
+        ));
+        // Some providers leave `choices` out of the usage chunk
+        assert_eq!(
+            chunk_events(r#"{"usage":{"prompt_tokens":1,"completion_tokens":2}}"#).len(),
+            1
+        );
+        // ... and others send `usage: null` on every chunk
+        assert!(chunk_events(r#"{"choices":[],"usage":null}"#).is_empty());
+    }
+
+    #[test]
+    fn completion_text_reads_the_first_choice() {
+        let body = r#"{"id":"x","choices":[{"index":0,"message":{"role":"assistant","content":"Hello"},"finish_reason":"stop"}],"usage":{"prompt_tokens":3,"completion_tokens":1}}"#;
+        assert_eq!(
+            completion_text(body).unwrap(),
+            ("Hello".to_string(), Some((3, 1)))
+        );
+        assert_eq!(
+            completion_text(r#"{"choices":[{"message":{"content":"Hi"}}]}"#).unwrap(),
+            ("Hi".to_string(), None)
+        );
+        assert!(completion_text(r#"{"choices":[]}"#).is_err());
+        let tool_only = r#"{"choices":[{"message":{"content":null,"tool_calls":[{"id":"c1","type":"function","function":{"name":"f","arguments":"{}"}}]}}]}"#;
+        assert!(completion_text(tool_only)
+            .unwrap_err()
+            .to_string()
+            .contains("DISABLE_STREAMING"));
+    }
+
+    #[test]
+    fn sse_data_prefix_with_or_without_space() {
+        assert_eq!(
+            decode("data: {\"a\":1}\n\ndata:{\"b\":2}\n\ndata:[DONE]\n\n"),
+            ["{\"a\":1}", "{\"b\":2}", "[DONE]"]
+        );
+    }
+
+    #[test]
+    fn sse_event_id_retry_and_comment_lines_are_skipped() {
+        assert_eq!(
+            decode(": keep-alive\nevent: message\nid: 7\nretry: 1000\ndata: {\"a\":1}\n\n"),
+            ["{\"a\":1}"]
+        );
+    }
+
+    #[test]
+    fn sse_multi_line_data_is_joined_until_the_blank_line() {
+        assert_eq!(
+            decode("data: {\"a\":\ndata: 1}\n\ndata: {\"b\":2}\n"),
+            ["{\"a\":\n1}", "{\"b\":2}"]
+        );
+    }
+
+    #[test]
+    fn sse_lines_without_blank_separators_and_crlf() {
+        assert_eq!(
+            decode("data: {\"a\":1}\r\ndata: {\"b\":2}\r\ndata: [DONE]"),
+            ["{\"a\":1}", "{\"b\":2}", "[DONE]"]
+        );
+    }
+
+    #[test]
+    fn chunk_events_maps_content_tool_calls_and_finish() {
+        let events = chunk_events(
+            r#"{"choices":[{"delta":{"content":"Hi","tool_calls":[{"id":"c1","function":{"name":"ls","arguments":"{}"}}]},"finish_reason":"tool_calls"}]}"#,
+        );
+        assert!(matches!(&events[0], StreamEvent::Content(c) if c == "Hi"));
+        assert!(
+            matches!(&events[1], StreamEvent::ToolCallDelta { id: Some(id), .. } if id == "c1")
+        );
+        assert!(matches!(events[2], StreamEvent::ToolCallsFinish));
+        assert!(chunk_events("not json").is_empty());
+    }
+
+    #[test]
+    fn resume_filter_skips_repeated_prefix() {
+        let mut f = ResumeFilter::new("Hello wor".into());
+        assert_eq!(f.feed("Hel"), None);
+        assert_eq!(f.feed("lo w"), None);
+        assert_eq!(f.feed("orld!"), Some("ld!".into()));
+        assert_eq!(f.feed(" Bye"), Some(" Bye".into()));
+    }
+
+    #[test]
+    fn resume_filter_passes_true_continuation() {
+        let mut f = ResumeFilter::new("Hello wor".into());
+        assert_eq!(f.feed("ld"), Some("ld".into()));
+
+        let mut fresh = ResumeFilter::new(String::new());
+        assert_eq!(fresh.feed("abc"), Some("abc".into()));
+    }
+
+    #[test]
+    fn resume_filter_drops_shared_prefix_of_diverging_restart() {
+        let mut f = ResumeFilter::new("Hello wor".into());
+        assert_eq!(f.feed("Hello "), None);
+        assert_eq!(f.feed("there"), Some("there".into()));
+        assert_eq!(f.feed("!"), Some("!".into()));
+
+        let mut multibyte = ResumeFilter::new("née".into());
+        assert_eq!(multibyte.feed("nàe"), Some("àe".into()));
+    }
+}
@@ -13,11 +13,11 @@ mod role;
 mod tui;
 mod utils;
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use config::Config;
 use is_terminal::IsTerminal;
 use role::{DefaultRole, SystemRole};
-use std::io::{self, Read};
+use std::io::{self, BufRead, Read};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -25,18 +25,80 @@ async fn main() -> Result<()> {
 
     // Optional: override target shell via CLI before loading config
     if let Some(ts) = args.target_shell.as_deref() {
-        // Normalize common values
-        let lower = ts.to_ascii_lowercase();
-        let norm_owned = match lower.as_str() {
-            "pwsh" | "powershell" | "powershell.exe" => "powershell.exe".to_string(),
-            "cmd" | "cmd.exe" => "cmd.exe".to_string(),
-            other => other.to_string(),
-        };
-        std::env::set_var("SHELL_NAME", norm_owned);
+        std::env::set_var("SHELL_NAME", role::normalize_shell_name(ts));
+    }
+
+    if let Some(width) = args.width {
+        std::env::set_var("MARKDOWN_WIDTH", width.to_string());
+    }
+    if let Some(color) = args.color.as_deref() {
+        std::env::set_var("DEFAULT_COLOR", color);
+    }
+    for (key, value, flag) in [
+        (
+            "PRESENCE_PENALTY",
+            args.presence_penalty,
+            "--presence-penalty",
+        ),
+        (
+            "FREQUENCY_PENALTY",
+            args.frequency_penalty,
+            "--frequency-penalty",
+        ),
+    ] {
+        if let Some(v) = value {
+            if !(-2.0..=2.0).contains(&v) {
+                bail!("{} must be between -2.0 and 2.0", flag);
+            }
+            std::env::set_var(key, v.to_string());
+        }
+    }
+    if let Some(n) = args.num_queries {
+        std::env::set_var("ENHANCED_SEARCH_QUERIES", n.to_string());
+    }
+    if let Some(n) = args.iterations {
+        std::env::set_var("ENHANCED_SEARCH_ITERATIONS", n.to_string());
+    }
+    if args.no_color {
+        std::env::set_var("NO_COLOR", "1");
+    }
+    if args.watch_functions {
+        std::env::set_var("WATCH_FUNCTIONS", "true");
+    }
+    if args.tool_dry_run {
+        std::env::set_var("TOOL_DRY_RUN", "true");
+    }
+    if args.fresh {
+        std::env::set_var("SEARCH_FRESH", "true");
+    }
+    if args.fix_loop {
+        std::env::set_var("SHELL_FIX_LOOP", "true");
+    }
+    if args.measure {
+        std::env::set_var("MEASURE_LATENCY", "true");
+    }
+    if args.no_system_role {
+        std::env::set_var("NO_SYSTEM_ROLE", "true");
+    }
+
+    // Per-invocation tool filters take precedence over the config file
+    if !args.tool.is_empty() {
+        std::env::set_var("FUNCTIONS_ALLOWLIST", args.tool.join(","));
+    }
+    if !args.exclude_tool.is_empty() {
+        std::env::set_var("FUNCTIONS_DENYLIST", args.exclude_tool.join(","));
     }
 
     // Load config
     let cfg = Config::load();
+    // Values from the config file get the same range check as the flags above
+    for key in ["PRESENCE_PENALTY", "FREQUENCY_PENALTY"] {
+        if let Some(raw) = cfg.get(key).filter(|v| !v.trim().is_empty()) {
+            if !cfg.get_f32(key).is_some_and(|v| (-2.0..=2.0).contains(&v)) {
+                bail!("{} must be between -2.0 and 2.0 (got '{}')", key, raw);
+            }
+        }
+    }
     // Ensure default roles exist
     let _ = SystemRole::create_defaults(&cfg);
 
@@ -47,6 +109,24 @@ async fn main() -> Result<()> {
         .or_else(|| cfg.get("DEFAULT_MODEL"))
         .unwrap_or_else(|| "gpt-4o".to_string());
 
+    // Resolve sampling: CLI flag, then the mode-specific config value, then the global default
+    let mode_temperature_key = if args.shell {
+        Some("SHELL_TEMPERATURE")
+    } else if args.code {
+        Some("CODE_TEMPERATURE")
+    } else {
+        None
+    };
+    let temperature = args
+        .temperature
+        .or_else(|| mode_temperature_key.and_then(|k| cfg.get_f32(k)))
+        .or_else(|| cfg.get_f32("DEFAULT_TEMPERATURE"))
+        .unwrap_or(0.0);
+    let top_p = args
+        .top_p
+        .or_else(|| cfg.get_f32("DEFAULT_TOP_P"))
+        .unwrap_or(1.0);
+
     // stdin handling (pipe support with __sgpt__eof__ delimiter)
     let mut prompt_from_stdin = String::new();
     let stdin_is_tty = io::stdin().is_terminal();
@@ -66,7 +146,7 @@ async fn main() -> Result<()> {
     }
 
     // Resolve prompt: stdin + optional positional + document
-    let arg_prompt = args.prompt.unwrap_or_default();
+    let arg_prompt = args.prompt.clone().unwrap_or_default();
     let mut prompt = if !prompt_from_stdin.is_empty() && !arg_prompt.is_empty() {
         format!("{}\n\n{}", prompt_from_stdin, arg_prompt)
     } else if !prompt_from_stdin.is_empty() {
@@ -75,11 +155,52 @@ async fn main() -> Result<()> {
         arg_prompt
     };
 
+    if args.paste {
+        let pasted = utils::clipboard::paste()?;
+        if pasted.trim().is_empty() {
+            bail!("--paste: the clipboard is empty");
+        }
+        prompt = if prompt.is_empty() {
+            pasted
+        } else {
+            format!("{}\n\n{}", pasted, prompt)
+        };
+    }
+
     // Process document files if --doc is provided
     if !args.doc.is_empty() {
-        let doc_content = utils::read_documents(&args.doc)
+        let doc_opts = utils::document::DocOptions::from_config(&cfg);
+        let docs = utils::document::expand_doc_paths(&args.doc, doc_opts.max_file_bytes)?;
+        if docs.expanded {
+            eprintln!("{}", docs.summary());
+        }
+        let mut documents = utils::read_documents(&docs.files, &doc_opts)
+            .await
             .map_err(|e| anyhow!("Document processing failed: {}", e))?;
-        prompt = utils::combine_doc_and_prompt(&doc_content, &prompt);
+        if let Some(limit) = doc_opts.max_input_tokens {
+            // Leave room for the question and the answer
+            let reserved = utils::document::estimate_tokens(&prompt)
+                + args
+                    .max_tokens
+                    .map_or(utils::document::DEFAULT_ANSWER_TOKENS, |n| n as usize);
+            utils::document::fit_documents(
+                &mut documents,
+                limit.saturating_sub(reserved),
+                doc_opts.truncate_strategy,
+            )?;
+            for doc in &documents {
+                if let Some(t) = &doc.truncation {
+                    eprintln!(
+                        "Truncated {} ({}): kept ~{} of ~{} tokens",
+                        doc.source,
+                        t.strategy.as_str(),
+                        t.kept_tokens,
+                        t.original_tokens
+                    );
+                }
+            }
+        }
+        prompt = utils::combine_doc_and_prompt(&documents, &prompt);
     }
 
     // Process image files if --image is provided
@@ -87,7 +208,7 @@ async fn main() -> Result<()> {
         // Check if images were provided but warn about potential compatibility
         let mut parts = Vec::new();
         for image_path in &args.image {
-            match llm::ContentPart::image_from_file(image_path, Some("high".to_string())) {
+            match llm::ContentPart::image_from_file(image_path, Some(args.image_detail.clone())) {
                 Ok(part) => parts.push(part),
                 Err(e) => {
                     return Err(anyhow!(
@@ -119,12 +240,47 @@ async fn main() -> Result<()> {
         }
         return Ok(());
     }
+    if let Some(file) = &args.export_roles {
+        let roles = SystemRole::export_bundle(&cfg)?;
+        std::fs::write(file, serde_json::to_string_pretty(&roles)? + "\n")
+            .with_context(|| format!("Failed to write '{}'", file))?;
+        println!("Exported {} role(s) to {}", roles.len(), file);
+        return Ok(());
+    }
+    if let Some(file) = &args.import_roles {
+        let text =
+            std::fs::read_to_string(file).with_context(|| format!("Failed to read '{}'", file))?;
+        let roles: Vec<SystemRole> = serde_json::from_str(&text)
+            .with_context(|| format!("'{}' is not a role bundle (JSON array of roles)", file))?;
+        let (imported, skipped) = SystemRole::import_bundle(&cfg, &roles, args.overwrite)?;
+        println!("Imported {} role(s)", imported.len());
+        if !skipped.is_empty() {
+            println!(
+                "Skipped {} existing role(s) (use --overwrite to replace): {}",
+                skipped.len(),
+                skipped.join(", ")
+            );
+        }
+        return Ok(());
+    }
     if let Some(name) = &args.show_role {
         println!("{}", SystemRole::show(&cfg, name)?);
         return Ok(());
     }
     if let Some(name) = &args.create_role {
-        SystemRole::create_interactive(&cfg, name)?;
+        if stdin_is_tty {
+            SystemRole::create_interactive(&cfg, name)?;
+        } else {
+            // Piped stdin was already read as the prompt
+            SystemRole::create_from_text(&cfg, name, &prompt)?;
+        }
+        println!("Created/updated role: {}", name);
+        return Ok(());
+    }
+    if let Some([name, file]) = args.create_role_from_file.as_deref() {
+        let body = std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read role file '{}'", file))?;
+        SystemRole::create_from_text(&cfg, name, &body)?;
         println!("Created/updated role: {}", name);
         return Ok(());
     }
@@ -141,6 +297,7 @@ async fn main() -> Result<()> {
             );
         }
         let messages = session.read(id)?;
+        let color = printer::color_enabled(&io::stdout());
         if md_for_show {
             let mut md_text = String::new();
             for m in messages {
@@ -151,35 +308,134 @@ async fn main() -> Result<()> {
                     llm::Role::Tool => "tool",
                     llm::Role::Developer => "developer",
                 };
-                md_text.push_str(&format!("### {}\n\n{}\n\n", role, m.content));
+                md_text.push_str(&format!(
+                    "### {}{}\n\n{}\n\n",
+                    role,
+                    tool_name_suffix(&m),
+                    show_chat_body(&m)
+                ));
             }
-            MarkdownPrinter::default().print(&md_text);
+            MarkdownPrinter::from_config(&cfg).print(&md_text);
         } else {
             for m in messages {
-                let (role, color) = match m.role {
+                let (role, role_color) = match m.role {
                     llm::Role::System => ("system", "cyan"),
                     llm::Role::User => ("user", "magenta"),
                     llm::Role::Assistant => ("assistant", "green"),
                     llm::Role::Tool => ("tool", "yellow"),
                     llm::Role::Developer => ("developer", "blue"),
                 };
-                let header = match color {
-                    "cyan" => format!("{}", role.cyan()),
-                    "magenta" => format!("{}", role.magenta()),
-                    "green" => format!("{}", role.green()),
-                    "yellow" => format!("{}", role.yellow()),
-                    "blue" => format!("{}", role.blue()),
+                let header = match color.then_some(role_color) {
+                    Some("cyan") => format!("{}", role.cyan()),
+                    Some("magenta") => format!("{}", role.magenta()),
+                    Some("green") => format!("{}", role.green()),
+                    Some("yellow") => format!("{}", role.yellow()),
+                    Some("blue") => format!("{}", role.blue()),
                     _ => role.to_string(),
                 };
-                println!("{}: {}\n", header, m.content);
+                println!(
+                    "{}{}: {}\n",
+                    header,
+                    tool_name_suffix(&m),
+                    show_chat_body(&m)
+                );
+            }
+        }
+        return Ok(());
+    }
+    if args.measure_stats {
+        print!("{}", utils::measure::stats_report(&cfg));
+        return Ok(());
+    }
+    if args.cache_info {
+        let req_cache = cache::RequestCache::from_config(&cfg);
+        let session = cache::ChatSession::from_config(&cfg);
+        println!("Request cache ({}):", cfg.cache_path().display());
+        println!("{}", req_cache.stats());
+        println!("Chat cache ({}):", cfg.chat_cache_path().display());
+        println!("{}", session.stats());
+        return Ok(());
+    }
+    if let Some(query) = &args.search_chat {
+        let pattern = if args.regex {
+            regex::Regex::new(query)?
+        } else {
+            regex::Regex::new(&format!("(?i){}", regex::escape(query)))?
+        };
+        let session = cache::ChatSession::from_config(&cfg);
+        let matches = session.search(&pattern, args.max_results);
+        if matches.is_empty() {
+            println!("No matches for: {}", query);
+        }
+        let color = printer::color_enabled(&io::stdout());
+        for m in matches {
+            let (before, hit, after) = m.snippet(80);
+            if color {
+                use owo_colors::OwoColorize;
+                println!(
+                    "{} [{}] {}{}{}",
+                    m.chat_id.cyan(),
+                    m.role.as_str(),
+                    before,
+                    hit.red().bold(),
+                    after
+                );
+            } else {
+                println!(
+                    "{} [{}] {}{}{}",
+                    m.chat_id,
+                    m.role.as_str(),
+                    before,
+                    hit,
+                    after
+                );
+            }
+        }
+        return Ok(());
+    }
+    if let Some(format) = args.format.as_deref() {
+        if args.shell_history.is_none() && !args.search {
+            bail!("--format can only be used with --search or --shell-history");
+        }
+        if args.shell_history.is_some() && format == "md" {
+            bail!("--format md is only supported with --search");
+        }
+    }
+    if let Some(n) = args.shell_history {
+        let entries = utils::shell_history::ShellHistory::from_config(&cfg).last(n);
+        if args.format.as_deref() == Some("json") {
+            for e in &entries {
+                println!("{}", serde_json::to_string(e)?);
+            }
+        } else {
+            if entries.is_empty() && !cfg.get_bool("SHELL_HISTORY") {
+                eprintln!("Shell history is disabled; set SHELL_HISTORY=true to record commands.");
+            }
+            for e in &entries {
+                let code = e
+                    .exit_code
+                    .map(|c| c.to_string())
+                    .unwrap_or_else(|| "-".into());
+                println!(
+                    "{}  {:<12} {:>4}  {}",
+                    cache::format_timestamp(e.timestamp),
+                    e.action.as_str(),
+                    code,
+                    e.command
+                );
+                println!("    # {}", e.prompt.lines().next().unwrap_or(""));
             }
         }
         return Ok(());
     }
     if args.list_chats {
         let session = cache::ChatSession::from_config(&cfg);
-        for p in session.list() {
-            println!("{}", p.display());
+        if args.long {
+            print_chat_table(&session);
+        } else {
+            for p in session.list() {
+                println!("{}", p.display());
+            }
         }
         return Ok(());
     }
@@ -199,20 +455,27 @@ async fn main() -> Result<()> {
     } else {
         cfg.get_bool("SHELL_INTERACTION")
     };
-    let cache = if args.no_cache {
+    let stop_sequences = Some(args.stop.clone()).filter(|s| !s.is_empty());
+    // A cached answer would skip the request --dry-run is meant to show
+    let cache = if args.no_cache || args.dry_run {
         false
     } else if args.cache {
         true
     } else {
         true // default enabled
     };
-    let mut functions = if args.functions {
+    // Naming a tool only makes sense with function calling on
+    let mut functions = if args.functions || !args.tool.is_empty() {
         true
     } else {
         cfg.get_bool("OPENAI_USE_FUNCTIONS")
     };
 
-    let role = DefaultRole::from_flags(args.shell, args.describe_shell, args.code);
+    let role = DefaultRole::from_flags(
+        args.shell,
+        args.describe_shell || args.explain_last,
+        args.code,
+    );
     // Force md off for shell/code/describe; and disable functions in those modes
     if matches!(
         role,
@@ -229,16 +492,63 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
+    if let Some(url) = &args.install_function {
+        let installed = functions::install_from_url(&cfg.functions_path(), url, |path| {
+            eprint!("{} already exists. Overwrite? [y/N]: ", path.display());
+            let mut answer = String::new();
+            utils::tty::prompt_reader(&cfg)
+                .map(|mut input| input.read_line(&mut answer).is_ok())
+                .unwrap_or(false)
+                && matches!(answer.trim(), "y" | "Y" | "yes")
+        })
+        .await?;
+        match installed {
+            Some((def, path)) => {
+                println!("Installed tool: {} ({})", def.name, path.display());
+                if let Some(desc) = &def.description {
+                    println!("{}", desc);
+                }
+            }
+            None => println!("Not installed."),
+        }
+        return Ok(());
+    }
+
     // Handle install-integration (bash/zsh) shortcut
     if args.install_integration {
         integration::install()?;
         return Ok(());
     }
 
+    // One client for whichever handler runs, so its requests share a connection pool.
+    // It also prints the system prompt and request body the handler actually sends.
+    let client = llm::LlmClient::from_config(&cfg)?
+        .with_print_system_role(args.print_system_role)
+        .with_dry_run(args.dry_run);
+
     // Route to handler
-    match (args.repl.as_deref(), args.chat.as_deref()) {
+    let result = match (args.repl.as_deref(), args.chat.as_deref()) {
+        // --chat keeps the search context and answer as a session for follow-up questions
+        (None, chat_id) if args.enhanced_search => {
+            if prompt.trim().is_empty() {
+                bail!("Provide a query after --enhanced-search or via stdin");
+            }
+            handlers::enhanced_search::EnhancedSearchHandler::run(
+                &client,
+                &prompt,
+                &effective_model,
+                Some(temperature),
+                Some(top_p),
+                &cfg,
+                md_for_show,
+                args.report.as_deref(),
+                chat_id,
+            )
+            .await
+        }
         (Some(repl_id), None) => {
             handlers::repl::run(
+                &client,
                 repl_id,
                 if prompt.is_empty() {
                     None
@@ -246,8 +556,8 @@ async fn main() -> Result<()> {
                     Some(prompt.as_str())
                 },
                 &effective_model,
-                args.temperature,
-                args.top_p,
+                temperature,
+                top_p,
                 args.max_tokens,
                 md_for_show,
                 args.shell,
@@ -260,20 +570,24 @@ async fn main() -> Result<()> {
                 } else {
                     None
                 },
+                functions,
             )
             .await
         }
         (None, Some(chat_id)) => {
             handlers::chat::run(
+                &client,
                 chat_id,
                 prompt.as_str(),
                 &effective_model,
-                args.temperature,
-                args.top_p,
+                temperature,
+                top_p,
                 args.max_tokens,
+                stop_sequences.clone(),
                 cache,
-                md_for_show,
+                md,
                 functions,
+                role,
                 args.role.as_deref(),
                 image_parts.clone(),
             )
@@ -284,81 +598,102 @@ async fn main() -> Result<()> {
                 if prompt.trim().is_empty() {
                     bail!("Provide a query after --search or via stdin");
                 }
-                let client = external::tavily::TavilyClient::from_config(&cfg)?;
-                let value = client.search(&prompt).await?;
-                if let Some(results) = value.get("results").and_then(|v| v.as_array()) {
-                    for (i, item) in results.iter().enumerate() {
-                        let title = item.get("title").and_then(|v| v.as_str()).unwrap_or("");
-                        let url = item.get("url").and_then(|v| v.as_str()).unwrap_or("");
-                        let snippet = item
-                            .get("snippet")
-                            .or_else(|| item.get("content"))
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("");
-                        println!("{}. {}\n{}\n{}\n", i + 1, title, url, snippet);
-                    }
+                let provider = external::provider_from_config(&cfg)?;
+                let (answer, items) = provider
+                    .search_with_answer(&prompt, &external::SearchParams::default())
+                    .await?;
+                let format = match args.format.as_deref() {
+                    Some(name) => external::SearchFormat::parse(name)
+                        .ok_or_else(|| anyhow!("unknown --format '{}'", name))?,
+                    None if md => external::SearchFormat::Markdown,
+                    None => external::SearchFormat::Text,
+                };
+                let output = external::format_search_results(answer.as_deref(), &items, format);
+                // Markdown stays raw for pasting unless rendering is on (--md / PRETTIFY_MARKDOWN)
+                if format == external::SearchFormat::Markdown && md {
+                    printer::MarkdownPrinter::from_config(&cfg).print(&output);
                 } else {
-                    println!(
-                        "{}",
-                        serde_json::to_string_pretty(&value).unwrap_or_else(|_| value.to_string())
-                    );
+                    print!("{}", output);
                 }
                 Ok(())
-            } else if args.enhanced_search {
-                if prompt.trim().is_empty() {
-                    bail!("Provide a query after --enhanced-search or via stdin");
-                }
-                handlers::enhanced_search::EnhancedSearchHandler::run(
-                    &prompt,
-                    &effective_model,
-                    Some(args.temperature),
-                    Some(args.top_p),
-                    &cfg,
-                    md_for_show,
-                )
-                .await
             } else if args.shell {
-                let no_interact = !interaction || !stdin_is_tty;
+                // Piped stdin is handled by the shell handler (it may fall back to /dev/tty)
+                let no_interact = !interaction;
                 let explicit_no_interact = args.no_interaction; // only auto-exec when user explicitly passed --no-interaction
                 handlers::shell::run(
+                    &client,
                     &prompt,
                     &effective_model,
-                    args.temperature,
-                    args.top_p,
+                    temperature,
+                    top_p,
                     args.max_tokens,
+                    stop_sequences.clone(),
                     no_interact,
                     explicit_no_interact,
+                    args.copy,
+                    args.i_know_what_im_doing,
                     image_parts.clone(),
                 )
                 .await
-            } else if args.describe_shell {
+            } else if args.describe_shell || args.explain_last {
+                let last;
+                let command = if args.explain_last {
+                    last = utils::shell_history::ShellHistory::from_config(&cfg)
+                        .last_command()
+                        .ok_or_else(|| anyhow!("No command has been executed by sgpt yet"))?;
+                    &last
+                } else {
+                    &prompt
+                };
                 handlers::describe::run(
-                    &prompt,
+                    &client,
+                    command,
                     &effective_model,
-                    args.temperature,
-                    args.top_p,
+                    temperature,
+                    top_p,
                     md,
                     args.max_tokens,
+                    stop_sequences.clone(),
                     image_parts.clone(),
                 )
                 .await
+            } else if let Some(file) = args.edit.as_deref() {
+                handlers::edit::run(
+                    &client,
+                    file,
+                    &prompt,
+                    &effective_model,
+                    temperature,
+                    top_p,
+                    args.max_tokens,
+                    stop_sequences.clone(),
+                )
+                .await
             } else if args.code {
                 handlers::code::run(
+                    &client,
                     &prompt,
                     &effective_model,
-                    args.temperature,
-                    args.top_p,
+                    temperature,
+                    top_p,
                     args.max_tokens,
+                    stop_sequences.clone(),
+                    args.raw,
+                    args.output_file.as_deref(),
+                    args.force,
+                    args.lang.as_deref(),
                     image_parts.clone(),
                 )
                 .await
             } else {
                 handlers::default::run(
+                    &client,
                     &prompt,
                     &effective_model,
-                    args.temperature,
-                    args.top_p,
+                    temperature,
+                    top_p,
                     args.max_tokens,
+                    stop_sequences.clone(),
                     cache,
                     md,
                     functions,
@@ -369,5 +704,84 @@ async fn main() -> Result<()> {
             }
         }
         _ => Err(anyhow!("--chat and --repl cannot be used together")),
+    };
+    match result {
+        // The body has been printed; stopping there is the point of --dry-run
+        Err(e) if e.downcast_ref::<llm::DryRun>().is_some() => Ok(()),
+        result => result,
+    }
+}
+
+/// `--list-chats --long`: one row per chat with its metadata.
+fn print_chat_table(session: &cache::ChatSession) {
+    let rows: Vec<[String; 4]> = session
+        .list_with_metadata()
+        .into_iter()
+        .filter_map(|info| {
+            let id = info.path.file_name()?.to_str()?.to_string();
+            Some(match (info.message_count, info.updated_at) {
+                (Some(count), Some(updated_at)) => [
+                    id,
+                    info.last_model.unwrap_or_else(|| "-".into()),
+                    cache::format_timestamp(updated_at),
+                    count.to_string(),
+                ],
+                _ => [id, "?".into(), "?".into(), "?".into()],
+            })
+        })
+        .collect();
+    let header = ["CHAT", "MODEL", "UPDATED (UTC)", "MESSAGES"].map(String::from);
+    let mut widths = [0usize; 4];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.chars().count());
+        }
+    }
+    for row in std::iter::once(&header).chain(&rows) {
+        println!(
+            "{:<w0$}  {:<w1$}  {:<w2$}  {:>w3$}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3]
+        );
+    }
+}
+
+/// Tool results carry the tool name; show it next to the role.
+fn tool_name_suffix(m: &llm::ChatMessage) -> String {
+    match (&m.role, &m.name) {
+        (llm::Role::Tool, Some(name)) => format!(" ({})", name),
+        _ => String::new(),
+    }
+}
+
+/// Message text for `--show-chat`: tool payloads are truncated and tool calls are listed.
+fn show_chat_body(m: &llm::ChatMessage) -> String {
+    const MAX_TOOL_PAYLOAD: usize = 300;
+    let mut body = m.content.to_string();
+    if m.role == llm::Role::Tool {
+        let total = body.chars().count();
+        if total > MAX_TOOL_PAYLOAD {
+            body = format!(
+                "{}… ({} more chars)",
+                body.chars().take(MAX_TOOL_PAYLOAD).collect::<String>(),
+                total - MAX_TOOL_PAYLOAD
+            );
+        }
+    }
+    for call in m.tool_calls.iter().flatten() {
+        if !body.is_empty() {
+            body.push('\n');
+        }
+        body.push_str(&format!(
+            "-> {}({})",
+            call.function.name, call.function.arguments
+        ));
     }
+    body
 }
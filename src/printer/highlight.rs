@@ -0,0 +1,129 @@
+//! Syntax highlighting for fenced code blocks in Markdown output (`CODE_THEME`).
+
+use std::sync::OnceLock;
+
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
+
+/// Used when `CODE_THEME` names a theme syntect doesn't bundle (e.g. the default `dracula`).
+const FALLBACK_THEME: &str = "base16-eighties.dark";
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// Look up a bundled theme by name, ignoring case. `none` disables highlighting.
+pub fn find_theme(name: &str) -> Option<Theme> {
+    let name = name.trim();
+    if name.is_empty() || name.eq_ignore_ascii_case("none") {
+        return None;
+    }
+    let mut themes = ThemeSet::load_defaults().themes;
+    let key = themes
+        .keys()
+        .find(|k| k.eq_ignore_ascii_case(name))
+        .cloned()
+        .unwrap_or_else(|| FALLBACK_THEME.to_string());
+    themes.remove(&key)
+}
+
+/// A run of Markdown prose or a fenced code block.
+#[derive(Debug, PartialEq)]
+pub enum Segment<'a> {
+    Prose(String),
+    Code { lang: &'a str, code: String },
+}
+
+/// Split Markdown into prose and fenced code blocks. An unclosed fence runs to the end.
+pub fn split_code_blocks(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut prose = String::new();
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let Some(lang) = line.trim_start().strip_prefix("```") else {
+            prose.push_str(line);
+            prose.push('\n');
+            continue;
+        };
+        if !prose.is_empty() {
+            segments.push(Segment::Prose(std::mem::take(&mut prose)));
+        }
+        let mut code = String::new();
+        for line in lines.by_ref() {
+            if line.trim_start().starts_with("```") {
+                break;
+            }
+            code.push_str(line);
+            code.push('\n');
+        }
+        segments.push(Segment::Code {
+            lang: lang.trim(),
+            code,
+        });
+    }
+    if !prose.is_empty() {
+        segments.push(Segment::Prose(prose));
+    }
+    segments
+}
+
+/// Render code as 24-bit ANSI colored text, ending with a style reset.
+pub fn highlight(code: &str, lang: &str, theme: &Theme) -> String {
+    let ps = syntax_set();
+    let syntax = ps
+        .find_syntax_by_token(lang)
+        .unwrap_or_else(|| ps.find_syntax_plain_text());
+    let mut h = HighlightLines::new(syntax, theme);
+    let mut out = String::new();
+    for line in LinesWithEndings::from(code) {
+        match h.highlight_line(line, ps) {
+            Ok(ranges) => out.push_str(&as_24_bit_terminal_escaped(&ranges, false)),
+            Err(_) => out.push_str(line),
+        }
+    }
+    out.push_str("\x1b[0m");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_fenced_blocks() {
+        let md = "Intro\n```rust\nfn main() {}\n```\nOutro\n```\nunclosed";
+        assert_eq!(
+            split_code_blocks(md),
+            vec![
+                Segment::Prose("Intro\n".into()),
+                Segment::Code {
+                    lang: "rust",
+                    code: "fn main() {}\n".into()
+                },
+                Segment::Prose("Outro\n".into()),
+                Segment::Code {
+                    lang: "",
+                    code: "unclosed\n".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn theme_lookup_ignores_case_and_falls_back() {
+        assert!(find_theme("solarized (dark)").is_some());
+        assert!(find_theme("dracula").is_some());
+        assert!(find_theme("none").is_none());
+    }
+
+    #[test]
+    fn highlight_keeps_code_text() {
+        let theme = find_theme("InspiredGitHub").unwrap();
+        let out = highlight("let x = 1;\n", "rust", &theme);
+        assert!(out.contains("\x1b[38;2;"));
+        assert!(out.ends_with("\x1b[0m"));
+    }
+}
@@ -1,22 +1,209 @@
-//! Printers: text and markdown (termimad).
+//! Printers: text and markdown (termimad, with syntect-highlighted code blocks).
 
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+};
+
+use is_terminal::IsTerminal;
+use owo_colors::{AnsiColors, OwoColorize};
+use syntect::highlighting::Theme;
 use termimad::MadSkin;
 
+use crate::config::Config;
+
+pub mod highlight;
+pub mod spinner;
+pub mod stream;
+
+pub use spinner::Spinner;
+pub use stream::MarkdownStream;
+
+/// Whether output written to `stream` should be colored: it must be a terminal and
+/// the user must not have opted out with `NO_COLOR` (or `--no-color`).
+pub fn color_enabled(stream: &impl IsTerminal) -> bool {
+    stream.is_terminal() && !no_color()
+}
+
+/// Whether the user asked for plain output via `NO_COLOR`.
+pub fn no_color() -> bool {
+    std::env::var_os("NO_COLOR").is_some()
+}
+
+/// Width used when `MARKDOWN_WIDTH=auto` but stdout isn't a terminal.
+const FALLBACK_MARKDOWN_WIDTH: usize = 100;
+
 pub struct MarkdownPrinter {
     pub skin: MadSkin,
+    pub width: usize,
+    /// Theme for fenced code blocks; `None` leaves them to termimad.
+    pub code_theme: Option<Theme>,
 }
 
 impl Default for MarkdownPrinter {
     fn default() -> Self {
         Self {
             skin: MadSkin::default(),
+            width: resolve_width("auto"),
+            code_theme: None,
         }
     }
 }
 
 impl MarkdownPrinter {
+    /// Wrap at `MARKDOWN_WIDTH` columns (`auto` follows the terminal width) and
+    /// highlight code blocks with `CODE_THEME` when writing to a color terminal.
+    pub fn from_config(cfg: &Config) -> Self {
+        let setting = cfg.get("MARKDOWN_WIDTH").unwrap_or_else(|| "auto".into());
+        let printer = if color_enabled(&io::stdout()) {
+            Self::with_syntect_theme(&cfg.get("CODE_THEME").unwrap_or_default())
+        } else if no_color() {
+            Self {
+                skin: MadSkin::no_style(),
+                ..Self::default()
+            }
+        } else {
+            Self::default()
+        };
+        Self {
+            width: resolve_width(&setting),
+            ..printer
+        }
+    }
+
+    /// Highlight code blocks with a bundled syntect theme (unknown names fall back to a dark theme).
+    pub fn with_syntect_theme(theme_name: &str) -> Self {
+        Self {
+            code_theme: highlight::find_theme(theme_name),
+            ..Self::default()
+        }
+    }
+
+    pub fn print(&self, text: &str) {
+        println!("{}", self.render(text));
+    }
+
+    /// The terminal rendering of `text`, without the trailing newline `print` adds.
+    pub fn render(&self, text: &str) -> String {
+        let Some(theme) = &self.code_theme else {
+            return self.skin.text(text, Some(self.width)).to_string();
+        };
+        let mut out = String::new();
+        for segment in highlight::split_code_blocks(text) {
+            let _ = match segment {
+                highlight::Segment::Prose(p) => {
+                    write!(out, "{}", self.skin.text(&p, Some(self.width)))
+                }
+                highlight::Segment::Code { lang, code } => {
+                    write!(out, "{}", highlight::highlight(&code, lang, theme))
+                }
+            };
+        }
+        out
+    }
+}
+
+/// A positive number is used as-is; anything else means "detect from the terminal".
+fn resolve_width(setting: &str) -> usize {
+    match setting.trim().parse::<usize>() {
+        Ok(w) if w > 0 => w,
+        _ => terminal_size::terminal_size()
+            .map(|(terminal_size::Width(w), _)| w as usize)
+            .unwrap_or(FALLBACK_MARKDOWN_WIDTH),
+    }
+}
+
+/// Plain streaming output, tinted with `DEFAULT_COLOR` when stdout is a terminal.
+pub struct TextPrinter {
+    color: Option<AnsiColors>,
+}
+
+impl TextPrinter {
+    /// `DEFAULT_COLOR=none` (or the `NO_COLOR` env var) disables coloring.
+    pub fn from_config(cfg: &Config) -> Self {
+        let color = if color_enabled(&io::stdout()) {
+            cfg.get("DEFAULT_COLOR").and_then(|name| {
+                let color = parse_color(&name);
+                if color.is_none() && !name.trim().eq_ignore_ascii_case("none") {
+                    warn_unknown_color(&name);
+                }
+                color
+            })
+        } else {
+            None
+        };
+        Self { color }
+    }
+
     pub fn print(&self, text: &str) {
-        self.skin.print_text(text);
+        match self.color {
+            Some(c) => print!("{}", text.color(c)),
+            None => print!("{}", text),
+        }
+        let _ = io::stdout().flush();
+    }
+
+    pub fn println(&self, text: &str) {
+        self.print(text);
         println!();
     }
 }
+
+/// Several printers may be built per run; only complain about a bad name once.
+fn warn_unknown_color(name: &str) {
+    static WARNED: std::sync::Once = std::sync::Once::new();
+    WARNED.call_once(|| {
+        eprintln!(
+            "Warning: unknown DEFAULT_COLOR '{}', printing without color",
+            name.trim()
+        );
+    });
+}
+
+fn parse_color(name: &str) -> Option<AnsiColors> {
+    let color = match name.trim().to_ascii_lowercase().replace('-', "_").as_str() {
+        "black" => AnsiColors::Black,
+        "red" => AnsiColors::Red,
+        "green" => AnsiColors::Green,
+        "yellow" => AnsiColors::Yellow,
+        "blue" => AnsiColors::Blue,
+        "magenta" => AnsiColors::Magenta,
+        "cyan" => AnsiColors::Cyan,
+        "white" => AnsiColors::White,
+        "bright_black" | "gray" | "grey" => AnsiColors::BrightBlack,
+        "bright_red" => AnsiColors::BrightRed,
+        "bright_green" => AnsiColors::BrightGreen,
+        "bright_yellow" => AnsiColors::BrightYellow,
+        "bright_blue" => AnsiColors::BrightBlue,
+        "bright_magenta" => AnsiColors::BrightMagenta,
+        "bright_cyan" => AnsiColors::BrightCyan,
+        "bright_white" => AnsiColors::BrightWhite,
+        _ => return None,
+    };
+    Some(color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_names_parse_and_none_disables() {
+        assert!(matches!(parse_color("Magenta"), Some(AnsiColors::Magenta)));
+        assert!(matches!(
+            parse_color("bright-cyan"),
+            Some(AnsiColors::BrightCyan)
+        ));
+        assert!(parse_color("none").is_none());
+        assert!(parse_color("chartreuse").is_none());
+    }
+
+    #[test]
+    fn explicit_markdown_width_wins() {
+        assert_eq!(resolve_width("72"), 72);
+        assert_eq!(resolve_width(" 120 "), 120);
+        // 0 and garbage fall back to detection, which always yields a usable width
+        assert!(resolve_width("0") > 0);
+        assert!(resolve_width("wide") > 0);
+    }
+}
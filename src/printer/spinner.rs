@@ -0,0 +1,44 @@
+//! Stderr spinner shown while waiting for the first streamed token.
+
+use std::time::Duration;
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use is_terminal::IsTerminal;
+
+/// Spins on stderr until [`Spinner::stop`] is called or the value is dropped.
+///
+/// Nothing is drawn when stdout is not a terminal, so piped output and scripts stay quiet.
+/// Colors are dropped when `NO_COLOR` is set.
+pub struct Spinner(Option<ProgressBar>);
+
+impl Spinner {
+    pub fn start(message: &str) -> Self {
+        if !std::io::stdout().is_terminal() {
+            return Self(None);
+        }
+        let template = if super::no_color() {
+            "{spinner} {msg}"
+        } else {
+            "{spinner:.cyan} {msg:.dim}"
+        };
+        let style = ProgressStyle::with_template(template)
+            .unwrap_or_else(|_| ProgressStyle::default_spinner());
+        let pb = ProgressBar::with_draw_target(None, ProgressDrawTarget::stderr());
+        pb.set_style(style);
+        pb.set_message(message.to_string());
+        pb.enable_steady_tick(Duration::from_millis(80));
+        Self(Some(pb))
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(pb) = self.0.take() {
+            pb.finish_and_clear();
+        }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,203 @@
+//! Progressive Markdown rendering for `--md` while an answer is still streaming.
+//!
+//! Completed blocks (paragraphs followed by a blank line, closed code fences) are rendered
+//! once and left alone. The unfinished tail is drawn provisionally and redrawn in place as
+//! more text arrives, by moving the cursor back up over it and clearing to the end of screen.
+
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+use is_terminal::IsTerminal;
+use unicode_width::UnicodeWidthStr;
+
+use super::MarkdownPrinter;
+use crate::{config::Config, utils::pty::strip_ansi};
+
+/// Minimum time between redraws of the unfinished tail, so fast streams don't flicker.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(80);
+
+pub struct MarkdownStream {
+    printer: MarkdownPrinter,
+    /// Redraw in place; off when stdout isn't a terminal, where everything is printed at the end.
+    live: bool,
+    buffer: String,
+    /// Bytes of `buffer` already rendered for good.
+    committed: usize,
+    /// Terminal rows the cursor moved down while drawing the provisional tail.
+    tail_rows: usize,
+    last_draw: Option<Instant>,
+}
+
+impl MarkdownStream {
+    pub fn from_config(cfg: &Config) -> Self {
+        Self {
+            printer: MarkdownPrinter::from_config(cfg),
+            live: io::stdout().is_terminal(),
+            buffer: String::new(),
+            committed: 0,
+            tail_rows: 0,
+            last_draw: None,
+        }
+    }
+
+    pub fn push(&mut self, chunk: &str) {
+        self.buffer.push_str(chunk);
+        if !self.live {
+            return;
+        }
+        let stable = self.committed + stable_prefix_len(&self.buffer[self.committed..]);
+        let due = !matches!(self.last_draw, Some(t) if t.elapsed() < REDRAW_INTERVAL);
+        if stable == self.committed && !due {
+            return;
+        }
+        let mut out = io::stdout().lock();
+        self.clear_tail(&mut out);
+        if stable > self.committed {
+            let _ = write!(
+                out,
+                "{}",
+                self.printer.render(&self.buffer[self.committed..stable])
+            );
+            self.committed = stable;
+        }
+        self.draw_tail(&mut out);
+        let _ = out.flush();
+    }
+
+    /// Render whatever is left once the stream is done.
+    pub fn finish(mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        if !self.live {
+            self.printer.print(&self.buffer);
+            return;
+        }
+        let mut out = io::stdout().lock();
+        self.clear_tail(&mut out);
+        let rest = &self.buffer[self.committed..];
+        if !rest.trim().is_empty() {
+            let _ = write!(out, "{}", self.printer.render(rest));
+        }
+        let _ = writeln!(out);
+        let _ = out.flush();
+    }
+
+    fn clear_tail(&mut self, out: &mut impl Write) {
+        if self.tail_rows > 0 {
+            let _ = write!(out, "\x1b[{}A", self.tail_rows);
+        }
+        let _ = write!(out, "\r\x1b[J");
+        self.tail_rows = 0;
+    }
+
+    fn draw_tail(&mut self, out: &mut impl Write) {
+        self.last_draw = Some(Instant::now());
+        let tail = &self.buffer[self.committed..];
+        if tail.trim().is_empty() {
+            return;
+        }
+        let rendered = if fence_is_open(tail) {
+            // Close it for the preview so the partial block still renders as code
+            self.printer
+                .render(&format!("{}\n```", tail.trim_end_matches('\n')))
+        } else {
+            self.printer.render(tail)
+        };
+        let (cols, lines) = terminal_size::terminal_size()
+            .map(|(w, h)| (w.0 as usize, h.0 as usize))
+            .unwrap_or((self.printer.width, usize::MAX));
+        let rows = rows_moved(&rendered, cols);
+        // The cursor can't move back above the top of the screen; wait for the block to close
+        if rows >= lines {
+            return;
+        }
+        let _ = write!(out, "{}", rendered);
+        self.tail_rows = rows;
+    }
+}
+
+/// Length of the prefix made of finished blocks: everything up to the last blank line
+/// outside a code fence, or the last closing fence. Fences still open never count.
+fn stable_prefix_len(text: &str) -> usize {
+    let mut stable = 0;
+    let mut pos = 0;
+    let mut in_fence = false;
+    for line in text.split_inclusive('\n') {
+        if !line.ends_with('\n') {
+            break;
+        }
+        pos += line.len();
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") {
+            in_fence = !in_fence;
+            if !in_fence {
+                stable = pos;
+            }
+        } else if !in_fence && trimmed.is_empty() {
+            stable = pos;
+        }
+    }
+    stable
+}
+
+fn fence_is_open(text: &str) -> bool {
+    text.lines()
+        .filter(|l| l.trim_start().starts_with("```"))
+        .count()
+        % 2
+        == 1
+}
+
+/// How many rows down the cursor ends up after printing `rendered` on a `cols`-wide terminal.
+fn rows_moved(rendered: &str, cols: usize) -> usize {
+    let cols = cols.max(1);
+    let plain = strip_ansi(rendered);
+    let mut rows = 0;
+    for line in plain.split_inclusive('\n') {
+        let width = UnicodeWidthStr::width(line.trim_end_matches('\n'));
+        if line.ends_with('\n') {
+            rows += width.div_ceil(cols).max(1);
+        } else {
+            rows += width.saturating_sub(1) / cols;
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_closed_blocks_are_stable() {
+        assert_eq!(
+            stable_prefix_len("# Title\n\nsome text"),
+            "# Title\n\n".len()
+        );
+        assert_eq!(stable_prefix_len("one line without end"), 0);
+        let open = "intro\n\n```rust\nfn main() {\n\n";
+        assert_eq!(stable_prefix_len(open), "intro\n\n".len());
+        let closed = "```rust\nfn main() {}\n```\nafter";
+        assert_eq!(
+            stable_prefix_len(closed),
+            "```rust\nfn main() {}\n```\n".len()
+        );
+    }
+
+    #[test]
+    fn open_fences_are_detected() {
+        assert!(fence_is_open("text\n```python\nprint(1)\n"));
+        assert!(!fence_is_open("```\ncode\n```\n"));
+    }
+
+    #[test]
+    fn rows_account_for_wrapping_and_escapes() {
+        assert_eq!(rows_moved("\x1b[1mab\x1b[0m\ncd\n", 10), 2);
+        assert_eq!(rows_moved("abcdefghij\n", 4), 3);
+        assert_eq!(rows_moved("partial", 10), 0);
+        assert_eq!(rows_moved("\n", 10), 1);
+    }
+}
@@ -1,9 +1,10 @@
 //! Interpreter process management (startup/IO/health).
 
 use anyhow::Result;
-use tokio::process::{Child, ChildStdin, ChildStdout};
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout};
 
 pub mod python;
+pub mod r;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InterpreterType {
@@ -11,15 +12,18 @@ pub enum InterpreterType {
     R,
 }
 
-#[allow(dead_code)]
 pub struct ProcessHandle {
     pub child: Child,
     pub stdin: ChildStdin,
     pub stdout: ChildStdout,
+    /// Interpreter diagnostics outside the NDJSON protocol (startup failures, crashes)
+    pub stderr: ChildStderr,
 }
 
-#[allow(dead_code)]
-pub async fn start(_ty: InterpreterType) -> Result<ProcessHandle> {
-    // Placeholder: implemented in language-specific modules.
-    anyhow::bail!("Interpreter start not implemented yet")
+/// Spawn the interpreter for `ty` running its NDJSON bootstrap loop.
+pub async fn start(ty: InterpreterType) -> Result<ProcessHandle> {
+    match ty {
+        InterpreterType::Python => python::start_python(python::BOOTSTRAP).await,
+        InterpreterType::R => r::start_r(r::BOOTSTRAP).await,
+    }
 }
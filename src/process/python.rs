@@ -5,7 +5,88 @@ use tokio::process::{Child, Command};
 
 use super::ProcessHandle;
 
-#[allow(dead_code)]
+/// NDJSON loop run with `python -c`: `execute` runs code in a persistent namespace and
+/// returns its captured output, `vars` summarizes the defined variables.
+pub const BOOTSTRAP: &str = r#"
+import sys, json, io, traceback, contextlib
+user_globals = {}
+orig_stdout = sys.stdout
+orig_stderr = sys.stderr
+
+def summarize_vars(g):
+    summary = {}
+    for k, v in g.items():
+        if k.startswith('_'):
+            continue
+        tname = type(v).__name__
+        info = tname
+        try:
+            if tname == 'DataFrame':
+                try:
+                    info = f'DataFrame({v.shape[0]}x{v.shape[1]})'
+                except Exception:
+                    info = 'DataFrame'
+            elif hasattr(v, 'shape'):
+                try:
+                    info = f'array{tuple(v.shape)}'
+                except Exception:
+                    pass
+        except Exception:
+            pass
+        summary[k] = info
+    return summary
+
+while True:
+    line = sys.stdin.readline()
+    if not line:
+        break
+    line = line.strip()
+    if not line:
+        continue
+    try:
+        req = json.loads(line)
+    except Exception as e:
+        print(json.dumps({"id": None, "error": {"message": "invalid_json", "detail": str(e)}}), file=orig_stdout, flush=True)
+        continue
+    rid = req.get('id')
+    method = req.get('method')
+    params = req.get('params', {})
+    if method == 'execute':
+        code = params.get('code', '')
+        capture_output = params.get('capture_output', True)
+        out = io.StringIO()
+        errors = []
+        success = True
+        exit_code = 0
+        try:
+            if capture_output:
+                with contextlib.redirect_stdout(out):
+                    with contextlib.redirect_stderr(out):
+                        exec(code, user_globals)
+            else:
+                exec(code, user_globals)
+        except SystemExit as e:
+            exit_code = e.code if isinstance(e.code, int) else (0 if e.code is None else 1)
+            success = exit_code == 0
+        except Exception as e:
+            success = False
+            exit_code = 1
+            tb = traceback.format_exc()
+            errors.append(tb)
+        output = out.getvalue() if capture_output else ''
+        vars_summary = summarize_vars(user_globals)
+        resp = {"id": rid, "result": {"success": success, "output": output, "errors": errors, "variables": vars_summary, "plots": [], "exit_code": exit_code}}
+        print(json.dumps(resp), file=orig_stdout, flush=True)
+    elif method == 'vars':
+        vars_summary = summarize_vars(user_globals)
+        resp = {"id": rid, "result": {"success": True, "output": "", "errors": [], "variables": vars_summary, "plots": []}}
+        print(json.dumps(resp), file=orig_stdout, flush=True)
+    elif method == 'ping':
+        print(json.dumps({"id": rid, "result": "pong"}), file=orig_stdout, flush=True)
+    else:
+        print(json.dumps({"id": rid, "error": {"message": "unknown_method"}}), file=orig_stdout, flush=True)
+"#;
+
 pub async fn start_python(bootstrap: &str) -> Result<ProcessHandle> {
     let mut cmd = Command::new("python");
     cmd.arg("-u") // unbuffered
@@ -24,10 +105,15 @@ pub async fn start_python(bootstrap: &str) -> Result<ProcessHandle> {
         .stdout
         .take()
         .ok_or_else(|| anyhow::anyhow!("no stdout"))?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("no stderr"))?;
 
     Ok(ProcessHandle {
         child,
         stdin,
         stdout,
+        stderr,
     })
 }
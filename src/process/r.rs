@@ -0,0 +1,123 @@
+//! R interpreter process bootstrap and I/O glue.
+
+use anyhow::Result;
+use tokio::process::{Child, Command};
+
+use super::ProcessHandle;
+
+/// NDJSON loop run with `Rscript -e`, speaking the same protocol as the Python bootstrap.
+/// Needs the `jsonlite` package; without it a single error line is written and R exits.
+pub const BOOTSTRAP: &str = r#"
+if (!requireNamespace("jsonlite", quietly = TRUE)) {
+  cat('{"id":null,"error":{"message":"R package jsonlite is required: install.packages(\\"jsonlite\\")"}}\n')
+  quit(save = "no", status = 1)
+}
+user_env <- new.env()
+
+summarize_vars <- function(env) {
+  summary <- list()
+  for (name in ls(env)) {
+    v <- get(name, envir = env)
+    info <- class(v)[1]
+    if (is.data.frame(v)) {
+      info <- sprintf("data.frame(%dx%d)", nrow(v), ncol(v))
+    } else if (!is.null(dim(v))) {
+      info <- sprintf("%s(%s)", info, paste(dim(v), collapse = "x"))
+    }
+    summary[[name]] <- info
+  }
+  summary
+}
+
+respond <- function(x) {
+  cat(jsonlite::toJSON(x, auto_unbox = TRUE, null = "null"), "\n", sep = "")
+  flush(stdout())
+}
+
+run_code <- function(code) {
+  out <- character()
+  errors <- character()
+  sink_con <- textConnection("out", "w", local = TRUE)
+  sink(sink_con)
+  success <- tryCatch({
+    withCallingHandlers({
+      for (expr in parse(text = code)) {
+        res <- withVisible(eval(expr, envir = user_env))
+        if (res$visible) print(res$value)
+      }
+    }, message = function(m) {
+      cat(conditionMessage(m))
+      invokeRestart("muffleMessage")
+    }, warning = function(w) {
+      cat("Warning:", conditionMessage(w), "\n")
+      invokeRestart("muffleWarning")
+    })
+    TRUE
+  }, error = function(e) {
+    errors <<- conditionMessage(e)
+    FALSE
+  })
+  sink()
+  close(sink_con)
+  list(success = success, output = paste(out, collapse = "\n"), errors = as.list(errors),
+       exit_code = if (success) 0L else 1L)
+}
+
+con <- file("stdin")
+open(con)
+while (length(line <- readLines(con, n = 1, warn = FALSE)) > 0) {
+  line <- trimws(line)
+  if (!nzchar(line)) next
+  req <- tryCatch(jsonlite::fromJSON(line, simplifyVector = FALSE), error = function(e) NULL)
+  if (is.null(req)) {
+    respond(list(id = NULL, error = list(message = "invalid_json")))
+    next
+  }
+  rid <- req$id
+  method <- req$method
+  if (identical(method, "execute")) {
+    res <- run_code(if (is.null(req$params$code)) "" else req$params$code)
+    res$variables <- summarize_vars(user_env)
+    res$plots <- list()
+    respond(list(id = rid, result = res))
+  } else if (identical(method, "vars")) {
+    respond(list(id = rid, result = list(success = TRUE, output = "", errors = list(),
+      variables = summarize_vars(user_env), plots = list())))
+  } else if (identical(method, "ping")) {
+    respond(list(id = rid, result = "pong"))
+  } else {
+    respond(list(id = rid, error = list(message = "unknown_method")))
+  }
+}
+"#;
+
+pub async fn start_r(bootstrap: &str) -> Result<ProcessHandle> {
+    let mut cmd = Command::new("Rscript");
+    cmd.arg("--vanilla")
+        .arg("-e")
+        .arg(bootstrap)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped());
+
+    let mut child: Child = cmd.spawn()?;
+    let stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("no stdin"))?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("no stdout"))?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("no stderr"))?;
+
+    Ok(ProcessHandle {
+        child,
+        stdin,
+        stdout,
+        stderr,
+    })
+}
@@ -9,7 +9,10 @@ use std::{
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::config::Config;
+use crate::{
+    config::Config,
+    llm::{ChatMessage, Role},
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DefaultRole {
@@ -17,6 +20,8 @@ pub enum DefaultRole {
     Shell,
     DescribeShell,
     Code,
+    /// `--code --edit`: answer with a unified diff against the given file.
+    EditDiff,
 }
 
 impl DefaultRole {
@@ -40,26 +45,55 @@ pub fn default_role_text(cfg: &Config, role: DefaultRole) -> String {
         DefaultRole::Default => format!(
             "You are programming and system administration assistant.\nYou are managing {os} operating system with {shell} shell.\nProvide short responses in about 100 words, unless you are specifically asked for more details.\nIf you need to store any data, assume it will be stored in the conversation."
         ),
-        DefaultRole::Shell => {
-            let ch = chain_hint(&shell);
-            let ph = platform_hint(&shell);
-            format!(
-                "Provide only {shell} commands for {os} without any description.\nIf there is a lack of details, provide most logical solution.\nEnsure the output is a valid shell command.\n{ch}\n{ph}\nProvide only plain text without Markdown formatting.\nDo not provide markdown formatting such as ```."
-            )
-        }
+        DefaultRole::Shell => shell_role_text(&os, &shell),
         DefaultRole::DescribeShell =>
             "Provide a terse, single sentence description of the given shell command.\nDescribe each argument and option of the command.\nProvide short responses in about 80 words.".to_string(),
         DefaultRole::Code =>
             "Provide only code as output without any description.\nProvide only code in plain text format without Markdown formatting.\nDo not include symbols such as ``` or ```python.\nIf there is a lack of details, provide most logical solution.\nYou are not allowed to ask for more details.\nFor example if the prompt is \"Hello world Python\", you should return \"print('Hello world')\".".to_string(),
+        DefaultRole::EditDiff =>
+            "You edit an existing file. The user gives you its path, its full content and the change to make.\nProvide only a unified diff of the file as output, without any description.\nStart with the headers \"--- a/<path>\" and \"+++ b/<path>\", followed by hunks with correct \"@@ -l,s +l,s @@\" headers.\nInclude 3 lines of unchanged context around each change, copied exactly from the file.\nPrefix unchanged lines with a space, removed lines with - and added lines with +.\nOnly change the given file and only what the request needs.\nDo not include symbols such as ``` or ```diff.".to_string(),
+    }
+}
+
+fn shell_role_text(os: &str, shell: &str) -> String {
+    let ch = chain_hint(shell);
+    let ph = platform_hint(shell);
+    format!(
+        "Provide only {shell} commands for {os} without any description.\nIf there is a lack of details, provide most logical solution.\nEnsure the output is a valid shell command.\n{ch}\n{ph}\nProvide only plain text without Markdown formatting.\nDo not provide markdown formatting such as ```."
+    )
+}
+
+/// Canonical name for a shell given by `--target-shell`, `SHELL_NAME` or `$SHELL`.
+pub fn normalize_shell_name(name: &str) -> String {
+    let lower = name.trim().to_ascii_lowercase();
+    match lower.as_str() {
+        "pwsh" | "pwsh.exe" => "pwsh".to_string(),
+        "powershell" | "powershell.exe" => "powershell.exe".to_string(),
+        "cmd" | "cmd.exe" => "cmd.exe".to_string(),
+        "fish" => "fish".to_string(),
+        "xonsh" => "xonsh".to_string(),
+        "nu" | "nu.exe" | "nushell" | "nushell.exe" => "nushell".to_string(),
+        _ => lower,
     }
 }
 
 fn chain_hint(shell: &str) -> String {
     let sh = shell.to_ascii_lowercase();
-    if sh.contains("powershell") {
+    if sh.contains("pwsh") {
+        // PowerShell 7 has pipeline chain operators, unlike Windows PowerShell
+        "If multiple steps are required, combine commands with && (PowerShell 7 syntax).".into()
+    } else if sh.contains("powershell") {
         "If multiple steps are required, separate commands with ; (not &&).".into()
     } else if sh.contains("cmd") {
         "If multiple steps are required, combine commands with &&.".into()
+    } else if sh.contains("fish") {
+        "If multiple steps are required, combine commands with ; and (fish syntax).".into()
+    } else if is_nushell(&sh) {
+        // Nushell has no && / ||; ; still stops at the first failure
+        "If multiple steps are required, separate commands with ; (not &&), and pass data between commands with | pipelines."
+            .into()
+    } else if sh.contains("xonsh") {
+        "If multiple steps are required, combine commands with and (xonsh syntax).".into()
     } else {
         "If multiple steps are required, combine commands with &&.".into()
     }
@@ -67,11 +101,20 @@ fn chain_hint(shell: &str) -> String {
 
 fn platform_hint(shell: &str) -> String {
     let sh = shell.to_ascii_lowercase();
-    if sh.contains("powershell") {
+    if sh.contains("powershell") || sh.contains("pwsh") {
         "Prefer native PowerShell cmdlets and parameters (e.g., Get-ChildItem, Select-String) rather than Unix commands."
             .into()
     } else if sh.contains("cmd") {
         "Prefer built-in Windows commands (e.g., dir, findstr) where appropriate.".into()
+    } else if sh.contains("fish") {
+        "Use fish syntax: set VAR value instead of VAR=value, (cmd) instead of $(cmd), and no heredocs."
+            .into()
+    } else if is_nushell(&sh) {
+        "Use Nushell built-in commands, which return structured tables: ls, ps, open, where, sort-by, select, get (e.g., ls | where size > 1mb | sort-by modified) rather than Unix tools like find, grep or awk."
+            .into()
+    } else if sh.contains("xonsh") {
+        "Use xonsh syntax: shell commands may be mixed with Python expressions, and variables are set with $VAR = 'value'."
+            .into()
     } else {
         String::new()
     }
@@ -94,23 +137,57 @@ fn detect_os(cfg: &Config) -> String {
 fn detect_shell(cfg: &Config) -> String {
     if let Some(v) = cfg.get("SHELL_NAME") {
         if v != "auto" {
-            return v;
+            return normalize_shell_name(&v);
         }
     }
     if cfg!(windows) {
         let ps = std::env::var("PSModulePath").unwrap_or_default();
-        let is_powershell = ps.split(std::path::MAIN_SEPARATOR).count() >= 3;
-        return if is_powershell {
-            "powershell.exe".into()
-        } else {
-            "cmd.exe".into()
-        };
+        return windows_shell_from_module_path(&ps).into();
+    }
+    // Shells that don't always put themselves in $SHELL announce their version instead
+    if let Some(shell) = shell_from_version_vars(|key| std::env::var_os(key).is_some()) {
+        return shell.into();
     }
     let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".into());
-    Path::new(&shell)
+    let name = Path::new(&shell)
         .file_name()
         .map(|s| s.to_string_lossy().into_owned())
-        .unwrap_or(shell)
+        .unwrap_or(shell);
+    normalize_shell_name(&name)
+}
+
+/// `nu` or `nushell`, with an optional `.exe` suffix.
+fn is_nushell(shell: &str) -> bool {
+    matches!(
+        shell.to_ascii_lowercase().trim_end_matches(".exe"),
+        "nu" | "nushell"
+    )
+}
+
+/// Shell named by a version variable it sets: `FISH_VERSION`, `NU_VERSION` (Nushell)
+/// or `XONSH_VERSION`.
+fn shell_from_version_vars(is_set: impl Fn(&str) -> bool) -> Option<&'static str> {
+    [
+        ("FISH_VERSION", "fish"),
+        ("NU_VERSION", "nushell"),
+        ("XONSH_VERSION", "xonsh"),
+    ]
+    .into_iter()
+    .find(|(key, _)| is_set(key))
+    .map(|(_, shell)| shell)
+}
+
+/// PowerShell 7 adds `...\PowerShell\Modules` directories to PSModulePath; Windows
+/// PowerShell only has `...\WindowsPowerShell\...` entries.
+fn windows_shell_from_module_path(ps: &str) -> &'static str {
+    let lower = ps.to_ascii_lowercase();
+    if lower.contains("\\powershell\\") {
+        "pwsh"
+    } else if ps.split(std::path::MAIN_SEPARATOR).count() >= 3 {
+        "powershell.exe"
+    } else {
+        "cmd.exe"
+    }
 }
 
 // Persistent roles
@@ -189,25 +266,73 @@ impl SystemRole {
     }
 
     pub fn create_interactive(cfg: &Config, name: &str) -> Result<()> {
-        let dir = Self::storage_dir(cfg);
-        fs::create_dir_all(&dir)?;
-        let rp = dir.join(format!("{}.json", name));
-        if rp.exists() {
-            // Overwrite without confirmation to keep it simple
-        }
         eprintln!(
             "Enter role description for \"{}\". Press Ctrl+D when done:\n",
             name
         );
         let mut buf = String::new();
         io::stdin().read_to_string(&mut buf)?;
-        if buf.trim().is_empty() {
+        Self::create_from_text(cfg, name, &buf)
+    }
+
+    /// Every stored role, sorted by name. Files that aren't valid roles are skipped with a warning.
+    pub fn export_bundle(cfg: &Config) -> Result<Vec<SystemRole>> {
+        let mut roles = Vec::new();
+        for path in Self::list(cfg) {
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            match fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|text| Ok(serde_json::from_str::<SystemRole>(&text)?))
+            {
+                Ok(role) => roles.push(role),
+                Err(e) => eprintln!("Warning: skipping {}: {}", path.display(), e),
+            }
+        }
+        roles.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(roles)
+    }
+
+    /// Store exported `roles`, keeping existing ones unless `overwrite` is set.
+    /// Returns the names imported and the names skipped.
+    pub fn import_bundle(
+        cfg: &Config,
+        roles: &[SystemRole],
+        overwrite: bool,
+    ) -> Result<(Vec<String>, Vec<String>)> {
+        // Check every name first so a bad bundle imports nothing
+        for role in roles {
+            validate_name(&role.name)?;
+        }
+        let (mut imported, mut skipped) = (Vec::new(), Vec::new());
+        for role in roles {
+            if !overwrite && Self::get(cfg, &role.name).is_ok() {
+                skipped.push(role.name.clone());
+                continue;
+            }
+            // Exported text already starts with the "You are <name>" line create_from_text adds
+            let prefix = format!("You are {}\n", role.name);
+            let body = role.role.strip_prefix(&prefix).unwrap_or(&role.role);
+            Self::create_from_text(cfg, &role.name, body)?;
+            imported.push(role.name.clone());
+        }
+        Ok((imported, skipped))
+    }
+
+    /// Create or overwrite role `name` with `body` as its description.
+    pub fn create_from_text(cfg: &Config, name: &str, body: &str) -> Result<()> {
+        validate_name(name)?;
+        if body.trim().is_empty() {
             return Err(anyhow!("empty role description"));
         }
-        let content = format!("You are {}\n{}", name, buf.trim());
+        let dir = Self::storage_dir(cfg);
+        fs::create_dir_all(&dir)?;
+        // Overwrite without confirmation to keep it simple
+        let rp = dir.join(format!("{}.json", name));
         let sr = SystemRole {
             name: name.to_string(),
-            role: content,
+            role: format!("You are {}\n{}", name, body.trim()),
         };
         let data = serde_json::to_string(&sr)?;
         let mut f = fs::File::create(rp)?;
@@ -216,6 +341,18 @@ impl SystemRole {
     }
 }
 
+/// Role names become file names in the roles directory, so they must stay inside it.
+fn validate_name(name: &str) -> Result<()> {
+    let name = name.trim();
+    if name.is_empty() || name == "." || name.contains("..") || name.contains(['/', '\\']) {
+        return Err(anyhow!(
+            "invalid role name {:?} (no path separators or '..')",
+            name
+        ));
+    }
+    Ok(())
+}
+
 pub fn resolve_role_text(cfg: &Config, user_role: Option<&str>, fallback: DefaultRole) -> String {
     if let Some(name) = user_role {
         if let Ok(sr) = SystemRole::get(cfg, name) {
@@ -227,3 +364,100 @@ pub fn resolve_role_text(cfg: &Config, user_role: Option<&str>, fallback: Defaul
         .replace("{os}", &os)
         .replace("{shell}", &shell)
 }
+
+/// Leading messages carrying the role prompt.
+///
+/// With `NO_SYSTEM_ROLE` (CLI `--no-system-role`) the system message is omitted for backends
+/// that reject it. When `behaviour` is set the prompt defines how the answer must look (shell,
+/// code, describe), so it is sent as a user prefix message instead of being dropped.
+pub fn role_messages(cfg: &Config, text: String, behaviour: bool) -> Vec<ChatMessage> {
+    if !cfg.get_bool("NO_SYSTEM_ROLE") {
+        vec![ChatMessage::new(Role::System, text)]
+    } else if behaviour {
+        vec![ChatMessage::new(Role::User, text)]
+    } else {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pwsh_gets_powershell_hints_with_chain_operators() {
+        assert!(chain_hint("pwsh").contains("&&"));
+        assert!(chain_hint("powershell.exe").contains("; (not &&)"));
+        assert!(platform_hint("pwsh").contains("PowerShell cmdlets"));
+        assert!(platform_hint("bash").is_empty());
+    }
+
+    #[test]
+    fn fish_nu_and_xonsh_are_detected_with_their_own_hints() {
+        assert_eq!(
+            shell_from_version_vars(|k| k == "FISH_VERSION"),
+            Some("fish")
+        );
+        assert_eq!(
+            shell_from_version_vars(|k| k == "NU_VERSION"),
+            Some("nushell")
+        );
+        assert_eq!(
+            shell_from_version_vars(|k| k == "XONSH_VERSION"),
+            Some("xonsh")
+        );
+        assert_eq!(shell_from_version_vars(|_| false), None);
+        assert!(chain_hint("fish").contains("; and"));
+        assert!(chain_hint("nu").contains("(not &&)"));
+        assert!(chain_hint("nushell.exe").contains("| pipelines"));
+        assert!(platform_hint("nu").contains("where"));
+        assert!(!is_nushell("gnu-sh"));
+        assert!(platform_hint("fish").contains("set VAR value"));
+        assert!(platform_hint("xonsh").contains("Python"));
+    }
+
+    #[test]
+    fn normalized_shell_names_get_a_shell_role() {
+        for name in [
+            "pwsh.exe",
+            "PowerShell",
+            "cmd",
+            "fish",
+            "xonsh",
+            "nu",
+            "nushell",
+            "bash",
+        ] {
+            let shell = normalize_shell_name(name);
+            let text = shell_role_text("Linux", &shell);
+            assert!(
+                !shell.is_empty() && !chain_hint(&shell).is_empty(),
+                "{}",
+                name
+            );
+            assert!(text.contains(&format!("Provide only {} commands", shell)));
+        }
+        assert_eq!(normalize_shell_name("nu"), "nushell");
+        assert_eq!(normalize_shell_name("Fish"), "fish");
+    }
+
+    #[test]
+    fn role_names_cannot_leave_the_roles_directory() {
+        for bad in ["", "../evil", "..", "a/b", r"a\b", "/etc/passwd"] {
+            assert!(validate_name(bad).is_err(), "{:?}", bad);
+        }
+        for good in ["ShellGPT", "code-review", "Commit Writer", "v1.2"] {
+            assert!(validate_name(good).is_ok(), "{:?}", good);
+        }
+    }
+
+    #[test]
+    fn pwsh_is_recognised_from_module_path() {
+        let pwsh =
+            r"C:\Users\me\Documents\PowerShell\Modules;C:\Program Files\PowerShell\7\Modules";
+        assert_eq!(windows_shell_from_module_path(pwsh), "pwsh");
+        let legacy = r"C:\Users\me\Documents\WindowsPowerShell\Modules";
+        assert_ne!(windows_shell_from_module_path(legacy), "pwsh");
+        assert_eq!(windows_shell_from_module_path(""), "cmd.exe");
+    }
+}
@@ -1,9 +1,25 @@
 //! TUI application state management.
 
+use std::time::SystemTime;
+
 use anyhow::Result;
 
-use crate::llm::{ChatMessage, Role};
+use crate::llm::{ChatMessage, FunctionCall, Role, ToolCall};
 use crate::process::InterpreterType;
+use crate::utils::clipboard::Clipboard;
+
+/// Snapshots kept for Ctrl+Z
+const UNDO_DEPTH: usize = 50;
+
+/// Everything Ctrl+Z restores: the lines being composed and the pastes they refer to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputSnapshot {
+    input: String,
+    cursor: usize,
+    multiline_buffer: Vec<String>,
+    input_mode: InputMode,
+    pending_pastes: Vec<(String, String)>,
+}
 
 /// Input mode for the TUI
 #[derive(Debug, Clone, PartialEq)]
@@ -12,6 +28,23 @@ pub enum InputMode {
     Normal,
     /// Multi-line input mode (activated by """)
     MultiLine,
+    /// Ctrl+R reverse search through the input history
+    HistorySearch,
+}
+
+/// State of an active Ctrl+R history search
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistorySearch {
+    /// Substring typed so far
+    pub query: String,
+    /// Index into `input_history` of the match shown in the input box
+    pub match_index: Option<usize>,
+    /// Whether the query (or the last Ctrl+R) found nothing
+    pub failing: bool,
+    /// Input restored on Esc
+    saved_input: String,
+    saved_cursor: usize,
+    saved_mode: InputMode,
 }
 
 /// Popup display state
@@ -19,8 +52,16 @@ pub enum InputMode {
 pub enum PopupState {
     /// No popup shown
     None,
-    /// Execution result popup
-    ExecutionResult { command: String, output: String },
+    /// Execution result popup; output streams in while `running`
+    ExecutionResult {
+        command: String,
+        output: String,
+        running: bool,
+        /// Rows scrolled up from the end of the output (0 follows new output)
+        scroll: usize,
+        /// Exit status once the command has finished (None while running or if killed)
+        exit_code: Option<i32>,
+    },
     /// Command description popup
     Description {
         command: String,
@@ -32,6 +73,16 @@ pub enum PopupState {
         current_description: String,
         is_loading: bool,
     },
+    /// Tool call waiting for the user to accept or skip it
+    ToolConfirm { name: String, arguments: String },
+}
+
+/// A tool call streamed by the model, accumulated from its deltas
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PendingToolCall {
+    pub id: Option<String>,
+    pub name: String,
+    pub arguments: String,
 }
 
 /// Application state for the TUI
@@ -41,10 +92,16 @@ pub struct App {
     pub chat_id: String,
     /// Current conversation history
     pub messages: Vec<ChatMessage>,
+    /// When each entry of `messages` was added (`None` for messages loaded from the session)
+    pub message_times: Vec<Option<SystemTime>>,
+    /// Prefix chat messages with `[HH:MM:SS]` (toggled with Ctrl+T)
+    pub show_timestamps: bool,
     /// Input buffer
     pub input: String,
     /// Cursor position in input (character index)
     pub input_cursor: usize,
+    /// Composer state before each destructive edit, newest last (Ctrl+Z)
+    pub undo_stack: Vec<InputSnapshot>,
     /// Current input mode
     pub input_mode: InputMode,
     /// Multi-line input buffer
@@ -53,6 +110,8 @@ pub struct App {
     pub input_history: Vec<String>,
     /// Current history index when navigating (None = new line)
     pub history_index: Option<usize>,
+    /// Active Ctrl+R search, while `input_mode` is `HistorySearch`
+    pub history_search: Option<HistorySearch>,
     /// Whether we're in shell mode
     pub is_shell_mode: bool,
     /// Active interpreter (Python/R) if in analytics mode
@@ -71,6 +130,8 @@ pub struct App {
     pub status_message: String,
     /// Model name being used
     pub model: String,
+    /// Sampling temperature for replies (changed with `/temp`)
+    pub temperature: f32,
     /// Whether to show help
     pub show_help: bool,
     /// Scroll offset for chat history
@@ -89,6 +150,18 @@ pub struct App {
     pub last_manual_scroll_time: Option<std::time::Instant>,
     /// Whether mouse capture is enabled (when disabled, terminal mouse selection works)
     pub mouse_capture_enabled: bool,
+    /// Tool call requested by the current response, if any
+    pub pending_tool: Option<PendingToolCall>,
+    /// Task streaming the current response, so Esc can stop it
+    pub response_task: Option<tokio::task::AbortHandle>,
+    /// Rough size of the conversation (~4 characters per token), refreshed after each response
+    pub estimated_tokens: usize,
+    /// `(prompt, completion)` tokens the provider reported for the latest reply
+    pub last_usage: Option<(u32, u32)>,
+    /// `MAX_CONTEXT_TOKENS`, used to color the estimate in the status bar
+    pub max_context_tokens: Option<usize>,
+    /// Held for the whole session so copied answers stay on the clipboard
+    pub clipboard: Clipboard,
 }
 
 impl App {
@@ -117,15 +190,19 @@ impl App {
         }
         .to_string();
 
-        Self {
+        let mut app = Self {
             chat_id,
+            message_times: vec![None; messages.len()],
             messages,
+            show_timestamps: false,
             input: String::new(),
             input_cursor: 0,
+            undo_stack: Vec::new(),
             input_mode: InputMode::Normal,
             multiline_buffer: Vec::new(),
             input_history: Vec::new(),
             history_index: None,
+            history_search: None,
             is_shell_mode,
             interpreter,
             allow_interaction,
@@ -135,6 +212,7 @@ impl App {
             message_queue: std::collections::VecDeque::new(),
             status_message,
             model,
+            temperature: 1.0,
             show_help: false,
             chat_scroll_offset: 0,
             max_display_messages: 100,
@@ -144,29 +222,113 @@ impl App {
             user_is_scrolling: false,
             last_manual_scroll_time: None,
             mouse_capture_enabled: true,
-        }
+            pending_tool: None,
+            response_task: None,
+            estimated_tokens: 0,
+            last_usage: None,
+            max_context_tokens: None,
+            clipboard: Clipboard::default(),
+        };
+        app.update_token_estimate();
+        app
+    }
+
+    /// Re-estimate the conversation size from the message text
+    pub fn update_token_estimate(&mut self) {
+        self.estimated_tokens = self
+            .messages
+            .iter()
+            .map(|msg| msg.content.extract_text().len() / 4)
+            .sum();
     }
 
     /// Add a new message to the conversation
     pub fn add_message(&mut self, message: ChatMessage) {
         self.messages.push(message);
+        self.message_times.push(Some(SystemTime::now()));
         // Keep only recent messages for display performance
         if self.messages.len() > self.max_display_messages {
-            self.messages
-                .drain(0..self.messages.len() - self.max_display_messages);
+            let excess = self.messages.len() - self.max_display_messages;
+            self.messages.drain(0..excess);
+            self.message_times
+                .drain(0..excess.min(self.message_times.len()));
         }
         // Force auto-scroll to bottom to show new message
         self.force_scroll_to_bottom();
     }
 
-    /// Get visible messages for display (excluding system messages)
-    pub fn visible_messages(&self) -> Vec<&ChatMessage> {
+    /// Replace the system prompt, adding one if the conversation has none
+    pub fn set_system_prompt(&mut self, text: String) {
+        match self.messages.iter_mut().find(|m| m.role == Role::System) {
+            Some(msg) => *msg = ChatMessage::new(Role::System, text),
+            None => {
+                self.messages
+                    .insert(0, ChatMessage::new(Role::System, text));
+                self.message_times.insert(0, None);
+            }
+        }
+        self.update_token_estimate();
+    }
+
+    /// Remove everything after the last user message (replies, tool calls and their results)
+    /// so it can be answered again. Returns false when there is no user message.
+    pub fn drop_trailing_replies(&mut self) -> bool {
+        let Some(last_user) = self.messages.iter().rposition(|m| m.role == Role::User) else {
+            return false;
+        };
+        self.messages.truncate(last_user + 1);
+        self.message_times
+            .truncate((last_user + 1).min(self.message_times.len()));
+        self.current_response.clear();
+        self.pending_tool = None;
+        self.last_command.clear();
+        self.update_token_estimate();
+        self.force_scroll_to_bottom();
+        true
+    }
+
+    /// Forget everything but the system prompt (`/clear`)
+    pub fn clear_conversation(&mut self) {
+        let mut times = self.message_times.iter();
+        let (messages, message_times) = self
+            .messages
+            .drain(..)
+            .map(|m| (m, times.next().copied().flatten()))
+            .filter(|(m, _)| m.role == Role::System)
+            .unzip();
+        self.messages = messages;
+        self.message_times = message_times;
+        self.last_command.clear();
+        self.pending_tool = None;
+        self.last_usage = None;
+        self.chat_scroll_offset = 0;
+        self.update_token_estimate();
+    }
+
+    /// Text of the most recent assistant answer (replies that only call a tool don't count)
+    pub fn last_assistant_text(&self) -> Option<String> {
         self.messages
             .iter()
-            .filter(|msg| msg.role != Role::System)
+            .rev()
+            .filter(|m| m.role == Role::Assistant)
+            .map(|m| m.content.extract_text())
+            .find(|text| !text.trim().is_empty())
+    }
+
+    /// Get visible messages for display (excluding system messages), with the time each was added
+    pub fn visible_messages(&self) -> Vec<(&ChatMessage, Option<SystemTime>)> {
+        self.messages
+            .iter()
+            .zip(self.message_times.iter().copied())
+            .filter(|(msg, _)| msg.role != Role::System)
             .collect()
     }
 
+    /// Toggle `[HH:MM:SS]` prefixes in the chat area
+    pub fn toggle_timestamps(&mut self) {
+        self.show_timestamps = !self.show_timestamps;
+    }
+
     /// Start receiving a new response
     pub fn start_response(&mut self) {
         self.current_response.clear();
@@ -191,6 +353,7 @@ impl App {
 
         self.current_response.clear();
         self.is_receiving_response = false;
+        self.update_token_estimate();
         self.update_status_message(); // Update status after finishing response
 
         // Extra safety: force scroll to bottom after finishing response
@@ -199,6 +362,100 @@ impl App {
         Ok(())
     }
 
+    /// Stop the streaming task and keep what arrived so far, tagged as cancelled.
+    /// Returns false when no response is being streamed.
+    pub fn cancel_response(&mut self) -> bool {
+        let Some(task) = self.response_task.take() else {
+            return false;
+        };
+        task.abort();
+        // A half-streamed tool call can't be run
+        self.pending_tool = None;
+
+        let partial = self.current_response.trim_end();
+        let content = if partial.is_empty() {
+            "(cancelled)".to_string()
+        } else {
+            format!("{} (cancelled)", partial)
+        };
+        // Not a usable command, so `last_command` is left alone
+        self.add_message(ChatMessage::new(Role::Assistant, content));
+        self.current_response.clear();
+        self.is_receiving_response = false;
+        self.update_token_estimate();
+        self.status_message = "Response cancelled".to_string();
+        self.force_scroll_to_bottom();
+        true
+    }
+
+    /// Accumulate a streamed tool call delta
+    pub fn append_tool_call_delta(
+        &mut self,
+        id: Option<String>,
+        name: Option<String>,
+        arguments: Option<String>,
+    ) {
+        let call = self
+            .pending_tool
+            .get_or_insert_with(PendingToolCall::default);
+        if id.is_some() {
+            call.id = id;
+        }
+        if let Some(n) = name {
+            call.name = n;
+        }
+        if let Some(a) = arguments {
+            call.arguments.push_str(&a);
+        }
+    }
+
+    /// Record the assistant tool call and ask the user to confirm it.
+    /// Returns false when the response didn't request a tool.
+    pub fn begin_tool_confirmation(&mut self) -> bool {
+        let Some(call) = self.pending_tool.as_mut() else {
+            return false;
+        };
+        if call.name.is_empty() {
+            self.pending_tool = None;
+            return false;
+        }
+        // Providers reject tool results that don't reference their call
+        let id = call
+            .id
+            .get_or_insert_with(|| format!("call_{}", self.messages.len()))
+            .clone();
+        let mut assistant_msg =
+            ChatMessage::new(Role::Assistant, std::mem::take(&mut self.current_response));
+        assistant_msg.tool_calls = Some(vec![ToolCall {
+            id: Some(id),
+            r#type: "function".into(),
+            function: FunctionCall {
+                name: call.name.clone(),
+                arguments: call.arguments.clone(),
+            },
+        }]);
+        self.popup_state = PopupState::ToolConfirm {
+            name: call.name.clone(),
+            arguments: call.arguments.clone(),
+        };
+        self.add_message(assistant_msg);
+        true
+    }
+
+    /// Close the confirmation popup and hand over the pending tool call
+    pub fn take_pending_tool(&mut self) -> Option<PendingToolCall> {
+        self.hide_popup();
+        self.pending_tool.take()
+    }
+
+    /// Append the tool result that answers `call`
+    pub fn add_tool_result(&mut self, call: PendingToolCall, output: String) {
+        let mut tool_msg = ChatMessage::new(Role::Tool, output);
+        tool_msg.name = Some(call.name);
+        tool_msg.tool_call_id = call.id;
+        self.add_message(tool_msg);
+    }
+
     /// Clear input buffers
     pub fn clear_input(&mut self) {
         self.input.clear();
@@ -206,6 +463,8 @@ impl App {
         self.multiline_buffer.clear();
         self.input_mode = InputMode::Normal;
         self.history_index = None;
+        self.history_search = None;
+        self.undo_stack.clear();
     }
 
     /// Get the current input text
@@ -314,7 +573,46 @@ impl App {
         self.cleanup_pending_pastes();
     }
 
+    /// Run an edit, saving an undo snapshot if it changed the composed text
+    pub fn undoable(&mut self, edit: impl FnOnce(&mut Self)) {
+        let snapshot = InputSnapshot {
+            input: self.input.clone(),
+            cursor: self.input_cursor,
+            multiline_buffer: self.multiline_buffer.clone(),
+            input_mode: self.input_mode.clone(),
+            pending_pastes: self.pending_pastes.clone(),
+        };
+        edit(self);
+        // History search edits its query, not the composer
+        let changed =
+            self.input != snapshot.input || self.multiline_buffer != snapshot.multiline_buffer;
+        if changed && self.input_mode != InputMode::HistorySearch {
+            self.undo_stack.push(snapshot);
+            if self.undo_stack.len() > UNDO_DEPTH {
+                self.undo_stack.remove(0);
+            }
+        }
+    }
+
+    /// Ctrl+Z: restore the input as it was before the last destructive edit
+    pub fn undo(&mut self) -> bool {
+        let Some(snapshot) = self.undo_stack.pop() else {
+            return false;
+        };
+        self.input = snapshot.input;
+        self.input_cursor = snapshot.cursor.min(self.input.chars().count());
+        self.multiline_buffer = snapshot.multiline_buffer;
+        self.input_mode = snapshot.input_mode;
+        self.pending_pastes = snapshot.pending_pastes;
+        self.update_status_message();
+        true
+    }
+
     pub fn backspace(&mut self) {
+        self.undoable(Self::backspace_unrecorded);
+    }
+
+    fn backspace_unrecorded(&mut self) {
         // Try atomic placeholder removal at boundary
         if self.try_remove_placeholder_at_cursor(true) {
             self.cleanup_pending_pastes();
@@ -346,6 +644,10 @@ impl App {
     }
 
     pub fn delete(&mut self) {
+        self.undoable(Self::delete_unrecorded);
+    }
+
+    fn delete_unrecorded(&mut self) {
         // Try atomic placeholder removal at boundary
         if self.try_remove_placeholder_at_cursor(false) {
             self.cleanup_pending_pastes();
@@ -361,6 +663,10 @@ impl App {
     }
 
     pub fn delete_prev_word(&mut self) {
+        self.undoable(Self::delete_prev_word_unrecorded);
+    }
+
+    fn delete_prev_word_unrecorded(&mut self) {
         if self.input_cursor == 0 {
             return;
         }
@@ -375,6 +681,10 @@ impl App {
     }
 
     pub fn delete_next_word(&mut self) {
+        self.undoable(Self::delete_next_word_unrecorded);
+    }
+
+    fn delete_next_word_unrecorded(&mut self) {
         let end = next_word_boundary(&self.input, self.input_cursor);
         let cur_b = crate::utils::unicode::char_to_byte_index(&self.input, self.input_cursor);
         let end_b = crate::utils::unicode::char_to_byte_index(&self.input, end);
@@ -385,6 +695,10 @@ impl App {
     }
 
     pub fn kill_to_line_start(&mut self) {
+        self.undoable(Self::kill_to_line_start_unrecorded);
+    }
+
+    fn kill_to_line_start_unrecorded(&mut self) {
         if self.input_cursor == 0 {
             return;
         }
@@ -395,6 +709,10 @@ impl App {
     }
 
     pub fn kill_to_line_end(&mut self) {
+        self.undoable(Self::kill_to_line_end_unrecorded);
+    }
+
+    fn kill_to_line_end_unrecorded(&mut self) {
         let cur_b = crate::utils::unicode::char_to_byte_index(&self.input, self.input_cursor);
         self.input.replace_range(cur_b..self.input.len(), "");
         self.cleanup_pending_pastes();
@@ -445,9 +763,158 @@ impl App {
         }
     }
 
+    /// Ctrl+R: start a reverse history search, or step to the next older match
+    pub fn history_search(&mut self) {
+        if self.input_mode == InputMode::HistorySearch {
+            self.history_search_older();
+            return;
+        }
+        self.history_search = Some(HistorySearch {
+            query: String::new(),
+            match_index: None,
+            failing: false,
+            saved_input: self.input.clone(),
+            saved_cursor: self.input_cursor,
+            saved_mode: self.input_mode.clone(),
+        });
+        self.input_mode = InputMode::HistorySearch;
+    }
+
+    pub fn history_search_insert(&mut self, c: char) {
+        if let Some(search) = self.history_search.as_mut() {
+            search.query.push(c);
+        }
+        self.refresh_history_search();
+    }
+
+    pub fn history_search_backspace(&mut self) {
+        if let Some(search) = self.history_search.as_mut() {
+            search.query.pop();
+        }
+        self.refresh_history_search();
+    }
+
+    /// Re-run the search from the newest entry after the query changed
+    fn refresh_history_search(&mut self) {
+        let Some(search) = self.history_search.as_mut() else {
+            return;
+        };
+        if search.query.is_empty() {
+            search.match_index = None;
+            search.failing = false;
+            self.input = search.saved_input.clone();
+            self.move_cursor_end();
+            return;
+        }
+        let found = self
+            .input_history
+            .iter()
+            .rposition(|entry| entry.contains(&search.query));
+        search.failing = found.is_none();
+        if let Some(i) = found {
+            search.match_index = Some(i);
+            self.input = self.input_history[i].clone();
+            self.move_cursor_end();
+        }
+    }
+
+    fn history_search_older(&mut self) {
+        let Some(search) = self.history_search.as_mut() else {
+            return;
+        };
+        let before = search.match_index.unwrap_or(self.input_history.len());
+        let found = self.input_history[..before]
+            .iter()
+            .rposition(|entry| entry.contains(&search.query));
+        search.failing = found.is_none();
+        if let Some(i) = found {
+            search.match_index = Some(i);
+            self.input = self.input_history[i].clone();
+            self.move_cursor_end();
+        }
+    }
+
+    /// Enter: keep the shown match in the input buffer
+    pub fn accept_history_search(&mut self) {
+        if let Some(search) = self.history_search.take() {
+            self.input_mode = search.saved_mode;
+            self.history_index = None;
+            self.move_cursor_end();
+        }
+    }
+
+    /// Esc: leave the search and put back what was typed before it
+    pub fn cancel_history_search(&mut self) {
+        if let Some(search) = self.history_search.take() {
+            self.input = search.saved_input;
+            self.input_cursor = search.saved_cursor;
+            self.input_mode = search.saved_mode;
+        }
+    }
+
     /// Show execution result popup
-    pub fn show_execution_result(&mut self, command: String, output: String) {
-        self.popup_state = PopupState::ExecutionResult { command, output };
+    pub fn show_execution_result(&mut self, command: String, output: String, code: Option<i32>) {
+        self.popup_state = PopupState::ExecutionResult {
+            command,
+            output,
+            running: false,
+            scroll: 0,
+            exit_code: code,
+        };
+    }
+
+    /// Open the execution popup for a command whose output will stream in
+    pub fn start_execution(&mut self, command: String) {
+        self.popup_state = PopupState::ExecutionResult {
+            command,
+            output: String::new(),
+            running: true,
+            scroll: 0,
+            exit_code: None,
+        };
+    }
+
+    /// Append a chunk of output from the running command
+    pub fn append_execution_output(&mut self, chunk: &str) {
+        if let PopupState::ExecutionResult { output, .. } = &mut self.popup_state {
+            output.push_str(chunk);
+        }
+    }
+
+    /// Mark the running command as finished and note how it exited
+    pub fn finish_execution(&mut self, code: Option<i32>) {
+        if let PopupState::ExecutionResult {
+            output,
+            running,
+            exit_code,
+            ..
+        } = &mut self.popup_state
+        {
+            *running = false;
+            *exit_code = code;
+            if !output.is_empty() && !output.ends_with('\n') {
+                output.push('\n');
+            }
+            match code {
+                Some(c) => output.push_str(&format!("[exit code: {}]", c)),
+                None => output.push_str("[terminated]"),
+            }
+        }
+    }
+
+    /// Whether a command is still running in the execution popup
+    pub fn is_execution_running(&self) -> bool {
+        matches!(
+            self.popup_state,
+            PopupState::ExecutionResult { running: true, .. }
+        )
+    }
+
+    /// Scroll the execution output; positive values move towards older output
+    pub fn scroll_execution_output(&mut self, rows: isize) {
+        if let PopupState::ExecutionResult { scroll, .. } = &mut self.popup_state {
+            *scroll = scroll.saturating_add_signed(rows);
+        }
     }
 
     /// Show command description popup
@@ -814,6 +1281,198 @@ mod tests {
         )
     }
 
+    #[test]
+    fn cancel_response_keeps_partial_reply() {
+        let mut app = new_empty_app();
+        // Nothing is streaming yet
+        assert!(!app.cancel_response());
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let task = rt.spawn(std::future::pending::<()>());
+        app.start_response();
+        app.append_response("partial answer\n");
+        app.response_task = Some(task.abort_handle());
+
+        assert!(app.cancel_response());
+        assert!(!app.is_receiving_response);
+        assert!(app.current_response.is_empty());
+        assert!(app.response_task.is_none());
+        let last = app.messages.last().unwrap();
+        assert_eq!(last.role, Role::Assistant);
+        assert_eq!(last.content.extract_text(), "partial answer (cancelled)");
+        assert_eq!(app.status_message, "Response cancelled");
+        assert!(rt.block_on(task).unwrap_err().is_cancelled());
+    }
+
+    #[test]
+    fn undo_restores_destructive_edits() {
+        let mut app = new_empty_app();
+        for c in "hello world".chars() {
+            app.insert_char(c);
+        }
+        // Typing isn't destructive, so there is nothing to undo yet
+        assert!(!app.undo());
+        app.delete_prev_word();
+        assert_eq!(app.input, "hello ");
+        app.backspace();
+        assert_eq!(app.input, "hello");
+        assert!(app.undo());
+        assert_eq!(app.input, "hello ");
+        assert!(app.undo());
+        assert_eq!(app.input, "hello world");
+        assert_eq!(app.input_cursor, 11);
+        assert!(!app.undo());
+
+        for _ in 0..(UNDO_DEPTH + 10) {
+            app.insert_char('x');
+            app.backspace();
+        }
+        assert_eq!(app.undo_stack.len(), UNDO_DEPTH);
+    }
+
+    #[test]
+    fn undo_restores_pastes_and_merged_lines() {
+        let mut app = new_empty_app();
+        let placeholder = "📋[PASTE: 300 chars]".to_string();
+        app.undoable(|app| {
+            for c in placeholder.chars() {
+                app.insert_char(c);
+            }
+            app.register_pending_paste(placeholder.clone(), "p".repeat(300));
+        });
+        // Backspace removes the whole placeholder and forgets the paste
+        app.backspace();
+        assert!(app.input.is_empty());
+        assert!(app.pending_pastes.is_empty());
+        assert!(app.undo());
+        assert_eq!(app.input, placeholder);
+        assert_eq!(app.expand_placeholders_for_submit(), "p".repeat(300));
+
+        app.clear_input();
+        app.input_mode = InputMode::MultiLine;
+        app.multiline_buffer = vec!["first".into()];
+        app.input = "second".into();
+        app.input_cursor = 0;
+        // Backspace at the start of a line joins it onto the previous one
+        app.backspace();
+        assert_eq!(app.input, "firstsecond");
+        assert_eq!(app.input_mode, InputMode::Normal);
+        assert!(app.undo());
+        assert_eq!(app.multiline_buffer, vec!["first".to_string()]);
+        assert_eq!(app.input, "second");
+        assert_eq!(app.input_mode, InputMode::MultiLine);
+    }
+
+    #[test]
+    fn token_estimate_is_refreshed_after_a_response() {
+        let mut app = new_empty_app();
+        // "test" system message: 4 chars -> 1 token
+        assert_eq!(app.estimated_tokens, 1);
+        app.start_response();
+        app.append_response(&"x".repeat(400));
+        app.finish_response().unwrap();
+        assert_eq!(app.estimated_tokens, 101);
+    }
+
+    #[test]
+    fn last_assistant_text_skips_tool_only_replies() {
+        let mut app = new_empty_app();
+        assert_eq!(app.last_assistant_text(), None);
+        app.add_message(ChatMessage::new(Role::User, "hi"));
+        app.add_message(ChatMessage::new(Role::Assistant, "first answer"));
+        app.add_message(ChatMessage::new(Role::User, "again"));
+        app.add_message(ChatMessage::new(Role::Assistant, ""));
+        assert_eq!(app.last_assistant_text().as_deref(), Some("first answer"));
+    }
+
+    #[test]
+    fn drop_trailing_replies_keeps_the_last_question() {
+        let mut app = new_empty_app();
+        assert!(!app.drop_trailing_replies());
+        app.add_message(ChatMessage::new(Role::User, "first"));
+        app.add_message(ChatMessage::new(Role::Assistant, "answer"));
+        app.add_message(ChatMessage::new(Role::User, "second"));
+        app.add_message(ChatMessage::new(Role::Assistant, "calling a tool"));
+        app.add_message(ChatMessage::new(Role::Tool, "tool output"));
+        app.add_message(ChatMessage::new(Role::Assistant, "partial (cancelled)"));
+
+        assert!(app.drop_trailing_replies());
+        assert_eq!(app.messages.len(), 4);
+        assert_eq!(app.message_times.len(), 4);
+        assert_eq!(app.messages[3].content.extract_text(), "second");
+        // Nothing left to drop, but the question can still be answered again
+        assert!(app.drop_trailing_replies());
+        assert_eq!(app.messages.len(), 4);
+    }
+
+    #[test]
+    fn clear_conversation_keeps_only_the_system_prompt() {
+        let mut app = new_empty_app();
+        app.add_message(ChatMessage::new(Role::User, "hi"));
+        app.add_message(ChatMessage::new(Role::Assistant, "ls -la"));
+        app.last_command = "ls -la".into();
+        app.clear_conversation();
+        assert_eq!(app.messages.len(), 1);
+        assert_eq!(app.messages[0].role, Role::System);
+        assert_eq!(app.message_times.len(), 1);
+        assert!(app.last_command.is_empty());
+        assert!(app.visible_messages().is_empty());
+
+        app.set_system_prompt("You are a reviewer".into());
+        assert_eq!(app.messages.len(), 1);
+        assert_eq!(app.messages[0].content.extract_text(), "You are a reviewer");
+    }
+
+    #[test]
+    fn message_times_follow_messages() {
+        let mut app = new_empty_app();
+        app.max_display_messages = 3;
+        for i in 0..5 {
+            app.add_message(ChatMessage::new(Role::User, format!("m{}", i)));
+        }
+        assert_eq!(app.messages.len(), 3);
+        assert_eq!(app.message_times.len(), 3);
+        assert!(app.message_times.iter().all(Option::is_some));
+        let visible = app.visible_messages();
+        assert_eq!(visible[0].0.content.to_string(), "m2");
+    }
+
+    #[test]
+    fn history_search_finds_cycles_and_restores() {
+        let mut app = new_empty_app();
+        for line in ["git status", "cargo build", "git push", "ls"] {
+            app.push_history(line.to_string());
+        }
+        app.input = "draft".to_string();
+        app.move_cursor_end();
+
+        app.history_search();
+        assert_eq!(app.input_mode, InputMode::HistorySearch);
+        for c in "git".chars() {
+            app.history_search_insert(c);
+        }
+        assert_eq!(app.input, "git push");
+        app.history_search();
+        assert_eq!(app.input, "git status");
+        // No older match: keep showing the last one
+        app.history_search();
+        assert_eq!(app.input, "git status");
+        assert!(app.history_search.as_ref().unwrap().failing);
+
+        app.cancel_history_search();
+        assert_eq!(app.input, "draft");
+        assert_eq!(app.input_mode, InputMode::Normal);
+
+        app.history_search();
+        app.history_search_insert('c');
+        app.accept_history_search();
+        assert_eq!(app.input, "cargo build");
+        assert_eq!(app.input_cursor, "cargo build".chars().count());
+        assert!(app.history_search.is_none());
+    }
+
     #[test]
     fn cjk_insert_backspace_delete_are_safe() {
         let mut app = new_empty_app();
@@ -858,4 +1517,57 @@ mod tests {
         app.cleanup_pending_pastes();
         assert!(app.pending_pastes.is_empty());
     }
+
+    #[test]
+    fn tool_call_round_pairs_call_and_result() {
+        let mut app = new_empty_app();
+        app.start_response();
+        app.append_tool_call_delta(None, Some("get_time".into()), Some("{\"tz\":".into()));
+        app.append_tool_call_delta(None, None, Some("\"UTC\"}".into()));
+        assert!(app.begin_tool_confirmation());
+        assert_eq!(
+            app.popup_state,
+            PopupState::ToolConfirm {
+                name: "get_time".into(),
+                arguments: "{\"tz\":\"UTC\"}".into(),
+            }
+        );
+
+        let call = app.take_pending_tool().unwrap();
+        assert!(!app.is_popup_shown());
+        let assistant = app.messages.last().unwrap();
+        let calls = assistant.tool_calls.as_ref().unwrap();
+        assert_eq!(calls[0].id, call.id);
+        assert_eq!(calls[0].function.name, "get_time");
+
+        app.add_tool_result(call.clone(), "12:00".into());
+        let tool_msg = app.messages.last().unwrap();
+        assert_eq!(tool_msg.role, Role::Tool);
+        assert_eq!(tool_msg.tool_call_id, call.id);
+
+        // A plain response has nothing to confirm
+        assert!(!app.begin_tool_confirmation());
+    }
+
+    #[test]
+    fn streamed_execution_accumulates_and_reports_exit() {
+        let mut app = new_empty_app();
+        app.start_execution("make".into());
+        assert!(app.is_execution_running());
+        app.append_execution_output("building");
+        app.scroll_execution_output(3);
+        app.scroll_execution_output(-5);
+        app.finish_execution(Some(2));
+        assert!(!app.is_execution_running());
+        assert_eq!(
+            app.popup_state,
+            PopupState::ExecutionResult {
+                command: "make".into(),
+                output: "building\n[exit code: 2]".into(),
+                running: false,
+                scroll: 0,
+                exit_code: Some(2),
+            }
+        );
+    }
 }
@@ -0,0 +1,128 @@
+//! Slash commands typed into the TUI composer (`/model`, `/clear`, ...).
+//!
+//! They change the session locally and are never sent to the model.
+
+/// Listed in the help overlay (F1, Ctrl+H or `/help`)
+pub const HELP: &str = "Slash commands (never sent to the model):
+/model [NAME]  show or switch the model
+/temp VALUE    set the temperature (0-2)
+/role NAME     replace the system prompt with a saved role
+/clear         forget the conversation (the saved session too)
+/copy          copy the latest answer to the clipboard (also Ctrl+Y)
+/retry         answer the last message again (also Ctrl+G)
+/help          show this list
+Start a message with // to send a literal leading /";
+
+/// A command handled by the TUI itself
+#[derive(Debug, Clone, PartialEq)]
+pub enum SlashCommand {
+    /// `/model` alone reports the current model
+    Model(Option<String>),
+    Temperature(f32),
+    Role(String),
+    Clear,
+    Copy,
+    Retry,
+    Help,
+}
+
+/// What a submitted line turned out to be
+#[derive(Debug, Clone, PartialEq)]
+pub enum Parsed {
+    /// Text for the model (`//` escapes a leading slash)
+    Message(String),
+    Command(SlashCommand),
+    /// Looked like a command but isn't one; the error is shown locally
+    Invalid(String),
+}
+
+/// Split `/name args` off a submitted line.
+///
+/// Only `/` followed by letters counts as a command, so paths like `/usr/bin/env` are sent as is.
+pub fn parse(input: &str) -> Parsed {
+    let line = input.trim();
+    if let Some(rest) = line.strip_prefix("//") {
+        return Parsed::Message(format!("/{}", rest));
+    }
+    let Some(body) = line.strip_prefix('/') else {
+        return Parsed::Message(input.to_string());
+    };
+    let (name, arg) = match body.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, arg.trim()),
+        None => (body, ""),
+    };
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Parsed::Message(input.to_string());
+    }
+
+    let command = match name.to_ascii_lowercase().as_str() {
+        "model" => SlashCommand::Model(Some(arg.to_string()).filter(|a| !a.is_empty())),
+        "temp" | "temperature" => match arg.parse::<f32>() {
+            Ok(t) if (0.0..=2.0).contains(&t) => SlashCommand::Temperature(t),
+            _ => return Parsed::Invalid("/temp needs a number between 0 and 2".to_string()),
+        },
+        "role" if arg.is_empty() => {
+            return Parsed::Invalid("/role needs the name of a saved role".to_string())
+        }
+        "role" => SlashCommand::Role(arg.to_string()),
+        "clear" => SlashCommand::Clear,
+        "copy" => SlashCommand::Copy,
+        "retry" => SlashCommand::Retry,
+        "help" => SlashCommand::Help,
+        _ => return Parsed::Invalid(format!("Unknown command /{} (try /help)", name)),
+    };
+    Parsed::Command(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_and_arguments() {
+        assert_eq!(
+            parse("/model gpt-4o-mini"),
+            Parsed::Command(SlashCommand::Model(Some("gpt-4o-mini".into())))
+        );
+        assert_eq!(parse("/model"), Parsed::Command(SlashCommand::Model(None)));
+        assert_eq!(
+            parse("/temp 0.7"),
+            Parsed::Command(SlashCommand::Temperature(0.7))
+        );
+        assert_eq!(
+            parse("  /role  reviewer "),
+            Parsed::Command(SlashCommand::Role("reviewer".into()))
+        );
+        assert_eq!(parse("/CLEAR"), Parsed::Command(SlashCommand::Clear));
+        assert_eq!(parse("/copy"), Parsed::Command(SlashCommand::Copy));
+        assert_eq!(parse("/retry"), Parsed::Command(SlashCommand::Retry));
+        assert_eq!(parse("/help"), Parsed::Command(SlashCommand::Help));
+    }
+
+    #[test]
+    fn bad_commands_stay_local() {
+        assert!(matches!(parse("/temp hot"), Parsed::Invalid(_)));
+        assert!(matches!(parse("/temp 3"), Parsed::Invalid(_)));
+        assert!(matches!(parse("/role"), Parsed::Invalid(_)));
+        assert_eq!(
+            parse("/frobnicate now"),
+            Parsed::Invalid("Unknown command /frobnicate (try /help)".into())
+        );
+    }
+
+    #[test]
+    fn paths_and_escapes_are_messages() {
+        assert_eq!(
+            parse("/usr/bin/env is what?"),
+            Parsed::Message("/usr/bin/env is what?".into())
+        );
+        assert_eq!(
+            parse("//model is a word"),
+            Parsed::Message("/model is a word".into())
+        );
+        assert_eq!(
+            parse("hello /model"),
+            Parsed::Message("hello /model".into())
+        );
+    }
+}
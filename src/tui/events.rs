@@ -1,5 +1,6 @@
 //! Custom event types for TUI application.
 
+use super::app::PendingToolCall;
 use crate::execution::ExecutionResult;
 use crate::llm::StreamEvent;
 use crate::process::InterpreterType;
@@ -22,7 +23,17 @@ pub enum TuiEvent {
     /// Request to execute a shell command
     ExecuteCommand(String),
     /// Command execution completed with result
-    ExecutionResult { command: String, output: String },
+    ExecutionResult {
+        command: String,
+        output: String,
+        code: Option<i32>,
+    },
+    /// Output chunk from the command running in the execution popup
+    ExecutionOutput(String),
+    /// The streamed command exited (`None` when no exit code is available)
+    ExecutionFinished { code: Option<i32> },
+    /// Stop the running command (Ctrl+C in the execution popup)
+    KillExecution,
     /// Request to describe a shell command
     DescribeCommand(String),
     /// Command description received from AI
@@ -36,6 +47,10 @@ pub enum TuiEvent {
     DescriptionContent(String),
     /// Description streaming finished
     DescriptionStreamFinished,
+    /// Stop the response being streamed (Esc or Ctrl+X)
+    CancelResponse,
+    /// Answer the last user message again (Ctrl+G or `/retry`)
+    Regenerate,
     /// Process next message from queue
     ProcessNextMessage,
     /// Session state change
@@ -55,10 +70,21 @@ pub enum TuiEvent {
     ShowVariables,
     /// Variables snapshot string to display
     VariablesSnapshot(String),
+    /// Diagnostics the interpreter wrote to stderr
+    InterpreterStderr(String),
     /// Bracketed paste content
     Paste(String),
     /// Clear current interpreter session (restart)
     ClearSession,
+    /// Run a tool call the user accepted
+    RunToolCall(PendingToolCall),
+    /// Tool output (or the reason it didn't run) to send back to the model
+    ToolCallResult {
+        call: PendingToolCall,
+        output: String,
+    },
+    /// A tool file changed on disk (`--watch-functions`)
+    FunctionsChanged,
     /// Toggle mouse capture (true = enable capture; false = allow terminal selection)
     ToggleMouseCapture(bool),
 }
@@ -1,11 +1,12 @@
 //! Async event handler for TUI REPL mode.
 
 use std::io;
+use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Result;
 use crossterm::event::{
-    self, DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyModifiers,
+    self, DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyEventKind, KeyModifiers,
     KeyboardEnhancementFlags, MouseEventKind, PopKeyboardEnhancementFlags,
     PushKeyboardEnhancementFlags,
 };
@@ -18,7 +19,8 @@ use ratatui::prelude::*;
 use tokio::sync::mpsc;
 
 use super::{
-    app::{App, InputMode},
+    app::{App, InputMode, PopupState},
+    commands::{self, Parsed, SlashCommand},
     events::TuiEvent,
     ui::render_ui,
 };
@@ -27,12 +29,21 @@ use crate::process::{self, InterpreterType};
 use crate::{
     cache::ChatSession,
     config::Config,
-    llm::{ChatMessage, ChatOptions, LlmClient, Role, StreamEvent},
+    functions::{watch::watch_functions, Registry},
+    llm::{ChatMessage, ChatOptions, LlmClient, Role, StreamEvent, ToolSchema},
+    role::{role_messages, SystemRole},
+    utils::{
+        clipboard,
+        code::sanitize_generated_code,
+        pty,
+        shell_history::{HistoryAction, ShellHistory},
+    },
 };
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 
 /// Run the TUI-based REPL
 pub async fn run_tui_repl(
+    client: &LlmClient,
     chat_id: &str,
     init_prompt: Option<&str>,
     model: &str,
@@ -44,6 +55,7 @@ pub async fn run_tui_repl(
     allow_interaction: bool,
     role_name: Option<&str>,
     interpreter: Option<InterpreterType>,
+    allow_functions: bool,
 ) -> Result<()> {
     // Check if we're in a proper terminal environment
     if !io::IsTerminal::is_terminal(&io::stdout()) {
@@ -52,25 +64,17 @@ pub async fn run_tui_repl(
         ));
     }
 
-    // Setup terminal
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    stdout.execute(EnterAlternateScreen)?;
-    stdout.execute(crossterm::event::EnableMouseCapture)?;
-    // Enable richer keyboard reporting so Shift+Enter etc. can be detected when supported.
-    let _ = stdout.execute(EnableBracketedPaste);
-    let _ = stdout.execute(PushKeyboardEnhancementFlags(
-        KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
-            | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
-            | KeyboardEnhancementFlags::REPORT_ALTERNATE_KEYS
-            | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES,
-    ));
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    let cfg = Config::load();
+    // Load tools before taking over the screen so MCP warnings stay readable.
+    // Interpreter mode only generates code, so tools are never offered there.
+    let tools_enabled = allow_functions && interpreter.is_none() && cfg.get_bool("REPL_FUNCTIONS");
+    let registry = if tools_enabled {
+        Registry::load(&cfg).await?
+    } else {
+        Registry::default()
+    };
 
     // Initialize application components
-    let cfg = Config::load();
-    let client = LlmClient::from_config(&cfg)?;
     let session = ChatSession::from_config(&cfg);
 
     if chat_id == "temp" {
@@ -90,6 +94,25 @@ pub async fn run_tui_repl(
         vec![ChatMessage::new(Role::System, system_role_text)]
     };
 
+    // --print-system-role has to print before the screen is taken over
+    client.show_system_role(&history);
+
+    // Setup terminal
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    stdout.execute(EnterAlternateScreen)?;
+    stdout.execute(crossterm::event::EnableMouseCapture)?;
+    // Enable richer keyboard reporting so Shift+Enter etc. can be detected when supported.
+    let _ = stdout.execute(EnableBracketedPaste);
+    let _ = stdout.execute(PushKeyboardEnhancementFlags(
+        KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+            | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+            | KeyboardEnhancementFlags::REPORT_ALTERNATE_KEYS
+            | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES,
+    ));
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
     // Initialize TUI app state
     let mut app = App::new(
         chat_id.to_string(),
@@ -99,10 +122,28 @@ pub async fn run_tui_repl(
         model.to_string(),
         interpreter,
     );
+    app.temperature = temperature;
+    app.max_context_tokens = cfg.get_usize("MAX_CONTEXT_TOKENS").filter(|&n| n > 0);
 
     // Create event channels
     let (event_tx, event_rx) = mpsc::unbounded_channel::<TuiEvent>();
 
+    // --watch-functions: reload tool files whenever they change (kept alive until exit)
+    let _functions_watcher = if tools_enabled && cfg.get_bool("WATCH_FUNCTIONS") {
+        let tx = event_tx.clone();
+        match watch_functions(&cfg, move || {
+            let _ = tx.send(TuiEvent::FunctionsChanged);
+        }) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                app.status_message = format!("Not watching tool files: {:#}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
     // Process initial prompt if provided
     if let Some(prompt) = init_prompt {
         let prompt_owned = prompt.to_string();
@@ -118,11 +159,11 @@ pub async fn run_tui_repl(
     let result = run_app(
         &mut terminal,
         &mut app,
-        client,
+        client.clone(),
         session,
+        Arc::new(registry),
         event_tx,
         event_rx,
-        temperature,
         top_p,
         max_tokens,
     )
@@ -156,97 +197,61 @@ async fn run_app(
     app: &mut App,
     client: LlmClient,
     session: ChatSession,
+    mut registry: Arc<Registry>,
     event_tx: mpsc::UnboundedSender<TuiEvent>,
     mut event_rx: mpsc::UnboundedReceiver<TuiEvent>,
-    temperature: f32,
     top_p: f32,
     max_tokens: Option<u32>,
 ) -> Result<()> {
-    // Optional: initialize interpreter session (Python MVP)
-    let mut py_stdin_opt: Option<tokio::process::ChildStdin> = None;
-    let mut _py_child_opt: Option<tokio::process::Child> = None;
-    if matches!(app.interpreter, Some(InterpreterType::Python)) {
-        // Python bootstrap script for NDJSON loop
-        let bootstrap = r#"
-import sys, json, io, traceback, contextlib
-user_globals = {}
-orig_stdout = sys.stdout
-orig_stderr = sys.stderr
-
-def summarize_vars(g):
-    summary = {}
-    for k, v in g.items():
-        if k.startswith('_'):
-            continue
-        tname = type(v).__name__
-        info = tname
-        try:
-            if tname == 'DataFrame':
-                try:
-                    info = f'DataFrame({v.shape[0]}x{v.shape[1]})'
-                except Exception:
-                    info = 'DataFrame'
-            elif hasattr(v, 'shape'):
-                try:
-                    info = f'array{tuple(v.shape)}'
-                except Exception:
-                    pass
-        except Exception:
-            pass
-        summary[k] = info
-    return summary
-
-while True:
-    line = sys.stdin.readline()
-    if not line:
-        break
-    line = line.strip()
-    if not line:
-        continue
-    try:
-        req = json.loads(line)
-    except Exception as e:
-        print(json.dumps({"id": None, "error": {"message": "invalid_json", "detail": str(e)}}), file=orig_stdout, flush=True)
-        continue
-    rid = req.get('id')
-    method = req.get('method')
-    params = req.get('params', {})
-    if method == 'execute':
-        code = params.get('code', '')
-        capture_output = params.get('capture_output', True)
-        out = io.StringIO()
-        errors = []
-        success = True
-        try:
-            if capture_output:
-                with contextlib.redirect_stdout(out):
-                    with contextlib.redirect_stderr(out):
-                        exec(code, user_globals)
-            else:
-                exec(code, user_globals)
-        except Exception as e:
-            success = False
-            tb = traceback.format_exc()
-            errors.append(tb)
-        output = out.getvalue() if capture_output else ''
-        vars_summary = summarize_vars(user_globals)
-        resp = {"id": rid, "result": {"success": success, "output": output, "errors": errors, "variables": vars_summary, "plots": []}}
-        print(json.dumps(resp), file=orig_stdout, flush=True)
-    elif method == 'vars':
-        vars_summary = summarize_vars(user_globals)
-        resp = {"id": rid, "result": {"success": True, "output": "", "errors": [], "variables": vars_summary, "plots": []}}
-        print(json.dumps(resp), file=orig_stdout, flush=True)
-    elif method == 'ping':
-        print(json.dumps({"id": rid, "result": "pong"}), file=orig_stdout, flush=True)
-    else:
-        print(json.dumps({"id": rid, "error": {"message": "unknown_method"}}), file=orig_stdout, flush=True)
-"#;
-
-        let handle = process::python::start_python(bootstrap).await?;
+    let schemas = registry.schemas();
+    let mut tools = if schemas.is_empty() {
+        None
+    } else {
+        Some(schemas)
+    };
+    // Shell command running in the execution popup, and the timer that stops it
+    let exec_timeout_secs = Config::load().get_usize("TUI_EXEC_TIMEOUT").unwrap_or(300) as u64;
+    let mut exec_killer: Option<pty::PtyKiller> = None;
+    let mut exec_timer: Option<tokio::task::JoinHandle<()>> = None;
+    // Optional: interpreter session speaking the NDJSON protocol (see `process`)
+    let mut interp_stdin_opt: Option<tokio::process::ChildStdin> = None;
+    let mut interp_child_opt: Option<tokio::process::Child> = None;
+    if let Some(language) = app.interpreter {
+        let handle = process::start(language).await?;
         let child = handle.child;
-        let py_stdin = handle.stdin;
+        let interp_stdin = handle.stdin;
         let stdout = handle.stdout;
 
+        // Spawn reader task for stderr, batching lines that arrive together (e.g. a traceback)
+        let mut err_lines = BufReader::new(handle.stderr).lines();
+        let tx = event_tx.clone();
+        tokio::spawn(async move {
+            let mut batch = String::new();
+            loop {
+                // `next_line` is cancel-safe, so a timed-out read loses nothing
+                let read = if batch.is_empty() {
+                    Ok(err_lines.next_line().await)
+                } else {
+                    tokio::time::timeout(Duration::from_millis(50), err_lines.next_line()).await
+                };
+                match read {
+                    Ok(Ok(Some(line))) => {
+                        batch.push_str(&line);
+                        batch.push('\n');
+                    }
+                    // Quiet for a moment: report what has been collected so far
+                    Err(_) => {
+                        let text = std::mem::take(&mut batch);
+                        let _ = tx.send(TuiEvent::InterpreterStderr(text));
+                    }
+                    _ => break,
+                }
+            }
+            if !batch.trim().is_empty() {
+                let _ = tx.send(TuiEvent::InterpreterStderr(batch));
+            }
+        });
+
         // Spawn reader task for NDJSON responses
         let mut reader = BufReader::new(stdout);
         let tx = event_tx.clone();
@@ -303,12 +308,17 @@ while True:
                             }
                         }
                     }
+                    let exit_code = obj
+                        .get("exit_code")
+                        .and_then(|v| v.as_i64())
+                        .map(|c| c as i32);
                     let plots = Vec::new();
                     CodeExecResult {
                         success,
                         output,
                         errors,
                         variables,
+                        exit_code,
                         plots,
                     }
                 } else if let Some(err) = parsed.get("error") {
@@ -321,6 +331,7 @@ while True:
                         output: String::new(),
                         errors: vec![msg.to_string()],
                         variables: Default::default(),
+                        exit_code: None,
                         plots: vec![],
                     }
                 } else {
@@ -329,6 +340,7 @@ while True:
                         output: String::new(),
                         errors: vec!["invalid_response".to_string()],
                         variables: Default::default(),
+                        exit_code: None,
                         plots: vec![],
                     }
                 };
@@ -353,8 +365,8 @@ while True:
             }
         });
 
-        py_stdin_opt = Some(py_stdin);
-        _py_child_opt = Some(child);
+        interp_stdin_opt = Some(interp_stdin);
+        interp_child_opt = Some(child);
     }
     let mut req_counter: u64 = 1;
     let running = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(true));
@@ -432,15 +444,15 @@ while True:
                             &client,
                             &session,
                             event_tx.clone(),
-                            temperature,
                             top_p,
                             max_tokens,
+                            tools.clone(),
                         )
                         .await?;
                     }
                 }
                 TuiEvent::Paste(content) => {
-                    app_paste_text(app, &content);
+                    app.undoable(|app| app_paste_text(app, &content));
                 }
                 TuiEvent::ProcessNextMessage => {
                     // Process next message from queue
@@ -451,31 +463,188 @@ while True:
                             &client,
                             &session,
                             event_tx.clone(),
-                            temperature,
                             top_p,
                             max_tokens,
+                            tools.clone(),
                         )
                         .await?;
                     }
                 }
+                TuiEvent::CancelResponse if app.cancel_response() => {
+                    if app.chat_id != "temp" {
+                        session.write(&app.chat_id, &app.model, app.messages.clone())?;
+                    }
+                    if !app.message_queue.is_empty() {
+                        let _ = event_tx.send(TuiEvent::ProcessNextMessage);
+                    }
+                }
+                TuiEvent::Regenerate => {
+                    if app.is_receiving_response {
+                        app.status_message =
+                            "Wait for the response to finish (Esc cancels it)".to_string();
+                    } else if app.drop_trailing_replies() {
+                        // The session must not keep the reply being replaced
+                        if app.chat_id != "temp" {
+                            session.write(&app.chat_id, &app.model, app.messages.clone())?;
+                        }
+                        app.start_response();
+                        stream_response(
+                            app,
+                            &client,
+                            event_tx.clone(),
+                            top_p,
+                            max_tokens,
+                            tools.clone(),
+                        );
+                    } else {
+                        app.status_message = "Nothing to regenerate yet".to_string();
+                    }
+                }
                 TuiEvent::LlmStream(stream_event) => {
                     handle_llm_stream_event(app, stream_event, &session, event_tx.clone()).await?;
                 }
+                TuiEvent::RunToolCall(call) => {
+                    let registry = registry.clone();
+                    let tx = event_tx.clone();
+                    tokio::spawn(async move {
+                        let output = registry
+                            .execute(&call.name, &call.arguments)
+                            .await
+                            .unwrap_or_else(|e| format!("tool error: {}", e));
+                        let _ = tx.send(TuiEvent::ToolCallResult { call, output });
+                    });
+                }
+                TuiEvent::FunctionsChanged => {
+                    // Tool calls already running keep the registry they started with
+                    let mut updated = (*registry).clone();
+                    match updated.reload_files(&Config::load().functions_path()) {
+                        Ok(()) => {
+                            let schemas = updated.schemas();
+                            app.status_message =
+                                format!("Reloaded tools ({} available)", schemas.len());
+                            tools = Some(schemas).filter(|s| !s.is_empty());
+                            registry = Arc::new(updated);
+                        }
+                        Err(e) => app.status_message = format!("Tool reload failed: {:#}", e),
+                    }
+                }
+                TuiEvent::ToolCallResult { call, output } => {
+                    app.add_tool_result(call, output);
+                    if app.chat_id != "temp" {
+                        session.write(&app.chat_id, &app.model, app.messages.clone())?;
+                    }
+                    // Let the model answer with the tool result in context
+                    stream_response(
+                        app,
+                        &client,
+                        event_tx.clone(),
+                        top_p,
+                        max_tokens,
+                        tools.clone(),
+                    );
+                }
                 TuiEvent::Quit => break,
                 TuiEvent::ExecuteCommand(cmd) => {
-                    // Execute command in background and capture output
-                    let cmd_clone = cmd.clone();
-                    let tx = event_tx.clone();
-                    tokio::task::spawn_blocking(move || {
-                        let output = execute_command_with_output(&cmd_clone);
-                        let _ = tx.send(TuiEvent::ExecutionResult {
-                            command: cmd_clone,
-                            output,
+                    let prompt = app
+                        .messages
+                        .iter()
+                        .rev()
+                        .find(|m| m.role == Role::User)
+                        .map(|m| m.content.extract_text())
+                        .unwrap_or_default();
+                    // Size the PTY like the popup body so programs wrap output sensibly
+                    let (cols, rows) = crossterm::terminal::size()
+                        .map(|(w, h)| (w * 85 / 100, h * 75 / 100))
+                        .unwrap_or((80, 24));
+                    let spawned = pty::spawn_shell(
+                        &cmd,
+                        cols.saturating_sub(2).max(20),
+                        rows.saturating_sub(7).max(5),
+                    );
+                    if let Ok(mut proc) = spawned {
+                        app.start_execution(cmd.clone());
+                        exec_killer = Some(proc.killer());
+                        if let Some(timer) = exec_timer.take() {
+                            timer.abort();
+                        }
+                        if exec_timeout_secs > 0 {
+                            let mut killer = proc.killer();
+                            let tx = event_tx.clone();
+                            exec_timer = Some(tokio::spawn(async move {
+                                tokio::time::sleep(Duration::from_secs(exec_timeout_secs)).await;
+                                let _ = tx.send(TuiEvent::ExecutionOutput(format!(
+                                    "\n[timed out after {}s]\n",
+                                    exec_timeout_secs
+                                )));
+                                killer.kill();
+                            }));
+                        }
+                        let tx = event_tx.clone();
+                        tokio::task::spawn_blocking(move || {
+                            use std::io::Read;
+                            let mut buf = [0u8; 4096];
+                            let mut pending = Vec::new();
+                            // Linux reports EIO rather than EOF once the command has exited
+                            while let Ok(n) = proc.reader.read(&mut buf) {
+                                if n == 0 {
+                                    break;
+                                }
+                                pending.extend_from_slice(&buf[..n]);
+                                let text = pty::take_utf8(&mut pending);
+                                if !text.is_empty() {
+                                    let _ = tx.send(TuiEvent::ExecutionOutput(text));
+                                }
+                            }
+                            let code = proc.wait();
+                            ShellHistory::from_config(&Config::load()).record(
+                                &prompt,
+                                &cmd,
+                                HistoryAction::Executed,
+                                code,
+                            );
+                            let _ = tx.send(TuiEvent::ExecutionFinished { code });
                         });
-                    });
+                    } else {
+                        // No PTY available: run to completion and show the captured output
+                        let cmd_clone = cmd.clone();
+                        let tx = event_tx.clone();
+                        tokio::task::spawn_blocking(move || {
+                            let (output, code) = execute_command_with_output(&cmd_clone);
+                            ShellHistory::from_config(&Config::load()).record(
+                                &prompt,
+                                &cmd_clone,
+                                HistoryAction::Executed,
+                                code,
+                            );
+                            let _ = tx.send(TuiEvent::ExecutionResult {
+                                command: cmd_clone,
+                                output,
+                                code,
+                            });
+                        });
+                    }
                 }
-                TuiEvent::ExecutionResult { command, output } => {
-                    app.show_execution_result(command, output);
+                TuiEvent::ExecutionResult {
+                    command,
+                    output,
+                    code,
+                } => {
+                    app.show_execution_result(command, output, code);
+                }
+                TuiEvent::ExecutionOutput(chunk) => {
+                    app.append_execution_output(&chunk);
+                }
+                TuiEvent::ExecutionFinished { code } => {
+                    exec_killer = None;
+                    if let Some(timer) = exec_timer.take() {
+                        timer.abort();
+                    }
+                    app.finish_execution(code);
+                }
+                TuiEvent::KillExecution => {
+                    if let Some(killer) = exec_killer.as_mut() {
+                        killer.kill();
+                    }
                 }
                 TuiEvent::DescribeCommand(cmd) => {
                     // Generate description using fake model or real describe function
@@ -489,8 +658,10 @@ while True:
                         let cmd_clone = cmd.clone();
                         let model_clone = app.model.clone();
                         let tx = event_tx.clone();
+                        let client = client.clone();
                         tokio::spawn(async move {
                             match generate_streaming_command_description(
+                                &client,
                                 &cmd_clone,
                                 &model_clone,
                                 tx.clone(),
@@ -511,51 +682,40 @@ while True:
                         });
                     }
                 }
-                TuiEvent::ExecuteCode { language, code } => match language {
-                    InterpreterType::Python => {
-                        if let Some(stdin) = py_stdin_opt.as_mut() {
-                            let id = {
-                                let cur = req_counter;
-                                req_counter = req_counter.wrapping_add(1);
-                                format!("req-{}", cur)
-                            };
-                            let code = sanitize_generated_code(&code);
-                            let req = serde_json::json!({
-                                "id": id,
-                                "method": "execute",
-                                "params": {"code": code, "capture_output": true}
-                            });
-                            let _ = stdin
-                                .write_all((serde_json::to_string(&req).unwrap() + "\n").as_bytes())
-                                .await;
-                        } else {
-                            app.add_message(ChatMessage::new(
-                                Role::Assistant,
-                                "Interpreter not initialized".to_string(),
-                            ));
-                        }
-                    }
-                    InterpreterType::R => {
+                TuiEvent::ExecuteCode { language, code } => {
+                    if let Some(stdin) = interp_stdin_opt.as_mut() {
+                        let id = {
+                            let cur = req_counter;
+                            req_counter = req_counter.wrapping_add(1);
+                            format!("req-{}", cur)
+                        };
+                        let code = sanitize_generated_code(&code);
+                        let req = serde_json::json!({
+                            "id": id,
+                            "method": "execute",
+                            "params": {"code": code, "capture_output": true}
+                        });
+                        let _ = stdin
+                            .write_all((serde_json::to_string(&req).unwrap() + "\n").as_bytes())
+                            .await;
+                    } else {
                         app.add_message(ChatMessage::new(
                             Role::Assistant,
-                            "R interpreter is not yet implemented".to_string(),
+                            format!("{:?} interpreter not initialized", language),
                         ));
                     }
-                },
+                }
                 TuiEvent::ShowVariables => {
-                    if matches!(app.interpreter, Some(InterpreterType::Python)) {
-                        if let Some(stdin) = py_stdin_opt.as_mut() {
-                            let id = {
-                                let cur = req_counter;
-                                req_counter = req_counter.wrapping_add(1);
-                                format!("vars-{}", cur)
-                            };
-                            let req =
-                                serde_json::json!({ "id": id, "method": "vars", "params": {} });
-                            let _ = stdin
-                                .write_all((serde_json::to_string(&req).unwrap() + "\n").as_bytes())
-                                .await;
-                        }
+                    if let Some(stdin) = interp_stdin_opt.as_mut() {
+                        let id = {
+                            let cur = req_counter;
+                            req_counter = req_counter.wrapping_add(1);
+                            format!("vars-{}", cur)
+                        };
+                        let req = serde_json::json!({ "id": id, "method": "vars", "params": {} });
+                        let _ = stdin
+                            .write_all((serde_json::to_string(&req).unwrap() + "\n").as_bytes())
+                            .await;
                     }
                 }
                 TuiEvent::CodeExecutionResult(res) => {
@@ -572,11 +732,26 @@ while True:
                     if text.is_empty() && res.success {
                         text = "(ok)".to_string();
                     }
+                    if let Some(code) = res.exit_code.filter(|c| *c != 0) {
+                        if !text.is_empty() {
+                            text.push('\n');
+                        }
+                        text.push_str(&format!("[exit code: {}]", code));
+                    }
                     app.add_message(ChatMessage::new(Role::Assistant, text));
                 }
                 TuiEvent::VariablesSnapshot(text) => {
                     app.add_message(ChatMessage::new(Role::Assistant, text));
                 }
+                TuiEvent::InterpreterStderr(text) => {
+                    let text = text.trim_end();
+                    if !text.is_empty() {
+                        app.add_message(ChatMessage::new(
+                            Role::Assistant,
+                            format!("[STDERR] {}", text),
+                        ));
+                    }
+                }
                 TuiEvent::CommandDescription {
                     command,
                     description,
@@ -605,7 +780,7 @@ while True:
     let _ = input_handle.await;
 
     // Attempt to terminate interpreter if running
-    if let Some(mut child) = _py_child_opt {
+    if let Some(mut child) = interp_child_opt {
         let _ = child.kill().await;
     }
     Ok(())
@@ -617,13 +792,83 @@ async fn handle_key_event(
     key: crossterm::event::KeyEvent,
     event_tx: mpsc::UnboundedSender<TuiEvent>,
 ) -> Result<bool> {
+    // A tool call waits for an explicit decision
+    if matches!(app.popup_state, PopupState::ToolConfirm { .. }) {
+        // Key releases (reported by some terminals) must not count as a decision
+        if key.kind != KeyEventKind::Press {
+            return Ok(false);
+        }
+        if let Some(call) = app.take_pending_tool() {
+            let accepted = matches!(
+                key.code,
+                KeyCode::Enter | KeyCode::Char('y') | KeyCode::Char('Y')
+            );
+            let _ = event_tx.send(if accepted {
+                TuiEvent::RunToolCall(call)
+            } else {
+                TuiEvent::ToolCallResult {
+                    call,
+                    output: "The user declined to run this tool.".to_string(),
+                }
+            });
+        }
+        return Ok(false);
+    }
+
+    // The execution popup scrolls, and a running command can only be stopped, not dismissed
+    if matches!(app.popup_state, PopupState::ExecutionResult { .. }) {
+        let running = app.is_execution_running();
+        if key.kind != KeyEventKind::Press {
+            return Ok(false);
+        }
+        match key.code {
+            KeyCode::Char('c') if running && key.modifiers.contains(KeyModifiers::CONTROL) => {
+                let _ = event_tx.send(TuiEvent::KillExecution);
+            }
+            KeyCode::Up => app.scroll_execution_output(1),
+            KeyCode::Down => app.scroll_execution_output(-1),
+            KeyCode::PageUp => app.scroll_execution_output(10),
+            KeyCode::PageDown => app.scroll_execution_output(-10),
+            _ if !running => app.hide_popup(),
+            _ => {}
+        }
+        return Ok(false);
+    }
+
     // If any popup is shown, any key closes it
     if app.is_popup_shown() {
         app.hide_popup();
         return Ok(false);
     }
 
+    // Ctrl+R search owns the keyboard until it's accepted or cancelled
+    if app.input_mode == InputMode::HistorySearch {
+        if key.kind != KeyEventKind::Press {
+            return Ok(false);
+        }
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('r') if ctrl => app.history_search(),
+            KeyCode::Char('c') | KeyCode::Char('g') if ctrl => app.cancel_history_search(),
+            KeyCode::Esc => app.cancel_history_search(),
+            KeyCode::Enter => app.accept_history_search(),
+            KeyCode::Backspace => app.history_search_backspace(),
+            KeyCode::Char(c) if !ctrl => app.history_search_insert(c),
+            _ => {}
+        }
+        return Ok(false);
+    }
+
     match key.code {
+        // Stop the reply being streamed, keeping what arrived so far
+        KeyCode::Esc if app.is_receiving_response => {
+            let _ = event_tx.send(TuiEvent::CancelResponse);
+        }
+        KeyCode::Char('x')
+            if app.is_receiving_response && key.modifiers.contains(KeyModifiers::CONTROL) =>
+        {
+            let _ = event_tx.send(TuiEvent::CancelResponse);
+        }
         // Fallback newline: Ctrl+J inserts newline (for terminals not reporting Shift+Enter)
         KeyCode::Char('j') if key.modifiers.contains(KeyModifiers::CONTROL) => {
             match app.input_mode {
@@ -640,6 +885,7 @@ async fn handle_key_event(
                     app.input.clear();
                     app.input_cursor = 0;
                 }
+                InputMode::HistorySearch => {}
             }
         }
         // Fallback submit: Ctrl+S to send (some terminals can't detect Ctrl+Enter)
@@ -792,6 +1038,7 @@ async fn handle_key_event(
                     }
                     app.input_mode = InputMode::Normal;
                 }
+                InputMode::HistorySearch => {}
             }
         }
         KeyCode::F(1) => {
@@ -803,6 +1050,26 @@ async fn handle_key_event(
             app.set_mouse_capture_enabled(enable_capture);
             let _ = event_tx.send(TuiEvent::ToggleMouseCapture(enable_capture));
         }
+        // Ctrl+Z: undo the last deletion, kill or paste
+        KeyCode::Char('z') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.undo();
+        }
+        // Ctrl+T: show/hide message timestamps
+        KeyCode::Char('t') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.toggle_timestamps();
+        }
+        // Ctrl+G: regenerate the latest answer
+        KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            let _ = event_tx.send(TuiEvent::Regenerate);
+        }
+        // Ctrl+Y: copy the latest answer to the clipboard
+        KeyCode::Char('y') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            copy_last_response(app);
+        }
+        // Ctrl+R: reverse search through input history
+        KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.history_search();
+        }
         // Ctrl+H: toggle help (some terminals map Ctrl+H to Backspace and may not trigger this)
         KeyCode::Char('h') if key.modifiers.contains(KeyModifiers::CONTROL) => {
             app.toggle_help();
@@ -849,6 +1116,7 @@ async fn handle_key_event(
                         app.input.clear();
                         app.input_cursor = 0;
                     }
+                    InputMode::HistorySearch => {}
                 }
             } else {
                 // Enter (no Shift) -> submit. Expand placeholders first
@@ -935,41 +1203,140 @@ async fn handle_user_input(
     app: &mut App,
     input: String,
     client: &LlmClient,
-    _session: &ChatSession,
+    session: &ChatSession,
     event_tx: mpsc::UnboundedSender<TuiEvent>,
-    temperature: f32,
     top_p: f32,
     max_tokens: Option<u32>,
+    tools: Option<Vec<ToolSchema>>,
 ) -> Result<()> {
     if input.trim().is_empty() {
         return Ok(());
     }
 
+    // Slash commands are handled here and never reach the model
+    let input = match commands::parse(&input) {
+        Parsed::Message(text) => text,
+        Parsed::Command(SlashCommand::Retry) => {
+            let _ = event_tx.send(TuiEvent::Regenerate);
+            return Ok(());
+        }
+        Parsed::Command(command) => {
+            run_slash_command(app, command, session);
+            return Ok(());
+        }
+        Parsed::Invalid(error) => {
+            app.status_message = format!("❌ {}", error);
+            return Ok(());
+        }
+    };
+
     // Add user message to history
     app.add_message(ChatMessage::new(Role::User, input.clone()));
 
     // Start streaming response
     app.start_response();
+    stream_response(app, client, event_tx, top_p, max_tokens, tools);
+
+    Ok(())
+}
+
+/// Apply a slash command and confirm it in the status bar.
+///
+/// Nothing is added to the conversation, so the model never sees commands or their results.
+fn run_slash_command(app: &mut App, command: SlashCommand, session: &ChatSession) {
+    let reply = match command {
+        SlashCommand::Model(None) => format!("Model: {}", app.model),
+        SlashCommand::Model(Some(model)) => {
+            app.model = model;
+            format!("Model set to {}", app.model)
+        }
+        SlashCommand::Temperature(t) => {
+            app.temperature = t;
+            format!("Temperature set to {}", t)
+        }
+        SlashCommand::Role(name) => match SystemRole::get(&Config::load(), &name) {
+            Ok(role) => {
+                app.set_system_prompt(role.role);
+                format!("Role set to {}", name)
+            }
+            Err(e) => format!("❌ {}", e),
+        },
+        SlashCommand::Clear => {
+            app.clear_conversation();
+            session.invalidate(&app.chat_id);
+            "Conversation cleared".to_string()
+        }
+        SlashCommand::Help => {
+            app.show_help = true;
+            return;
+        }
+        SlashCommand::Copy => {
+            copy_last_response(app);
+            return;
+        }
+        // Needs the request options, so `handle_user_input` turns it into an event
+        SlashCommand::Retry => return,
+    };
+    app.status_message = reply;
+}
 
+/// Put the latest assistant answer on the clipboard and report the outcome in the status bar
+fn copy_last_response(app: &mut App) {
+    let Some(text) = app.last_assistant_text() else {
+        app.status_message = "Nothing to copy yet".to_string();
+        return;
+    };
+    let chars = text.chars().count();
+    app.status_message = match app.clipboard.copy(&text) {
+        Ok(clipboard::CopyTarget::System) => format!("Copied {} chars", chars),
+        Ok(clipboard::CopyTarget::Terminal) => {
+            format!("Copied {} chars via terminal (OSC 52)", chars)
+        }
+        Err(e) => format!("Copy failed: {}", e),
+    };
+}
+
+/// Stream the model's reply to the current conversation into `LlmStream` events
+fn stream_response(
+    app: &mut App,
+    client: &LlmClient,
+    event_tx: mpsc::UnboundedSender<TuiEvent>,
+    top_p: f32,
+    max_tokens: Option<u32>,
+    tools: Option<Vec<ToolSchema>>,
+) {
+    let cfg = Config::load();
     // Prepare messages for LLM
-    // If in interpreter mode, inject a system message to produce code only
+    // If in interpreter mode, prepend a role prompt asking for code only
     let mut messages: Vec<ChatMessage> = Vec::new();
     if let Some(lang) = app.interpreter {
         let content = match lang {
             InterpreterType::Python => "You are a Python code generator. Given the user's request, produce ONLY executable Python code without explanations, comments, or Markdown fences. Avoid triple backticks.",
             InterpreterType::R => "You are an R code generator. Given the user's request, produce ONLY executable R code without explanations, comments, or Markdown fences. Avoid triple backticks.",
         };
-        messages.push(ChatMessage::new(Role::System, content.to_string()));
+        messages.extend(role_messages(&cfg, content.to_string(), true));
+    }
+    // The conversation keeps its role prompt as a system message (hidden from the chat
+    // area, replaced by /role); NO_SYSTEM_ROLE turns it into a user message on the way out
+    for msg in &app.messages {
+        if msg.role == Role::System {
+            messages.extend(role_messages(&cfg, msg.content.extract_text(), true));
+        } else {
+            messages.push(msg.clone());
+        }
     }
-    messages.extend(app.messages.clone());
     let opts = ChatOptions {
         model: app.model.clone(),
-        temperature,
+        temperature: app.temperature,
         top_p,
-        tools: None,
+        tool_choice: tools.as_ref().map(|_| "auto".to_string()),
+        tools,
         parallel_tool_calls: false,
-        tool_choice: None,
         max_tokens,
+        stop_sequences: None,
+        presence_penalty: cfg.get_f32("PRESENCE_PENALTY"),
+        frequency_penalty: cfg.get_f32("FREQUENCY_PENALTY"),
+        resume_on_error: false,
     };
 
     // Create streaming request
@@ -978,34 +1345,42 @@ async fn handle_user_input(
     // Spawn task to handle streaming response
     let _chat_id = app.chat_id.clone();
     let model_for_error = app.model.clone();
-    tokio::spawn(async move {
+    let task = tokio::spawn(async move {
         while let Some(event_result) = stream.next().await {
             match event_result {
                 Ok(stream_event) => {
-                    if event_tx.send(TuiEvent::LlmStream(stream_event)).is_err() {
-                        break; // Channel closed
+                    let done = matches!(stream_event, StreamEvent::Done);
+                    if event_tx.send(TuiEvent::LlmStream(stream_event)).is_err() || done {
+                        return; // Channel closed or response complete
                     }
                 }
                 Err(err) => {
                     // On stream error, surface a friendly message and ensure we close the response
                     let friendly = format_stream_error_message(&err.to_string(), &model_for_error);
                     let _ = event_tx.send(TuiEvent::LlmStream(StreamEvent::Content(friendly)));
-                    let _ = event_tx.send(TuiEvent::LlmStream(StreamEvent::Done));
                     break;
                 }
             }
         }
-        // If the stream ended without explicitly sending Done (rare), send Done to unblock queue
+        // If the stream ended without explicitly sending Done, send Done to unblock queue.
+        // A duplicate Done would close a tool round while it waits for confirmation.
         let _ = event_tx.send(TuiEvent::LlmStream(StreamEvent::Done));
     });
-
-    Ok(())
+    app.response_task = Some(task.abort_handle());
 }
 
 fn app_paste_text(app: &mut App, content: &str) {
     const LARGE_PASTE_CHAR_THRESHOLD: usize = 200;
     const MEDIUM_PASTE_CHAR_THRESHOLD: usize = 50;
 
+    if app.input_mode == InputMode::HistorySearch {
+        // A paste extends the search query; only the first line makes sense there
+        for c in content.lines().next().unwrap_or_default().chars() {
+            app.history_search_insert(c);
+        }
+        return;
+    }
+
     let char_count = content.chars().count();
     let content_to_insert = if char_count > LARGE_PASTE_CHAR_THRESHOLD {
         // Show compact placeholder instead of full content
@@ -1108,6 +1483,8 @@ fn app_paste_text(app: &mut App, content: &str) {
                 app.input_cursor += content.chars().count();
             }
         }
+        // Handled before the placeholder logic above
+        InputMode::HistorySearch => {}
     }
 
     // Force scroll to bottom after paste to ensure pasted content is visible
@@ -1121,6 +1498,10 @@ async fn handle_llm_stream_event(
     session: &ChatSession,
     event_tx: mpsc::UnboundedSender<TuiEvent>,
 ) -> Result<()> {
+    // Events already queued by a cancelled stream are dropped
+    if !app.is_receiving_response {
+        return Ok(());
+    }
     match event {
         StreamEvent::Content(content) => {
             app.append_response(&content);
@@ -1130,22 +1511,39 @@ async fn handle_llm_stream_event(
             }
         }
         StreamEvent::Done => {
+            app.response_task = None;
+            // A tool call keeps the response open until its result has been answered
+            // (the session is saved once the tool result is in, so it never ends on a bare call)
+            if app.begin_tool_confirmation() {
+                return Ok(());
+            }
+
             // Finish the response first
             app.finish_response()?;
 
             // Save session if not temporary
             if app.chat_id != "temp" && !app.messages.is_empty() {
-                session.write(&app.chat_id, app.messages.clone())?;
+                session.write(&app.chat_id, &app.model, app.messages.clone())?;
             }
 
             // Process next message from queue if available
             let _ = event_tx.send(TuiEvent::ProcessNextMessage);
         }
-        StreamEvent::ToolCallDelta { .. } => {
-            // Handle tool calls if needed in the future
+        StreamEvent::ToolCallDelta {
+            id,
+            name,
+            arguments,
+        } => {
+            app.append_tool_call_delta(id, name, arguments);
         }
         StreamEvent::ToolCallsFinish => {
-            // Handle tool call completion
+            // The call is confirmed once the stream reports Done
+        }
+        StreamEvent::UsageReport {
+            prompt_tokens,
+            completion_tokens,
+        } => {
+            app.last_usage = Some((prompt_tokens, completion_tokens));
         }
     }
 
@@ -1198,8 +1596,8 @@ fn format_stream_error_message(err_text: &str, model: &str) -> String {
     msg
 }
 
-/// Execute a command and capture its output
-fn execute_command_with_output(command: &str) -> String {
+/// Execute a command and capture its output (plus the exit code, when there is one)
+fn execute_command_with_output(command: &str) -> (String, Option<i32>) {
     use std::process::{Command, Stdio};
 
     // Determine shell based on platform
@@ -1220,7 +1618,7 @@ fn execute_command_with_output(command: &str) -> String {
             let stdout = String::from_utf8_lossy(&output.stdout);
             let stderr = String::from_utf8_lossy(&output.stderr);
 
-            if output.status.success() {
+            let text = if output.status.success() {
                 if stdout.is_empty() && stderr.is_empty() {
                     "Command executed successfully (no output)".to_string()
                 } else if stderr.is_empty() {
@@ -1235,54 +1633,28 @@ fn execute_command_with_output(command: &str) -> String {
                     stdout,
                     stderr
                 )
-            }
-        }
-        Err(e) => {
-            format!("Failed to execute command: {}", e)
+            };
+            (text, output.status.code())
         }
+        Err(e) => (format!("Failed to execute command: {}", e), None),
     }
 }
 
-/// Sanitize generated code by stripping common Markdown code fences
-fn sanitize_generated_code(s: &str) -> String {
-    let trimmed = s.trim();
-    // If contains triple backticks, attempt to extract the first fenced block
-    if trimmed.starts_with("```") {
-        let mut lines = trimmed.lines();
-        // Skip first fence line (possibly ```python)
-        let _ = lines.next();
-        let mut buf = String::new();
-        for line in lines {
-            if line.starts_with("```") {
-                break;
-            }
-            buf.push_str(line);
-            buf.push('\n');
-        }
-        return buf;
-    }
-    // Also handle indented or leading language lines
-    if trimmed.starts_with("# %%") {
-        // Simple noop for now
-        return trimmed.to_string();
-    }
-    trimmed.to_string()
-}
-
 /// Generate real command description using AI (non-streaming, kept for compatibility)
 #[expect(dead_code)]
-async fn generate_real_command_description(command: &str, model: &str) -> Result<String> {
+async fn generate_real_command_description(
+    client: &LlmClient,
+    command: &str,
+    model: &str,
+) -> Result<String> {
     use crate::config::Config;
     use crate::role::{default_role_text, DefaultRole};
 
     let cfg = Config::load();
-    let client = LlmClient::from_config(&cfg)?;
     let role_text = default_role_text(&cfg, DefaultRole::DescribeShell);
 
-    let messages = vec![
-        ChatMessage::new(Role::System, role_text),
-        ChatMessage::new(Role::User, command.to_string()),
-    ];
+    let mut messages = role_messages(&cfg, role_text, true);
+    messages.push(ChatMessage::new(Role::User, command.to_string()));
 
     let opts = ChatOptions {
         model: model.to_string(),
@@ -1292,6 +1664,10 @@ async fn generate_real_command_description(command: &str, model: &str) -> Result
         parallel_tool_calls: false,
         tool_choice: None,
         max_tokens: Some(500), // Limit description length
+        stop_sequences: None,
+        presence_penalty: None,
+        frequency_penalty: None,
+        resume_on_error: false,
     };
 
     let mut stream = client.chat_stream(messages, opts);
@@ -1312,6 +1688,7 @@ async fn generate_real_command_description(command: &str, model: &str) -> Result
 
 /// Generate streaming command description using AI
 async fn generate_streaming_command_description(
+    client: &LlmClient,
     command: &str,
     model: &str,
     event_sender: mpsc::UnboundedSender<TuiEvent>,
@@ -1320,13 +1697,10 @@ async fn generate_streaming_command_description(
     use crate::role::{default_role_text, DefaultRole};
 
     let cfg = Config::load();
-    let client = LlmClient::from_config(&cfg)?;
     let role_text = default_role_text(&cfg, DefaultRole::DescribeShell);
 
-    let messages = vec![
-        ChatMessage::new(Role::System, role_text),
-        ChatMessage::new(Role::User, command.to_string()),
-    ];
+    let mut messages = role_messages(&cfg, role_text, true);
+    messages.push(ChatMessage::new(Role::User, command.to_string()));
 
     let opts = ChatOptions {
         model: model.to_string(),
@@ -1336,6 +1710,10 @@ async fn generate_streaming_command_description(
         parallel_tool_calls: false,
         tool_choice: None,
         max_tokens: Some(500), // Limit description length
+        stop_sequences: None,
+        presence_penalty: None,
+        frequency_penalty: None,
+        resume_on_error: false,
     };
 
     let mut stream = client.chat_stream(messages, opts);
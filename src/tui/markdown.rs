@@ -0,0 +1,357 @@
+//! Markdown to ratatui rows for the chat pane.
+//!
+//! A small line-oriented renderer: headers, lists, block quotes, rules, fenced code blocks and
+//! inline emphasis/code/links. Output is already wrapped to the pane width, so the caller can
+//! count rows for scrolling exactly as they are drawn.
+
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Render `text` as rows at most `width` columns wide. `lead` (e.g. a timestamp) is drawn
+/// before the first row and `base` is the style of plain text.
+pub fn render_markdown(text: &str, width: usize, lead: &str, base: Style) -> Vec<Line<'static>> {
+    let code_style = Style::default().fg(Color::White).bg(Color::DarkGray);
+    let muted = Style::default().fg(Color::DarkGray);
+    let mut lines = Vec::new();
+    let mut lead = Some(lead).filter(|l| !l.is_empty());
+    // Marker of the open code fence; an unclosed fence (mid-stream) runs to the end
+    let mut fence: Option<&str> = None;
+
+    for raw in text.lines() {
+        let trimmed = raw.trim_start();
+        let mut first: Vec<Span<'static>> = lead
+            .take()
+            .map(|l| vec![Span::styled(l.to_string(), base)])
+            .unwrap_or_default();
+
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) && trimmed[marker.len()..].trim().is_empty() {
+                fence = None;
+                if !first.is_empty() {
+                    lines.push(Line::from(first));
+                }
+                continue;
+            }
+            let code = raw.replace('\t', "    ");
+            let mut rows = Rows::new(width, first, Vec::new(), Some(code_style));
+            rows.push(&code, code_style);
+            lines.extend(rows.finish());
+            continue;
+        }
+        if let Some(marker) = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m)) {
+            fence = Some(marker);
+            if !first.is_empty() {
+                lines.push(Line::from(first));
+            }
+            continue;
+        }
+
+        if trimmed.is_empty() {
+            lines.push(Line::from(first));
+        } else if let Some((level, title)) = heading(trimmed) {
+            let mut style = base.fg(Color::Yellow).add_modifier(Modifier::BOLD);
+            if level == 1 {
+                style = style.add_modifier(Modifier::UNDERLINED);
+            }
+            let mut rows = Rows::new(width, first, Vec::new(), None);
+            push_inline(&mut rows, title, style);
+            lines.extend(rows.finish());
+        } else if is_rule(trimmed) {
+            let used: usize = first.iter().map(|s| s.width()).sum();
+            first.push(Span::styled("─".repeat(width.saturating_sub(used)), muted));
+            lines.push(Line::from(first));
+        } else if let Some(quote) = trimmed.strip_prefix('>') {
+            let bar = Span::styled("│ ", muted);
+            first.push(bar.clone());
+            let mut rows = Rows::new(width, first, vec![bar], None);
+            push_inline(
+                &mut rows,
+                quote.trim_start(),
+                base.add_modifier(Modifier::ITALIC),
+            );
+            lines.extend(rows.finish());
+        } else if let Some((marker, item)) = list_item(trimmed) {
+            let indent = raw.len() - trimmed.len();
+            let hang = " ".repeat(indent + marker.width());
+            first.push(Span::raw(" ".repeat(indent)));
+            first.push(Span::styled(marker, base.fg(Color::Yellow)));
+            let mut rows = Rows::new(width, first, vec![Span::raw(hang)], None);
+            push_inline(&mut rows, item, base);
+            lines.extend(rows.finish());
+        } else {
+            let mut rows = Rows::new(width, first, Vec::new(), None);
+            push_inline(&mut rows, raw, base);
+            lines.extend(rows.finish());
+        }
+    }
+    if let Some(l) = lead {
+        lines.push(Line::from(Span::styled(l.to_string(), base)));
+    }
+    lines
+}
+
+/// `# Title` .. `###### Title`
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let rest = &line[level..];
+    if (1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(' ')) {
+        Some((level, rest.trim()))
+    } else {
+        None
+    }
+}
+
+/// `---`, `***` or `___`, optionally spaced out
+fn is_rule(line: &str) -> bool {
+    let marks: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|&m| marks.iter().all(|&c| c == m))
+}
+
+/// Bullet (`-`, `*`, `+`) or numbered (`1.`, `1)`) item: the marker to draw and the item text
+fn list_item(line: &str) -> Option<(String, &str)> {
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(item) = line.strip_prefix(bullet) {
+            return Some(("• ".to_string(), item));
+        }
+    }
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    let rest = &line[digits..];
+    if (1..=9).contains(&digits) && (rest.starts_with(". ") || rest.starts_with(") ")) {
+        return Some((format!("{}. ", &line[..digits]), &rest[2..]));
+    }
+    None
+}
+
+/// Append `text` with inline markup: `**bold**`, `*italic*`, `~~strike~~`, `` `code` ``,
+/// `[text](url)` and backslash escapes. Unmatched markers are kept as literal text.
+fn push_inline(rows: &mut Rows, text: &str, base: Style) {
+    let chars: Vec<char> = text.chars().collect();
+    let rest = |i: usize| chars[i..].iter().collect::<String>();
+    let (mut bold, mut italic, mut strike) = (false, false, false);
+    let mut i = 0;
+    while i < chars.len() {
+        let mut style = base;
+        if bold {
+            style = style.add_modifier(Modifier::BOLD);
+        }
+        if italic {
+            style = style.add_modifier(Modifier::ITALIC);
+        }
+        if strike {
+            style = style.add_modifier(Modifier::CROSSED_OUT);
+        }
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        // Markers inside a word (`2*3`, `snake__case`) never open emphasis
+        let intraword = i > 0 && chars[i - 1].is_alphanumeric();
+
+        if c == '\\' && next.is_some_and(|n| n.is_ascii_punctuation()) {
+            rows.push(&chars[i + 1].to_string(), style);
+            i += 2;
+        } else if c == '`' {
+            match chars[i + 1..].iter().position(|&c| c == '`') {
+                Some(len) => {
+                    let code: String = chars[i + 1..i + 1 + len].iter().collect();
+                    rows.push(&code, style.fg(Color::Yellow));
+                    i += len + 2;
+                }
+                None => {
+                    rows.push("`", style);
+                    i += 1;
+                }
+            }
+        } else if (c == '*' || c == '_') && next == Some(c) {
+            let marker: String = [c, c].iter().collect();
+            if bold || (!intraword && rest(i + 2).contains(&marker)) {
+                bold = !bold;
+            } else {
+                rows.push(&marker, style);
+            }
+            i += 2;
+        } else if c == '~' && next == Some('~') {
+            if strike || rest(i + 2).contains("~~") {
+                strike = !strike;
+            } else {
+                rows.push("~~", style);
+            }
+            i += 2;
+        } else if c == '*'
+            && (italic
+                || (!intraword
+                    && next.is_some_and(|n| !n.is_whitespace())
+                    && rest(i + 1).contains('*')))
+        {
+            italic = !italic;
+            i += 1;
+        } else if let Some((label, url, len)) = (c == '[').then(|| link(&chars[i..])).flatten() {
+            rows.push(&label, style.add_modifier(Modifier::UNDERLINED));
+            if url != label {
+                rows.push(&format!(" ({})", url), Style::default().fg(Color::DarkGray));
+            }
+            i += len;
+        } else {
+            rows.push(&c.to_string(), style);
+            i += 1;
+        }
+    }
+}
+
+/// `[label](url)` at the start of `chars`: label, url and the number of chars consumed
+fn link(chars: &[char]) -> Option<(String, String, usize)> {
+    let close = chars.iter().position(|&c| c == ']')?;
+    if chars.get(close + 1) != Some(&'(') {
+        return None;
+    }
+    let end = close + 2 + chars[close + 2..].iter().position(|&c| c == ')')?;
+    let label: String = chars[1..close].iter().collect();
+    let url: String = chars[close + 2..end].iter().collect();
+    Some((label, url, end + 1))
+}
+
+/// Builds wrapped rows from styled text; continuation rows start with `cont` (hanging indent)
+/// and, with `pad`, rows are filled to the full width so a background colour forms a block.
+struct Rows {
+    width: usize,
+    cont: Vec<Span<'static>>,
+    pad: Option<Style>,
+    lines: Vec<Line<'static>>,
+    spans: Vec<Span<'static>>,
+    used: usize,
+    has_text: bool,
+}
+
+impl Rows {
+    fn new(
+        width: usize,
+        first: Vec<Span<'static>>,
+        cont: Vec<Span<'static>>,
+        pad: Option<Style>,
+    ) -> Self {
+        let used = first.iter().map(|s| s.width()).sum();
+        Self {
+            width,
+            cont,
+            pad,
+            lines: Vec::new(),
+            spans: first,
+            used,
+            has_text: false,
+        }
+    }
+
+    fn push(&mut self, text: &str, style: Style) {
+        for ch in text.chars() {
+            let w = UnicodeWidthChar::width(ch).unwrap_or(0);
+            if self.has_text && self.used + w > self.width {
+                self.end_row();
+                self.spans = self.cont.clone();
+                self.used = self.cont.iter().map(|s| s.width()).sum();
+                self.has_text = false;
+            }
+            match self.spans.last_mut() {
+                Some(last) if last.style == style && self.has_text => {
+                    last.content.to_mut().push(ch)
+                }
+                _ => self.spans.push(Span::styled(ch.to_string(), style)),
+            }
+            self.used += w;
+            self.has_text = true;
+        }
+    }
+
+    fn end_row(&mut self) {
+        if let Some(style) = self.pad {
+            let fill = self.width.saturating_sub(self.used);
+            self.spans.push(Span::styled(" ".repeat(fill), style));
+        }
+        self.lines.push(Line::from(std::mem::take(&mut self.spans)));
+    }
+
+    fn finish(mut self) -> Vec<Line<'static>> {
+        self.end_row();
+        self.lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(lines: &[Line]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|l| l.spans.iter().map(|s| s.content.as_ref()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn block_elements_lose_their_markup() {
+        let md = "# Title\n\n- one\n  2. two\n> quoted\n---\nplain";
+        let lines = render_markdown(md, 10, "", Style::default());
+        assert_eq!(
+            texts(&lines),
+            [
+                "Title",
+                "",
+                "• one",
+                "  2. two",
+                "│ quoted",
+                "──────────",
+                "plain"
+            ]
+        );
+        assert!(lines[0].spans[0]
+            .style
+            .add_modifier
+            .contains(Modifier::BOLD));
+    }
+
+    #[test]
+    fn inline_markup_is_styled() {
+        let lines = render_markdown(
+            "a **b** *c* `d` [e](http://x) 2*3 \\*",
+            80,
+            "",
+            Style::default(),
+        );
+        assert_eq!(texts(&lines), ["a b c d e (http://x) 2*3 *"]);
+        let style_of = |t: &str| {
+            lines[0]
+                .spans
+                .iter()
+                .find(|s| s.content == t)
+                .map(|s| s.style)
+                .unwrap()
+        };
+        assert!(style_of("b").add_modifier.contains(Modifier::BOLD));
+        assert!(style_of("c").add_modifier.contains(Modifier::ITALIC));
+        assert_eq!(style_of("d").fg, Some(Color::Yellow));
+        assert!(style_of("e").add_modifier.contains(Modifier::UNDERLINED));
+    }
+
+    #[test]
+    fn code_blocks_are_padded_and_wrapped_rows_are_counted() {
+        let lines = render_markdown(
+            "```rust\nlet x = 1;\n```\n- abcdefgh",
+            6,
+            "",
+            Style::default(),
+        );
+        assert_eq!(texts(&lines), ["let x ", "= 1;  ", "• abcd", "  efgh"]);
+        assert_eq!(lines[0].spans[0].style.bg, Some(Color::DarkGray));
+    }
+
+    #[test]
+    fn lead_goes_before_the_first_row_and_unclosed_fences_still_render() {
+        let lines = render_markdown("```\ncode", 12, "[12:00] ", Style::default());
+        assert_eq!(texts(&lines), ["[12:00] ", "code        "]);
+        assert_eq!(
+            texts(&render_markdown("", 12, "[12:00] ", Style::default())),
+            ["[12:00] "]
+        );
+    }
+}
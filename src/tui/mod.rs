@@ -1,8 +1,10 @@
 //! TUI module for enhanced REPL experience using Ratatui.
 
 pub mod app;
+pub mod commands;
 pub mod events;
 pub mod handler;
+pub mod markdown;
 pub mod ui;
 
 // Public exports available if needed in the future
@@ -9,6 +9,7 @@ use ratatui::{
 };
 
 use super::app::{App, InputMode, PopupState};
+use super::markdown::render_markdown;
 use crate::llm::Role;
 use unicode_width::{UnicodeWidthChar};
 
@@ -17,9 +18,10 @@ pub fn render_ui(frame: &mut Frame, app: &App) {
     // Dynamically size the input area based on multiline state
     let area = frame.area();
     let input_lines = match app.input_mode {
-        InputMode::Normal => 1u16,
+        InputMode::Normal | InputMode::HistorySearch => 1u16,
         InputMode::MultiLine => (app.multiline_buffer.len() as u16).saturating_add(1),
     };
+    let search_height = u16::from(app.input_mode == InputMode::HistorySearch);
     // Account for borders around the input box (+2). Minimum visual height is 3.
     let desired_input_height = input_lines.saturating_add(2).max(3);
     // Ensure chat area (min 3) and status bar (1) always have room
@@ -29,9 +31,10 @@ pub fn render_ui(frame: &mut Frame, app: &App) {
     let main_layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Min(3),               // Chat area
-            Constraint::Length(input_height), // Input area (dynamic)
-            Constraint::Length(1),            // Status bar
+            Constraint::Min(3),                // Chat area
+            Constraint::Length(input_height),  // Input area (dynamic)
+            Constraint::Length(search_height), // Ctrl+R search panel
+            Constraint::Length(1),             // Status bar
         ])
         .split(area);
 
@@ -41,8 +44,12 @@ pub fn render_ui(frame: &mut Frame, app: &App) {
     // Render input area
     render_input_area(frame, app, main_layout[1]);
 
+    if search_height > 0 {
+        render_history_search_bar(frame, app, main_layout[2]);
+    }
+
     // Render status bar
-    render_status_bar(frame, app, main_layout[2]);
+    render_status_bar(frame, app, main_layout[3]);
 
     // Render help overlay if requested
     if app.show_help {
@@ -51,8 +58,25 @@ pub fn render_ui(frame: &mut Frame, app: &App) {
 
     // Render popup if requested
     match &app.popup_state {
-        PopupState::ExecutionResult { command, output } => {
-            render_execution_result_popup(frame, command, output);
+        PopupState::ExecutionResult {
+            command,
+            output,
+            running,
+            scroll,
+            exit_code,
+        } => {
+            render_execution_result_popup(frame, command, output, *running, *scroll, *exit_code);
+        }
+        PopupState::ToolConfirm { name, arguments } => {
+            render_command_popup(
+                frame,
+                "Tool Call",
+                &format!("Tool: {}", name),
+                "Arguments",
+                Color::Cyan,
+                arguments,
+                "Enter/y: run tool | any other key: skip",
+            );
         }
         PopupState::Description {
             command,
@@ -77,34 +101,10 @@ fn render_chat_area(frame: &mut Frame, app: &App, area: Rect) {
     let available_height = area.height.saturating_sub(2) as usize; // inner rows excluding borders
     let inner_width = area.width.saturating_sub(2) as usize; // inner columns excluding borders
 
-    // Helper: wrap a single logical line into visual rows honoring unicode display width
-    fn wrap_line(s: &str, width: usize) -> Vec<String> {
-        if width == 0 {
-            return vec![String::new()];
-        }
-        if s.is_empty() {
-            return vec![String::new()];
-        }
-        let mut rows = Vec::new();
-        let mut cur = String::new();
-        let mut cur_w = 0usize;
-        for ch in s.chars() {
-            let w = UnicodeWidthChar::width(ch).unwrap_or(0);
-            if cur_w + w > width && !cur.is_empty() {
-                rows.push(std::mem::take(&mut cur));
-                cur_w = 0;
-            }
-            cur.push(ch);
-            cur_w += w;
-        }
-        rows.push(cur);
-        rows
-    }
-
-    // Pre-wrap all content into visual rows with styles
-    let mut rows: Vec<(String, Style)> = Vec::new();
+    // Pre-wrap all content into visual rows; assistant replies are rendered as markdown
+    let mut rows: Vec<Line<'static>> = Vec::new();
     let visible_msgs = app.visible_messages();
-    for msg in visible_msgs {
+    for (msg, added_at) in visible_msgs {
         let (prefix, style) = match msg.role {
             Role::User => ("> ", Style::default().fg(Color::Green)),
             Role::Assistant => ("", Style::default().fg(Color::Cyan)),
@@ -112,24 +112,35 @@ fn render_chat_area(frame: &mut Frame, app: &App, area: Rect) {
             Role::Tool => ("TOOL ", Style::default().fg(Color::Magenta)),
             Role::Developer => ("DEV ", Style::default().fg(Color::Blue)),
         };
+        let stamp = if app.show_timestamps {
+            let stamp = added_at.map_or_else(|| "--:--:--".to_string(), clock_time);
+            format!("[{}] ", stamp)
+        } else {
+            String::new()
+        };
         let content = format!("{}{}", prefix, msg.content);
-        for line in content.lines() {
-            for r in wrap_line(line, inner_width) {
-                rows.push((r, style));
+        if msg.role == Role::Assistant {
+            rows.extend(render_markdown(&content, inner_width, &stamp, style));
+        } else {
+            for line in format!("{}{}", stamp, content).lines() {
+                for r in wrap_line(line, inner_width) {
+                    rows.push(Line::from(Span::styled(r, style)));
+                }
             }
         }
         // Blank separator row between messages
-        if !content.is_empty() {
-            rows.push((String::new(), Style::default()));
+        if !stamp.is_empty() || !content.is_empty() {
+            rows.push(Line::default());
         }
     }
     if app.is_receiving_response && !app.current_response.is_empty() {
         let style = Style::default().fg(Color::Cyan);
-        for line in app.current_response.lines() {
-            for r in wrap_line(line, inner_width) {
-                rows.push((r, style));
-            }
-        }
+        rows.extend(render_markdown(
+            &app.current_response,
+            inner_width,
+            "",
+            style,
+        ));
     }
 
     // Compute slice of rows to display based on scroll offset
@@ -138,13 +149,7 @@ fn render_chat_area(frame: &mut Frame, app: &App, area: Rect) {
     let actual_offset = app.chat_scroll_offset.min(max_scroll);
     let start = max_scroll.saturating_sub(actual_offset);
     let end = start.saturating_add(available_height).min(total_rows);
-    let visible_slice = &rows[start..end];
-
-    // Build Text for visible rows
-    let mut content_lines: Vec<Line> = Vec::with_capacity(visible_slice.len());
-    for (text, style) in visible_slice.iter() {
-        content_lines.push(Line::from(vec![Span::styled(text.clone(), *style)]));
-    }
+    let content_lines = rows[start..end].to_vec();
 
     let title = format!(
         "Chat History - Session: {} | Model: {}",
@@ -169,6 +174,39 @@ fn render_chat_area(frame: &mut Frame, app: &App, area: Rect) {
     frame.render_widget(paragraph, area);
 }
 
+/// `HH:MM:SS` in local time (UTC where the offset isn't available)
+fn clock_time(t: std::time::SystemTime) -> String {
+    let secs = t
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let day_secs = (secs + local_utc_offset(secs)).rem_euclid(86400);
+    format!(
+        "{:02}:{:02}:{:02}",
+        day_secs / 3600,
+        day_secs % 3600 / 60,
+        day_secs % 60
+    )
+}
+
+#[cfg(unix)]
+fn local_utc_offset(secs: i64) -> i64 {
+    let t = secs as libc::time_t;
+    // SAFETY: localtime_r only writes into the zeroed `tm` we own
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&t, &mut tm).is_null() {
+            return 0;
+        }
+        tm.tm_gmtoff as i64
+    }
+}
+
+#[cfg(not(unix))]
+fn local_utc_offset(_secs: i64) -> i64 {
+    0
+}
+
 /// Render the input area
 fn render_input_area(frame: &mut Frame, app: &App, area: Rect) {
     use unicode_width::UnicodeWidthChar;
@@ -213,7 +251,7 @@ fn render_input_area(frame: &mut Frame, app: &App, area: Rect) {
     }
     // Compute lines for rendering
     let (lines, cursor_line_idx, cursor_col) = match app.input_mode {
-        InputMode::Normal => {
+        InputMode::Normal | InputMode::HistorySearch => {
             let l = vec![app.input.clone()];
             let max_chars = app.input.chars().count();
             (l, 0usize, app.input_cursor.min(max_chars))
@@ -230,6 +268,7 @@ fn render_input_area(frame: &mut Frame, app: &App, area: Rect) {
     let title = match app.input_mode {
         InputMode::Normal => "Input",
         InputMode::MultiLine => "Multi-line Input",
+        InputMode::HistorySearch => "Input (history match)",
     };
 
     // Horizontal scrolling: clamp each line to visible width (columns); for current line ensure cursor is visible
@@ -290,13 +329,44 @@ fn render_input_area(frame: &mut Frame, app: &App, area: Rect) {
     } as u16;
 
     let y_off = match app.input_mode {
-        InputMode::Normal => 0u16,
+        InputMode::Normal | InputMode::HistorySearch => 0u16,
         InputMode::MultiLine => (cursor_line_idx.min(inner_height.saturating_sub(1))) as u16,
     };
 
     frame.set_cursor_position((inner_x + x_off, inner_y + y_off));
 }
 
+/// Render the Ctrl+R search panel, with the cursor after the query
+fn render_history_search_bar(frame: &mut Frame, app: &App, area: Rect) {
+    let Some(search) = &app.history_search else {
+        return;
+    };
+    let (label, color) = if search.failing {
+        ("(failing reverse-i-search)", Color::Red)
+    } else {
+        ("(reverse-i-search)", Color::Yellow)
+    };
+    let prompt = format!("{}`", label);
+    let hint = "': Ctrl+R older | Enter accept | Esc cancel";
+    let line = Line::from(vec![
+        Span::styled(prompt.clone(), Style::default().fg(color)),
+        Span::raw(search.query.clone()),
+        Span::styled(hint, Style::default().fg(Color::DarkGray)),
+    ]);
+    frame.render_widget(Paragraph::new(line), area);
+
+    let query_width: usize = search
+        .query
+        .chars()
+        .map(|c| UnicodeWidthChar::width(c).unwrap_or(0))
+        .sum();
+    let x = area
+        .x
+        .saturating_add((prompt.len() + query_width) as u16)
+        .min(area.right().saturating_sub(1));
+    frame.set_cursor_position((x, area.y));
+}
+
 /// Render the status bar
 fn render_status_bar(frame: &mut Frame, app: &App, area: Rect) {
     // Build base status text (reuse existing semantics)
@@ -332,6 +402,29 @@ fn render_status_bar(frame: &mut Frame, app: &App, area: Rect) {
     let line = Line::from(spans);
     let status_paragraph = Paragraph::new(line).style(Style::default().bg(Color::DarkGray));
     frame.render_widget(status_paragraph, area);
+
+    // Context size on the right, drawn over the status text's background
+    let tokens = match app.last_usage {
+        Some((prompt, completion)) => format!(
+            "[last: {}+{} | ~{} tokens] ",
+            prompt, completion, app.estimated_tokens
+        ),
+        None => format!("[~{} tokens] ", app.estimated_tokens),
+    };
+    let width = (tokens.len() as u16).min(area.width);
+    let tokens_area = Rect {
+        x: area.right().saturating_sub(width),
+        width,
+        ..area
+    };
+    let color = match app.max_context_tokens {
+        Some(max) if app.estimated_tokens * 100 > max * 90 => Color::Red,
+        Some(max) if app.estimated_tokens * 100 > max * 75 => Color::Yellow,
+        _ => Color::Gray,
+    };
+    let tokens_paragraph =
+        Paragraph::new(tokens).style(Style::default().fg(color).bg(Color::DarkGray));
+    frame.render_widget(tokens_paragraph, tokens_area);
 }
 
 /// Render help overlay
@@ -344,7 +437,7 @@ fn render_help_overlay(frame: &mut Frame, app: &App) {
     // Clear the background
     frame.render_widget(Clear, popup_area);
 
-    let help_lines = if app.is_shell_mode && app.allow_interaction {
+    let mut help_lines = if app.is_shell_mode && app.allow_interaction {
         vec![
             Line::from("Shell REPL Help (Ctrl+H to close)"),
             Line::from(""),
@@ -354,6 +447,12 @@ fn render_help_overlay(frame: &mut Frame, app: &App) {
             Line::from("↑/↓ = Scroll    | Ctrl+↑/↓ = Scroll chat"),
             Line::from("Ctrl+C = Clear (2x=Quit) | Ctrl+D = Quit | F1/Ctrl+H = Help | F2 = Toggle selection"),
             Line::from("Ctrl+E = Expand paste placeholders inline"),
+            Line::from("Ctrl+R = Search input history (again for older, Enter accept, Esc cancel)"),
+            Line::from("Ctrl+T = Show/hide message timestamps"),
+            Line::from("Ctrl+Y = Copy the latest answer to the clipboard"),
+            Line::from("Ctrl+G = Regenerate the latest answer"),
+            Line::from("Esc/Ctrl+X = Stop the response being streamed"),
+            Line::from("Ctrl+Z = Undo last deletion/kill/paste in the input"),
             Line::from("e = Execute last | r = Repeat | d = Describe | exit() = Quit REPL"),
         ]
     } else {
@@ -366,9 +465,19 @@ fn render_help_overlay(frame: &mut Frame, app: &App) {
             Line::from("↑/↓ = History    | Ctrl+↑/↓ = Scroll chat"),
             Line::from("Ctrl+C = Clear (2x=Quit) | Ctrl+D = Quit | F1/Ctrl+H = Help | F2 = Toggle selection"),
             Line::from("Ctrl+E = Expand paste placeholders inline"),
+            Line::from("Ctrl+R = Search input history (again for older, Enter accept, Esc cancel)"),
+            Line::from("Ctrl+T = Show/hide message timestamps"),
+            Line::from("Ctrl+Y = Copy the latest answer to the clipboard"),
+            Line::from("Ctrl+G = Regenerate the latest answer"),
+            Line::from("Esc/Ctrl+X = Stop the response being streamed"),
+            Line::from("Ctrl+Z = Undo last deletion/kill/paste in the input"),
         ]
     };
 
+    // Slash commands are typed into the composer and never sent to the model
+    help_lines.push(Line::from(""));
+    help_lines.extend(super::commands::HELP.lines().map(Line::from));
+
     let help_text = Text::from(help_lines);
     let help_paragraph = Paragraph::new(help_text)
         .block(
@@ -408,8 +517,84 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         .split(popup_layout[1])[1]
 }
 
-/// Render execution result popup
-fn render_execution_result_popup(frame: &mut Frame, command: &str, output: &str) {
+/// Wrap a single logical line into visual rows honoring unicode display width
+fn wrap_line(s: &str, width: usize) -> Vec<String> {
+    if width == 0 {
+        return vec![String::new()];
+    }
+    if s.is_empty() {
+        return vec![String::new()];
+    }
+    let mut rows = Vec::new();
+    let mut cur = String::new();
+    let mut cur_w = 0usize;
+    for ch in s.chars() {
+        let w = UnicodeWidthChar::width(ch).unwrap_or(0);
+        if cur_w + w > width && !cur.is_empty() {
+            rows.push(std::mem::take(&mut cur));
+            cur_w = 0;
+        }
+        cur.push(ch);
+        cur_w += w;
+    }
+    rows.push(cur);
+    rows
+}
+
+/// Render execution result popup, showing the rows selected by `scroll` (counted from the end)
+fn render_execution_result_popup(
+    frame: &mut Frame,
+    command: &str,
+    output: &str,
+    running: bool,
+    scroll: usize,
+    exit_code: Option<i32>,
+) {
+    // Body size inside render_command_popup's layout: borders plus header and instruction rows
+    let popup_area = centered_rect(85, 75, frame.area());
+    let width = popup_area.width.saturating_sub(2) as usize;
+    let height = popup_area.height.saturating_sub(7) as usize;
+
+    let text = crate::utils::pty::strip_ansi(output);
+    let rows: Vec<String> = text.lines().flat_map(|l| wrap_line(l, width)).collect();
+    let max_scroll = rows.len().saturating_sub(height);
+    let end = rows.len() - scroll.min(max_scroll);
+    let body = rows[end.saturating_sub(height)..end].join("\n");
+
+    let (title, instructions) = if running {
+        ("Running Command", "Ctrl+C: stop | ↑/↓ PgUp/PgDn: scroll")
+    } else {
+        (
+            "Executed Command",
+            "↑/↓ PgUp/PgDn: scroll | any other key: close",
+        )
+    };
+    // Flag a failing exit status in the output title so it stands out from the text
+    let (body_title, body_title_color) = match exit_code {
+        Some(code) if code != 0 => (format!("Output (exit code {})", code), Color::Red),
+        _ => ("Output".to_string(), Color::Cyan),
+    };
+    render_command_popup(
+        frame,
+        title,
+        &format!("Command: {}", command),
+        &body_title,
+        body_title_color,
+        &body,
+        instructions,
+    );
+}
+
+/// Render a popup with a header line, a body section and an instruction line
+fn render_command_popup(
+    frame: &mut Frame,
+    title: &str,
+    header: &str,
+    body_title: &str,
+    body_title_color: Color,
+    body: &str,
+    instructions: &str,
+) {
     let area = frame.area();
 
     // Create centered popup area
@@ -429,12 +614,12 @@ fn render_execution_result_popup(frame: &mut Frame, command: &str, output: &str)
         .split(popup_area);
 
     // Render command that was executed
-    let command_paragraph = Paragraph::new(format!("Command: {}", command))
+    let command_paragraph = Paragraph::new(header)
         .block(
             Block::default()
                 .borders(Borders::ALL)
                 .border_type(BorderType::Rounded)
-                .title("Executed Command")
+                .title(title)
                 .title_style(
                     Style::default()
                         .fg(Color::Green)
@@ -445,15 +630,15 @@ fn render_execution_result_popup(frame: &mut Frame, command: &str, output: &str)
     frame.render_widget(command_paragraph, popup_layout[0]);
 
     // Render execution result
-    let result_paragraph = Paragraph::new(output)
+    let result_paragraph = Paragraph::new(body)
         .block(
             Block::default()
                 .borders(Borders::ALL)
                 .border_type(BorderType::Rounded)
-                .title("Output")
+                .title(body_title)
                 .title_style(
                     Style::default()
-                        .fg(Color::Cyan)
+                        .fg(body_title_color)
                         .add_modifier(Modifier::BOLD),
                 ),
         )
@@ -461,7 +646,7 @@ fn render_execution_result_popup(frame: &mut Frame, command: &str, output: &str)
     frame.render_widget(result_paragraph, popup_layout[1]);
 
     // Render instructions
-    let instructions = Paragraph::new("Press any key to close")
+    let instructions = Paragraph::new(instructions)
         .style(Style::default().fg(Color::Yellow))
         .block(
             Block::default()
@@ -0,0 +1,156 @@
+//! System clipboard access with an OSC 52 fallback for headless/SSH sessions.
+//! Reading (`--paste`) falls back to the `SGPT_CLIPBOARD` env var instead.
+
+use std::io::{self, IsTerminal, Write};
+
+use anyhow::{bail, Result};
+
+/// Where the copied text ended up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyTarget {
+    /// The native clipboard (via `arboard`).
+    System,
+    /// The terminal, through an OSC 52 escape sequence.
+    Terminal,
+}
+
+/// Clipboard handle that keeps what it copied available.
+///
+/// On X11 and Wayland the copied text is served by the process that set it, and goes away
+/// with the last native handle, so this one is held for as long as the copy should last.
+#[derive(Default)]
+pub struct Clipboard {
+    native: Option<arboard::Clipboard>,
+    /// Text last put on the native clipboard, re-served on drop with `wait_on_drop`
+    copied: Option<String>,
+    wait_on_drop: bool,
+}
+
+impl Clipboard {
+    /// For a command that exits when it is done: dropping the handle blocks until another
+    /// program (usually a clipboard manager) has taken over the copied text. Only Linux
+    /// needs this; elsewhere the system keeps the contents.
+    pub fn for_cli() -> Self {
+        Self {
+            native: None,
+            copied: None,
+            wait_on_drop: true,
+        }
+    }
+
+    /// Put `text` on the clipboard, preferring the native one.
+    pub fn copy(&mut self, text: &str) -> Result<CopyTarget> {
+        let native = match self.native.take() {
+            Some(clipboard) => Ok(clipboard),
+            None => arboard::Clipboard::new(),
+        };
+        let result = native.and_then(|mut clipboard| {
+            let set = clipboard.set_text(text.to_string());
+            self.native = Some(clipboard);
+            set
+        });
+        match result {
+            Ok(()) => {
+                self.copied = Some(text.to_string());
+                Ok(CopyTarget::System)
+            }
+            Err(native_err) => {
+                // No display server (e.g. over SSH): let the local terminal do the copying
+                let mut out = io::stdout();
+                if !out.is_terminal() {
+                    bail!("clipboard unavailable ({})", native_err);
+                }
+                let seq = osc52_sequence(text, std::env::var_os("TMUX").is_some());
+                out.write_all(seq.as_bytes())?;
+                out.flush()?;
+                Ok(CopyTarget::Terminal)
+            }
+        }
+    }
+}
+
+impl Drop for Clipboard {
+    fn drop(&mut self) {
+        #[cfg(all(
+            unix,
+            not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+        ))]
+        if let (true, Some(clipboard), Some(text)) =
+            (self.wait_on_drop, self.native.as_mut(), self.copied.take())
+        {
+            use arboard::SetExtLinux;
+            eprintln!(
+                "Keeping the copied text on the clipboard until something else is copied \
+                 (Ctrl+C to stop)"
+            );
+            let _ = clipboard.set().wait().text(text);
+        }
+    }
+}
+
+impl std::fmt::Debug for Clipboard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Clipboard")
+            .field("copied", &self.copied)
+            .field("wait_on_drop", &self.wait_on_drop)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Read text from the clipboard, or from `SGPT_CLIPBOARD` when there is no native one.
+pub fn paste() -> Result<String> {
+    let native = arboard::Clipboard::new().and_then(|mut c| c.get_text());
+    paste_or_env(native, std::env::var("SGPT_CLIPBOARD").ok())
+}
+
+fn paste_or_env(native: Result<String, arboard::Error>, env: Option<String>) -> Result<String> {
+    match native {
+        Ok(text) => Ok(text),
+        Err(native_err) => match env {
+            Some(text) => Ok(text),
+            None => bail!(
+                "clipboard unavailable ({}); set SGPT_CLIPBOARD on headless systems",
+                native_err
+            ),
+        },
+    }
+}
+
+/// Build the OSC 52 "set clipboard" sequence, wrapped for tmux passthrough when needed.
+fn osc52_sequence(text: &str, tmux: bool) -> String {
+    let seq = format!(
+        "\x1b]52;c;{}\x07",
+        crate::llm::base64_encode(text.as_bytes())
+    );
+    if tmux {
+        format!("\x1bPtmux;\x1b{}\x1b\\", seq)
+    } else {
+        seq
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn osc52_encodes_payload() {
+        assert_eq!(osc52_sequence("ls -la", false), "\x1b]52;c;bHMgLWxh\x07");
+        assert_eq!(
+            osc52_sequence("ls -la", true),
+            "\x1bPtmux;\x1b\x1b]52;c;bHMgLWxh\x07\x1b\\"
+        );
+    }
+
+    #[test]
+    fn paste_falls_back_to_env() {
+        let text = paste_or_env(
+            Err(arboard::Error::ClipboardNotSupported),
+            Some("hi".into()),
+        );
+        assert_eq!(text.unwrap(), "hi");
+        assert!(paste_or_env(Err(arboard::Error::ClipboardNotSupported), None).is_err());
+        let native = paste_or_env(Ok("native".into()), Some("env".into()));
+        assert_eq!(native.unwrap(), "native");
+    }
+}
@@ -0,0 +1,179 @@
+//! Cleaning up generated code that models wrapped in Markdown despite being told not to,
+//! and saving it to files (`--code --output-file`).
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+
+use crate::printer::highlight::{split_code_blocks, Segment};
+
+/// Strip Markdown fences (and their language tag) from generated code.
+///
+/// A single fenced block is returned on its own, dropping any prose around it
+/// ("Here is the script:"). Output made only of fenced blocks has each one unwrapped and
+/// joined by a blank line. Several blocks with prose between them are returned untouched,
+/// since that prose may matter. Unfenced output is returned trimmed.
+pub fn sanitize_generated_code(s: &str) -> String {
+    let trimmed = s.trim();
+    let segments = split_code_blocks(trimmed);
+    let blocks: Vec<&str> = segments
+        .iter()
+        .filter_map(|seg| match seg {
+            Segment::Code { code, .. } => Some(code.trim_end()),
+            Segment::Prose(_) => None,
+        })
+        .collect();
+    let only_code = segments.iter().all(|seg| match seg {
+        Segment::Code { .. } => true,
+        Segment::Prose(text) => text.trim().is_empty(),
+    });
+    match blocks.as_slice() {
+        [] => trimmed.to_string(),
+        [code] => code.to_string(),
+        _ if only_code => blocks.join("\n\n"),
+        _ => trimmed.to_string(),
+    }
+}
+
+/// File extension for a `--lang` name, if it's a language we know.
+pub fn extension_for(lang: &str) -> Option<&'static str> {
+    let ext = match lang.trim().to_ascii_lowercase().as_str() {
+        "python" | "py" => "py",
+        "rust" | "rs" => "rs",
+        "javascript" | "js" | "node" => "js",
+        "typescript" | "ts" => "ts",
+        "go" | "golang" => "go",
+        "bash" | "sh" | "shell" | "zsh" => "sh",
+        "powershell" | "pwsh" => "ps1",
+        "ruby" | "rb" => "rb",
+        "java" => "java",
+        "kotlin" | "kt" => "kt",
+        "c" => "c",
+        "cpp" | "c++" => "cpp",
+        "csharp" | "c#" | "cs" => "cs",
+        "r" => "R",
+        "sql" => "sql",
+        "lua" => "lua",
+        "php" => "php",
+        "perl" | "pl" => "pl",
+        "swift" => "swift",
+        "html" => "html",
+        "css" => "css",
+        "yaml" | "yml" => "yaml",
+        "json" => "json",
+        _ => return None,
+    };
+    Some(ext)
+}
+
+/// Write generated code to `path`, returning the final path and the number of bytes written.
+///
+/// A path without an extension gets one from `lang`. Parent directories are created, an
+/// existing file is only replaced with `force`, and scripts starting with a shebang are
+/// made executable.
+pub fn write_code_file(
+    path: &Path,
+    code: &str,
+    lang: Option<&str>,
+    force: bool,
+) -> Result<(PathBuf, usize)> {
+    let mut path = path.to_path_buf();
+    if path.extension().is_none() {
+        if let Some(ext) = lang.and_then(extension_for) {
+            path.set_extension(ext);
+        }
+    }
+    if path.exists() && !force {
+        bail!(
+            "{} already exists (pass --force to overwrite)",
+            path.display()
+        );
+    }
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let mut content = code.to_string();
+    if !content.ends_with('\n') {
+        content.push('\n');
+    }
+    fs::write(&path, &content)?;
+    #[cfg(unix)]
+    if content.starts_with("#!") {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = fs::metadata(&path)?.permissions();
+        perms.set_mode(perms.mode() | 0o111);
+        fs::set_permissions(&path, perms)?;
+    }
+    Ok((path, content.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fences_and_language_tags_are_removed() {
+        assert_eq!(
+            sanitize_generated_code("```python\nprint('hi')\n```\n"),
+            "print('hi')"
+        );
+        assert_eq!(
+            sanitize_generated_code("Here you go:\n\n```sh\nls -la\n```\nThis lists files."),
+            "ls -la"
+        );
+        // Unclosed fence from a truncated stream
+        assert_eq!(sanitize_generated_code("```\nfn main() {}"), "fn main() {}");
+    }
+
+    #[test]
+    fn plain_code_and_multi_block_prose_are_kept() {
+        assert_eq!(sanitize_generated_code("  x = 1\n"), "x = 1");
+        let two = "Step one:\n```\na\n```\nStep two:\n```\nb\n```";
+        assert_eq!(sanitize_generated_code(two), two);
+        // Leading block followed by prose and another block: nothing is dropped
+        let mixed = "```\na\n```\nThen:\n```\nb\n```";
+        assert_eq!(sanitize_generated_code(mixed), mixed);
+    }
+
+    #[test]
+    fn back_to_back_blocks_are_all_unwrapped() {
+        let blocks = "```python\nimport os\n```\n\n```python\nprint(os.getcwd())\n```";
+        assert_eq!(
+            sanitize_generated_code(blocks),
+            "import os\n\nprint(os.getcwd())"
+        );
+    }
+
+    #[test]
+    fn code_files_get_extension_and_refuse_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("scripts").join("hello");
+        let (path, bytes) = write_code_file(&target, "print('hi')", Some("Python"), false).unwrap();
+        assert_eq!(path, dir.path().join("scripts").join("hello.py"));
+        assert_eq!(bytes, "print('hi')\n".len());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "print('hi')\n");
+
+        assert!(write_code_file(&target, "print(1)", Some("python"), false).is_err());
+        write_code_file(&target, "print(1)", Some("python"), true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "print(1)\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shebang_scripts_are_executable() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let (path, _) = write_code_file(
+            &dir.path().join("run.sh"),
+            "#!/bin/sh\necho hi",
+            None,
+            false,
+        )
+        .unwrap();
+        let mode = fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o111, 0o111);
+    }
+}
@@ -1,12 +1,17 @@
 //! Shell command execution utilities.
 
-use std::process::Command;
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
 
 /// Execute a shell command using the appropriate shell for the current platform.
 ///
+/// When SHELL_NAME is `pwsh` and PowerShell Core is in PATH: `pwsh` on any OS
 /// On Windows: Uses PowerShell if available (determined by PSModulePath), otherwise cmd.exe
 /// On Unix-like systems: Uses the shell specified by SHELL environment variable, or /bin/sh as fallback
 ///
+/// Returns the exit code, or `None` if the shell couldn't be started or was killed by a signal.
+///
 /// # Examples
 ///
 /// ```rust
@@ -14,29 +19,210 @@ use std::process::Command;
 ///
 /// run_command("echo 'Hello World'");
 /// ```
-pub fn run_command(cmd: &str) {
-    if cfg!(windows) {
-        // Allow explicit override via SHELL_NAME
-        let override_shell = std::env::var("SHELL_NAME")
+pub fn run_command(cmd: &str) -> Option<i32> {
+    shell_command(cmd).status().ok().and_then(|s| s.code())
+}
+
+/// Exit status and output of a command run with [`run_command_captured`].
+#[derive(Debug, Clone)]
+pub struct CommandOutput {
+    /// `None` when the process couldn't be started or was killed by a signal.
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// Like [`run_command`], but also captures stdout/stderr while still echoing them live.
+pub fn run_command_captured(cmd: &str) -> CommandOutput {
+    let mut child = match shell_command(cmd)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(c) => c,
+        Err(e) => {
+            return CommandOutput {
+                code: None,
+                stdout: String::new(),
+                stderr: format!("failed to start shell: {}", e),
+            }
+        }
+    };
+    let out = child.stdout.take().map(|r| tee(r, io::stdout()));
+    let err = child.stderr.take().map(|r| tee(r, io::stderr()));
+    let code = child.wait().ok().and_then(|s| s.code());
+    let join = |h: Option<thread::JoinHandle<Vec<u8>>>| {
+        h.and_then(|h| h.join().ok())
+            .map(|b| String::from_utf8_lossy(&b).into_owned())
             .unwrap_or_default()
-            .to_ascii_lowercase();
-        let prefer_ps = if override_shell.contains("powershell") {
-            true
-        } else if override_shell.contains("cmd") {
-            false
-        } else {
-            // Fallback heuristic: if PSModulePath exists, prefer PowerShell; otherwise cmd
-            !std::env::var("PSModulePath").unwrap_or_default().is_empty()
-        };
-        if prefer_ps {
-            let _ = Command::new("powershell.exe")
-                .args(["-NoLogo", "-NoProfile", "-Command", cmd])
-                .status();
-        } else {
-            let _ = Command::new("cmd.exe").args(["/c", cmd]).status();
+    };
+    CommandOutput {
+        code,
+        stdout: join(out),
+        stderr: join(err),
+    }
+}
+
+/// Copy a child pipe to `sink` as it arrives, returning everything read.
+fn tee<R, W>(mut reader: R, mut sink: W) -> thread::JoinHandle<Vec<u8>>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut all = Vec::new();
+        let mut buf = [0u8; 4096];
+        while let Ok(n) = reader.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            let _ = sink.write_all(&buf[..n]);
+            let _ = sink.flush();
+            all.extend_from_slice(&buf[..n]);
         }
+        all
+    })
+}
+
+/// Interpreter used to run a generated command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShellKind {
+    /// `$SHELL -c` (or `/bin/sh`)
+    Posix,
+    /// PowerShell Core, available on every OS
+    Pwsh,
+    /// Windows PowerShell 5.x
+    WindowsPowerShell,
+    Cmd,
+}
+
+/// Pick the interpreter from the (lowercased) SHELL_NAME override and the environment.
+fn select_shell(
+    shell_name: &str,
+    windows: bool,
+    has_ps_module_path: bool,
+    pwsh_in_path: bool,
+) -> ShellKind {
+    // Outside Windows, "powershell" can only mean PowerShell Core
+    let wants_pwsh = shell_name.contains("pwsh") || (!windows && shell_name.contains("powershell"));
+    if wants_pwsh && pwsh_in_path {
+        return ShellKind::Pwsh;
+    }
+    if !windows {
+        return ShellKind::Posix;
+    }
+    if shell_name.contains("powershell") || shell_name.contains("pwsh") {
+        ShellKind::WindowsPowerShell
+    } else if shell_name.contains("cmd") {
+        ShellKind::Cmd
+    } else if has_ps_module_path {
+        // Fallback heuristic: if PSModulePath exists, prefer PowerShell; otherwise cmd
+        ShellKind::WindowsPowerShell
     } else {
-        let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".into());
-        let _ = Command::new(shell).arg("-c").arg(cmd).status();
+        ShellKind::Cmd
+    }
+}
+
+/// Whether `program` can be found in one of the PATH directories.
+fn in_path(program: &str) -> bool {
+    let exe = if cfg!(windows) {
+        format!("{}.exe", program)
+    } else {
+        program.to_string()
+    };
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(&exe).is_file()))
+        .unwrap_or(false)
+}
+
+fn shell_command(cmd: &str) -> Command {
+    // Allow explicit override via SHELL_NAME
+    let override_shell = std::env::var("SHELL_NAME")
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let kind = select_shell(
+        &override_shell,
+        cfg!(windows),
+        !std::env::var("PSModulePath").unwrap_or_default().is_empty(),
+        // Only probe PATH when PowerShell was asked for
+        (override_shell.contains("pwsh") || override_shell.contains("powershell"))
+            && in_path("pwsh"),
+    );
+    match kind {
+        ShellKind::Pwsh | ShellKind::WindowsPowerShell => {
+            let program = if kind == ShellKind::Pwsh {
+                "pwsh"
+            } else {
+                "powershell.exe"
+            };
+            let mut c = Command::new(program);
+            c.args(["-NoLogo", "-NoProfile", "-Command", cmd]);
+            c
+        }
+        ShellKind::Cmd => {
+            let mut c = Command::new("cmd.exe");
+            c.args(["/c", cmd]);
+            c
+        }
+        ShellKind::Posix => {
+            let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".into());
+            let mut c = Command::new(shell);
+            c.arg("-c").arg(cmd);
+            c
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pwsh_is_selected_on_any_os_when_installed() {
+        assert_eq!(select_shell("pwsh", false, false, true), ShellKind::Pwsh);
+        assert_eq!(select_shell("pwsh", true, true, true), ShellKind::Pwsh);
+        assert_eq!(
+            select_shell("powershell.exe", false, false, true),
+            ShellKind::Pwsh
+        );
+        // Windows PowerShell stays the default there
+        assert_eq!(
+            select_shell("powershell.exe", true, true, true),
+            ShellKind::WindowsPowerShell
+        );
+        // Missing pwsh falls back to the platform shell
+        assert_eq!(select_shell("pwsh", false, false, false), ShellKind::Posix);
+        assert_eq!(
+            select_shell("pwsh", true, false, false),
+            ShellKind::WindowsPowerShell
+        );
+    }
+
+    #[test]
+    fn platform_defaults_without_override() {
+        assert_eq!(select_shell("", false, true, false), ShellKind::Posix);
+        assert_eq!(select_shell("zsh", false, false, false), ShellKind::Posix);
+        assert_eq!(
+            select_shell("", true, true, false),
+            ShellKind::WindowsPowerShell
+        );
+        assert_eq!(select_shell("", true, false, false), ShellKind::Cmd);
+        assert_eq!(select_shell("cmd.exe", true, true, false), ShellKind::Cmd);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn captured_run_reports_code_and_output() {
+        let out = run_command_captured("echo out; echo err >&2; exit 3");
+        assert_eq!(out.code, Some(3));
+        assert!(!out.success());
+        assert_eq!(out.stdout.trim(), "out");
+        assert_eq!(out.stderr.trim(), "err");
     }
 }
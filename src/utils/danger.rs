@@ -0,0 +1,137 @@
+//! Pattern-based detection of destructive shell commands.
+
+use std::fs;
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::config::Config;
+
+/// Built-in rules as (name, regex).
+const BUILTIN_RULES: &[(&str, &str)] = &[
+    (
+        "recursive forced delete (rm -rf)",
+        r"\brm\s(?:.*\s)?(?:-[a-zA-Z]*(?:[rR][a-zA-Z]*f|f[a-zA-Z]*[rR])|--recursive\b.*--force\b|--force\b.*--recursive\b)",
+    ),
+    ("filesystem format (mkfs)", r"\bmkfs(?:\.\w+)?\b"),
+    (
+        "raw write to a block device",
+        r"(?:\bdd\b.*\bof=|>\s*)/dev/(?:sd|hd|vd|xvd|nvme|mmcblk|disk)",
+    ),
+    ("fork bomb", r":\(\)\s*\{\s*:\s*\|\s*:\s*&\s*\}\s*;\s*:"),
+    (
+        "forced git history rewrite",
+        r"\bgit\s+(?:push|filter-branch|filter-repo|branch|reset|clean|checkout|rebase)\b.*\s(?:--force(?:-with-lease)?\b|-f\b)",
+    ),
+];
+
+#[derive(Debug, Clone)]
+pub struct DangerRule {
+    pub name: String,
+    pub pattern: Regex,
+}
+
+/// Matches commands against the built-in rules plus those in the `DANGEROUS_PATTERNS` file.
+#[derive(Debug, Clone)]
+pub struct DangerChecker {
+    rules: Vec<DangerRule>,
+}
+
+impl DangerChecker {
+    pub fn builtin() -> Self {
+        let rules = BUILTIN_RULES
+            .iter()
+            .map(|(name, re)| DangerRule {
+                name: name.to_string(),
+                pattern: Regex::new(re).expect("built-in danger pattern"),
+            })
+            .collect();
+        Self { rules }
+    }
+
+    pub fn from_config(cfg: &Config) -> Self {
+        let mut checker = Self::builtin();
+        if let Some(path) = cfg.get("DANGEROUS_PATTERNS") {
+            checker.load_file(Path::new(&path));
+        }
+        checker
+    }
+
+    /// Add one regex per line; blank lines and `#` comments are ignored.
+    /// A missing file is fine, invalid patterns are reported and skipped.
+    fn load_file(&mut self, path: &Path) {
+        let Ok(text) = fs::read_to_string(path) else {
+            return;
+        };
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match Regex::new(line) {
+                Ok(pattern) => self.rules.push(DangerRule {
+                    name: line.to_string(),
+                    pattern,
+                }),
+                Err(e) => eprintln!(
+                    "Warning: ignoring invalid pattern in {}: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+    }
+
+    /// The first rule the command matches, if any.
+    pub fn check(&self, cmd: &str) -> Option<&DangerRule> {
+        self.rules.iter().find(|r| r.pattern.is_match(cmd))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_rules_flag_destructive_commands() {
+        let checker = DangerChecker::builtin();
+        for cmd in [
+            "rm -rf /",
+            "sudo rm -fr ~/projects",
+            "rm -v -Rf build",
+            "rm --recursive --force /tmp/x",
+            "mkfs.ext4 /dev/sdb1",
+            "dd if=image.iso of=/dev/sdb bs=4M",
+            "cat junk > /dev/nvme0n1",
+            ":(){ :|:& };:",
+            "git push --force origin main",
+            "git push -f",
+            "git filter-branch --force --index-filter x HEAD",
+        ] {
+            assert!(checker.check(cmd).is_some(), "not flagged: {}", cmd);
+        }
+        for cmd in [
+            "rm file.txt",
+            "rm -r build",
+            "git push origin main",
+            "dd if=a.img of=b.img",
+            "ls -rf",
+            "echo mkfsx",
+        ] {
+            assert!(checker.check(cmd).is_none(), "flagged: {}", cmd);
+        }
+    }
+
+    #[test]
+    fn extra_patterns_come_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dangerous_patterns");
+        fs::write(&path, "# custom\n\\bshutdown\\b\n(unclosed\n").unwrap();
+        let mut checker = DangerChecker::builtin();
+        checker.load_file(&path);
+        assert_eq!(
+            checker.check("sudo shutdown -h now").unwrap().name,
+            r"\bshutdown\b"
+        );
+        assert!(checker.check("echo hi").is_none());
+    }
+}
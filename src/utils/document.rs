@@ -1,21 +1,182 @@
 //! Document processing utilities for reading and combining various file formats.
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8};
+use is_terminal::IsTerminal;
+use sha2::{Digest, Sha256};
 use std::fs;
-use std::path::Path;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::{sync::Semaphore, task::JoinSet};
 
-/// Read multiple document files and return their combined content as string.
+use super::pdf::{PageRange, PdfOptions};
+use crate::config::Config;
+
+/// Default for `DOC_CODE_EXTENSIONS`: source and config files sent as fenced code blocks.
+/// Names without a dot (`dockerfile`, `makefile`) match whole file names.
+pub const DEFAULT_CODE_EXTENSIONS: &str = "rs,py,js,mjs,ts,jsx,tsx,go,java,kt,kts,scala,c,h,cpp,cc,hpp,cs,swift,rb,php,pl,lua,r,jl,hs,ex,exs,erl,clj,dart,zig,nim,sh,bash,zsh,fish,ps1,bat,sql,css,scss,vue,svelte,proto,graphql,tf,nix,toml,json,jsonc,yaml,yml,xml,ini,cfg,conf,properties,gradle,cmake,dockerfile,makefile";
+
+/// Bytes inspected for NUL bytes when deciding whether a file is binary.
+const BINARY_SNIFF_BYTES: usize = 8192;
+
+/// Tokens kept free for the answer when `--max-tokens` isn't given.
+pub const DEFAULT_ANSWER_TOKENS: usize = 1024;
+
+/// What to do when the documents don't fit in `MAX_INPUT_TOKENS` (`DOC_TRUNCATE_STRATEGY`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TruncateStrategy {
+    /// Keep the beginning of each oversized document.
+    Head,
+    /// Keep the end (useful for logs).
+    Tail,
+    /// Keep the beginning and the end, dropping the middle.
+    HeadTail,
+    /// Refuse to send a prompt that doesn't fit.
+    Error,
+}
+
+impl TruncateStrategy {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "head" => Some(TruncateStrategy::Head),
+            "tail" => Some(TruncateStrategy::Tail),
+            "head+tail" => Some(TruncateStrategy::HeadTail),
+            "error" => Some(TruncateStrategy::Error),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TruncateStrategy::Head => "head",
+            TruncateStrategy::Tail => "tail",
+            TruncateStrategy::HeadTail => "head+tail",
+            TruncateStrategy::Error => "error",
+        }
+    }
+}
+
+/// One `--doc` input after reading.
+#[derive(Debug, Clone)]
+pub struct Document {
+    /// File path or URL, shown in the document header.
+    pub source: String,
+    pub content: String,
+    /// Detected source encoding when the file was not UTF-8, shown in the header.
+    pub encoding: Option<&'static str>,
+    /// Set when the token budget cut this document.
+    pub truncation: Option<Truncation>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Truncation {
+    pub original_tokens: usize,
+    pub kept_tokens: usize,
+    pub strategy: TruncateStrategy,
+}
+
+impl Truncation {
+    /// Note placed under the document header so the model knows the content is partial.
+    pub fn note(&self) -> String {
+        let part = match self.strategy {
+            TruncateStrategy::Tail => "the last",
+            TruncateStrategy::HeadTail => "the beginning and end,",
+            _ => "the first",
+        };
+        format!(
+            "[Partial document: showing {} ~{} of ~{} tokens]",
+            part, self.kept_tokens, self.original_tokens
+        )
+    }
+}
+
+/// Rough token count (characters / 4), the same estimate used elsewhere in sgpt.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Settings for reading `--doc` inputs, taken from the config.
+#[derive(Debug, Clone)]
+pub struct DocOptions {
+    /// Truncate each document to this many characters (`MAX_DOC_CHARS`).
+    pub max_chars: Option<usize>,
+    /// Files larger than this are skipped when expanding globs and directories
+    /// (`DOC_MAX_FILE_BYTES`, 0 = no limit).
+    pub max_file_bytes: u64,
+    /// Rows sampled from each CSV/TSV file (`DOC_CSV_SAMPLE_ROWS`).
+    pub csv_sample_rows: usize,
+    /// Lowercase extensions (or file names) wrapped in a fenced code block (`DOC_CODE_EXTENSIONS`).
+    pub code_extensions: Vec<String>,
+    /// Client for fetching URLs, shared with web search (honors `REQUEST_TIMEOUT`).
+    pub http: reqwest::Client,
+    /// Where fetched pages are cached (`CACHE_PATH/docs`).
+    pub cache_dir: PathBuf,
+    /// How long a fetched page is reused (`DOC_URL_CACHE_TTL`); zero disables the cache.
+    pub cache_ttl: Duration,
+    /// Token budget for the whole prompt (`MAX_INPUT_TOKENS`); `None` disables budgeting.
+    pub max_input_tokens: Option<usize>,
+    /// How oversized documents are cut (`DOC_TRUNCATE_STRATEGY`).
+    pub truncate_strategy: TruncateStrategy,
+    /// OCR and `pdftotext` settings (`PDF_OCR`, `PDF_EXTERNAL_TOOL`, `PDFTOTEXT_PATH`).
+    pub pdf: PdfOptions,
+}
+
+impl DocOptions {
+    pub fn from_config(cfg: &Config) -> Self {
+        Self {
+            max_chars: cfg.get_usize("MAX_DOC_CHARS").filter(|&n| n > 0),
+            max_file_bytes: cfg
+                .get("DOC_MAX_FILE_BYTES")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1024 * 1024),
+            csv_sample_rows: cfg.get_usize("DOC_CSV_SAMPLE_ROWS").unwrap_or(10),
+            pdf: PdfOptions::from_config(cfg),
+            code_extensions: parse_extensions(
+                &cfg.get("DOC_CODE_EXTENSIONS")
+                    .unwrap_or_else(|| DEFAULT_CODE_EXTENSIONS.to_string()),
+            ),
+            http: crate::external::http_client(cfg).unwrap_or_default(),
+            cache_dir: cfg.cache_path().join("docs"),
+            cache_ttl: Duration::from_secs(
+                cfg.get("DOC_URL_CACHE_TTL")
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(3600),
+            ),
+            max_input_tokens: cfg.get_usize("MAX_INPUT_TOKENS").filter(|&n| n > 0),
+            truncate_strategy: match cfg.get("DOC_TRUNCATE_STRATEGY") {
+                None => TruncateStrategy::HeadTail,
+                Some(s) => TruncateStrategy::parse(&s).unwrap_or_else(|| {
+                    eprintln!(
+                        "Warning: unknown DOC_TRUNCATE_STRATEGY '{}' (expected head, tail, head+tail or error); using head+tail",
+                        s
+                    );
+                    TruncateStrategy::HeadTail
+                }),
+            },
+        }
+    }
+}
+
+/// Read multiple document files.
 ///
-/// Supports various file formats including .md, .txt, .rst, .log, .pdf, and files without extension.
-/// Each document is prefixed with a header indicating the file path.
+/// Supports various file formats including .md, .txt, .rst, .log, .pdf, .epub, .csv, .tsv, .html, and files without extension.
+/// Entries starting with `http://` or `https://` are fetched and converted to text.
 ///
 /// # Arguments
 ///
-/// * `file_paths` - A slice of file path strings
+/// * `file_paths` - A slice of file paths or URLs
+/// * `opts` - Truncation, CSV sampling and URL fetching settings
 ///
 /// # Returns
 ///
-/// * `Result<String>` - Combined content of all documents, or error if any file fails to read
+/// * `Result<Vec<Document>>` - The documents in input order, or one error listing every
+///   input that failed
+///
+/// Inputs are read concurrently (up to the number of CPUs at a time); when stderr is a
+/// terminal a `processed N/M documents` line tracks progress.
 ///
 /// # Examples
 ///
@@ -23,48 +184,366 @@ use std::path::Path;
 /// use crate::utils::document::read_documents;
 ///
 /// let files = vec!["doc1.md".to_string(), "doc2.txt".to_string()];
-/// let content = read_documents(&files)?;
+/// let docs = read_documents(&files, &DocOptions::from_config(&cfg)).await?;
 /// ```
-pub fn read_documents(file_paths: &[String]) -> Result<String> {
-    let mut combined_content = String::new();
-
+pub async fn read_documents(file_paths: &[String], opts: &DocOptions) -> Result<Vec<Document>> {
+    let total = file_paths.len();
+    let workers = std::thread::available_parallelism().map_or(4, |n| n.get());
+    let permits = Arc::new(Semaphore::new(workers));
+    let shared = Arc::new(opts.clone());
+    let mut tasks = JoinSet::new();
     for (i, file_path) in file_paths.iter().enumerate() {
-        let content = read_single_document(file_path)?;
+        let permits = Arc::clone(&permits);
+        let opts = Arc::clone(&shared);
+        let file_path = file_path.clone();
+        tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            (i, read_one(file_path, opts).await)
+        });
+    }
 
-        if i > 0 {
-            combined_content.push_str("\n\n");
+    let progress = total > 1 && io::stderr().is_terminal();
+    let mut slots: Vec<Option<Result<Document>>> = file_paths.iter().map(|_| None).collect();
+    let mut done = 0;
+    while let Some(joined) = tasks.join_next().await {
+        let (i, outcome) = joined?;
+        slots[i] = Some(outcome);
+        done += 1;
+        if progress {
+            eprint!("\rprocessed {}/{} documents", done, total);
+        }
+    }
+    if progress {
+        eprintln!();
+    }
+
+    // Report every failure at once, in input order
+    let mut documents = Vec::with_capacity(total);
+    let mut failures = Vec::new();
+    for (file_path, slot) in file_paths.iter().zip(slots) {
+        match slot {
+            Some(Ok(document)) => documents.push(document),
+            Some(Err(e)) => failures.push(format!("  - {}: {:#}", file_path, e)),
+            None => failures.push(format!("  - {}: not processed", file_path)),
+        }
+    }
+    if !failures.is_empty() {
+        bail!(
+            "{} of {} document{} could not be read:\n{}",
+            failures.len(),
+            total,
+            if total == 1 { "" } else { "s" },
+            failures.join("\n")
+        );
+    }
+    Ok(documents)
+}
+
+/// Read one input: URLs are fetched, files are extracted on a blocking thread.
+async fn read_one(file_path: String, opts: Arc<DocOptions>) -> Result<Document> {
+    let mut document = if is_url(&file_path) {
+        Document {
+            content: fetch_url(&file_path, &opts).await?,
+            source: file_path,
+            encoding: None,
+            truncation: None,
+        }
+    } else {
+        let opts = Arc::clone(&opts);
+        tokio::task::spawn_blocking(move || read_single_document(&file_path, &opts)).await??
+    };
+    if let Some(max) = opts.max_chars {
+        truncate_chars(&mut document.content, max);
+    }
+    Ok(document)
+}
+
+/// Cut documents so together they fit in `budget` tokens.
+///
+/// Documents smaller than an even share of the budget are kept whole; what they leave
+/// over is split evenly between the larger ones, which are cut according to `strategy`.
+/// With [`TruncateStrategy::Error`], an oversized set is an error listing each size.
+pub fn fit_documents(
+    documents: &mut [Document],
+    budget: usize,
+    strategy: TruncateStrategy,
+) -> Result<()> {
+    let sizes: Vec<usize> = documents
+        .iter()
+        .map(|d| estimate_tokens(&d.content))
+        .collect();
+    let total: usize = sizes.iter().sum();
+    if total <= budget {
+        return Ok(());
+    }
+    if strategy == TruncateStrategy::Error {
+        let list: Vec<String> = documents
+            .iter()
+            .zip(&sizes)
+            .map(|(d, n)| format!("  {}: ~{} tokens", d.source, n))
+            .collect();
+        bail!(
+            "documents need ~{} tokens but only ~{} are available under MAX_INPUT_TOKENS:\n{}\nSet DOC_TRUNCATE_STRATEGY to head, tail or head+tail to cut them instead",
+            total,
+            budget,
+            list.join("\n")
+        );
+    }
+
+    // Water-fill: settle the small documents first, then share what's left
+    let mut order: Vec<usize> = (0..documents.len()).collect();
+    order.sort_by_key(|&i| sizes[i]);
+    let mut remaining = budget;
+    for (n, &i) in order.iter().enumerate() {
+        let share = remaining / (order.len() - n);
+        if sizes[i] <= share {
+            remaining -= sizes[i];
+            continue;
+        }
+        let doc = &mut documents[i];
+        doc.content = cut_content(&doc.content, share * 4, strategy);
+        doc.truncation = Some(Truncation {
+            original_tokens: sizes[i],
+            kept_tokens: share,
+            strategy,
+        });
+        remaining -= share;
+    }
+    Ok(())
+}
+
+/// Keep `max_chars` characters of `content` according to `strategy`.
+fn cut_content(content: &str, max_chars: usize, strategy: TruncateStrategy) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    match strategy {
+        TruncateStrategy::Tail => {
+            let mut out = String::from("[...]\n");
+            out.extend(&chars[chars.len() - max_chars..]);
+            out
+        }
+        TruncateStrategy::HeadTail => {
+            let head = max_chars / 2;
+            let tail = max_chars - head;
+            let mut out: String = chars[..head].iter().collect();
+            out.push_str(&format!(
+                "\n[... ~{} tokens omitted ...]\n",
+                (chars.len() - max_chars).div_ceil(4)
+            ));
+            out.extend(&chars[chars.len() - tail..]);
+            out
+        }
+        _ => {
+            let mut out: String = chars[..max_chars].iter().collect();
+            out.push_str("\n[...]");
+            out
+        }
+    }
+}
+
+/// Cut `content` to `max` characters, noting how much was dropped.
+fn truncate_chars(content: &mut String, max: usize) {
+    let total = content.chars().count();
+    if total <= max {
+        return;
+    }
+    let cut = content
+        .char_indices()
+        .nth(max)
+        .map(|(i, _)| i)
+        .unwrap_or(content.len());
+    content.truncate(cut);
+    content.push_str(&format!(
+        "\n[... truncated: showing {} of {} characters]",
+        max, total
+    ));
+}
+
+/// Files selected by the `--doc` arguments, after expanding globs and directories.
+#[derive(Debug, Default)]
+pub struct DocPaths {
+    /// Local paths and URLs, in argument order (sorted within each expansion).
+    pub files: Vec<String>,
+    /// Whether any glob pattern or directory was expanded.
+    pub expanded: bool,
+    /// Combined size of the local files in `files`.
+    pub total_bytes: u64,
+    /// Expanded entries left out because they are binary or too large.
+    pub skipped: usize,
+}
+
+impl DocPaths {
+    /// One-line summary for stderr, e.g. `Processing 3 files (12.0 KiB): a.rs, b.rs, c.rs`.
+    pub fn summary(&self) -> String {
+        const LISTED: usize = 10;
+        let mut line = format!(
+            "Processing {} files ({}): {}",
+            self.files.len(),
+            crate::cache::format_bytes(self.total_bytes),
+            self.files
+                .iter()
+                .take(LISTED)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        if self.files.len() > LISTED {
+            line.push_str(&format!(" and {} more", self.files.len() - LISTED));
+        }
+        if self.skipped > 0 {
+            line.push_str(&format!(
+                "; skipped {} binary or oversized files",
+                self.skipped
+            ));
+        }
+        line
+    }
+}
+
+/// Expand `--doc` arguments into files. Arguments containing glob characters (`*`, `?`,
+/// `[...]`) expand to the matching files and directories are walked recursively, honouring
+/// `.gitignore` and skipping hidden files; both are sorted. In expansions, binary files and
+/// files over `max_file_bytes` (0 = no limit) are skipped, and symlink loops are not
+/// followed. Other arguments, including URLs, are passed through unchanged.
+///
+/// # Returns
+///
+/// * `Result<DocPaths>` - The selected files; a pattern or directory that yields no
+///   usable files is an error
+///
+/// # Examples
+///
+/// ```rust
+/// use crate::utils::document::expand_doc_paths;
+///
+/// let docs = expand_doc_paths(&["src/**/*.rs".to_string(), "doc/".to_string()], 1 << 20)?;
+/// ```
+pub fn expand_doc_paths(args: &[String], max_file_bytes: u64) -> Result<DocPaths> {
+    let mut docs = DocPaths::default();
+    for arg in args {
+        let is_glob = !is_url(arg) && arg.contains(['*', '?', '[']);
+        let is_dir = !is_url(arg) && !is_glob && Path::new(arg).is_dir();
+        if !is_glob && !is_dir {
+            let path = split_page_range(arg).map_or(arg.as_str(), |(path, _)| path);
+            if let Ok(meta) = fs::metadata(path) {
+                docs.total_bytes += meta.len();
+            }
+            docs.files.push(arg.clone());
+            continue;
+        }
+        docs.expanded = true;
+
+        let mut matched: Vec<PathBuf> = if is_glob {
+            glob::glob(arg)
+                .map_err(|e| anyhow::anyhow!("Invalid glob pattern '{}': {}", arg, e))?
+                .filter_map(|e| e.ok())
+                .filter(|p| p.is_file())
+                .collect()
+        } else {
+            // Following links lets the walker detect loops; those entries come back as errors
+            ignore::WalkBuilder::new(arg)
+                .follow_links(true)
+                .require_git(false)
+                .build()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_some_and(|t| t.is_file()))
+                .map(|e| e.into_path())
+                .collect()
+        };
+        if matched.is_empty() {
+            if is_glob {
+                bail!("No files match pattern '{}'", arg);
+            }
+            bail!("No files found in directory '{}'", arg);
         }
+        matched.sort();
 
-        combined_content.push_str(&format!("=== Document: {} ===\n", file_path));
-        combined_content.push_str(&content);
+        let found = matched.len();
+        let mut kept = 0;
+        for path in matched {
+            match usable_size(&path, max_file_bytes) {
+                Some(size) => {
+                    docs.total_bytes += size;
+                    docs.files.push(path.to_string_lossy().into_owned());
+                    kept += 1;
+                }
+                None => docs.skipped += 1,
+            }
+        }
+        if kept == 0 {
+            bail!(
+                "All {} files from '{}' were skipped (binary, or larger than DOC_MAX_FILE_BYTES)",
+                found,
+                arg
+            );
+        }
     }
+    Ok(docs)
+}
 
-    Ok(combined_content)
+/// Size of `path` if it is worth including from an expansion: not larger than
+/// `max_bytes` (0 = no limit) and, unless it's a PDF or EPUB, free of NUL bytes.
+fn usable_size(path: &Path, max_bytes: u64) -> Option<u64> {
+    let size = fs::metadata(path).ok()?.len();
+    if max_bytes > 0 && size > max_bytes {
+        return None;
+    }
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    if !matches!(ext.as_str(), "pdf" | "epub") {
+        let mut head = Vec::with_capacity(BINARY_SNIFF_BYTES);
+        fs::File::open(path)
+            .ok()?
+            .take(BINARY_SNIFF_BYTES as u64)
+            .read_to_end(&mut head)
+            .ok()?;
+        if head.contains(&0) {
+            return None;
+        }
+    }
+    Some(size)
 }
 
-/// Read single document file and return its content as string.
+/// Read single document file into a [`Document`].
 ///
 /// Supports multiple file formats:
 /// - Text files: .md, .txt, .rst, .log, and files without extension
-/// - PDF files: .pdf (text extraction)
+/// - PDF files: .pdf (text extraction); `file.pdf:12-18` keeps only pages 12 to 18
+/// - EPUB books: .epub (chapters in reading order)
+/// - Tables: .csv, .tsv (schema summary, row count and a sample of rows)
+/// - Web pages: .html, .htm (readable text, without scripts and navigation)
+/// - Source and config files listed in `opts.code_extensions`: wrapped in a fenced code
+///   block tagged with the language
+/// - Any other text file; files with NUL bytes are rejected as binary
+///
+/// Text is decoded from UTF-8 when valid (a leading BOM is dropped); otherwise the
+/// encoding (GBK, Shift_JIS, Windows-1252, ...) is detected and the content transcoded,
+/// with the detected name kept in `Document::encoding`.
 ///
 /// # Arguments
 ///
 /// * `file_path` - Path to the document file
+/// * `opts` - CSV sampling and code extension settings
 ///
 /// # Returns
 ///
-/// * `Result<String>` - File content as string, or error if file doesn't exist or unsupported format
+/// * `Result<Document>` - File content, or error if file doesn't exist or is binary
 ///
 /// # Examples
 ///
 /// ```rust
 /// use crate::utils::document::read_single_document;
 ///
-/// let content = read_single_document("document.pdf")?;
-/// let text_content = read_single_document("notes.txt")?;
+/// let opts = DocOptions::from_config(&cfg);
+/// let pdf = read_single_document("document.pdf", &opts)?;
+/// let code = read_single_document("src/main.rs", &opts)?;
+/// println!("{}", code.content);
 /// ```
-pub fn read_single_document(file_path: &str) -> Result<String> {
+pub fn read_single_document(file_path: &str, opts: &DocOptions) -> Result<Document> {
+    let source = file_path;
+    let (file_path, pages) = split_page_range(file_path)?;
     let path = Path::new(file_path);
 
     // Check if file exists
@@ -84,41 +563,262 @@ pub fn read_single_document(file_path: &str) -> Result<String> {
         .unwrap_or("")
         .to_lowercase();
 
+    let file_name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    let code_key = if extension.is_empty() {
+        &file_name
+    } else {
+        &extension
+    };
+    let document = |content: String, encoding: Option<&'static str>| Document {
+        source: source.to_string(),
+        content,
+        encoding,
+        truncation: None,
+    };
+
+    if opts.code_extensions.contains(code_key) {
+        let (code, encoding) = read_text(path, file_path)?;
+        return Ok(document(
+            fence_code(&code, code_language(code_key)),
+            encoding,
+        ));
+    }
+
     match extension.as_str() {
-        "md" | "txt" | "rst" | "log" | "" => {
-            // Read text files directly
-            fs::read_to_string(path)
-                .map_err(|e| anyhow::anyhow!("Failed to read file '{}': {}", file_path, e))
-        }
         "pdf" => {
             // Use PDF module for PDF files
-            super::pdf::extract_pdf_text(file_path)
+            super::pdf::extract_pdf_pages(file_path, pages, &opts.pdf)
+                .map(|text| document(text, None))
+        }
+        "epub" => super::epub::extract_epub_text(file_path).map(|text| document(text, None)),
+        "csv" | "tsv" => super::tabular::summarize_table(file_path, opts.csv_sample_rows)
+            .map(|table| document(table, None)),
+        "html" | "htm" => {
+            let (html, encoding) = read_text(path, file_path)?;
+            Ok(document(super::html::page_to_text(&html), encoding))
         }
+        // .md, .txt, .rst, .log, extensionless and anything else that decodes as text
         _ => {
-            bail!("Unsupported file type: .{}\nCurrently supported: .md, .txt, .rst, .log, .pdf, and files without extension", extension);
+            let (text, encoding) = read_text(path, file_path)?;
+            Ok(document(text, encoding))
+        }
+    }
+}
+
+/// Split a `report.pdf:12-18` argument into the PDF path and its page range.
+///
+/// Anything that isn't `<something>.pdf:<pages>`, or names an existing file as-is,
+/// is returned unchanged.
+pub fn split_page_range(arg: &str) -> Result<(&str, Option<PageRange>)> {
+    if Path::new(arg).exists() {
+        return Ok((arg, None));
+    }
+    match arg.rsplit_once(':') {
+        Some((path, spec))
+            if path.to_lowercase().ends_with(".pdf")
+                && !spec.is_empty()
+                && spec.chars().all(|c| c.is_ascii_digit() || c == '-') =>
+        {
+            let range = PageRange::parse(spec).with_context(|| {
+                format!(
+                    "Invalid page range '{}' for '{}' (expected N or FIRST-LAST, starting at 1)",
+                    spec, path
+                )
+            })?;
+            Ok((path, Some(range)))
+        }
+        _ => Ok((arg, None)),
+    }
+}
+
+/// Read a text file, rejecting binary content (NUL bytes near the start) up front.
+///
+/// Returns the text plus the detected encoding name when the file was not UTF-8.
+fn read_text(path: &Path, file_path: &str) -> Result<(String, Option<&'static str>)> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read file '{}'", file_path))?;
+    // UTF-16 text is full of NUL bytes, so honour its BOM before the binary check.
+    if let Some((encoding, bom_len)) = Encoding::for_bom(&bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+        let name = (encoding != UTF_8).then(|| encoding.name());
+        return Ok((text.into_owned(), name));
+    }
+    if bytes.iter().take(BINARY_SNIFF_BYTES).any(|&b| b == 0) {
+        bail!(
+            "'{}' looks like a binary file (it contains NUL bytes); --doc only reads text, source code, PDF, EPUB, CSV and HTML",
+            file_path
+        );
+    }
+    Ok(decode_text(bytes))
+}
+
+/// Decode BOM-less bytes: UTF-8 when valid, otherwise the encoding chardetng guesses.
+/// Undecodable sequences become U+FFFD instead of failing the whole document.
+fn decode_text(bytes: Vec<u8>) -> (String, Option<&'static str>) {
+    let bytes = match String::from_utf8(bytes) {
+        Ok(text) => return (text, None),
+        Err(e) => e.into_bytes(),
+    };
+    let mut detector = EncodingDetector::new();
+    detector.feed(&bytes, true);
+    let encoding = detector.guess(None, true);
+    let (text, _) = encoding.decode_without_bom_handling(&bytes);
+    (text.into_owned(), Some(encoding.name()))
+}
+
+/// Split a comma-separated extension list, ignoring dots, case and blanks.
+fn parse_extensions(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|e| e.trim().trim_start_matches('.').to_lowercase())
+        .filter(|e| !e.is_empty())
+        .collect()
+}
+
+/// Fence language tag for an extension (or extensionless file name).
+fn code_language(ext: &str) -> &str {
+    match ext {
+        "rs" => "rust",
+        "py" => "python",
+        "js" | "mjs" => "javascript",
+        "ts" => "typescript",
+        "kt" | "kts" => "kotlin",
+        "h" => "c",
+        "cc" | "hpp" => "cpp",
+        "cs" => "csharp",
+        "rb" => "ruby",
+        "pl" => "perl",
+        "jl" => "julia",
+        "hs" => "haskell",
+        "ex" | "exs" => "elixir",
+        "erl" => "erlang",
+        "clj" => "clojure",
+        "sh" | "zsh" => "bash",
+        "ps1" => "powershell",
+        "bat" => "batch",
+        "yml" => "yaml",
+        "tf" => "hcl",
+        "cfg" | "conf" => "ini",
+        other => other,
+    }
+}
+
+/// Wrap `code` in a fence longer than any backtick run inside it.
+fn fence_code(code: &str, language: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in code.chars() {
+        run = if c == '`' { run + 1 } else { 0 };
+        longest = longest.max(run);
+    }
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!(
+        "{}{}\n{}\n{}",
+        fence,
+        language,
+        code.trim_end_matches('\n'),
+        fence
+    )
+}
+
+fn is_url(arg: &str) -> bool {
+    let lower = arg.to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// Fetch `url` and return its readable text, reusing a cached copy younger than
+/// `opts.cache_ttl`. HTML is converted with [`super::html::page_to_text`]; other text
+/// responses are used as-is. Proxies come from the usual `HTTPS_PROXY`/`HTTP_PROXY`
+/// environment variables.
+async fn fetch_url(url: &str, opts: &DocOptions) -> Result<String> {
+    let cache_file = opts
+        .cache_dir
+        .join(format!("{:x}.txt", Sha256::digest(url.as_bytes())));
+    if let Some(text) = read_fresh(&cache_file, opts.cache_ttl) {
+        return Ok(text);
+    }
+
+    let resp = opts
+        .http
+        .get(url)
+        .send()
+        .await
+        .with_context(|| format!("Failed to fetch '{}'", url))?;
+    let status = resp.status();
+    if status != reqwest::StatusCode::OK {
+        bail!("Failed to fetch '{}': HTTP {}", url, status);
+    }
+    let content_type = resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_ascii_lowercase());
+    if let Some(ct) = &content_type {
+        if !["html", "text", "json", "xml"]
+            .iter()
+            .any(|t| ct.contains(t))
+        {
+            bail!("Unsupported content type for '{}': {}", url, ct);
         }
     }
+    let body = resp
+        .text()
+        .await
+        .with_context(|| format!("Failed to read response from '{}'", url))?;
+    let is_html = match &content_type {
+        Some(ct) => ct.contains("html"),
+        None => body.trim_start().starts_with('<'),
+    };
+    let text = if is_html {
+        super::html::page_to_text(&body)
+    } else {
+        body
+    };
+
+    if !opts.cache_ttl.is_zero() {
+        // Caching is best-effort; a read-only cache dir must not fail the request
+        let _ = fs::create_dir_all(&opts.cache_dir);
+        let _ = fs::write(&cache_file, &text);
+    }
+    Ok(text)
+}
+
+/// Cached text at `path` if it was written less than `ttl` ago.
+fn read_fresh(path: &Path, ttl: Duration) -> Option<String> {
+    if ttl.is_zero() {
+        return None;
+    }
+    let age = fs::metadata(path).ok()?.modified().ok()?.elapsed().ok()?;
+    if age >= ttl {
+        return None;
+    }
+    fs::read_to_string(path).ok()
 }
 
-/// Combine document content with user prompt.
+/// Combine documents with the user prompt.
 ///
-/// Creates a formatted string combining document content and user question.
-/// If user prompt is empty, returns only the document content.
+/// Each document is prefixed with a header indicating the file path or URL, followed by a
+/// note when the token budget cut it. If the user prompt is empty, only the documents are
+/// returned.
 ///
 /// # Arguments
 ///
-/// * `doc_content` - The content of the document(s)
+/// * `documents` - The documents, as returned by [`read_documents`]
 /// * `user_prompt` - The user's question or prompt
 ///
 /// # Returns
 ///
-/// * `String` - Formatted combination of document and prompt
+/// * `String` - Formatted combination of documents and prompt
 ///
 /// # Format
 ///
 /// ```text
 /// Document content:
-/// [doc_content]
+/// === Document: [source] ===
+/// [truncation note, if any]
+/// [content]
 ///
 /// User question: [user_prompt]
 /// ```
@@ -128,9 +828,10 @@ pub fn read_single_document(file_path: &str) -> Result<String> {
 /// ```rust
 /// use crate::utils::document::combine_doc_and_prompt;
 ///
-/// let combined = combine_doc_and_prompt("File content here", "Summarize this");
+/// let combined = combine_doc_and_prompt(&docs, "Summarize this");
 /// ```
-pub fn combine_doc_and_prompt(doc_content: &str, user_prompt: &str) -> String {
+pub fn combine_doc_and_prompt(documents: &[Document], user_prompt: &str) -> String {
+    let doc_content = render_documents(documents);
     if user_prompt.trim().is_empty() {
         // If no user prompt, just return document content
         format!("Document content:\n{}", doc_content)
@@ -142,3 +843,363 @@ pub fn combine_doc_and_prompt(doc_content: &str, user_prompt: &str) -> String {
         )
     }
 }
+
+fn render_documents(documents: &[Document]) -> String {
+    let mut out = String::new();
+    for (i, doc) in documents.iter().enumerate() {
+        if i > 0 {
+            out.push_str("\n\n");
+        }
+        match doc.encoding {
+            Some(encoding) => out.push_str(&format!(
+                "=== Document: {} (encoding: {}) ===\n",
+                doc.source, encoding
+            )),
+            None => out.push_str(&format!("=== Document: {} ===\n", doc.source)),
+        }
+        if let Some(t) = &doc.truncation {
+            out.push_str(&t.note());
+            out.push('\n');
+        }
+        out.push_str(&doc.content);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(cache_dir: &Path) -> DocOptions {
+        DocOptions {
+            max_chars: None,
+            max_file_bytes: 0,
+            csv_sample_rows: 10,
+            pdf: PdfOptions::default(),
+            code_extensions: parse_extensions(DEFAULT_CODE_EXTENSIONS),
+            http: reqwest::Client::new(),
+            cache_dir: cache_dir.to_path_buf(),
+            cache_ttl: Duration::from_secs(60),
+            max_input_tokens: None,
+            truncate_strategy: TruncateStrategy::HeadTail,
+        }
+    }
+
+    fn doc(source: &str, content: &str) -> Document {
+        Document {
+            source: source.to_string(),
+            content: content.to_string(),
+            encoding: None,
+            truncation: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn glob_arguments_expand_to_sorted_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("b.rs"), "fn b() {}").unwrap();
+        fs::write(dir.path().join("sub").join("a.rs"), "fn a() {}").unwrap();
+        fs::write(dir.path().join("notes.md"), "notes").unwrap();
+        let root = dir.path().to_string_lossy();
+
+        let args = vec![format!("{}/**/*.rs", root), format!("{}/notes.md", root)];
+        let docs = expand_doc_paths(&args, 0).unwrap();
+        assert!(docs.expanded);
+        assert_eq!(docs.total_bytes, 23);
+        let files = docs.files;
+        let names: Vec<String> = files
+            .iter()
+            .map(|f| {
+                Path::new(f)
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        assert_eq!(names, ["b.rs", "a.rs", "notes.md"]);
+        assert_eq!(
+            read_single_document(&files[0], &options(dir.path()))
+                .unwrap()
+                .content,
+            "```rust\nfn b() {}\n```"
+        );
+
+        assert!(expand_doc_paths(&[format!("{}/*.py", root)], 0).is_err());
+
+        let opts = DocOptions {
+            max_chars: Some(3),
+            ..options(dir.path())
+        };
+        let docs = read_documents(&[format!("{}/notes.md", root)], &opts)
+            .await
+            .unwrap();
+        let combined = render_documents(&docs);
+        assert!(combined.ends_with("not\n[... truncated: showing 3 of 5 characters]"));
+        let plain = expand_doc_paths(&["README.md".to_string()], 0).unwrap();
+        assert_eq!(plain.files, ["README.md"]);
+        assert!(!plain.expanded);
+    }
+
+    #[tokio::test]
+    async fn urls_are_fetched_converted_and_cached() {
+        use httpmock::prelude::*;
+
+        let server = MockServer::start_async().await;
+        let page = server.mock(|when, then| {
+            when.method(GET).path("/post.html");
+            then.status(200)
+                .header("content-type", "text/html; charset=utf-8")
+                .body("<html><body><nav>Menu</nav><p>Hello <i>web</i></p></body></html>");
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/missing");
+            then.status(404);
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let opts = options(dir.path());
+        let url = server.url("/post.html?utm=1");
+        let docs = expand_doc_paths(std::slice::from_ref(&url), 0).unwrap();
+        assert_eq!(docs.files, std::slice::from_ref(&url));
+        assert!(!docs.expanded);
+        let paths = docs.files;
+
+        for _ in 0..2 {
+            let content = render_documents(&read_documents(&paths, &opts).await.unwrap());
+            assert_eq!(content, format!("=== Document: {} ===\nHello web", url));
+        }
+        page.assert_hits(1);
+
+        let err = read_documents(&[server.url("/missing")], &opts)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("HTTP 404"));
+    }
+
+    #[test]
+    fn code_files_are_fenced_and_binaries_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = DocOptions {
+            code_extensions: parse_extensions(" .yml, Dockerfile ,,"),
+            ..options(dir.path())
+        };
+        assert_eq!(opts.code_extensions, ["yml", "dockerfile"]);
+
+        let yaml = dir.path().join("ci.yml");
+        fs::write(&yaml, "on: push\nrun: echo ```x```\n").unwrap();
+        assert_eq!(
+            read_single_document(yaml.to_str().unwrap(), &opts)
+                .unwrap()
+                .content,
+            "````yaml\non: push\nrun: echo ```x```\n````"
+        );
+        let docker = dir.path().join("Dockerfile");
+        fs::write(&docker, "FROM rust").unwrap();
+        assert!(read_single_document(docker.to_str().unwrap(), &opts)
+            .unwrap()
+            .content
+            .starts_with("```dockerfile\n"));
+        // Not in this allowlist: still read, but as plain text
+        let script = dir.path().join("run.py");
+        fs::write(&script, "print(1)").unwrap();
+        assert_eq!(
+            read_single_document(script.to_str().unwrap(), &opts)
+                .unwrap()
+                .content,
+            "print(1)"
+        );
+
+        let blob = dir.path().join("data.bin");
+        fs::write(&blob, b"\x7fELF\x00\x00\x01").unwrap();
+        let err = read_single_document(blob.to_str().unwrap(), &opts).unwrap_err();
+        assert!(err.to_string().contains("binary file"));
+    }
+
+    #[tokio::test]
+    async fn documents_keep_input_order_and_report_all_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = options(dir.path());
+        let paths: Vec<String> = (0..6)
+            .map(|i| {
+                let path = dir.path().join(format!("{}.txt", i));
+                fs::write(&path, format!("doc {}", i)).unwrap();
+                path.to_string_lossy().into_owned()
+            })
+            .collect();
+        let docs = read_documents(&paths, &opts).await.unwrap();
+        let contents: Vec<&str> = docs.iter().map(|d| d.content.as_str()).collect();
+        assert_eq!(
+            contents,
+            ["doc 0", "doc 1", "doc 2", "doc 3", "doc 4", "doc 5"]
+        );
+
+        let mixed = vec![
+            paths[0].clone(),
+            "missing-a.md".to_string(),
+            paths[1].clone(),
+            "missing-b.md".to_string(),
+        ];
+        let err = read_documents(&mixed, &opts).await.unwrap_err().to_string();
+        assert!(
+            err.starts_with("2 of 4 documents could not be read:"),
+            "{}",
+            err
+        );
+        let a = err.find("missing-a.md").unwrap();
+        let b = err.find("missing-b.md").unwrap();
+        assert!(a < b);
+        assert!(!err.contains(&paths[0]));
+    }
+
+    #[test]
+    fn pdf_page_ranges_are_split_from_arguments() {
+        assert_eq!(
+            split_page_range("report.pdf:12-18").unwrap(),
+            ("report.pdf", PageRange::parse("12-18"))
+        );
+        assert_eq!(
+            split_page_range("Report.PDF:3").unwrap(),
+            ("Report.PDF", PageRange::parse("3"))
+        );
+        assert_eq!(
+            split_page_range("notes.md:3").unwrap(),
+            ("notes.md:3", None)
+        );
+        assert_eq!(
+            split_page_range("report.pdf:intro").unwrap(),
+            ("report.pdf:intro", None)
+        );
+        assert!(split_page_range("report.pdf:18-12").is_err());
+    }
+
+    #[test]
+    fn legacy_encodings_are_detected_and_transcoded() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = options(dir.path());
+
+        let chinese = "这是一个使用简体中文编写的测试文档，用于检查编码识别是否正确。";
+        let gbk = dir.path().join("notes.txt");
+        fs::write(&gbk, encoding_rs::GBK.encode(chinese).0).unwrap();
+        let doc = read_single_document(gbk.to_str().unwrap(), &opts).unwrap();
+        assert_eq!(doc.content, chinese);
+        assert_eq!(doc.encoding, Some("GBK"));
+        assert!(render_documents(&[doc]).starts_with(&format!(
+            "=== Document: {} (encoding: GBK) ===\n",
+            gbk.display()
+        )));
+
+        let latin = dir.path().join("menu.md");
+        fs::write(&latin, b"Caf\xe9 cr\xe8me br\xfbl\xe9e, na\xefve gar\xe7on").unwrap();
+        let doc = read_single_document(latin.to_str().unwrap(), &opts).unwrap();
+        assert_eq!(doc.content, "Café crème brûlée, naïve garçon");
+        assert_eq!(doc.encoding, Some("windows-1252"));
+
+        let bom = dir.path().join("bom.txt");
+        fs::write(&bom, b"\xef\xbb\xbfhello").unwrap();
+        let doc = read_single_document(bom.to_str().unwrap(), &opts).unwrap();
+        assert_eq!(doc.content, "hello");
+        assert_eq!(doc.encoding, None);
+    }
+
+    #[test]
+    fn directories_are_walked_with_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src/nested")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        fs::write(root.join("src/lib.rs"), "pub mod nested;").unwrap();
+        fs::write(root.join("src/nested/mod.rs"), "// nested").unwrap();
+        fs::write(root.join("src/logo.png"), b"\x89PNG\x00\x00").unwrap();
+        fs::write(root.join("src/big.txt"), "x".repeat(100)).unwrap();
+        fs::write(root.join("target/out.rs"), "// build output").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.join("src"), root.join("src/nested/loop")).unwrap();
+
+        let docs = expand_doc_paths(&[root.to_string_lossy().into_owned()], 50).unwrap();
+        let names: Vec<String> = docs
+            .files
+            .iter()
+            .map(|f| {
+                Path::new(f)
+                    .strip_prefix(root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        assert_eq!(names, ["src/lib.rs", "src/nested/mod.rs"]);
+        assert!(docs.expanded);
+        assert_eq!(docs.skipped, 2);
+        assert_eq!(docs.total_bytes, 24);
+        assert!(docs
+            .summary()
+            .ends_with("; skipped 2 binary or oversized files"));
+
+        let empty = root.join("empty");
+        fs::create_dir(&empty).unwrap();
+        let err = expand_doc_paths(&[empty.to_string_lossy().into_owned()], 0).unwrap_err();
+        assert!(err.to_string().contains("No files found in directory"));
+    }
+
+    #[test]
+    fn budget_keeps_small_documents_and_shares_the_rest() {
+        let mut docs = vec![
+            doc("small.md", &"s".repeat(40)),
+            doc(
+                "big.log",
+                &format!("{}{}", "h".repeat(400), "t".repeat(400)),
+            ),
+            doc("huge.txt", &"x".repeat(4000)),
+        ];
+        // 10 + 200 + 1000 tokens into 110: small fits, the others get 50 each
+        fit_documents(&mut docs, 110, TruncateStrategy::HeadTail).unwrap();
+        assert!(docs[0].truncation.is_none());
+        let big = docs[1].truncation.unwrap();
+        assert_eq!((big.original_tokens, big.kept_tokens), (200, 50));
+        assert_eq!(
+            docs[1].content,
+            format!(
+                "{}\n[... ~150 tokens omitted ...]\n{}",
+                "h".repeat(100),
+                "t".repeat(100)
+            )
+        );
+        assert_eq!(docs[2].truncation.unwrap().kept_tokens, 50);
+
+        let combined = combine_doc_and_prompt(&docs, "why?");
+        assert!(combined.contains(
+            "=== Document: big.log ===\n[Partial document: showing the beginning and end, ~50 of ~200 tokens]\nhhh"
+        ));
+        assert!(combined.starts_with("Document content:\n=== Document: small.md ===\nssss"));
+        assert!(combined.ends_with("\n\nUser question: why?"));
+    }
+
+    #[test]
+    fn budget_strategies_cut_head_tail_or_refuse() {
+        let text = format!("{}{}", "a".repeat(40), "b".repeat(40));
+        let mut head = vec![doc("a", &text)];
+        fit_documents(&mut head, 5, TruncateStrategy::Head).unwrap();
+        assert_eq!(head[0].content, format!("{}\n[...]", "a".repeat(20)));
+
+        let mut tail = vec![doc("a", &text)];
+        fit_documents(&mut tail, 5, TruncateStrategy::Tail).unwrap();
+        assert_eq!(tail[0].content, format!("[...]\n{}", "b".repeat(20)));
+        assert!(tail[0]
+            .truncation
+            .unwrap()
+            .note()
+            .contains("the last ~5 of ~20"));
+
+        let mut strict = vec![doc("a", &text)];
+        let err = fit_documents(&mut strict, 5, TruncateStrategy::Error).unwrap_err();
+        assert!(err.to_string().contains("a: ~20 tokens"));
+        assert!(fit_documents(&mut strict, 20, TruncateStrategy::Error).is_ok());
+        assert_eq!(
+            TruncateStrategy::parse(" Head+Tail "),
+            Some(TruncateStrategy::HeadTail)
+        );
+    }
+}
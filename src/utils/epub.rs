@@ -0,0 +1,151 @@
+//! EPUB text extraction utilities.
+//!
+//! An EPUB is a ZIP container: `META-INF/container.xml` points at the OPF package file,
+//! whose `<spine>` lists the (X)HTML content documents in reading order.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+
+use anyhow::{anyhow, Context, Result};
+use scraper::{Html, Selector};
+
+use super::html::html_to_text;
+
+/// Extract the text of an EPUB book, one chapter per spine entry.
+///
+/// Chapters are joined with `--- Chapter N ---` separators; entries without any
+/// text (cover pages, images) are skipped.
+///
+/// # Examples
+///
+/// ```rust
+/// use crate::utils::epub::extract_epub_text;
+///
+/// let content = extract_epub_text("book.epub")?;
+/// ```
+pub fn extract_epub_text(file_path: &str) -> Result<String> {
+    let file = File::open(file_path)
+        .with_context(|| format!("Failed to open EPUB file '{}'", file_path))?;
+    let mut archive = zip::ZipArchive::new(file)
+        .with_context(|| format!("'{}' is not a valid EPUB (ZIP) file", file_path))?;
+
+    let container = read_entry(&mut archive, "META-INF/container.xml")?;
+    let opf_path = first_attr(&container, "rootfile", "full-path")
+        .ok_or_else(|| anyhow!("EPUB container.xml has no rootfile"))?;
+    let opf = read_entry(&mut archive, &opf_path)?;
+    // Manifest hrefs are relative to the OPF file's directory
+    let base = opf_path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+
+    let mut chapters = Vec::new();
+    for href in spine_hrefs(&opf) {
+        let path = if base.is_empty() {
+            href
+        } else {
+            format!("{}/{}", base, href)
+        };
+        let Ok(html) = read_entry(&mut archive, &path) else {
+            continue;
+        };
+        let text = html_to_text(&html);
+        if !text.is_empty() {
+            chapters.push(text);
+        }
+    }
+    if chapters.is_empty() {
+        return Err(anyhow!("No readable text found in EPUB '{}'", file_path));
+    }
+
+    let mut out = String::new();
+    for (i, chapter) in chapters.iter().enumerate() {
+        out.push_str(&format!("\n\n--- Chapter {} ---\n\n", i + 1));
+        out.push_str(chapter);
+    }
+    Ok(out.trim_start().to_string())
+}
+
+fn read_entry(archive: &mut zip::ZipArchive<File>, name: &str) -> Result<String> {
+    let mut entry = archive
+        .by_name(name)
+        .with_context(|| format!("EPUB entry '{}' not found", name))?;
+    let mut text = String::new();
+    entry.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+fn first_attr(xml: &str, element: &str, attr: &str) -> Option<String> {
+    let doc = Html::parse_document(xml);
+    let selector = Selector::parse(element).ok()?;
+    let value = doc.select(&selector).find_map(|e| e.value().attr(attr))?;
+    Some(value.to_string())
+}
+
+/// Content document paths from the OPF, in spine (reading) order.
+fn spine_hrefs(opf: &str) -> Vec<String> {
+    let doc = Html::parse_document(opf);
+    let (Ok(items), Ok(refs)) = (Selector::parse("item"), Selector::parse("itemref")) else {
+        return Vec::new();
+    };
+    let manifest: HashMap<&str, &str> = doc
+        .select(&items)
+        .filter_map(|e| Some((e.value().attr("id")?, e.value().attr("href")?)))
+        .collect();
+    doc.select(&refs)
+        .filter_map(|e| e.value().attr("idref"))
+        .filter_map(|id| manifest.get(id))
+        .map(|href| href.split('#').next().unwrap_or(href).replace("%20", " "))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_epub(path: &std::path::Path) {
+        let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+        let opts = zip::write::SimpleFileOptions::default();
+        let mut add = |name: &str, body: &str| {
+            zip.start_file(name, opts).unwrap();
+            zip.write_all(body.as_bytes()).unwrap();
+        };
+        add("mimetype", "application/epub+zip");
+        add(
+            "META-INF/container.xml",
+            r#"<?xml version="1.0"?><container><rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#,
+        );
+        add(
+            "OEBPS/content.opf",
+            r#"<package><manifest>
+                <item id="c2" href="text/two.xhtml" media-type="application/xhtml+xml"/>
+                <item id="cover" href="cover.xhtml" media-type="application/xhtml+xml"/>
+                <item id="c1" href="text/one.xhtml" media-type="application/xhtml+xml"/>
+            </manifest><spine><itemref idref="cover"/><itemref idref="c1"/><itemref idref="c2"/></spine></package>"#,
+        );
+        add(
+            "OEBPS/cover.xhtml",
+            "<html><body><img src='c.png'/></body></html>",
+        );
+        add(
+            "OEBPS/text/one.xhtml",
+            "<html><head><style>p{}</style></head><body><h1>Start</h1><p>It was a  dark\n night.</p></body></html>",
+        );
+        add(
+            "OEBPS/text/two.xhtml",
+            "<html><body><p>The end.</p></body></html>",
+        );
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn chapters_follow_spine_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.epub");
+        write_epub(&path);
+        let text = extract_epub_text(path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            text,
+            "--- Chapter 1 ---\n\nStart\nIt was a dark night.\n\n--- Chapter 2 ---\n\nThe end."
+        );
+    }
+}
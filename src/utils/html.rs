@@ -0,0 +1,102 @@
+//! Plain-text extraction from HTML, shared by EPUB chapters and `--doc` web pages.
+
+use scraper::{Html, Node, Selector};
+
+/// Elements whose text is never shown to a reader.
+const HIDDEN: &[&str] = &["script", "style", "noscript", "template"];
+/// Page chrome dropped from web pages on top of [`HIDDEN`].
+const BOILERPLATE: &[&str] = &[
+    "nav", "header", "footer", "aside", "form", "svg", "iframe", "button",
+];
+
+/// Visible text of an (X)HTML document, keeping paragraph breaks.
+pub fn html_to_text(html: &str) -> String {
+    extract_text(html, HIDDEN)
+}
+
+/// Readable text of a web page: like [`html_to_text`], but navigation, headers, footers
+/// and other page chrome are left out as well.
+pub fn page_to_text(html: &str) -> String {
+    let skip: Vec<&str> = HIDDEN.iter().chain(BOILERPLATE).copied().collect();
+    extract_text(html, &skip)
+}
+
+fn extract_text(html: &str, skip: &[&str]) -> String {
+    const BLOCKS: &[&str] = &[
+        "p",
+        "div",
+        "br",
+        "li",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "tr",
+        "blockquote",
+        "pre",
+        "section",
+    ];
+    let doc = Html::parse_document(html);
+    let root = Selector::parse("body")
+        .ok()
+        .and_then(|s| doc.select(&s).next())
+        .unwrap_or_else(|| doc.root_element());
+
+    let mut raw = String::new();
+    for node in root.descendants() {
+        match node.value() {
+            Node::Text(t) => {
+                let mut ancestors = node.ancestors().filter_map(|a| a.value().as_element());
+                if ancestors.clone().any(|e| skip.contains(&e.name())) {
+                    continue;
+                }
+                // Line breaks in the markup are only layout inside <pre>
+                if ancestors.any(|e| e.name() == "pre") {
+                    raw.push_str(t);
+                } else {
+                    raw.push_str(&t.replace(['\n', '\r'], " "));
+                }
+            }
+            Node::Element(e) if BLOCKS.contains(&e.name()) => raw.push('\n'),
+            _ => {}
+        }
+    }
+
+    // Collapse the markup's whitespace: one line per block, no runs of blank lines
+    let mut out = String::new();
+    for line in raw.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !line.is_empty() {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&line);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<html><head><title>Post</title><script>var x = 1;</script></head>
+<body><header><a href="/">Home</a></header><nav><ul><li>Blog</li></ul></nav>
+<article><h1>Hello</h1><p>First <b>bold</b> paragraph.</p><noscript>Enable JS</noscript></article>
+<footer>Copyright</footer></body></html>"#;
+
+    #[test]
+    fn page_text_drops_chrome() {
+        assert_eq!(page_to_text(PAGE), "Hello\nFirst bold paragraph.");
+    }
+
+    #[test]
+    fn plain_html_text_keeps_layout_elements() {
+        let text = html_to_text(PAGE);
+        assert!(text.starts_with("Home\nBlog\nHello"));
+        assert!(!text.contains("var x"));
+        assert!(text.ends_with("Copyright"));
+    }
+}
@@ -0,0 +1,219 @@
+//! Latency metrics for streamed responses (`--measure` / `--measure-stats`) and the
+//! provider-reported token usage (`SHOW_TOKEN_USAGE`).
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, llm::StreamEvent};
+
+/// How many recent samples `--measure-stats` averages over.
+const HISTORY_LEN: usize = 50;
+const HISTORY_FILE: &str = "latency.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Sample {
+    model: String,
+    ttft_ms: u64,
+    total_ms: u64,
+    /// Completion tokens reported by the provider, else estimated as characters / 4.
+    tokens: u64,
+}
+
+impl Sample {
+    fn tokens_per_sec(&self) -> f64 {
+        // Rate over the generation phase; fall back to the whole request for one-chunk replies
+        let gen_ms = self.total_ms.saturating_sub(self.ttft_ms);
+        let ms = if gen_ms > 0 {
+            gen_ms
+        } else {
+            self.total_ms.max(1)
+        };
+        self.tokens as f64 * 1000.0 / ms as f64
+    }
+}
+
+/// Times one streamed response. Does nothing unless `MEASURE_LATENCY` or `SHOW_TOKEN_USAGE` is enabled.
+pub struct Measure {
+    enabled: bool,
+    show_usage: bool,
+    model: String,
+    history: PathBuf,
+    started: Instant,
+    first_token: Option<Duration>,
+    finished: Option<Duration>,
+    chars: usize,
+    /// `(prompt, completion)` tokens from the stream's `UsageReport`
+    usage: Option<(u32, u32)>,
+}
+
+impl Measure {
+    pub fn start(cfg: &Config, model: &str) -> Self {
+        Self {
+            enabled: cfg.get_bool("MEASURE_LATENCY"),
+            show_usage: cfg.get_bool("SHOW_TOKEN_USAGE"),
+            model: model.to_string(),
+            history: cfg.cache_path().join(HISTORY_FILE),
+            started: Instant::now(),
+            first_token: None,
+            finished: None,
+            chars: 0,
+            usage: None,
+        }
+    }
+
+    pub fn observe(&mut self, ev: &StreamEvent) {
+        match ev {
+            StreamEvent::Content(t) => {
+                self.first_token
+                    .get_or_insert_with(|| self.started.elapsed());
+                self.chars += t.chars().count();
+            }
+            StreamEvent::UsageReport {
+                prompt_tokens,
+                completion_tokens,
+            } => {
+                self.usage = Some((*prompt_tokens, *completion_tokens));
+            }
+            StreamEvent::Done => {
+                self.finished.get_or_insert_with(|| self.started.elapsed());
+            }
+            _ => {}
+        }
+    }
+
+    /// Print the metrics line to stderr and record the sample.
+    pub fn report(&mut self) {
+        let usage = self.usage.take();
+        if self.show_usage {
+            if let Some((prompt, completion)) = usage {
+                eprintln!("{}", usage_line(prompt, completion));
+            }
+        }
+        if !self.enabled {
+            return;
+        }
+        // Tool-call-only turns have no content to time
+        let Some(ttft) = self.first_token.take() else {
+            return;
+        };
+        let total = self
+            .finished
+            .take()
+            .unwrap_or_else(|| self.started.elapsed());
+        let sample = Sample {
+            model: self.model.clone(),
+            ttft_ms: ttft.as_millis() as u64,
+            total_ms: total.as_millis() as u64,
+            tokens: usage.map_or((self.chars / 4) as u64, |(_, c)| c as u64),
+        };
+        eprintln!(
+            "[ttft: {:.1}s total: {:.1}s {}{:.0} tok/s]",
+            ttft.as_secs_f64(),
+            total.as_secs_f64(),
+            if usage.is_some() { "" } else { "~" },
+            sample.tokens_per_sec()
+        );
+
+        let mut samples = read_history(&self.history);
+        samples.push(sample);
+        let excess = samples.len().saturating_sub(HISTORY_LEN);
+        samples.drain(..excess);
+        if let Some(dir) = self.history.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        if let Ok(data) = serde_json::to_string(&samples) {
+            let _ = fs::write(&self.history, data);
+        }
+    }
+}
+
+/// `SHOW_TOKEN_USAGE` line, e.g. `[tokens: 120 prompt + 45 completion = 165]`.
+pub fn usage_line(prompt: u32, completion: u32) -> String {
+    format!(
+        "[tokens: {} prompt + {} completion = {}]",
+        prompt,
+        completion,
+        prompt + completion
+    )
+}
+
+fn read_history(path: &Path) -> Vec<Sample> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|t| serde_json::from_str(&t).ok())
+        .unwrap_or_default()
+}
+
+/// Rolling averages per model over the recorded samples, for `--measure-stats`.
+pub fn stats_report(cfg: &Config) -> String {
+    let samples = read_history(&cfg.cache_path().join(HISTORY_FILE));
+    if samples.is_empty() {
+        return "No measurements recorded yet; run a prompt with --measure.".to_string();
+    }
+    let mut models: Vec<&str> = samples.iter().map(|s| s.model.as_str()).collect();
+    models.sort_unstable();
+    models.dedup();
+
+    let mut out = format!("Last {} measurements:\n", samples.len());
+    for model in models {
+        let group: Vec<&Sample> = samples.iter().filter(|s| s.model == model).collect();
+        let n = group.len() as f64;
+        let ttft = group.iter().map(|s| s.ttft_ms as f64).sum::<f64>() / n / 1000.0;
+        let total = group.iter().map(|s| s.total_ms as f64).sum::<f64>() / n / 1000.0;
+        let rate = group.iter().map(|s| s.tokens_per_sec()).sum::<f64>() / n;
+        out.push_str(&format!(
+            "  {}  n={}  [ttft: {:.1}s total: {:.1}s ~{:.0} tok/s]\n",
+            model,
+            group.len(),
+            ttft,
+            total,
+            rate
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage_report_is_kept_until_reported() {
+        let cfg = Config::load();
+        let mut m = Measure::start(&cfg, "fake");
+        m.observe(&StreamEvent::Content("hello".into()));
+        m.observe(&StreamEvent::UsageReport {
+            prompt_tokens: 120,
+            completion_tokens: 45,
+        });
+        m.observe(&StreamEvent::Done);
+        assert_eq!(m.usage, Some((120, 45)));
+        assert_eq!(
+            usage_line(120, 45),
+            "[tokens: 120 prompt + 45 completion = 165]"
+        );
+    }
+
+    #[test]
+    fn tokens_per_sec_uses_generation_time() {
+        let s = Sample {
+            model: "m".into(),
+            ttft_ms: 1000,
+            total_ms: 3000,
+            tokens: 90,
+        };
+        assert_eq!(s.tokens_per_sec().round(), 45.0);
+
+        let instant = Sample {
+            ttft_ms: 500,
+            total_ms: 500,
+            ..s
+        };
+        assert_eq!(instant.tokens_per_sec().round(), 180.0);
+    }
+}
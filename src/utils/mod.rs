@@ -2,13 +2,37 @@
 //!
 //! This module provides various utility functions organized into submodules:
 //! - `command`: Shell command execution across platforms
+//! - `code`: Stripping Markdown fences from generated code
 //! - `document`: Document reading and processing for multiple file formats
 //! - `pdf`: PDF text extraction utilities
+//! - `pdf_meta`: PDF document info and bookmarks for the extracted header
+//! - `epub`: EPUB text extraction utilities
+//! - `html`: Readable text from HTML pages and EPUB chapters
+//! - `tabular`: CSV/TSV schema summaries with sampled rows
+//! - `patch`: Applying unified diffs for `--code --edit`
+//! - `measure`: Latency metrics for streamed responses
+//! - `clipboard`: Copying text to the system clipboard (OSC 52 fallback)
+//! - `danger`: Detection of destructive shell commands before execution
+//! - `shell_history`: Log of generated shell commands and their outcome
+//! - `pty`: Running commands on a pseudo-terminal with streamed output
+//! - `tty`: Reading interactive answers from the controlling terminal when stdin is piped
 
 // Declare submodules
+pub mod clipboard;
+pub mod code;
 pub mod command;
+pub mod danger;
 pub mod document;
+pub mod epub;
+pub mod html;
+pub mod measure;
+pub mod patch;
 pub mod pdf;
+pub mod pdf_meta;
+pub mod pty;
+pub mod shell_history;
+pub mod tabular;
+pub mod tty;
 pub mod unicode;
 
 // Re-export commonly used functions for backward compatibility
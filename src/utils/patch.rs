@@ -0,0 +1,223 @@
+//! A small unified-diff applier for `--code --edit`.
+//!
+//! Only single-file diffs are supported. Hunks are located by their context rather than
+//! trusted line counts, since models routinely get the `@@` numbers slightly wrong.
+
+use anyhow::{anyhow, bail, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// 1-based first line of the hunk in the original file, as stated in the header.
+    old_start: usize,
+    lines: Vec<HunkLine>,
+}
+
+/// Parse the hunks of a unified diff, skipping `diff`/`index`/`---`/`+++` headers.
+pub fn parse_unified_diff(diff: &str) -> Result<Vec<Hunk>> {
+    let lines: Vec<&str> = diff.lines().collect();
+    let mut hunks = Vec::new();
+    let mut current: Option<Hunk> = None;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        i += 1;
+        if line.starts_with("@@") {
+            hunks.extend(current.take());
+            current = Some(Hunk {
+                old_start: parse_old_start(line)?,
+                lines: Vec::new(),
+            });
+            continue;
+        }
+        // A `---` line only starts a new file header when `+++` follows it;
+        // otherwise it's the removal of a line starting with "--".
+        let is_header =
+            line.starts_with("--- ") && lines.get(i).is_some_and(|next| next.starts_with("+++ "));
+        if is_header {
+            if !hunks.is_empty() || current.as_ref().is_some_and(|h| !h.lines.is_empty()) {
+                bail!("diff touches more than one file");
+            }
+            i += 1;
+            continue;
+        }
+        if line.starts_with("diff ") || line.starts_with("index ") {
+            continue;
+        }
+        let Some(hunk) = current.as_mut() else {
+            continue;
+        };
+        if let Some(rest) = line.strip_prefix('+') {
+            hunk.lines.push(HunkLine::Add(rest.to_string()));
+        } else if let Some(rest) = line.strip_prefix('-') {
+            hunk.lines.push(HunkLine::Remove(rest.to_string()));
+        } else if let Some(rest) = line.strip_prefix(' ') {
+            hunk.lines.push(HunkLine::Context(rest.to_string()));
+        } else if line.is_empty() {
+            // Blank context lines often lose their leading space on the way through
+            hunk.lines.push(HunkLine::Context(String::new()));
+        } else if line.starts_with('\\') {
+            // "\ No newline at end of file"
+        } else {
+            bail!("unexpected line in hunk: {}", line);
+        }
+    }
+    hunks.extend(current);
+    // Trailing blank "context" after the last change is an artifact of the text ending in newlines
+    for hunk in &mut hunks {
+        while matches!(hunk.lines.last(), Some(HunkLine::Context(s)) if s.is_empty()) {
+            hunk.lines.pop();
+        }
+    }
+    hunks.retain(|h| !h.lines.is_empty());
+    if hunks.is_empty() {
+        bail!("no hunks found in diff");
+    }
+    Ok(hunks)
+}
+
+fn parse_old_start(header: &str) -> Result<usize> {
+    // @@ -12,7 +12,8 @@ optional section
+    let range = header
+        .split_whitespace()
+        .find_map(|part| part.strip_prefix('-'))
+        .ok_or_else(|| anyhow!("malformed hunk header: {}", header))?;
+    let start = range.split(',').next().unwrap_or_default();
+    start
+        .parse()
+        .map_err(|_| anyhow!("malformed hunk header: {}", header))
+}
+
+/// Apply a unified diff to `original`, failing without partial results if any hunk
+/// doesn't match.
+pub fn apply_unified_diff(original: &str, diff: &str) -> Result<String> {
+    let hunks = parse_unified_diff(diff)?;
+    let src: Vec<&str> = original.lines().collect();
+    let mut out: Vec<String> = Vec::with_capacity(src.len());
+    let mut cursor = 0;
+    for (n, hunk) in hunks.iter().enumerate() {
+        let old: Vec<&str> = hunk
+            .lines
+            .iter()
+            .filter_map(|l| match l {
+                HunkLine::Context(s) | HunkLine::Remove(s) => Some(s.as_str()),
+                HunkLine::Add(_) => None,
+            })
+            .collect();
+        let pos = locate(&src, &old, hunk.old_start, cursor).ok_or_else(|| {
+            anyhow!(
+                "hunk {} does not apply (context not found near line {})",
+                n + 1,
+                hunk.old_start
+            )
+        })?;
+        out.extend(src[cursor..pos].iter().map(|s| s.to_string()));
+        let mut at = pos;
+        for line in &hunk.lines {
+            match line {
+                // Keep the file's own context lines so whitespace the model mangled survives
+                HunkLine::Context(_) => {
+                    out.push(src[at].to_string());
+                    at += 1;
+                }
+                HunkLine::Remove(_) => at += 1,
+                HunkLine::Add(s) => out.push(s.clone()),
+            }
+        }
+        cursor = at;
+    }
+    out.extend(src[cursor..].iter().map(|s| s.to_string()));
+    let mut patched = out.join("\n");
+    if original.ends_with('\n') || original.is_empty() {
+        patched.push('\n');
+    }
+    Ok(patched)
+}
+
+/// Index where `old` matches `src`, searching outward from the stated start but never
+/// before `cursor` (the end of the previous hunk).
+fn locate(src: &[&str], old: &[&str], old_start: usize, cursor: usize) -> Option<usize> {
+    if old.is_empty() {
+        // Pure insertion: "-N,0" means after line N
+        let pos = old_start.clamp(cursor, src.len());
+        return Some(pos);
+    }
+    if old.len() > src.len() {
+        return None;
+    }
+    let last = src.len() - old.len();
+    let matches_at = |pos: usize| {
+        src[pos..pos + old.len()]
+            .iter()
+            .zip(old)
+            .all(|(a, b)| a.trim_end() == b.trim_end())
+    };
+    let expected = old_start.saturating_sub(1).clamp(cursor, last.max(cursor));
+    for delta in 0..=src.len() {
+        let after = expected + delta;
+        if after >= cursor && after <= last && matches_at(after) {
+            return Some(after);
+        }
+        if let Some(before) = expected.checked_sub(delta) {
+            if delta > 0 && before >= cursor && before <= last && matches_at(before) {
+                return Some(before);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGINAL: &str = "fn main() {\n    let a = 1;\n    println!(\"{}\", a);\n}\n";
+
+    #[test]
+    fn applies_simple_hunk() {
+        let diff = "--- a/src/main.rs\n+++ b/src/main.rs\n@@ -1,4 +1,5 @@\n fn main() {\n     let a = 1;\n+    let b = 2;\n-    println!(\"{}\", a);\n+    println!(\"{}\", a + b);\n }\n";
+        let out = apply_unified_diff(ORIGINAL, diff).unwrap();
+        assert_eq!(
+            out,
+            "fn main() {\n    let a = 1;\n    let b = 2;\n    println!(\"{}\", a + b);\n}\n"
+        );
+    }
+
+    #[test]
+    fn tolerates_wrong_line_numbers() {
+        let diff =
+            "@@ -10,2 +10,2 @@\n-    let a = 1;\n+    let a = 42;\n     println!(\"{}\", a);\n";
+        let out = apply_unified_diff(ORIGINAL, diff).unwrap();
+        assert!(out.contains("let a = 42;"));
+        assert!(!out.contains("let a = 1;"));
+    }
+
+    #[test]
+    fn rejects_mismatched_context() {
+        let diff = "@@ -1,2 +1,2 @@\n fn other() {\n-    let a = 1;\n+    let a = 2;\n";
+        let err = apply_unified_diff(ORIGINAL, diff).unwrap_err();
+        assert!(err.to_string().contains("hunk 1 does not apply"));
+    }
+
+    #[test]
+    fn rejects_multi_file_diffs() {
+        let diff = "--- a/x\n+++ b/x\n@@ -1 +1 @@\n-a\n+b\n--- a/y\n+++ b/y\n@@ -1 +1 @@\n-c\n+d\n";
+        assert!(parse_unified_diff(diff).is_err());
+    }
+
+    #[test]
+    fn applies_multiple_hunks_in_order() {
+        let original = "a\nb\nc\nd\ne\nf\n";
+        let diff = "@@ -1,2 +1,2 @@\n-a\n+A\n b\n@@ -5,2 +5,2 @@\n e\n-f\n+F\n";
+        assert_eq!(
+            apply_unified_diff(original, diff).unwrap(),
+            "A\nb\nc\nd\ne\nF\n"
+        );
+    }
+}
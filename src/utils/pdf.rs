@@ -7,12 +7,113 @@
 //!   --page N----\n
 //!   L1: ...\n
 //!   L2: ...
+//! - Optionally keep only a page range (`--doc report.pdf:12-18`); page headers keep
+//!   the original page numbers.
+//! - Scanned PDFs (next to no text per page) are OCRed with `pdftoppm` + `tesseract`
+//!   when `PDF_OCR=true`. Otherwise a PDF without any text layer is rejected with a hint,
+//!   and a thin one is used with a warning.
+//! - Prepend the document info and bookmarks (see `pdf_meta`) when available.
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use pdf_extract::extract_text;
+use std::path::PathBuf;
 use std::process::{Command, Stdio};
 
-/// Extract text content from a PDF file.
+use crate::config::Config;
+
+/// When to use the external `pdftotext` (`PDF_EXTERNAL_TOOL`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExternalTool {
+    /// Use it when it runs and produces output, else fall back to pdf-extract.
+    #[default]
+    Auto,
+    /// Always use pdf-extract.
+    Never,
+    /// Fail when `pdftotext` can't be started instead of falling back.
+    Always,
+}
+
+impl ExternalTool {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "auto" => Some(Self::Auto),
+            "never" => Some(Self::Never),
+            "always" => Some(Self::Always),
+            _ => None,
+        }
+    }
+}
+
+/// PDF extraction settings taken from the config.
+#[derive(Debug, Clone, Default)]
+pub struct PdfOptions {
+    /// OCR pages without a text layer (`PDF_OCR`) instead of using what little text they have.
+    pub ocr: bool,
+    pub external_tool: ExternalTool,
+    /// `pdftotext` binary (`PDFTOTEXT_PATH`); `None` looks it up on PATH.
+    pub pdftotext_path: Option<PathBuf>,
+}
+
+impl PdfOptions {
+    pub fn from_config(cfg: &Config) -> Self {
+        Self {
+            ocr: cfg.get_bool("PDF_OCR"),
+            external_tool: match cfg.get("PDF_EXTERNAL_TOOL") {
+                None => ExternalTool::Auto,
+                Some(s) => ExternalTool::parse(&s).unwrap_or_else(|| {
+                    eprintln!(
+                        "Warning: unknown PDF_EXTERNAL_TOOL '{}' (expected auto, never or always); using auto",
+                        s
+                    );
+                    ExternalTool::Auto
+                }),
+            },
+            pdftotext_path: cfg
+                .get("PDFTOTEXT_PATH")
+                .filter(|p| !p.trim().is_empty())
+                .map(PathBuf::from),
+        }
+    }
+
+    fn pdftotext(&self) -> PathBuf {
+        self.pdftotext_path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("pdftotext"))
+    }
+}
+
+/// Inclusive, 1-based page range selected with `--doc file.pdf:FIRST-LAST`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageRange {
+    pub first: usize,
+    pub last: usize,
+}
+
+impl PageRange {
+    /// Parse `N` or `N-M` (1-based, `N <= M`).
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (first, last) = match spec.split_once('-') {
+            Some((a, b)) => (a.trim().parse().ok()?, b.trim().parse().ok()?),
+            None => {
+                let n = spec.trim().parse().ok()?;
+                (n, n)
+            }
+        };
+        (first >= 1 && first <= last).then_some(Self { first, last })
+    }
+}
+
+impl std::fmt::Display for PageRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.first == self.last {
+            write!(f, "{}", self.first)
+        } else {
+            write!(f, "{}-{}", self.first, self.last)
+        }
+    }
+}
+
+/// Extract text content from a PDF file, optionally keeping only the pages in `range`.
 ///
 /// Uses the pdf-extract crate to parse PDF files and extract readable text content.
 /// This function handles various PDF encodings and font mappings, though some
@@ -21,6 +122,8 @@ use std::process::{Command, Stdio};
 /// # Arguments
 ///
 /// * `file_path` - Path to the PDF file
+/// * `range` - Pages to keep; headers keep the original page numbers
+/// * `opts` - OCR and external tool settings
 ///
 /// # Returns
 ///
@@ -29,10 +132,12 @@ use std::process::{Command, Stdio};
 /// # Examples
 ///
 /// ```rust
-/// use crate::utils::pdf::extract_pdf_text;
+/// use crate::utils::pdf::{extract_pdf_pages, PageRange, PdfOptions};
 ///
-/// let content = extract_pdf_text("document.pdf")?;
+/// let opts = PdfOptions::from_config(&cfg);
+/// let content = extract_pdf_pages("document.pdf", None, &opts)?;
 /// println!("PDF contains {} characters", content.len());
+/// let chapter = extract_pdf_pages("report.pdf", PageRange::parse("12-18"), &opts)?;
 /// ```
 ///
 /// # Notes
@@ -40,25 +145,108 @@ use std::process::{Command, Stdio};
 /// - The extraction process may produce stderr warnings about font encoding issues
 /// - These warnings are normal for complex PDFs and don't affect the extraction
 /// - Empty or corrupted PDFs will return an error
-pub fn extract_pdf_text(file_path: &str) -> Result<String> {
-    // Strategy A: Use external `pdftotext` if available.
+/// - A range starting past the last page is an error; one that only runs past the
+///   end is clipped with a warning on stderr
+/// - `--metadata----` and `--outline----` sections come first when the PDF has them;
+///   failing to read them only drops the sections
+pub fn extract_pdf_pages(
+    file_path: &str,
+    range: Option<PageRange>,
+    opts: &PdfOptions,
+) -> Result<String> {
+    let text = extract_page_text(file_path, range, opts)?;
+    Ok(match super::pdf_meta::pdf_header(file_path) {
+        Some(header) => format!("{}\n{}", header, text),
+        None => text,
+    })
+}
+
+fn extract_page_text(
+    file_path: &str,
+    range: Option<PageRange>,
+    opts: &PdfOptions,
+) -> Result<String> {
+    let (pages, first) = extract_raw_pages(file_path, range, opts)?;
+    format_text_layer(file_path, pages, first, opts.ocr)
+}
+
+/// Format the extracted `pages`, OCRing them instead when the text layer looks scanned and
+/// `ocr` is on. Without OCR a blank text layer is an error; short documents can trip the
+/// heuristic too, so whatever text they have is kept and only a warning is printed.
+fn format_text_layer(
+    file_path: &str,
+    pages: Vec<String>,
+    first: usize,
+    ocr: bool,
+) -> Result<String> {
+    let pages = if !looks_scanned(&pages) {
+        pages
+    } else if ocr {
+        ocr_pages(file_path, first, first + pages.len() - 1)?
+    } else if pages.iter().all(|p| p.trim().is_empty()) {
+        bail!(
+            "'{}' appears to be a scanned PDF (it has no text layer). Set PDF_OCR=true to OCR it; this needs `tesseract` and `pdftoppm` (poppler-utils) in PATH",
+            file_path
+        );
+    } else {
+        warn_scanned(file_path);
+        pages
+    };
+    let refs: Vec<&str> = pages.iter().map(String::as_str).collect();
+    Ok(format_pages_and_lines(&refs, first))
+}
+
+/// Text of each selected page plus the number of the first one.
+fn extract_raw_pages(
+    file_path: &str,
+    range: Option<PageRange>,
+    opts: &PdfOptions,
+) -> Result<(Vec<String>, usize)> {
+    // Strategy A: Use external `pdftotext` (PDFTOTEXT_PATH, default from PATH) unless
+    // PDF_EXTERNAL_TOOL=never.
     // - `-q` quiet mode suppresses stderr warnings from the tool.
     // - `-enc UTF-8` enforces UTF-8 output.
     // - `-layout` keeps visual order reasonably.
+    // - `-f`/`-l` limit extraction to the requested pages.
     // - Output to stdout ("-") so we can capture it and format.
-    if let Ok(output) = Command::new("pdftotext")
-        .arg("-q")
-        .arg("-enc")
-        .arg("UTF-8")
-        .arg("-layout")
-        .arg(file_path)
-        .arg("-")
-        .stderr(Stdio::null())
-        .output()
-    {
-        if output.status.success() {
+    let output = if opts.external_tool == ExternalTool::Never {
+        None
+    } else {
+        let tool = opts.pdftotext();
+        let mut cmd = Command::new(&tool);
+        cmd.arg("-q").arg("-enc").arg("UTF-8").arg("-layout");
+        if let Some(r) = range {
+            cmd.arg("-f")
+                .arg(r.first.to_string())
+                .arg("-l")
+                .arg(r.last.to_string());
+        }
+        match cmd.arg(file_path).arg("-").stderr(Stdio::null()).output() {
+            Ok(output) => Some(output),
+            Err(e) if opts.external_tool == ExternalTool::Always => {
+                bail!(
+                    "PDF_EXTERNAL_TOOL=always but '{}' could not be run: {}",
+                    tool.display(),
+                    e
+                );
+            }
+            Err(_) => None,
+        }
+    };
+    if let Some(output) = output {
+        // A range starting past the end makes pdftotext fail; the fallback reports it.
+        // A broken pdftotext may also "succeed" without writing anything.
+        if output.status.success() && !output.stdout.is_empty() {
             let raw = String::from_utf8_lossy(&output.stdout).to_string();
-            return Ok(format_pages_and_lines(&raw));
+            let first = range.map_or(1, |r| r.first);
+            let pages = split_pages(&raw);
+            if let Some(r) = range {
+                let shown = first + pages.len() - 1;
+                if shown < r.last {
+                    warn_clipped(file_path, r, shown);
+                }
+            }
+            return Ok((owned(&pages), first));
         }
     }
 
@@ -67,27 +255,162 @@ pub fn extract_pdf_text(file_path: &str) -> Result<String> {
         .unwrap_or_else(|_| extract_text(file_path))
         .map_err(|e| anyhow::anyhow!("Failed to extract text from PDF '{}': {}", file_path, e))?;
 
-    Ok(format_pages_and_lines(&raw))
+    let pages = split_pages(&raw);
+    match range {
+        None => Ok((owned(&pages), 1)),
+        Some(r) => {
+            let (kept, shown) = select_pages(&pages, r).map_err(|total| {
+                anyhow::anyhow!(
+                    "Pages {} are out of range: '{}' has {} page{}",
+                    r,
+                    file_path,
+                    total,
+                    if total == 1 { "" } else { "s" }
+                )
+            })?;
+            if shown < r.last {
+                warn_clipped(file_path, r, shown);
+            }
+            Ok((owned(kept), r.first))
+        }
+    }
 }
 
-/// Format raw PDF text into page/line sections.
-///
-/// Page boundaries are detected via form feed (\x0C) if present; otherwise the
-/// entire document is treated as a single page.
-fn format_pages_and_lines(raw: &str) -> String {
-    let pages: Vec<&str> = if raw.contains('\u{000C}') {
-        // form feed
-        raw.split('\u{000C}').collect()
-    } else {
-        vec![raw]
-    };
+fn owned(pages: &[&str]) -> Vec<String> {
+    pages.iter().map(|p| p.to_string()).collect()
+}
+
+/// A text layer this thin (non-whitespace characters per page, on average) means the
+/// pages are images: page numbers and stray marks, but no real content.
+const SCANNED_CHARS_PER_PAGE: usize = 16;
+
+fn looks_scanned(pages: &[String]) -> bool {
+    let chars: usize = pages
+        .iter()
+        .map(|p| p.chars().filter(|c| !c.is_whitespace()).count())
+        .sum();
+    chars < SCANNED_CHARS_PER_PAGE * pages.len().max(1)
+}
+
+/// OCR pages `first..=last` by rendering them with `pdftoppm` and reading each image
+/// with `tesseract`. Errors explain what to install when the tools are missing.
+fn ocr_pages(file_path: &str, first: usize, last: usize) -> Result<Vec<String>> {
+    let missing: Vec<&str> = ["pdftoppm", "tesseract"]
+        .into_iter()
+        .filter(|tool| !has_tool(tool))
+        .collect();
+    if !missing.is_empty() {
+        bail!(
+            "'{}' appears to be a scanned PDF, but OCR needs {} in PATH (install tesseract and poppler-utils)",
+            file_path,
+            missing
+                .iter()
+                .map(|t| format!("`{}`", t))
+                .collect::<Vec<_>>()
+                .join(" and ")
+        );
+    }
+
+    let dir = tempfile::tempdir()?;
+    let prefix = dir.path().join("page");
+    let status = Command::new("pdftoppm")
+        .args(["-r", "300", "-gray", "-png"])
+        .arg("-f")
+        .arg(first.to_string())
+        .arg("-l")
+        .arg(last.to_string())
+        .arg(file_path)
+        .arg(&prefix)
+        .stderr(Stdio::null())
+        .status()
+        .with_context(|| format!("Failed to run pdftoppm on '{}'", file_path))?;
+    if !status.success() {
+        bail!("pdftoppm could not render '{}' for OCR", file_path);
+    }
+
+    // Images are named page-<N>.png, N being the original (zero-padded) page number
+    let mut pages = vec![String::new(); last - first + 1];
+    for entry in std::fs::read_dir(dir.path())? {
+        let path = entry?.path();
+        let Some(number) = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.rsplit('-').next())
+            .and_then(|n| n.parse::<usize>().ok())
+        else {
+            continue;
+        };
+        if !(first..=last).contains(&number) {
+            continue;
+        }
+        let output = Command::new("tesseract")
+            .arg(&path)
+            .arg("stdout")
+            .stderr(Stdio::null())
+            .output()
+            .with_context(|| format!("Failed to run tesseract on page {}", number))?;
+        if !output.status.success() {
+            bail!("tesseract failed on page {} of '{}'", number, file_path);
+        }
+        pages[number - first] = String::from_utf8_lossy(&output.stdout).into_owned();
+    }
+    Ok(pages)
+}
+
+fn has_tool(name: &str) -> bool {
+    Command::new(name)
+        .arg("-v")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok()
+}
+
+fn warn_scanned(file_path: &str) {
+    eprintln!(
+        "Warning: '{}' has very little text and may be a scanned PDF. Set PDF_OCR=true to OCR it; this needs `tesseract` and `pdftoppm` (poppler-utils) in PATH",
+        file_path
+    );
+}
+
+fn warn_clipped(file_path: &str, range: PageRange, last_page: usize) {
+    eprintln!(
+        "Warning: '{}' ends at page {}; showing pages {}-{} of the requested {}",
+        file_path, last_page, range.first, last_page, range
+    );
+}
+
+/// Split raw text into pages on form feeds (\x0C); without any, the whole text is one page.
+/// The empty remainder after a final form feed (as pdftotext writes it) is not a page.
+fn split_pages(raw: &str) -> Vec<&str> {
+    let mut pages: Vec<&str> = raw.split('\u{000C}').collect();
+    if pages.len() > 1 && pages.last().is_some_and(|p| p.trim().is_empty()) {
+        pages.pop();
+    }
+    pages
+}
+
+/// Pages of `range` present in `pages`, with the last page number actually kept.
+/// Errors with the page count when the range starts after the last page.
+fn select_pages<'a>(
+    pages: &'a [&'a str],
+    range: PageRange,
+) -> Result<(&'a [&'a str], usize), usize> {
+    if range.first > pages.len() {
+        return Err(pages.len());
+    }
+    let last = range.last.min(pages.len());
+    Ok((&pages[range.first - 1..last], last))
+}
 
+/// Format pages into page/line sections, numbering them from `first_page`.
+fn format_pages_and_lines(pages: &[&str], first_page: usize) -> String {
     let mut out = String::new();
     for (pi, page) in pages.iter().enumerate() {
         if pi > 0 {
             out.push('\n');
         }
-        out.push_str(&format!("--page {}----\n", pi + 1));
+        out.push_str(&format!("--page {}----\n", first_page + pi));
         for (li, line) in page.lines().enumerate() {
             // Preserve leading spaces; trim only trailing newlines/spaces
             let line = line.trim_end_matches(['\r', '\n']);
@@ -105,6 +428,7 @@ where
     use std::fs::OpenOptions;
     use std::io;
     use std::os::unix::io::AsRawFd;
+    use std::sync::Mutex;
 
     extern "C" {
         fn dup(fd: i32) -> i32;
@@ -112,6 +436,11 @@ where
         fn close(fd: i32) -> i32;
     }
 
+    // fd 2 is process-wide: overlapping redirections from parallel `--doc` workers could
+    // restore each other's /dev/null and silence stderr for good, so take turns.
+    static STDERR_REDIRECT: Mutex<()> = Mutex::new(());
+    let _guard = STDERR_REDIRECT.lock().unwrap_or_else(|e| e.into_inner());
+
     // Open /dev/null for writing
     let null = OpenOptions::new().write(true).open("/dev/null")?;
     let null_fd = null.as_raw_fd();
@@ -168,14 +497,14 @@ mod tests {
 
     #[test]
     fn test_extract_nonexistent_pdf() {
-        let result = extract_pdf_text("nonexistent.pdf");
+        let result = extract_pdf_pages("nonexistent.pdf", None, &PdfOptions::default());
         assert!(result.is_err());
     }
 
     #[test]
     fn test_format_pages_and_lines_single_page() {
         let raw = "Title\nHello world\n";
-        let formatted = super::format_pages_and_lines(raw);
+        let formatted = super::format_pages_and_lines(&split_pages(raw), 1);
         let expected_start = "--page 1----\nL1: Title\nL2: Hello world\n";
         assert!(formatted.starts_with(expected_start), "Got: {}", formatted);
     }
@@ -183,8 +512,98 @@ mod tests {
     #[test]
     fn test_format_pages_and_lines_multi_page() {
         let raw = "A\nB\n\u{000C}C\nD\n"; // page break between B and C
-        let formatted = super::format_pages_and_lines(raw);
+        let formatted = super::format_pages_and_lines(&split_pages(raw), 1);
         let want = "--page 1----\nL1: A\nL2: B\n\n--page 2----\nL1: C\nL2: D\n";
         assert_eq!(formatted, want);
     }
+
+    #[test]
+    fn page_ranges_parse_and_keep_original_numbers() {
+        assert_eq!(
+            PageRange::parse("12-18"),
+            Some(PageRange {
+                first: 12,
+                last: 18
+            })
+        );
+        assert_eq!(PageRange::parse("3"), Some(PageRange { first: 3, last: 3 }));
+        assert_eq!(PageRange::parse("0-2"), None);
+        assert_eq!(PageRange::parse("5-2"), None);
+        assert_eq!(PageRange::parse("a-b"), None);
+
+        let raw = "one\u{000C}two\u{000C}three\u{000C}four\u{000C}";
+        let pages = split_pages(raw);
+        assert_eq!(pages.len(), 4);
+
+        let (kept, last) = select_pages(&pages, PageRange::parse("2-3").unwrap()).unwrap();
+        assert_eq!(last, 3);
+        assert_eq!(
+            format_pages_and_lines(kept, 2),
+            "--page 2----\nL1: two\n\n--page 3----\nL1: three\n"
+        );
+
+        let (kept, last) = select_pages(&pages, PageRange::parse("4-9").unwrap()).unwrap();
+        assert_eq!((kept.len(), last), (1, 4));
+        assert_eq!(
+            select_pages(&pages, PageRange::parse("5-6").unwrap()),
+            Err(4)
+        );
+    }
+
+    #[test]
+    fn external_tool_setting_is_honoured() {
+        assert_eq!(ExternalTool::parse(" Never "), Some(ExternalTool::Never));
+        assert_eq!(ExternalTool::parse("sometimes"), None);
+
+        let missing = PdfOptions {
+            external_tool: ExternalTool::Always,
+            pdftotext_path: Some(PathBuf::from("/nonexistent/bin/pdftotext")),
+            ..PdfOptions::default()
+        };
+        let err = extract_pdf_pages("missing.pdf", None, &missing)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("PDF_EXTERNAL_TOOL=always"), "{}", err);
+
+        // `true` exits successfully without output, so pdf-extract takes over
+        let silent = PdfOptions {
+            pdftotext_path: Some(PathBuf::from("true")),
+            ..PdfOptions::default()
+        };
+        let err = extract_pdf_pages("missing.pdf", None, &silent)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Failed to extract text"), "{}", err);
+    }
+
+    #[test]
+    fn thin_text_layers_count_as_scanned() {
+        let pages = |texts: &[&str]| texts.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        assert!(looks_scanned(&pages(&["", "  \n ", "3"])));
+        assert!(looks_scanned(&pages(&[])));
+        assert!(!looks_scanned(&pages(&[
+            "Chapter 1\nThe quick brown fox jumps over the lazy dog.",
+            "2"
+        ])));
+    }
+
+    #[test]
+    fn thin_text_layer_is_kept_without_ocr() {
+        let pages = vec!["Invoice #12".to_string()];
+        let text = format_text_layer("short.pdf", pages, 1, false).unwrap();
+        assert!(text.contains("Invoice #12"), "{}", text);
+    }
+
+    #[test]
+    fn blank_text_layer_without_ocr_is_an_error() {
+        let pages = vec![String::new(), " \n ".to_string()];
+        let err = format_text_layer("scan.pdf", pages, 1, false)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("scanned PDF") && err.contains("PDF_OCR=true"),
+            "{}",
+            err
+        );
+    }
 }
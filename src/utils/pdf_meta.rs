@@ -0,0 +1,346 @@
+//! PDF document info and bookmarks, shown above the extracted page text.
+//!
+//! Output sections (each omitted when empty):
+//!   --metadata----\n
+//!   Title: ...\n
+//!   --outline----\n
+//!   1 Introduction (p. 3)\n
+//!     1.1 Scope (p. 4)
+
+use std::collections::{HashMap, HashSet};
+
+use lopdf::{Dictionary, Document, Object, ObjectId};
+
+/// Bookmarks listed at most; huge outlines would crowd out the page text.
+const MAX_OUTLINE_ENTRIES: usize = 200;
+/// Guard against deeply nested or self-referencing outline trees.
+const MAX_OUTLINE_DEPTH: usize = 8;
+
+/// `Info` dictionary keys shown in the metadata section, with their labels.
+const INFO_FIELDS: &[(&[u8], &str)] = &[
+    (b"Title", "Title"),
+    (b"Author", "Author"),
+    (b"Subject", "Subject"),
+    (b"Keywords", "Keywords"),
+    (b"CreationDate", "Created"),
+    (b"ModDate", "Modified"),
+];
+
+/// Metadata and outline sections for `file_path`, or `None` when the file can't be
+/// parsed or has neither. Never fails: text extraction goes ahead without it.
+pub fn pdf_header(file_path: &str) -> Option<String> {
+    let doc = Document::load(file_path).ok()?;
+    let mut out = String::new();
+
+    let info = metadata(&doc);
+    if !info.is_empty() {
+        out.push_str("--metadata----\n");
+        for (label, value) in info {
+            out.push_str(&format!("{}: {}\n", label, value));
+        }
+        out.push_str(&format!("Pages: {}\n", doc.get_pages().len()));
+    }
+
+    let entries = outline(&doc);
+    if !entries.is_empty() {
+        out.push_str("--outline----\n");
+        for entry in &entries {
+            out.push_str(&"  ".repeat(entry.level));
+            out.push_str(&entry.title);
+            if let Some(page) = entry.page {
+                out.push_str(&format!(" (p. {})", page));
+            }
+            out.push('\n');
+        }
+    }
+
+    (!out.is_empty()).then_some(out)
+}
+
+/// Labelled, non-empty `Info` fields; dates are shown as `YYYY-MM-DD HH:MM`.
+fn metadata(doc: &Document) -> Vec<(&'static str, String)> {
+    let Some(info) = doc
+        .trailer
+        .get(b"Info")
+        .ok()
+        .and_then(|o| resolve(doc, o))
+        .and_then(as_dict)
+    else {
+        return Vec::new();
+    };
+    INFO_FIELDS
+        .iter()
+        .filter_map(|(key, label)| {
+            let raw = text_string(resolve(doc, info.get(key).ok()?)?)?;
+            let value = if key.ends_with(b"Date") {
+                format_pdf_date(&raw)
+            } else {
+                raw.trim().to_string()
+            };
+            (!value.is_empty()).then_some((*label, value))
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct OutlineEntry {
+    level: usize,
+    title: String,
+    page: Option<u32>,
+}
+
+/// Bookmarks in document order with nesting level and target page, when resolvable.
+fn outline(doc: &Document) -> Vec<OutlineEntry> {
+    let first = doc
+        .trailer
+        .get(b"Root")
+        .ok()
+        .and_then(|o| resolve(doc, o))
+        .and_then(as_dict)
+        .and_then(|root| root.get(b"Outlines").ok())
+        .and_then(|o| resolve(doc, o))
+        .and_then(as_dict)
+        .and_then(|outlines| outlines.get(b"First").ok());
+    let Some(Object::Reference(first)) = first else {
+        return Vec::new();
+    };
+
+    let pages: HashMap<ObjectId, u32> = doc
+        .get_pages()
+        .into_iter()
+        .map(|(number, id)| (id, number))
+        .collect();
+    let mut entries = Vec::new();
+    let mut seen = HashSet::new();
+    walk_outline(doc, *first, 0, &pages, &mut seen, &mut entries);
+    entries
+}
+
+fn walk_outline(
+    doc: &Document,
+    mut id: ObjectId,
+    level: usize,
+    pages: &HashMap<ObjectId, u32>,
+    seen: &mut HashSet<ObjectId>,
+    entries: &mut Vec<OutlineEntry>,
+) {
+    if level > MAX_OUTLINE_DEPTH {
+        return;
+    }
+    loop {
+        if entries.len() >= MAX_OUTLINE_ENTRIES || !seen.insert(id) {
+            return;
+        }
+        let Some(item) = doc.get_object(id).ok().and_then(as_dict) else {
+            return;
+        };
+        let title = item
+            .get(b"Title")
+            .ok()
+            .and_then(|o| resolve(doc, o))
+            .and_then(text_string)
+            .unwrap_or_default();
+        entries.push(OutlineEntry {
+            level,
+            title: title.trim().to_string(),
+            page: target_page(doc, item, pages),
+        });
+        if let Ok(Object::Reference(child)) = item.get(b"First") {
+            walk_outline(doc, *child, level + 1, pages, seen, entries);
+        }
+        match item.get(b"Next") {
+            Ok(Object::Reference(next)) => id = *next,
+            _ => return,
+        }
+    }
+}
+
+/// Page number of an outline item's explicit destination (`Dest`, or a GoTo action's `D`).
+/// Named destinations are not looked up.
+fn target_page(doc: &Document, item: &Dictionary, pages: &HashMap<ObjectId, u32>) -> Option<u32> {
+    let dest = match item.get(b"Dest") {
+        Ok(dest) => dest,
+        Err(_) => {
+            let action = as_dict(resolve(doc, item.get(b"A").ok()?)?)?;
+            action.get(b"D").ok()?
+        }
+    };
+    match resolve(doc, dest)? {
+        Object::Array(items) => match items.first()? {
+            Object::Reference(page) => pages.get(page).copied(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Follow indirect references (a few hops at most) to the actual object.
+fn resolve<'a>(doc: &'a Document, mut obj: &'a Object) -> Option<&'a Object> {
+    for _ in 0..8 {
+        match obj {
+            Object::Reference(id) => obj = doc.get_object(*id).ok()?,
+            _ => return Some(obj),
+        }
+    }
+    None
+}
+
+fn as_dict(obj: &Object) -> Option<&Dictionary> {
+    match obj {
+        Object::Dictionary(d) => Some(d),
+        _ => None,
+    }
+}
+
+/// Decode a PDF text string: UTF-16BE with a BOM, UTF-8 with a BOM, or
+/// PDFDocEncoding (treated as Latin-1, which matches it for printable text).
+fn text_string(obj: &Object) -> Option<String> {
+    let Object::String(bytes, _) = obj else {
+        return None;
+    };
+    if let Some(utf16) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect();
+        return Some(String::from_utf16_lossy(&units));
+    }
+    if let Some(utf8) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        return Some(String::from_utf8_lossy(utf8).into_owned());
+    }
+    Some(bytes.iter().map(|&b| b as char).collect())
+}
+
+/// `D:YYYYMMDDHHmmSS+hh'mm'` to `YYYY-MM-DD HH:MM`; anything unparseable is shown as-is.
+fn format_pdf_date(raw: &str) -> String {
+    let s = raw.trim();
+    let digits: String = s
+        .strip_prefix("D:")
+        .unwrap_or(s)
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    match digits.len() {
+        n if n >= 12 => format!(
+            "{}-{}-{} {}:{}",
+            &digits[0..4],
+            &digits[4..6],
+            &digits[6..8],
+            &digits[8..10],
+            &digits[10..12]
+        ),
+        n if n >= 8 => format!("{}-{}-{}", &digits[0..4], &digits[4..6], &digits[6..8]),
+        _ => s.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, StringFormat};
+
+    fn literal(s: &str) -> Object {
+        Object::String(s.as_bytes().to_vec(), StringFormat::Literal)
+    }
+
+    /// Two pages; outline "Intro" -> p.1 with child "Scope" -> p.2 (via a GoTo action),
+    /// then "Appendix" with a named destination.
+    fn sample() -> Document {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let page1 = doc.add_object(dictionary! { "Type" => "Page", "Parent" => pages_id });
+        let page2 = doc.add_object(dictionary! { "Type" => "Page", "Parent" => pages_id });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page1.into(), page2.into()],
+                "Count" => 2,
+            }),
+        );
+
+        let outlines_id = doc.new_object_id();
+        let intro = doc.new_object_id();
+        let scope = doc.new_object_id();
+        let appendix = doc.new_object_id();
+        doc.objects.insert(
+            intro,
+            Object::Dictionary(dictionary! {
+                "Title" => literal("Intro"),
+                "Parent" => outlines_id,
+                "Dest" => vec![page1.into(), "Fit".into()],
+                "First" => scope,
+                "Next" => appendix,
+            }),
+        );
+        let mut utf16 = vec![0xFE, 0xFF];
+        utf16.extend("Scope".encode_utf16().flat_map(|u| u.to_be_bytes()));
+        doc.objects.insert(
+            scope,
+            Object::Dictionary(dictionary! {
+                "Title" => Object::String(utf16, StringFormat::Hexadecimal),
+                "Parent" => intro,
+                "A" => dictionary! { "S" => "GoTo", "D" => vec![page2.into(), "Fit".into()] },
+            }),
+        );
+        doc.objects.insert(
+            appendix,
+            Object::Dictionary(dictionary! {
+                "Title" => literal("Appendix"),
+                "Parent" => outlines_id,
+                "Dest" => literal("appendix"),
+                // A broken file pointing back at itself must not loop forever
+                "Next" => intro,
+            }),
+        );
+        doc.objects.insert(
+            outlines_id,
+            Object::Dictionary(dictionary! { "Type" => "Outlines", "First" => intro }),
+        );
+
+        let catalog = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+            "Outlines" => outlines_id,
+        });
+        let info = doc.add_object(dictionary! {
+            "Title" => literal("Annual Report"),
+            "Author" => literal("  "),
+            "CreationDate" => literal("D:20240131093000+08'00'"),
+        });
+        doc.trailer.set("Root", catalog);
+        doc.trailer.set("Info", info);
+        doc
+    }
+
+    #[test]
+    fn metadata_skips_blank_fields_and_formats_dates() {
+        assert_eq!(
+            metadata(&sample()),
+            [
+                ("Title", "Annual Report".to_string()),
+                ("Created", "2024-01-31 09:30".to_string()),
+            ]
+        );
+        assert_eq!(format_pdf_date("D:2024"), "D:2024");
+        assert_eq!(format_pdf_date("20240131"), "2024-01-31");
+    }
+
+    #[test]
+    fn outline_lists_nested_bookmarks_with_pages() {
+        let entry = |level, title: &str, page| OutlineEntry {
+            level,
+            title: title.to_string(),
+            page,
+        };
+        assert_eq!(
+            outline(&sample()),
+            [
+                entry(0, "Intro", Some(1)),
+                entry(1, "Scope", Some(2)),
+                entry(0, "Appendix", None),
+            ]
+        );
+        assert!(outline(&Document::with_version("1.5")).is_empty());
+    }
+}
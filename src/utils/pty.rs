@@ -0,0 +1,176 @@
+//! Running shell commands on a pseudo-terminal so their output can be shown as it arrives.
+
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use portable_pty::{native_pty_system, Child, ChildKiller, CommandBuilder, MasterPty, PtySize};
+use regex::Regex;
+
+/// A shell command running on its own PTY.
+pub struct PtyProcess {
+    /// Everything the command writes to its terminal; hits EOF once it exits.
+    pub reader: Box<dyn Read + Send>,
+    pub child: Box<dyn Child + Send + Sync>,
+    // Dropping the master closes the terminal, so it lives as long as the reader is used
+    _master: Box<dyn MasterPty + Send>,
+    exited: Arc<AtomicBool>,
+}
+
+/// How long a command may ignore the hangup before it is killed outright.
+const KILL_GRACE: Duration = Duration::from_secs(2);
+
+impl PtyProcess {
+    /// A handle that can stop the command from another task.
+    pub fn killer(&self) -> PtyKiller {
+        PtyKiller {
+            inner: self.child.clone_killer(),
+            pid: self.child.process_id(),
+            exited: self.exited.clone(),
+        }
+    }
+
+    /// Wait for the command to exit and return its exit code.
+    pub fn wait(&mut self) -> Option<i32> {
+        let status = self.child.wait().ok();
+        self.exited.store(true, Ordering::SeqCst);
+        status.map(|s| s.exit_code() as i32)
+    }
+}
+
+/// Stops a [`PtyProcess`], see [`PtyProcess::killer`].
+pub struct PtyKiller {
+    inner: Box<dyn ChildKiller + Send + Sync>,
+    pid: Option<u32>,
+    exited: Arc<AtomicBool>,
+}
+
+impl PtyKiller {
+    /// Hang up the command, then SIGKILL its process group if it is still running
+    /// after [`KILL_GRACE`] (e.g. because it ignores SIGHUP).
+    pub fn kill(&mut self) {
+        let _ = self.inner.kill();
+        #[cfg(unix)]
+        if let Some(pid) = self.pid {
+            let exited = self.exited.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(KILL_GRACE);
+                if !exited.load(Ordering::SeqCst) {
+                    // SAFETY: plain syscall; the command leads its own session and process
+                    // group on the PTY, and has not been reaped yet so the id is still its own.
+                    unsafe {
+                        libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// Start `command` with `sh -c` on a `cols`×`rows` PTY.
+///
+/// Not supported on Windows: ConPTY keeps the output pipe open until the PTY itself is
+/// closed, so the reader would never see the command finish. Callers fall back to pipes.
+pub fn spawn_shell(command: &str, cols: u16, rows: u16) -> Result<PtyProcess> {
+    if cfg!(windows) {
+        bail!("PTY execution is not supported on Windows");
+    }
+    let pair = native_pty_system().openpty(PtySize {
+        rows,
+        cols,
+        pixel_width: 0,
+        pixel_height: 0,
+    })?;
+    let mut cmd = CommandBuilder::new("sh");
+    cmd.args(["-c", command]);
+    if let Ok(dir) = std::env::current_dir() {
+        cmd.cwd(dir);
+    }
+    let child = pair.slave.spawn_command(cmd)?;
+    // Only the child may hold the slave end, otherwise the reader never sees EOF
+    drop(pair.slave);
+    let reader = pair.master.try_clone_reader()?;
+    Ok(PtyProcess {
+        reader,
+        child,
+        _master: pair.master,
+        exited: Arc::new(AtomicBool::new(false)),
+    })
+}
+
+/// Drain the complete UTF-8 prefix of `pending`, keeping a character split across reads.
+pub fn take_utf8(pending: &mut Vec<u8>) -> String {
+    let valid = match std::str::from_utf8(pending) {
+        Ok(_) => pending.len(),
+        // Incomplete trailing sequence: wait for the rest of it
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        Err(_) => pending.len(),
+    };
+    let text = String::from_utf8_lossy(&pending[..valid]).into_owned();
+    pending.drain(..valid);
+    text
+}
+
+/// Remove terminal escape sequences and carriage returns so PTY output renders as plain text.
+pub fn strip_ansi(text: &str) -> String {
+    static ESCAPES: OnceLock<Regex> = OnceLock::new();
+    let re = ESCAPES.get_or_init(|| {
+        Regex::new(
+            r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)|\x1b[()*+].|\x1b[@-_]",
+        )
+        .unwrap()
+    });
+    re.replace_all(text, "").replace('\r', "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_ansi_removes_colors_titles_and_carriage_returns() {
+        let raw = "\x1b]0;title\x07\x1b[1;31merror\x1b[0m: bad\r\nnext\x1b(B";
+        assert_eq!(strip_ansi(raw), "error: bad\nnext");
+    }
+
+    #[test]
+    fn split_utf8_is_carried_to_the_next_read() {
+        let bytes = "né".as_bytes();
+        let mut pending = bytes[..2].to_vec();
+        assert_eq!(take_utf8(&mut pending), "n");
+        pending.extend_from_slice(&bytes[2..]);
+        assert_eq!(take_utf8(&mut pending), "é");
+        assert!(pending.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pty_streams_output_and_reports_exit_code() {
+        let mut proc = spawn_shell("printf 'hi'; exit 4", 80, 24).unwrap();
+        let mut out = Vec::new();
+        let mut buf = [0u8; 1024];
+        // Linux reports EIO instead of EOF once the child side is closed
+        while let Ok(n) = proc.reader.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            out.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(strip_ansi(&String::from_utf8_lossy(&out)), "hi");
+        assert_eq!(proc.wait(), Some(4));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn kill_escalates_when_hangup_is_ignored() {
+        let mut proc = spawn_shell("trap '' HUP; echo ready; sleep 30", 80, 24).unwrap();
+        let mut buf = [0u8; 64];
+        let _ = proc.reader.read(&mut buf);
+        let started = std::time::Instant::now();
+        proc.killer().kill();
+        proc.wait();
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,189 @@
+//! Append-only log of generated shell commands and what happened to them (`SHELL_HISTORY`).
+//!
+//! Independently of the log, the most recently executed command is always kept in
+//! `CACHE_PATH/last_command` for `--explain-last`.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{cache::unix_now, config::Config};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryAction {
+    Executed,
+    Aborted,
+    /// Replaced by a new candidate via [M]odify.
+    Modified,
+    Copied,
+    /// Printed only (non-interactive mode without auto-execution).
+    NotExecuted,
+    /// Auto-execution refused by the dangerous command check.
+    Refused,
+}
+
+impl HistoryAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HistoryAction::Executed => "executed",
+            HistoryAction::Aborted => "aborted",
+            HistoryAction::Modified => "modified",
+            HistoryAction::Copied => "copied",
+            HistoryAction::NotExecuted => "not_executed",
+            HistoryAction::Refused => "refused",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Unix timestamp (seconds).
+    pub timestamp: i64,
+    pub prompt: String,
+    pub command: String,
+    pub action: HistoryAction,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+}
+
+/// File under `CACHE_PATH` holding the last executed command.
+const LAST_COMMAND_FILE: &str = "last_command";
+
+pub struct ShellHistory {
+    path: PathBuf,
+    enabled: bool,
+    last_command_path: PathBuf,
+}
+
+impl ShellHistory {
+    pub fn from_config(cfg: &Config) -> Self {
+        Self {
+            path: PathBuf::from(cfg.get("SHELL_HISTORY_PATH").unwrap_or_default()),
+            enabled: cfg.get_bool("SHELL_HISTORY"),
+            last_command_path: cfg.cache_path().join(LAST_COMMAND_FILE),
+        }
+    }
+
+    /// Append an entry; does nothing unless `SHELL_HISTORY=true`. Failures only warn.
+    /// Executed commands are also saved as the last command, whatever `SHELL_HISTORY` says.
+    pub fn record(
+        &self,
+        prompt: &str,
+        command: &str,
+        action: HistoryAction,
+        exit_code: Option<i32>,
+    ) {
+        if action == HistoryAction::Executed {
+            self.save_last_command(command);
+        }
+        if !self.enabled {
+            return;
+        }
+        let entry = HistoryEntry {
+            timestamp: unix_now(),
+            prompt: prompt.to_string(),
+            command: command.to_string(),
+            action,
+            exit_code,
+        };
+        if let Err(e) = append(&self.path, &entry) {
+            eprintln!("Warning: could not write shell history: {}", e);
+        }
+    }
+
+    fn save_last_command(&self, command: &str) {
+        let saved = self
+            .last_command_path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&self.last_command_path, command));
+        if let Err(e) = saved {
+            eprintln!("Warning: could not save the last command: {}", e);
+        }
+    }
+
+    /// The most recently executed command, if any.
+    pub fn last_command(&self) -> Option<String> {
+        let command = fs::read_to_string(&self.last_command_path).ok()?;
+        Some(command.trim().to_string()).filter(|c| !c.is_empty())
+    }
+
+    /// The last `n` entries, oldest first. Lines that don't parse are skipped.
+    pub fn last(&self, n: usize) -> Vec<HistoryEntry> {
+        let Ok(text) = fs::read_to_string(&self.path) else {
+            return Vec::new();
+        };
+        let entries: Vec<HistoryEntry> = text
+            .lines()
+            .filter_map(|l| serde_json::from_str(l).ok())
+            .collect();
+        let skip = entries.len().saturating_sub(n);
+        entries.into_iter().skip(skip).collect()
+    }
+}
+
+/// One `write` of a complete line on an O_APPEND handle, so lines from concurrent
+/// sgpt processes don't interleave.
+fn append(path: &Path, entry: &HistoryEntry) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_append_and_last_returns_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = ShellHistory {
+            path: dir.path().join("nested").join("shell_history.jsonl"),
+            enabled: true,
+            last_command_path: dir.path().join("cache").join(LAST_COMMAND_FILE),
+        };
+        history.record("list", "ls", HistoryAction::Executed, Some(0));
+        history.record("remove", "rm x", HistoryAction::Aborted, None);
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&history.path)
+            .unwrap()
+            .write_all(b"not json\n")
+            .unwrap();
+        history.record("copy", "pwd", HistoryAction::Copied, None);
+
+        let last = history.last(2);
+        assert_eq!(last.len(), 2);
+        assert_eq!(last[0].command, "rm x");
+        assert_eq!(last[0].action, HistoryAction::Aborted);
+        assert_eq!(last[1].command, "pwd");
+        assert_eq!(history.last(10)[0].exit_code, Some(0));
+        // Only executed commands count as the last command
+        assert_eq!(history.last_command().as_deref(), Some("ls"));
+    }
+
+    #[test]
+    fn disabled_history_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = ShellHistory {
+            path: dir.path().join("shell_history.jsonl"),
+            enabled: false,
+            last_command_path: dir.path().join(LAST_COMMAND_FILE),
+        };
+        assert_eq!(history.last_command(), None);
+        history.record("list", "ls -la", HistoryAction::Executed, Some(0));
+        assert!(!history.path.exists());
+        assert_eq!(history.last_command().as_deref(), Some("ls -la"));
+    }
+}
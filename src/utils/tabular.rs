@@ -0,0 +1,263 @@
+//! CSV/TSV summaries for `--doc`.
+//!
+//! Large tables are not sent row by row. The file is streamed once to count rows and
+//! infer column types, and only the first few rows are kept as a sample, so memory stays
+//! bounded no matter how big the file is.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+use anyhow::{Context, Result};
+
+/// Columns shown in the schema and sample table; wider files get a note instead.
+const MAX_COLUMNS: usize = 20;
+/// Cells longer than this are cut in the sample table.
+const MAX_CELL_CHARS: usize = 60;
+/// Bytes read from the start of the file to guess the delimiter.
+const SNIFF_BYTES: u64 = 64 * 1024;
+const CANDIDATE_DELIMITERS: [u8; 4] = [b',', b'\t', b';', b'|'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColumnType {
+    /// No non-empty value seen yet.
+    Empty,
+    Boolean,
+    Integer,
+    Float,
+    Text,
+}
+
+impl ColumnType {
+    fn of(value: &str) -> Self {
+        if value.is_empty() {
+            ColumnType::Empty
+        } else if value.parse::<i64>().is_ok() {
+            ColumnType::Integer
+        } else if value.parse::<f64>().is_ok() {
+            ColumnType::Float
+        } else if matches!(
+            value.to_ascii_lowercase().as_str(),
+            "true" | "false" | "yes" | "no"
+        ) {
+            ColumnType::Boolean
+        } else {
+            ColumnType::Text
+        }
+    }
+
+    fn merge(self, other: Self) -> Self {
+        use ColumnType::*;
+        match (self, other) {
+            (Empty, t) | (t, Empty) => t,
+            (a, b) if a == b => a,
+            (Integer, Float) | (Float, Integer) => Float,
+            _ => Text,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ColumnType::Empty => "empty",
+            ColumnType::Boolean => "boolean",
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Text => "text",
+        }
+    }
+}
+
+/// Summarize a `.csv`/`.tsv` file: header, inferred column types, row count and the
+/// first `sample_rows` rows as a Markdown table.
+///
+/// `.tsv` files are always tab-separated; for anything else the delimiter is sniffed
+/// from the start of the file (comma, tab, semicolon or pipe).
+///
+/// # Examples
+///
+/// ```rust
+/// use crate::utils::tabular::summarize_table;
+///
+/// let summary = summarize_table("sales.csv", 10)?;
+/// ```
+pub fn summarize_table(file_path: &str, sample_rows: usize) -> Result<String> {
+    let mut file =
+        File::open(file_path).with_context(|| format!("Failed to open '{}'", file_path))?;
+    let delimiter = if file_path.to_lowercase().ends_with(".tsv") {
+        b'\t'
+    } else {
+        let mut head = Vec::new();
+        (&mut file).take(SNIFF_BYTES).read_to_end(&mut head)?;
+        file.seek(SeekFrom::Start(0))?;
+        sniff_delimiter(&String::from_utf8_lossy(&head))
+    };
+    summarize_reader(BufReader::new(file), delimiter, sample_rows)
+        .with_context(|| format!("Failed to parse '{}'", file_path))
+}
+
+/// Guess the delimiter from the first lines of a file: the candidate that appears the
+/// same (non-zero) number of times on every line wins, preferring the most frequent.
+/// Delimiters inside double quotes are ignored. Falls back to a comma.
+fn sniff_delimiter(sample: &str) -> u8 {
+    let mut lines: Vec<&str> = sample.lines().filter(|l| !l.trim().is_empty()).collect();
+    // The last line may have been cut off mid-way by the sniff limit
+    if lines.len() > 1 && !sample.ends_with('\n') {
+        lines.pop();
+    }
+    lines.truncate(10);
+
+    let mut best: Option<(bool, usize, u8)> = None;
+    for &delim in &CANDIDATE_DELIMITERS {
+        let counts: Vec<usize> = lines.iter().map(|l| count_unquoted(l, delim)).collect();
+        let Some(&first) = counts.first() else {
+            continue;
+        };
+        if first == 0 {
+            continue;
+        }
+        let consistent = counts.iter().all(|&c| c == first);
+        let candidate = (consistent, first, delim);
+        if !matches!(best, Some(b) if (b.0, b.1) >= (candidate.0, candidate.1)) {
+            best = Some(candidate);
+        }
+    }
+    best.map(|(_, _, d)| d).unwrap_or(b',')
+}
+
+fn count_unquoted(line: &str, delim: u8) -> usize {
+    let mut in_quotes = false;
+    let mut count = 0;
+    for b in line.bytes() {
+        if b == b'"' {
+            in_quotes = !in_quotes;
+        } else if b == delim && !in_quotes {
+            count += 1;
+        }
+    }
+    count
+}
+
+fn summarize_reader<R: Read>(reader: R, delimiter: u8, sample_rows: usize) -> Result<String> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(reader);
+    let headers: Vec<String> = rdr
+        .headers()?
+        .iter()
+        .map(|h| h.trim().to_string())
+        .collect();
+    let mut types = vec![ColumnType::Empty; headers.len()];
+    let mut sample: Vec<Vec<String>> = Vec::new();
+    let mut rows = 0usize;
+
+    let mut record = csv::StringRecord::new();
+    while rdr.read_record(&mut record)? {
+        rows += 1;
+        for (i, value) in record.iter().enumerate().take(types.len()) {
+            types[i] = types[i].merge(ColumnType::of(value.trim()));
+        }
+        if sample.len() < sample_rows {
+            sample.push(record.iter().map(str::to_string).collect());
+        }
+    }
+
+    let shown = headers.len().min(MAX_COLUMNS);
+    let mut out = format!(
+        "Table: {} rows, {} columns (delimiter: {})\n",
+        rows,
+        headers.len(),
+        delimiter_name(delimiter)
+    );
+    if shown < headers.len() {
+        out.push_str(&format!(
+            "[... showing the first {} of {} columns]\n",
+            shown,
+            headers.len()
+        ));
+    }
+
+    out.push_str("\nColumns:\n");
+    for (name, ty) in headers.iter().zip(&types).take(shown) {
+        out.push_str(&format!("- {}: {}\n", name, ty.name()));
+    }
+
+    if sample.is_empty() {
+        return Ok(out);
+    }
+    out.push_str(&format!("\nFirst {} rows:\n\n", sample.len()));
+    let header_cells: Vec<String> = headers[..shown].iter().map(|h| cell(h)).collect();
+    out.push_str(&format!("| {} |\n", header_cells.join(" | ")));
+    out.push_str(&format!("|{}\n", " --- |".repeat(shown)));
+    for row in &sample {
+        let cells: Vec<String> = (0..shown)
+            .map(|i| cell(row.get(i).map(String::as_str).unwrap_or("")))
+            .collect();
+        out.push_str(&format!("| {} |\n", cells.join(" | ")));
+    }
+    Ok(out)
+}
+
+fn delimiter_name(delimiter: u8) -> String {
+    match delimiter {
+        b'\t' => "tab".to_string(),
+        d => format!("'{}'", d as char),
+    }
+}
+
+/// Make a value safe for a Markdown table cell.
+fn cell(value: &str) -> String {
+    let flat = value.trim().replace(['\r', '\n'], " ").replace('|', "\\|");
+    if flat.chars().count() > MAX_CELL_CHARS {
+        let cut: String = flat.chars().take(MAX_CELL_CHARS).collect();
+        format!("{}…", cut)
+    } else {
+        flat
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_common_delimiters() {
+        assert_eq!(sniff_delimiter("a,b,c\n1,2,3\n"), b',');
+        assert_eq!(sniff_delimiter("a;b;c\n1,5;2;3\n"), b';');
+        assert_eq!(sniff_delimiter("a\tb\n1\t2\n"), b'\t');
+        assert_eq!(sniff_delimiter("a|b\n1|2\n"), b'|');
+        // Commas inside quotes don't count
+        assert_eq!(sniff_delimiter("name;note\n\"x, y, z\";1\n"), b';');
+        assert_eq!(sniff_delimiter("single column\nvalue\n"), b',');
+    }
+
+    #[test]
+    fn handles_quoted_fields() {
+        let data = "id,comment,score\n1,\"hello, world\",2.5\n2,\"multi\nline \"\"quoted\"\"\",3\n3,\"a|b\",\n";
+        let out = summarize_reader(data.as_bytes(), b',', 10).unwrap();
+        assert!(out.starts_with("Table: 3 rows, 3 columns (delimiter: ',')"));
+        assert!(out.contains("- id: integer\n- comment: text\n- score: float\n"));
+        assert!(out.contains("| 1 | hello, world | 2.5 |"));
+        assert!(out.contains("| 2 | multi line \"quoted\" | 3 |"));
+        assert!(out.contains("| 3 | a\\|b |  |"));
+    }
+
+    #[test]
+    fn samples_rows_and_truncates_wide_tables() {
+        let header: Vec<String> = (0..25).map(|i| format!("c{}", i)).collect();
+        let mut data = header.join("\t");
+        data.push('\n');
+        for r in 0..100 {
+            let row: Vec<String> = (0..25).map(|i| (r * i).to_string()).collect();
+            data.push_str(&row.join("\t"));
+            data.push('\n');
+        }
+        let out = summarize_reader(data.as_bytes(), b'\t', 2).unwrap();
+        assert!(out.starts_with("Table: 100 rows, 25 columns (delimiter: tab)"));
+        assert!(out.contains("[... showing the first 20 of 25 columns]"));
+        assert!(out.contains("First 2 rows:"));
+        assert!(out.contains("- c19: integer\n"));
+        assert!(!out.contains("c20"));
+        // Header, separator and two sample rows
+        assert_eq!(out.matches("\n| ").count(), 4);
+    }
+}
@@ -0,0 +1,34 @@
+//! Reading interactive answers when stdin is already taken by a pipe.
+
+use std::io::{self, BufRead, BufReader};
+
+use is_terminal::IsTerminal;
+
+use crate::config::Config;
+
+/// Where interactive prompts read their answers from.
+///
+/// Stdin when it is a terminal; otherwise, on Unix and with `SHELL_TTY_PROMPT=true`, the
+/// controlling terminal (`/dev/tty`), so `echo … | sgpt -s` can still show the menu.
+/// `None` means there is nobody to ask and callers should stay non-interactive.
+pub fn prompt_reader(cfg: &Config) -> Option<Box<dyn BufRead>> {
+    if io::stdin().is_terminal() {
+        return Some(Box::new(BufReader::new(io::stdin())));
+    }
+    if !cfg.get_bool("SHELL_TTY_PROMPT") {
+        return None;
+    }
+    controlling_tty()
+}
+
+#[cfg(unix)]
+fn controlling_tty() -> Option<Box<dyn BufRead>> {
+    // Fails with ENXIO when the process has no controlling terminal (CI, cron, …)
+    let file = std::fs::File::open("/dev/tty").ok()?;
+    Some(Box::new(BufReader::new(file)))
+}
+
+#[cfg(not(unix))]
+fn controlling_tty() -> Option<Box<dyn BufRead>> {
+    None
+}
@@ -0,0 +1,141 @@
+//! End-to-end check that a `--chat` session containing a tool round can be continued.
+//!
+//! The provider is an httpmock server that, like OpenAI, only accepts tool results that
+//! reference the id of the preceding assistant tool call.
+#![cfg(unix)]
+
+use std::{fs, path::Path, process::Command};
+
+use httpmock::prelude::*;
+
+const TOOL_CALL_SSE: &str = concat!(
+    "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"call_abc\",\"type\":\"function\",",
+    "\"function\":{\"name\":\"echo_tool\",\"arguments\":\"{\\\"text\\\":\\\"pong\\\"}\"}}]}}]}\n\n",
+    "data: {\"choices\":[{\"delta\":{},\"finish_reason\":\"tool_calls\"}]}\n\n",
+    "data: [DONE]\n\n",
+);
+
+fn content_sse(text: &str) -> String {
+    format!(
+        "data: {{\"choices\":[{{\"delta\":{{\"content\":\"{}\"}}}}]}}\n\ndata: [DONE]\n\n",
+        text
+    )
+}
+
+fn body(req: &HttpMockRequest) -> String {
+    String::from_utf8_lossy(req.body.as_deref().unwrap_or_default()).into_owned()
+}
+
+/// The tool result must point at the assistant call that produced it.
+fn tool_round_is_well_formed(req: &HttpMockRequest) -> bool {
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(&body(req)) else {
+        return false;
+    };
+    let messages = json["messages"].as_array().cloned().unwrap_or_default();
+    let call_ids: Vec<&str> = messages
+        .iter()
+        .flat_map(|m| m["tool_calls"].as_array().into_iter().flatten())
+        .filter_map(|c| c["id"].as_str())
+        .collect();
+    let results: Vec<&serde_json::Value> =
+        messages.iter().filter(|m| m["role"] == "tool").collect();
+    !results.is_empty()
+        && results.iter().all(|m| {
+            m["tool_call_id"]
+                .as_str()
+                .is_some_and(|id| call_ids.contains(&id))
+        })
+}
+
+fn sgpt(home: &Path, base_url: &str, args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_sgpt"))
+        .args(args)
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("API_BASE_URL", base_url)
+        .env("OPENAI_API_KEY", "test-key")
+        .env("CHAT_CACHE_PATH", home.join("chat_cache"))
+        .env("CACHE_PATH", home.join("cache"))
+        .env("ROLE_STORAGE_PATH", home.join("roles"))
+        .env("OPENAI_FUNCTIONS_PATH", home.join("functions"))
+        .env("MCP_CONFIG_PATH", home.join("mcp.json"))
+        .output()
+        .expect("failed to run sgpt")
+}
+
+#[test]
+fn chat_with_tool_call_can_be_continued() {
+    let home = tempfile::tempdir().unwrap();
+    let functions = home.path().join("functions");
+    fs::create_dir_all(&functions).unwrap();
+    fs::write(
+        functions.join("echo_tool.json"),
+        r#"{
+            "name": "echo_tool",
+            "parameters": {"type": "object", "properties": {"text": {"type": "string"}}},
+            "exec": {"program": "echo", "args_template": ["{{text}}"]}
+        }"#,
+    )
+    .unwrap();
+
+    let server = MockServer::start();
+    let first = server.mock(|when, then| {
+        when.method(POST)
+            .path("/v1/chat/completions")
+            .matches(|req| !body(req).contains("\"role\":\"tool\""));
+        then.status(200)
+            .header("content-type", "text/event-stream")
+            .body(TOOL_CALL_SSE);
+    });
+    let follow_up = server.mock(|when, then| {
+        when.method(POST)
+            .path("/v1/chat/completions")
+            .matches(|req| !body(req).contains("second question"))
+            .matches(tool_round_is_well_formed);
+        then.status(200)
+            .header("content-type", "text/event-stream")
+            .body(content_sse("tool said pong"));
+    });
+    let next_turn = server.mock(|when, then| {
+        when.method(POST)
+            .path("/v1/chat/completions")
+            .body_contains("second question")
+            .matches(tool_round_is_well_formed);
+        then.status(200)
+            .header("content-type", "text/event-stream")
+            .body(content_sse("still fine"));
+    });
+
+    let base_url = server.base_url();
+    let common = ["--chat", "tools", "--functions", "--no-md", "--no-cache"];
+
+    let out = sgpt(
+        home.path(),
+        &base_url,
+        &[&common[..], &["ping it"]].concat(),
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("tool said pong"));
+    first.assert();
+    follow_up.assert();
+
+    let history = fs::read_to_string(home.path().join("chat_cache").join("tools")).unwrap();
+    assert!(history.contains("\"tool_call_id\""), "{}", history);
+
+    let out = sgpt(
+        home.path(),
+        &base_url,
+        &[&common[..], &["second question"]].concat(),
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("still fine"));
+    next_turn.assert();
+}
@@ -0,0 +1,132 @@
+//! Roles can be created without a terminal: from a file, from piped stdin, or by importing
+//! a bundle written by `--export-roles`.
+
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Output, Stdio},
+};
+
+fn sgpt(home: &Path, args: &[&str], stdin: Option<&str>) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sgpt"))
+        .args(args)
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("OPENAI_API_KEY", "test-key")
+        .env("ROLE_STORAGE_PATH", home.join("roles"))
+        .env("CACHE_PATH", home.join("cache"))
+        .env("CHAT_CACHE_PATH", home.join("chat_cache"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run sgpt");
+    let mut input = child.stdin.take().unwrap();
+    input
+        .write_all(stdin.unwrap_or_default().as_bytes())
+        .unwrap();
+    drop(input);
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+fn show_role(home: &Path, name: &str) -> String {
+    let output = sgpt(home, &["--show-role", name], None);
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+#[test]
+fn roles_are_created_from_a_file_or_piped_stdin() {
+    let home = tempfile::tempdir().unwrap();
+    let file = home.path().join("reviewer.txt");
+    std::fs::write(&file, "Review Rust code.\nBe terse.\n").unwrap();
+
+    sgpt(
+        home.path(),
+        &[
+            "--create-role-from-file",
+            "reviewer",
+            file.to_str().unwrap(),
+        ],
+        None,
+    );
+    assert_eq!(
+        show_role(home.path(), "reviewer"),
+        "You are reviewer\nReview Rust code.\nBe terse."
+    );
+
+    sgpt(
+        home.path(),
+        &["--create-role", "poet"],
+        Some("Answer in haiku.\n"),
+    );
+    assert_eq!(
+        show_role(home.path(), "poet"),
+        "You are poet\nAnswer in haiku."
+    );
+}
+
+#[test]
+fn exported_roles_import_on_another_machine() {
+    let source = tempfile::tempdir().unwrap();
+    let target = tempfile::tempdir().unwrap();
+    let bundle = source.path().join("roles.json");
+    let body = source.path().join("body.txt");
+    std::fs::write(&body, "Review Rust code.").unwrap();
+    sgpt(
+        source.path(),
+        &[
+            "--create-role-from-file",
+            "reviewer",
+            body.to_str().unwrap(),
+        ],
+        None,
+    );
+    sgpt(
+        source.path(),
+        &["--export-roles", bundle.to_str().unwrap()],
+        None,
+    );
+
+    std::fs::write(target.path().join("old.txt"), "Old text.").unwrap();
+    sgpt(
+        target.path(),
+        &[
+            "--create-role-from-file",
+            "reviewer",
+            target.path().join("old.txt").to_str().unwrap(),
+        ],
+        None,
+    );
+    let output = sgpt(
+        target.path(),
+        &["--import-roles", bundle.to_str().unwrap()],
+        None,
+    );
+    // The default roles exist on both sides as well
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("(use --overwrite to replace)") && stdout.contains("reviewer"),
+        "{}",
+        stdout
+    );
+    assert_eq!(
+        show_role(target.path(), "reviewer"),
+        "You are reviewer\nOld text."
+    );
+
+    sgpt(
+        target.path(),
+        &["--import-roles", bundle.to_str().unwrap(), "--overwrite"],
+        None,
+    );
+    assert_eq!(
+        show_role(target.path(), "reviewer"),
+        "You are reviewer\nReview Rust code."
+    );
+}
@@ -0,0 +1,135 @@
+//! `--chat` and `--repl` combined with `--shell` start their session with the shell role.
+//!
+//! Both run against the built-in `fake` model, which answers with a shell command only when
+//! the system message is the shell role. The REPL needs a terminal, so it is driven through
+//! a pseudo-terminal. `temp` sessions are never saved, so the stored system message is
+//! checked on named sessions.
+#![cfg(unix)]
+
+use std::{
+    io::Read,
+    path::Path,
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+
+use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+
+/// Sentence from the default shell role prompt.
+const SHELL_ROLE_TEXT: &str = "Ensure the output is a valid shell command.";
+
+const ENV: &[&str] = &[
+    "CHAT_CACHE_PATH",
+    "CACHE_PATH",
+    "ROLE_STORAGE_PATH",
+    "OPENAI_FUNCTIONS_PATH",
+];
+
+fn env_dir(home: &Path, key: &str) -> std::path::PathBuf {
+    home.join(key.to_lowercase())
+}
+
+/// System message of a stored session, if it exists yet.
+fn system_message(home: &Path, chat_id: &str) -> Option<String> {
+    let path = env_dir(home, "CHAT_CACHE_PATH").join(chat_id);
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()?;
+    let messages = json["messages"].as_array()?;
+    let system = messages.iter().find(|m| m["role"] == "system")?;
+    system["content"].as_str().map(str::to_string)
+}
+
+fn chat(home: &Path, chat_id: &str) -> String {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_sgpt"));
+    cmd.args([
+        "--chat",
+        chat_id,
+        "--shell",
+        "--model",
+        "fake",
+        "list files",
+    ])
+    .env("HOME", home)
+    .env("XDG_CONFIG_HOME", home.join("config"))
+    .env("OPENAI_API_KEY", "test-key");
+    for key in ENV {
+        cmd.env(key, env_dir(home, key));
+    }
+    let output = cmd.output().expect("failed to run sgpt");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+#[test]
+fn chat_with_shell_uses_shell_role() {
+    let home = tempfile::tempdir().unwrap();
+    assert_eq!(chat(home.path(), "temp"), "ls -la");
+
+    assert_eq!(chat(home.path(), "shell-chat"), "ls -la");
+    let system = system_message(home.path(), "shell-chat").expect("session was not saved");
+    assert!(
+        system.contains(SHELL_ROLE_TEXT),
+        "system message: {}",
+        system
+    );
+}
+
+#[test]
+fn repl_with_shell_uses_shell_role() {
+    let home = tempfile::tempdir().unwrap();
+    let pty = native_pty_system()
+        .openpty(PtySize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 0,
+            pixel_height: 0,
+        })
+        .unwrap();
+    let mut cmd = CommandBuilder::new(env!("CARGO_BIN_EXE_sgpt"));
+    cmd.args([
+        "--repl",
+        "shell-repl",
+        "--shell",
+        "--model",
+        "fake",
+        "list files",
+    ]);
+    cmd.env("HOME", home.path());
+    cmd.env("XDG_CONFIG_HOME", home.path().join("config"));
+    cmd.env("OPENAI_API_KEY", "test-key");
+    cmd.env("TERM", "xterm");
+    for key in ENV {
+        cmd.env(key, env_dir(home.path(), key));
+    }
+    let mut child = pty.slave.spawn_command(cmd).unwrap();
+    drop(pty.slave);
+
+    // Keep draining the terminal so the TUI never blocks on a full buffer
+    let mut reader = pty.master.try_clone_reader().unwrap();
+    thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        while matches!(reader.read(&mut buf), Ok(n) if n > 0) {}
+    });
+
+    // The initial prompt is answered and the session saved without any input
+    let deadline = Instant::now() + Duration::from_secs(20);
+    let mut system = None;
+    while system.is_none() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(100));
+        system = system_message(home.path(), "shell-repl");
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+
+    let system = system.expect("REPL session was not saved");
+    assert!(
+        system.contains(SHELL_ROLE_TEXT),
+        "system message: {}",
+        system
+    );
+}
@@ -0,0 +1,170 @@
+//! Exercises the TUI event loop (`run_tui_repl`) end to end.
+//!
+//! sgpt is a binary crate, so the REPL is reached through `sgpt --repl` rather than by
+//! calling `run_tui_repl` directly. It refuses to start without a terminal (raw mode needs
+//! one), so it runs on a pseudo-terminal; typing a line and Enter produces the same
+//! `TuiEvent::UserInput` the event loop handles interactively. The `fake` model answers
+//! without network access.
+#![cfg(unix)]
+
+use std::{
+    io::{Read, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use httpmock::prelude::*;
+use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+
+/// Poll `check` every 100ms until it returns `Some` or `timeout` passes.
+fn wait_for<T>(timeout: Duration, mut check: impl FnMut() -> Option<T>) -> Option<T> {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if let Some(value) = check() {
+            return Some(value);
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    None
+}
+
+/// Assistant replies stored in a chat session file.
+fn assistant_messages(chat_dir: &Path, chat_id: &str) -> Vec<String> {
+    let Ok(text) = std::fs::read_to_string(chat_dir.join(chat_id)) else {
+        return Vec::new();
+    };
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(&text) else {
+        return Vec::new();
+    };
+    json["messages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|m| m["role"] == "assistant")
+        .filter_map(|m| m["content"].as_str().map(str::to_string))
+        .collect()
+}
+
+/// Run `sgpt --repl <args>` on a pseudo-terminal with its storage under `home` and `env` on
+/// top. Once the TUI has drawn, `line` is typed and `done` polled until it returns `Some`.
+/// Returns whether the TUI drew a screen and what `done` found.
+fn type_into_repl<T>(
+    home: &Path,
+    args: &[&str],
+    env: &[(&str, &str)],
+    line: &str,
+    done: impl FnMut() -> Option<T>,
+) -> (bool, Option<T>) {
+    let pty = native_pty_system()
+        .openpty(PtySize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 0,
+            pixel_height: 0,
+        })
+        .unwrap();
+    let mut cmd = CommandBuilder::new(env!("CARGO_BIN_EXE_sgpt"));
+    cmd.arg("--repl");
+    cmd.args(args);
+    cmd.env("HOME", home);
+    cmd.env("XDG_CONFIG_HOME", home.join("config"));
+    cmd.env("OPENAI_API_KEY", "test-key");
+    cmd.env("TERM", "xterm");
+    cmd.env("CHAT_CACHE_PATH", home.join("chat_cache"));
+    cmd.env("CACHE_PATH", home.join("cache"));
+    cmd.env("ROLE_STORAGE_PATH", home.join("roles"));
+    cmd.env("OPENAI_FUNCTIONS_PATH", home.join("functions"));
+    for (key, value) in env {
+        cmd.env(key, value);
+    }
+    let mut child = pty.slave.spawn_command(cmd).unwrap();
+    drop(pty.slave);
+
+    // Drain the screen so the TUI never blocks, and note when it has drawn something
+    let drawn = Arc::new(AtomicBool::new(false));
+    let mut reader = pty.master.try_clone_reader().unwrap();
+    let seen = drawn.clone();
+    thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        while matches!(reader.read(&mut buf), Ok(n) if n > 0) {
+            seen.store(true, Ordering::SeqCst);
+        }
+    });
+
+    // Input typed before raw mode is on would be eaten by the line discipline
+    let started = wait_for(Duration::from_secs(20), || {
+        drawn.load(Ordering::SeqCst).then_some(())
+    });
+    let found = started.and_then(|()| {
+        thread::sleep(Duration::from_millis(300));
+        let mut writer = pty.master.take_writer().unwrap();
+        writer.write_all(format!("{}\r", line).as_bytes()).unwrap();
+        writer.flush().unwrap();
+        wait_for(Duration::from_secs(20), done)
+    });
+    let _ = child.kill();
+    let _ = child.wait();
+    (started.is_some(), found)
+}
+
+#[test]
+fn typed_message_is_answered_and_saved() {
+    let home = tempfile::tempdir().unwrap();
+    let chat_dir = home.path().join("chat_cache");
+    let (started, replies) = type_into_repl(
+        home.path(),
+        &["tui-test", "--model", "fake"],
+        &[],
+        "hello there",
+        || Some(assistant_messages(&chat_dir, "tui-test")).filter(|m| !m.is_empty()),
+    );
+
+    assert!(started, "the TUI never drew a screen");
+    let replies = replies.expect("no assistant message was saved");
+    assert!(
+        replies[0].contains("fake AI assistant"),
+        "reply: {}",
+        replies[0]
+    );
+}
+
+#[test]
+fn no_system_role_keeps_system_messages_out_of_repl_requests() {
+    let home = tempfile::tempdir().unwrap();
+    let chat_dir = home.path().join("chat_cache");
+    let server = MockServer::start();
+    let completion = server.mock(|when, then| {
+        when.method(POST)
+            .path("/v1/chat/completions")
+            .matches(|req| {
+                let body = req.body.as_deref().unwrap_or_default();
+                let Ok(json) = serde_json::from_slice::<serde_json::Value>(body) else {
+                    return false;
+                };
+                let messages = json["messages"].as_array().cloned().unwrap_or_default();
+                // The role prompt still leads the conversation, as a user message
+                messages.len() == 2 && messages.iter().all(|m| m["role"] == "user")
+            });
+        then.status(200)
+            .header("content-type", "text/event-stream")
+            .body("data: {\"choices\":[{\"delta\":{\"content\":\"no system here\"}}]}\n\ndata: [DONE]\n\n");
+    });
+    let base_url = server.base_url();
+    let (started, replies) = type_into_repl(
+        home.path(),
+        &["nosys", "--model", "gpt-4o", "--no-system-role"],
+        &[("API_BASE_URL", &base_url)],
+        "hello there",
+        || Some(assistant_messages(&chat_dir, "nosys")).filter(|m| !m.is_empty()),
+    );
+
+    assert!(started, "the TUI never drew a screen");
+    let replies = replies.expect("no assistant message was saved");
+    assert_eq!(replies[0], "no system here");
+    completion.assert();
+}